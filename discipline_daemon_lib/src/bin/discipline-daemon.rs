@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use discipline_daemon::launcher::{run, remove_browser_policies, LaunchConfiguration};
+
+const USAGE: &str = "Usage: discipline-daemon [--config <path>] [--remove-browser-policies]";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+  let mut configuration_path = PathBuf::from(LaunchConfiguration::DEFAULT_PATH);
+  let mut should_remove_browser_policies = false;
+  let mut arguments = std::env::args().skip(1);
+
+  while let Some(argument) = arguments.next() {
+    match argument.as_str() {
+      "--config" => {
+        let Some(path) = arguments.next() else {
+          eprintln!("{USAGE}");
+          return ExitCode::FAILURE;
+        };
+        configuration_path = PathBuf::from(path);
+      }
+      "--remove-browser-policies" => {
+        should_remove_browser_policies = true;
+      }
+      "--help" => {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+      }
+      _ => {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+      }
+    }
+  }
+
+  if should_remove_browser_policies {
+    return remove_browser_policies(&configuration_path);
+  }
+
+  run(&configuration_path).await
+}
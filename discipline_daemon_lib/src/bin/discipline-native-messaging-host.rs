@@ -0,0 +1,130 @@
+// The native messaging host browsers start for the Discipline
+// extension. It relays the web rules the daemon has for the user
+// running the browser, which the extension then enforces.
+//
+// Browsers find it through the manifests in packaging/native-messaging,
+// and pass it arguments we don't need, so none are read.
+
+use std::io::{BufRead, BufReader, Stdout, stdin, stdout};
+use std::os::unix::net::UnixStream;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use discipline_daemon::launcher::native_messaging::{
+  ExtensionMessage, HostMessage, Server, WebRules, read_native_message,
+  write_native_message,
+};
+
+const RECONNECTION_DELAY: Duration = Duration::from_secs(5);
+
+fn main() -> ExitCode {
+  let stdout = Arc::new(Mutex::new(stdout()));
+  let latest_rules: Arc<Mutex<Option<WebRules>>> = Arc::new(Mutex::new(None));
+
+  {
+    let stdout = Arc::clone(&stdout);
+    let latest_rules = Arc::clone(&latest_rules);
+    thread::spawn(move || relay_rules(&stdout, &latest_rules));
+  }
+
+  let mut stdin = stdin().lock();
+
+  loop {
+    let message = match read_native_message(&mut stdin) {
+      Ok(Some(message)) => {
+        message
+      }
+      // The browser closes stdin when the extension is done with us.
+      Ok(None) => {
+        return ExitCode::SUCCESS;
+      }
+      Err(error) => {
+        eprintln!("Discipline native messaging host: Failed to read a message from the browser: {error}");
+        return ExitCode::FAILURE;
+      }
+    };
+
+    match serde_json::from_slice(&message) {
+      Ok(ExtensionMessage::GetRules) => {
+        let message = match latest_rules.lock().unwrap().clone() {
+          Some(rules) => {
+            HostMessage::Rules { rules }
+          }
+          None => {
+            HostMessage::DaemonUnavailable
+          }
+        };
+
+        if !send(&stdout, &message) {
+          return ExitCode::FAILURE;
+        }
+      }
+      Err(error) => {
+        eprintln!("Discipline native messaging host: Ignoring a message we don't understand: {error}");
+      }
+    }
+  }
+}
+
+// Forwards every rules update from the daemon to the extension,
+// reconnecting whenever the daemon goes away, like when it restarts.
+fn relay_rules(stdout: &Mutex<Stdout>, latest_rules: &Mutex<Option<WebRules>>) {
+  let mut is_daemon_available = true;
+
+  loop {
+    if let Ok(connection) = UnixStream::connect(Server::PATH) {
+      for line in BufReader::new(connection).lines() {
+        let Ok(line) = line else {
+          break;
+        };
+
+        let rules: WebRules = match serde_json::from_str(&line) {
+          Ok(rules) => {
+            rules
+          }
+          Err(error) => {
+            eprintln!("Discipline native messaging host: Ignoring rules we don't understand: {error}");
+            continue;
+          }
+        };
+
+        *latest_rules.lock().unwrap() = Some(rules.clone());
+        is_daemon_available = true;
+
+        if !send(stdout, &HostMessage::Rules { rules }) {
+          std::process::exit(1);
+        }
+      }
+    }
+
+    // Only tell the extension once per outage.
+    if is_daemon_available {
+      is_daemon_available = false;
+      *latest_rules.lock().unwrap() = None;
+
+      if !send(stdout, &HostMessage::DaemonUnavailable) {
+        std::process::exit(1);
+      }
+    }
+
+    thread::sleep(RECONNECTION_DELAY);
+  }
+}
+
+fn send(stdout: &Mutex<Stdout>, message: &HostMessage) -> bool {
+  let Ok(message) = serde_json::to_vec(message) else {
+    return false;
+  };
+
+  let mut stdout = stdout.lock().unwrap();
+  match write_native_message(&mut *stdout, &message) {
+    Ok(()) => {
+      true
+    }
+    Err(error) => {
+      eprintln!("Discipline native messaging host: Failed to write a message to the browser: {error}");
+      false
+    }
+  }
+}
@@ -84,8 +84,7 @@ impl Countdown {
       return CountdownState::Pending;
     } 
     
-    let elapsed_time = self.get_elapsed_time_or_zero(now);
-    if elapsed_time.is_shorter_than_or_equal_to(self.duration) {
+    if now.is_eariler_than(self.get_till()) {
       return CountdownState::Running;
     }
     
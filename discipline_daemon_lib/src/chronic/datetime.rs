@@ -1,5 +1,5 @@
-use chrono::Timelike;
-use crate::x::{Duration, TextualErrorContext, Time, ToTextualError};
+use chrono::{Datelike, Timelike};
+use crate::x::{Duration, TextualErrorContext, Time, ToTextualError, Weekday};
 
 #[derive(Debug, Clone)]
 pub enum CreateFromMillisecondTimestampError {
@@ -52,16 +52,22 @@ impl DateTime {
     match later
       .as_timestamp()
       .checked_sub(self.as_timestamp())
+      .and_then(|milliseconds| u64::try_from(milliseconds).ok())
     {
       None => {
         Duration::zero()
       }
       Some(milliseconds) => {
-        Duration::from_milliseconds(milliseconds.try_into().unwrap())
+        Duration::from_milliseconds(milliseconds)
       }
     }
   }
 
+  pub fn weekday(&self) -> Weekday {
+    // num_days_from_monday is always below 7.
+    Weekday::from_number_from_monday(self.inner.weekday().num_days_from_monday() as u8).unwrap()
+  }
+
   pub fn time(&self) -> Time {
     unsafe {
       let time = self.inner.time();
@@ -76,10 +76,11 @@ impl MonotonicClock {
       return;
     }
 
+    // Cap how far the clock may jump at once, so that changing the 
+    // system time can't fast-forward it.
     self.total_elapsed_duration = self
       .total_elapsed_duration
-      .saturating_add(realtime_since_prev_sync)
-      .min(self.maximum_synchronization_interval);
+      .saturating_add(realtime_since_prev_sync.min(self.maximum_synchronization_interval));
     
     self.previous_synchronization_realtime = realtime;
   }
@@ -101,8 +102,7 @@ impl MonotonicClock {
 
     self.total_elapsed_duration = self
       .total_elapsed_duration
-      .saturating_add(boottime_since_prev_sync)
-      .min(self.maximum_synchronization_interval);
+      .saturating_add(boottime_since_prev_sync.min(self.maximum_synchronization_interval));
 
     self.previous_synchronization_boottime = boottime;
   }
@@ -147,7 +147,7 @@ impl Instant {
   }
 
   pub fn since_or_zero(self, eariler: Instant) -> Duration {
-    eariler.0.saturating_sub(self.0)
+    self.0.saturating_sub(eariler.0)
   }
 
   pub fn saturating_add(self, duration: Duration) -> Instant {
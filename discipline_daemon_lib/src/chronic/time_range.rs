@@ -87,7 +87,12 @@ impl TimeRange {
 
   pub fn contains(&self, time: Time) -> bool {
     let time = time.as_timestamp();
-    self.from <= time && self.till >= time
+
+    // Ranges that cross midnight go on past the end of the day, where
+    // the times after midnight are a day later.
+    (self.from <= time && self.till >= time)
+    ||
+    time + MILLISECONDS_PER_DAY <= self.till
   }
 
   pub fn duration(&self) -> Duration {
@@ -91,6 +91,11 @@ impl UserUptimeClock {
     if time_since_prev_sync.is_longer_than(self.maximum_synchronization_interval) {
       time_since_prev_sync = self.maximum_synchronization_interval;
     }
+    // Days and weeks still roll over while the user isn't logged in,
+    // but no uptime is counted.
+    if !self.is_running {
+      time_since_prev_sync = Duration::zero();
+    }
     
     let time_since_day_start = self.day_start.till_or_zero(now);
     if time_since_day_start.is_shorter_than(DAY) {
@@ -105,11 +110,11 @@ impl UserUptimeClock {
       self.day_uptime = self
         .day_start
         .till_or_zero(now)
-        .min(self.maximum_synchronization_interval);
+        .min(time_since_prev_sync);
     }
 
     let time_since_week_start = self.week_start.till_or_zero(now);
-    if time_since_day_start.is_shorter_than(WEEK) {
+    if time_since_week_start.is_shorter_than(WEEK) {
       self.week_uptime = self
         .week_uptime
         .saturating_add(time_since_prev_sync)
@@ -121,7 +126,7 @@ impl UserUptimeClock {
       self.week_uptime = self
         .week_start
         .till_or_zero(now)
-        .min(self.maximum_synchronization_interval);
+        .min(time_since_prev_sync);
     }
 
     self.previous_synchronization_time = now;
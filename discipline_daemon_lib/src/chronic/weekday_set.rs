@@ -22,7 +22,7 @@ impl WeekdaySet {
   const SAT_ONLY_SET: Self = Self(Self::SAT_BITMASK);
   const SUN_ONLY_SET: Self = Self(Self::SUN_BITMASK);
 
-  // const ALL: Self = Self(0b111_1111);
+  pub const ALL: Self = Self(0b111_1111);
   const EMPTY: Self = Self(0b000_0000);
 
   pub fn from_bitmask(bitmask: u8) -> Self {
@@ -84,11 +84,11 @@ impl WeekdaySet {
 ///
 /// # Example
 /// ```
-/// # use chrono::Self;
-/// use chrono::Weekday::*;
-/// assert_eq!(format!("{:?}", Self::single(Mon)), "Self(0000001)");
-/// assert_eq!(format!("{:?}", Self::single(Tue)), "Self(0000010)");
-/// assert_eq!(format!("{:?}", Self::ALL), "Self(1111111)");
+/// use discipline_daemon::chronic::weekday::Weekday::*;
+/// use discipline_daemon::chronic::weekday_set::WeekdaySet;
+/// assert_eq!(format!("{:?}", WeekdaySet::from_weekday(Mon)), "Self(0000001)");
+/// assert_eq!(format!("{:?}", WeekdaySet::from_weekday(Tue)), "Self(0000010)");
+/// assert_eq!(format!("{:?}", WeekdaySet::ALL), "Self(1111111)");
 /// ```
 impl Debug for WeekdaySet {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use crate::x::{Countdown, Duration, Instant};
+use crate::x::{Countdown, CountdownState, Duration, Instant};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CountdownConditional {
@@ -16,6 +16,23 @@ impl CountdownConditional {
     matches!(&self.countdown, Some(countdown) if countdown.is_running(now))
   }
 
+  // A countdown that starts in the future activates by itself.
+  pub fn get_duration_till_active(&self, now: Instant) -> Option<Duration> {
+    let countdown = self.countdown.as_ref()?;
+
+    match countdown.get_state(now) {
+      CountdownState::Pending => {
+        Some(countdown.get_duration_till_start_or_zero(now))
+      }
+      CountdownState::Running => {
+        Some(Duration::zero())
+      }
+      CountdownState::Finished => {
+        None
+      }
+    }
+  }
+
   pub fn activate(&mut self, now: Instant) {
     self.countdown = Some(Countdown::construct(now, self.duration));
   }
@@ -1,80 +1,65 @@
-use std::path::PathBuf;
-use crate::{procedures::AlwaysRuleLocation, x::{AlwaysRule, TextualError, UuidV4, database}};
+use std::fs::create_dir_all;
+use std::path::Path;
+use crate::x::IsTextualError;
 use super::{SqlCode, MyConnection};
+use super::{always_rule_table, application_usage_table, group_profile_table, monotonic_clock_table, password_escrow_table, session_record_table, user_profile_table, user_usage_table, vault_data_table, vault_reveal_table, vault_table};
 
 pub struct Database {
   pub connection: MyConnection,
 }
 
 impl Database {
-  pub async fn open(database_directory: PathBuf) -> Result<Self, TextualError> {
-    // let connection = Connection::open(database_directory)?;
+  pub const FILE_NAME: &'static str = "data.sqlite";
 
-    // let user_collection = database
-    //   ::users
-    //   ::Collection
-    //   ::new("UserCollection");
+  pub fn open(
+    database_directory: &Path, 
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Self, ()> {
+    if let Err(error) = create_dir_all(database_directory) {
+      textual_error.change_context("Opening the database");
+      textual_error.add_message("Failed to create the database directory");
+      textual_error.add_attachement_display("Database directory", database_directory.display());
+      textual_error.add_attachement_display("Io error", error);
+      return Err(());
+    }
 
-    // let user_block_info_vault_table = database
-    //   ::block_info_access
-    //   ::VaultTable
-    //   ::new("UserBlockInfoVaultCollection");
+    let connection = MyConnection::open(
+      &database_directory.join(Self::FILE_NAME),
+      textual_error,
+    )?;
 
-    // let user_block_info_datum_collection = database
-    //   ::block_info_access
-    //   ::DatumTable
-    //   ::new("UserBlockInfoDatumCollection");
-
-    // let user_device_access_regulation_rule_table = database
-    //   ::rules
-    //   ::user_rule_table
-    //   ::Table
-    //   ::new("UserDeviceAccessRegulationRuleCollection");
-
-    // let user_account_access_regulation_rule_table = database
-    //   ::rules
-    //   ::user_rule_table
-    //   ::Table
-    //   ::new("UserAccountAccessRegulationRuleCollection");
-    
-    // let user_internet_access_regulation_rule_table = database
-    //   ::rules
-    //   ::user_rule_table
-    //   ::Table
-    //   ::new("UserInternetAccessRegulationRuleCollection");
+    Self::create(connection, textual_error)
+  }
 
-    // let singleton_collection = database
-    //   ::singleton
-    //   ::SingletonTable
-    //   ::new("Singleton");
+  // A database that lives only as long as it's open, for tests.
+  #[cfg(test)]
+  pub fn open_in_memory(textual_error: &mut impl IsTextualError) -> Result<Self, ()> {
+    let connection = MyConnection::open(Path::new(":memory:"), textual_error)?;
+    Self::create(connection, textual_error)
+  }
 
-    // let mut code = SqlCode::new();
-    // database::users::write_initialize(&mut code, &user_collection);
-    // database::rules::user_rule_table::write_initialize(&mut code, &user_device_access_regulation_rule_table);
-    // database::rules::user_rule_table::write_initialize(&mut code, &user_account_access_regulation_rule_table);
-    // database::rules::user_rule_table::write_initialize(&mut code, &user_internet_access_regulation_rule_table);
-    // database::block_info_access::write_initialize_datum_table(&mut code, &user_block_info_datum_collection);
-    // database::block_info_access::write_initialize_vault_table(&mut code, &user_block_info_vault_table);
-    // database::singleton::write_initialize(&mut code, &singleton_collection);
+  fn create(
+    connection: MyConnection,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Self, ()> {
+    let mut code = SqlCode::new();
+    always_rule_table::write_create_table(&mut code);
+    vault_table::write_create_table(&mut code);
+    vault_reveal_table::write_create_table(&mut code);
+    vault_data_table::write_create_table(&mut code);
+    password_escrow_table::write_create_table(&mut code);
+    monotonic_clock_table::write_create_table(&mut code);
+    application_usage_table::write_create_table(&mut code);
+    user_profile_table::write_create_table(&mut code);
+    user_usage_table::write_create_table(&mut code);
+    group_profile_table::write_create_table(&mut code);
+    session_record_table::write_create_table(&mut code);
 
-    // connection.execute_2(&code).await.map_err(|error| {
-    //   TextualError::new("Opening a SQLite database connection")
-    //     .with_message("A SQLite error occured while ensuring the database is initialized")
-    //     .with_attachement_display("SQLite code", code.as_str())
-    //     .with_attachement_display("SQLite error", error)
-    // })?;
+    if connection.execute(&code, textual_error).is_err() {
+      textual_error.change_context("Opening the database: Ensuring the tables exist");
+      return Err(());
+    }
 
-    // Ok(Self {
-    //   connection,
-    //   singleton_table: singleton_collection,
-    //   user_collection,
-    //   user_block_info_vault_table,
-    //   user_block_info_datum_collection,
-    //   user_device_access_regulation_rule_table,
-    //   user_account_access_regulation_rule_table,
-    //   user_internet_access_regulation_rule_table,
-    // })
-    todo!()
+    Ok(Self { connection })
   }
-
 }
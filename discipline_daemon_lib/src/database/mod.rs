@@ -3,7 +3,7 @@ mod other;
 mod utilities;
 pub use utilities::*;
 
-mod v2;
+// mod v2;
 // mod other;
 
 // mod tables;
@@ -84,7 +84,9 @@ impl CountdownConditionalDbAdapter {
   }
 }
 
-pub enum CountdownAfterPleaConditionalDbAdapterError {}
+pub enum CountdownAfterPleaConditionalDbAdapterError {
+  Other,
+}
 
 pub struct CountdownAfterPleaConditionalDbAdapter {}
 
@@ -95,7 +97,18 @@ impl CountdownAfterPleaConditionalDbAdapter {
     location: &CountdownAfterPleaConditionalLocation,
     textual_error: &mut impl IsTextualError,
   ) -> Result<(), CountdownAfterPleaConditionalDbAdapterError> {
-    todo!()
+    match location {
+      CountdownAfterPleaConditionalLocation::VaultProtector { vault_id } => {
+        vault_table::update_protector_countdown(database, vault_id, None, textual_error).map_err(|_| {
+          CountdownAfterPleaConditionalDbAdapterError::Other
+        })
+      }
+      CountdownAfterPleaConditionalLocation::A(_) => {
+        textual_error.change_context("Activating a CountdownAfterPleaConditional in the database");
+        textual_error.add_message("No table stores conditionals at this location");
+        Err(CountdownAfterPleaConditionalDbAdapterError::Other)
+      }
+    }
   }
   
   pub fn redactivate(
@@ -105,6 +118,23 @@ impl CountdownAfterPleaConditionalDbAdapter {
     re_deactivate_state: &CountdownAfterPleaConditionalDeactivatingState,
     textual_error: &mut impl IsTextualError,
   ) -> Result<(), CountdownAfterPleaConditionalDbAdapterError> {
-    todo!()
+    match location {
+      CountdownAfterPleaConditionalLocation::VaultProtector { vault_id } => {
+        vault_table::update_protector_countdown(
+          database, 
+          vault_id, 
+          Some(&re_deactivate_state.countdown), 
+          textual_error,
+        ).map_err(|_| {
+          CountdownAfterPleaConditionalDbAdapterError::Other
+        })
+      }
+      CountdownAfterPleaConditionalLocation::A(_) => {
+        textual_error.change_context("Deactivating a CountdownAfterPleaConditional in the database");
+        textual_error.add_message("No table stores conditionals at this location");
+        Err(CountdownAfterPleaConditionalDbAdapterError::Other)
+      }
+    }
   }
 }
+
@@ -2,8 +2,8 @@
 macro_rules! sql {
   ($dst:expr $(,)?) => {};
 
-    ($dst:expr, { $value:expr }) => {
-    $dst.write_value($value);
+  ($dst:expr, { $value:expr }) => {
+    $dst.write_identifier($value);
   };
 
   ($dst:expr, [ $value:expr ]) => {
@@ -23,7 +23,7 @@ macro_rules! sql {
 
 use crate::IsTextualError;
 use crate::x::{AlwaysRule, CountdownAfterPleaConditionalDeactivatingState, CountdownConditionalActivateState, RuleEnabler, RuleEnablerType, UuidV4};
-use crate::x::procedures::{AlwaysRuleContext, AlwaysRuleLocation};
+use crate::x::procedures::AlwaysRuleLocation;
 use crate::x::database::*;
 use crate::sql;
 
@@ -63,7 +63,7 @@ pub fn write_create_table(code: &mut SqlCode) {
       {ENABLER_TYPE} " INTEGER NOT NULL, "
       {ENABLER_DURATION} " INTEGER NOT NULL, "
       {ENABLER_COUNTDOWN_FROM} " INTEGER, "
-      {ENABLER_COUNTDOWN_DURATION} " INTEGER, "
+      {ENABLER_COUNTDOWN_DURATION} " INTEGER "
     ") STRICT, WITHOUT ROWID;"
   )
 }
@@ -256,7 +256,6 @@ pub fn enabler_countdown_write_activate(
 
 pub fn enabler_countdown_activate(
   database: &Database,
-  rule_context: &AlwaysRuleContext<'_>,
   rule_id: &UuidV4,
   activate_state: &CountdownConditionalActivateState,
   textual_error: &mut impl IsTextualError,
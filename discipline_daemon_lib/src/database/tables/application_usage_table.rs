@@ -0,0 +1,107 @@
+use crate::x::{Database, Duration, IsTextualError};
+use crate::x::launcher::UserId;
+use crate::x::database::*;
+use crate::sql;
+
+const TABLE: &str = "ApplicationUsage";
+
+const USER_ID: &str = "user_id";
+const EXECUTABLE: &str = "executable";
+// Days since January 1, 1970 UTC.
+const DAY: &str = "day";
+const RUN_TIME: &str = "run_time";
+
+pub fn write_create_table(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "CREATE TABLE IF NOT EXISTS " {TABLE} "( "
+      {USER_ID} " INTEGER NOT NULL, "
+      {EXECUTABLE} " TEXT NOT NULL, "
+      {DAY} " INTEGER NOT NULL, "
+      {RUN_TIME} " INTEGER NOT NULL, "
+      "PRIMARY KEY (" {USER_ID} ", " {EXECUTABLE} ", " {DAY} ")"
+    ") STRICT, WITHOUT ROWID;"
+  )
+}
+
+pub fn write_add_run_time(
+  code: &mut SqlCode,
+  user_id: UserId,
+  executable: &str,
+  day: i64,
+  run_time: Duration,
+) {
+  sql!(
+    code,
+
+    "INSERT INTO " {TABLE} " VALUES ("
+      [&user_id.inner()] ", "
+      [&executable] ", "
+      [&day] ", "
+      [&run_time.as_total_milliseconds()]
+    ") ON CONFLICT DO UPDATE SET "
+      {RUN_TIME} " = " {RUN_TIME} " + excluded." {RUN_TIME} ";"
+  )
+}
+
+pub fn write_delete_older_than(code: &mut SqlCode, day: i64) {
+  sql!(
+    code,
+
+    "DELETE FROM " {TABLE} " WHERE " {DAY} " < " [&day] ";"
+  )
+}
+
+// Adds every run time, then forgets the days before `oldest_kept_day`.
+pub fn add_run_times(
+  database: &Database,
+  run_times: &[(UserId, String, i64, Duration)],
+  oldest_kept_day: i64,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let mut code = SqlCode::new();
+  for (user_id, executable, day, run_time) in run_times {
+    write_add_run_time(&mut code, *user_id, executable, *day, *run_time);
+  }
+  write_delete_older_than(&mut code, oldest_kept_day);
+
+  database.connection.execute(&code, textual_error).map_err(|_| ())
+}
+
+pub fn write_select_top(
+  code: &mut SqlCode,
+  user_id: UserId,
+  since_day: i64,
+  limit: usize,
+) {
+  sql!(
+    code,
+
+    "SELECT " {EXECUTABLE} ", SUM(" {RUN_TIME} ") AS total"
+    " FROM " {TABLE}
+    " WHERE " {USER_ID} " = " [&user_id.inner()] " AND " {DAY} " >= " [&since_day]
+    " GROUP BY " {EXECUTABLE}
+    " ORDER BY total DESC"
+    " LIMIT " [&limit] ";"
+  )
+}
+
+// The executables the user ran the longest since `since_day`, longest
+// first.
+pub fn select_top(
+  database: &Database,
+  user_id: UserId,
+  since_day: i64,
+  limit: usize,
+  textual_error: &mut impl IsTextualError,
+) -> Result<Vec<(String, Duration)>, ()> {
+  let mut code = SqlCode::new();
+  write_select_top(&mut code, user_id, since_day, limit);
+
+  database.connection.query_rows(&code, |row| {
+    let executable: String = row.get(0)?;
+    let run_time: i64 = row.get(1)?;
+    Ok((executable, Duration::from_milliseconds(run_time.max(0) as u64)))
+  }, textual_error)
+}
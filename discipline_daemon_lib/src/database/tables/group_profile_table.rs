@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use crate::x::{Database, IsTextualError, UuidV4};
+use crate::x::launcher::GroupProfile;
+use crate::x::database::*;
+use crate::sql;
+
+const TABLE: &str = "GroupProfiles";
+
+const ID: &str = "id";
+// The whole profile as JSON, since its regulation is nested too deep
+// for columns of its own.
+const PROFILE: &str = "profile";
+
+pub fn write_create_table(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "CREATE TABLE IF NOT EXISTS " {TABLE} "( "
+      {ID} " TEXT PRIMARY KEY, "
+      {PROFILE} " TEXT NOT NULL "
+    ") STRICT, WITHOUT ROWID;"
+  )
+}
+
+pub fn write_save(
+  code: &mut SqlCode,
+  group_profile_id: &UuidV4,
+  profile: &str,
+) {
+  sql!(
+    code,
+
+    "INSERT INTO " {TABLE} " VALUES ("
+      [&group_profile_id.to_string()] ", "
+      [&profile]
+    ") ON CONFLICT DO UPDATE SET "
+      {PROFILE} " = excluded." {PROFILE} ";"
+  )
+}
+
+// Inserts the profile, or replaces the saved one.
+pub fn save_group_profile(
+  database: &Database,
+  group_profile_id: &UuidV4,
+  group_profile: &GroupProfile,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let profile = match serde_json::to_string(group_profile) {
+    Ok(profile) => {
+      profile
+    }
+    Err(error) => {
+      textual_error.change_context("Saving a group profile");
+      textual_error.add_message("Failed to serialize the profile");
+      textual_error.add_attachement_display("Serialization error", error);
+      return Err(());
+    }
+  };
+
+  let mut code = SqlCode::new();
+  write_save(&mut code, group_profile_id, &profile);
+  database.connection.execute(&code, textual_error).map_err(|_| ())
+}
+
+pub fn write_delete(
+  code: &mut SqlCode,
+  group_profile_id: &UuidV4,
+) {
+  sql!(
+    code,
+
+    "DELETE FROM " {TABLE} " WHERE " {ID} " = " [&group_profile_id.to_string()] ";"
+  )
+}
+
+pub fn delete_group_profile(
+  database: &Database,
+  group_profile_id: &UuidV4,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let mut code = SqlCode::new();
+  write_delete(&mut code, group_profile_id);
+  database.connection.execute(&code, textual_error).map_err(|_| ())
+}
+
+pub fn write_select_all(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "SELECT " {ID} ", " {PROFILE} " FROM " {TABLE} ";"
+  )
+}
+
+// Memberships aren't saved, so the loaded profiles have no members
+// until they're refreshed.
+pub fn load_group_profiles(
+  database: &Database,
+  textual_error: &mut impl IsTextualError,
+) -> Result<HashMap<UuidV4, GroupProfile>, ()> {
+  let mut code = SqlCode::new();
+  write_select_all(&mut code);
+
+  let rows = database.connection.query_rows(&code, |row| {
+    let group_profile_id: String = row.get(0)?;
+    let profile: String = row.get(1)?;
+    Ok((group_profile_id, profile))
+  }, textual_error)?;
+
+  let mut group_profiles = HashMap::new();
+  for (group_profile_id, profile) in rows {
+    let Ok(group_profile_id) = UuidV4::from_string(&group_profile_id) else {
+      textual_error.change_context("Loading group profiles");
+      textual_error.add_message("A saved profile's id is invalid");
+      textual_error.add_attachement_display("Id", group_profile_id);
+      return Err(());
+    };
+
+    let group_profile = match serde_json::from_str(&profile) {
+      Ok(group_profile) => {
+        group_profile
+      }
+      Err(error) => {
+        textual_error.change_context("Loading group profiles");
+        textual_error.add_message("Failed to deserialize a saved profile");
+        textual_error.add_attachement_display("Deserialization error", error);
+        textual_error.add_attachement_display("Id", group_profile_id.to_string());
+        return Err(());
+      }
+    };
+
+    group_profiles.insert(group_profile_id, group_profile);
+  }
+
+  Ok(group_profiles)
+}
@@ -1,5 +1,15 @@
 pub mod always_rule_table;
 // pub mod time_range_rule_table;
+pub mod vault_table;
+pub mod vault_reveal_table;
+pub mod vault_data_table;
+pub mod password_escrow_table;
+pub mod monotonic_clock_table;
+pub mod application_usage_table;
+pub mod group_profile_table;
+pub mod user_profile_table;
+pub mod user_usage_table;
+pub mod session_record_table;
 
 pub mod locations_table;
 pub use locations_table::LocationId;
\ No newline at end of file
@@ -0,0 +1,92 @@
+use crate::x::{Database, Duration, Instant, IsTextualError, MonotonicClock};
+use crate::x::database::*;
+use crate::sql;
+
+const TABLE: &str = "MonotonicClock";
+
+const ID: &str = "id";
+const TOTAL_ELAPSED_DURATION: &str = "total_elapsed_duration";
+const PREVIOUS_SYNCHRONIZATION_REALTIME: &str = "previous_synchronization_realtime";
+const MAXIMUM_SYNCHRONIZATION_INTERVAL: &str = "maximum_synchronization_interval";
+
+// There is only ever one row.
+const SINGLETON_ID: u8 = 0;
+
+pub fn write_create_table(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "CREATE TABLE IF NOT EXISTS " {TABLE} "( "
+      {ID} " INTEGER PRIMARY KEY, "
+      {TOTAL_ELAPSED_DURATION} " INTEGER NOT NULL, "
+      {PREVIOUS_SYNCHRONIZATION_REALTIME} " INTEGER NOT NULL, "
+      {MAXIMUM_SYNCHRONIZATION_INTERVAL} " INTEGER NOT NULL "
+    ") STRICT;"
+  )
+}
+
+pub fn write_save(
+  code: &mut SqlCode,
+  clock: &MonotonicClock,
+) {
+  sql!(
+    code,
+
+    "INSERT OR REPLACE INTO " {TABLE} " VALUES ("
+      [&SINGLETON_ID] ", "
+      [&clock.total_elapsed_duration.as_total_milliseconds()] ", "
+      [&clock.previous_synchronization_realtime.as_timestamp()] ", "
+      [&clock.maximum_synchronization_interval.as_total_milliseconds()]
+    ");"
+  )
+}
+
+pub fn save_clock(
+  database: &Database,
+  clock: &MonotonicClock,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let mut code = SqlCode::new();
+  write_save(&mut code, clock);
+  database.connection.execute(&code, textual_error).map_err(|_| ())
+}
+
+pub fn write_select(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "SELECT " 
+      {TOTAL_ELAPSED_DURATION} ", "
+      {PREVIOUS_SYNCHRONIZATION_REALTIME} ", "
+      {MAXIMUM_SYNCHRONIZATION_INTERVAL}
+    " FROM " {TABLE} " WHERE " {ID} " = " [&SINGLETON_ID] ";"
+  )
+}
+
+// Returns None if the clock was never saved.
+pub fn load_clock(
+  database: &Database,
+  realtime: Instant,
+  boottime: Instant,
+  textual_error: &mut impl IsTextualError,
+) -> Result<Option<MonotonicClock>, ()> {
+  let mut code = SqlCode::new();
+  write_select(&mut code);
+
+  database.connection.query_optional_row(&code, |row| {
+    let total_elapsed_duration: i64 = row.get(0)?;
+    let previous_synchronization_realtime: i64 = row.get(1)?;
+    let maximum_synchronization_interval: i64 = row.get(2)?;
+
+    // The boottime clock restarts from zero on every boot, so the
+    // saved value is meaningless now. Only realtime is caught up on.
+    Ok(MonotonicClock::construct(
+      realtime,
+      boottime,
+      Duration::from_milliseconds(total_elapsed_duration.max(0) as u64),
+      Duration::from_milliseconds(maximum_synchronization_interval.max(0) as u64),
+      Instant::from_timestamp(previous_synchronization_realtime.max(0) as u64),
+      boottime,
+    ))
+  }, textual_error)
+}
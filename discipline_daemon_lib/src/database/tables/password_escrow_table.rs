@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use crate::x::{Database, IsTextualError, UuidV4};
+use crate::x::launcher::{PasswordEscrow, UserId, UserName};
+use crate::x::database::*;
+use crate::sql;
+
+const TABLE: &str = "PasswordEscrows";
+
+const ID: &str = "id";
+const VAULT_ID: &str = "vault_id";
+const DATUM_ID: &str = "datum_id";
+const USER_ID: &str = "user_id";
+const USER_NAME: &str = "user_name";
+const ROTATE_AFTER_REVEAL: &str = "rotate_after_reveal";
+
+pub fn write_create_table(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "CREATE TABLE IF NOT EXISTS " {TABLE} "( "
+      {ID} " TEXT PRIMARY KEY, "
+      {VAULT_ID} " TEXT NOT NULL, "
+      {DATUM_ID} " TEXT NOT NULL, "
+      {USER_ID} " INTEGER NOT NULL, "
+      {USER_NAME} " TEXT NOT NULL, "
+      {ROTATE_AFTER_REVEAL} " INTEGER NOT NULL "
+    ") STRICT, WITHOUT ROWID;"
+  )
+}
+
+pub fn write_insert(
+  code: &mut SqlCode,
+  escrow_id: &UuidV4,
+  escrow: &PasswordEscrow,
+) {
+  sql!(
+    code,
+
+    "INSERT INTO " {TABLE} " VALUES ("
+      [&escrow_id.to_string()] ", "
+      [&escrow.vault_id.to_string()] ", "
+      [&escrow.datum_id.to_string()] ", "
+      [&escrow.user_id.inner()] ", "
+      [escrow.user_name.inner()] ", "
+      [&escrow.rotate_after_reveal]
+    ");"
+  )
+}
+
+pub fn insert_escrow(
+  database: &Database,
+  escrow_id: &UuidV4,
+  escrow: &PasswordEscrow,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), InsertError> {
+  let mut code = SqlCode::new();
+  write_insert(&mut code, escrow_id, escrow);
+  database.connection.execute(&code, textual_error).map_err(|error| match error {
+    DbExecuteError::PrimaryKeyViolation => {
+      InsertError::DuplicateEscrowId
+    }
+    DbExecuteError::ForiegnKeyViolation => {
+      InsertError::Other
+    }
+    DbExecuteError::Other => {
+      InsertError::Other
+    }
+  })
+}
+
+pub fn write_delete(
+  code: &mut SqlCode,
+  escrow_id: &UuidV4,
+) {
+  sql!(
+    code,
+
+    "DELETE FROM " {TABLE} " WHERE " {ID} " = " [&escrow_id.to_string()] ";"
+  )
+}
+
+pub fn delete_escrow(
+  database: &Database,
+  escrow_id: &UuidV4,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), DeleteError> {
+  let mut code = SqlCode::new();
+  write_delete(&mut code, escrow_id);
+  database.connection.execute(&code, textual_error).map_err(|_| {
+    DeleteError::Other
+  })
+}
+
+pub fn write_select_all(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "SELECT "
+      {ID} ", "
+      {VAULT_ID} ", "
+      {DATUM_ID} ", "
+      {USER_ID} ", "
+      {USER_NAME} ", "
+      {ROTATE_AFTER_REVEAL}
+    " FROM " {TABLE} ";"
+  )
+}
+
+pub fn load_escrows(
+  database: &Database,
+  textual_error: &mut impl IsTextualError,
+) -> Result<HashMap<UuidV4, PasswordEscrow>, ()> {
+  let mut code = SqlCode::new();
+  write_select_all(&mut code);
+
+  let rows = database.connection.query_rows(&code, |row| {
+    let escrow_id: String = row.get(0)?;
+    let vault_id: String = row.get(1)?;
+    let datum_id: String = row.get(2)?;
+    let user_id: u32 = row.get(3)?;
+    let user_name: String = row.get(4)?;
+    let rotate_after_reveal: bool = row.get(5)?;
+    Ok((escrow_id, vault_id, datum_id, user_id, user_name, rotate_after_reveal))
+  }, textual_error)?;
+
+  let mut escrows = HashMap::new();
+  for (escrow_id, vault_id, datum_id, user_id, user_name, rotate_after_reveal) in rows {
+    let (Ok(escrow_id), Ok(vault_id), Ok(datum_id)) = (
+      UuidV4::from_string(&escrow_id),
+      UuidV4::from_string(&vault_id),
+      UuidV4::from_string(&datum_id),
+    ) else {
+      textual_error.change_context("Loading password escrows");
+      textual_error.add_message("A saved escrow's id, vault id or datum id is invalid");
+      textual_error.add_attachement_display("Id", escrow_id);
+      return Err(());
+    };
+
+    let Ok(user_name) = CString::new(user_name) else {
+      textual_error.change_context("Loading password escrows");
+      textual_error.add_message("A saved escrow's user name contains a nul byte");
+      textual_error.add_attachement_display("Id", escrow_id.to_string());
+      return Err(());
+    };
+
+    escrows.insert(escrow_id, PasswordEscrow {
+      vault_id,
+      datum_id,
+      user_id: UserId::new(user_id),
+      user_name: UserName::new(user_name),
+      rotate_after_reveal,
+    });
+  }
+
+  Ok(escrows)
+}
+
+pub enum InsertError {
+  DuplicateEscrowId,
+  Other,
+}
+
+pub enum DeleteError {
+  Other,
+}
@@ -0,0 +1,119 @@
+use crate::x::{Database, IsTextualError, UuidV4};
+use crate::x::launcher::SessionRecord;
+use crate::x::database::*;
+use crate::sql;
+
+const TABLE: &str = "SessionRecords";
+
+const ID: &str = "id";
+// The whole record as JSON. Records are only ever read back all at
+// once, when the daemon starts.
+const RECORD: &str = "record";
+
+pub fn write_create_table(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "CREATE TABLE IF NOT EXISTS " {TABLE} "( "
+      {ID} " TEXT PRIMARY KEY, "
+      {RECORD} " TEXT NOT NULL "
+    ") STRICT, WITHOUT ROWID;"
+  )
+}
+
+pub fn write_save(
+  code: &mut SqlCode,
+  session_record_id: &UuidV4,
+  record: &str,
+) {
+  sql!(
+    code,
+
+    "INSERT INTO " {TABLE} " VALUES ("
+      [&session_record_id.to_string()] ", "
+      [&record]
+    ") ON CONFLICT DO UPDATE SET "
+      {RECORD} " = excluded." {RECORD} ";"
+  )
+}
+
+pub fn write_delete(
+  code: &mut SqlCode,
+  session_record_id: &UuidV4,
+) {
+  sql!(
+    code,
+
+    "DELETE FROM " {TABLE} " WHERE " {ID} " = " [&session_record_id.to_string()] ";"
+  )
+}
+
+// Inserts or replaces `saved_records`, and deletes `deleted_records`,
+// in one go.
+pub fn update_session_records(
+  database: &Database,
+  saved_records: &[SessionRecord],
+  deleted_records: &[SessionRecord],
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let mut code = SqlCode::new();
+
+  for record in saved_records {
+    let serialized_record = match serde_json::to_string(record) {
+      Ok(serialized_record) => {
+        serialized_record
+      }
+      Err(error) => {
+        textual_error.change_context("Saving session records");
+        textual_error.add_message("Failed to serialize a record");
+        textual_error.add_attachement_display("Serialization error", error);
+        return Err(());
+      }
+    };
+
+    write_save(&mut code, &record.id, &serialized_record);
+  }
+  for record in deleted_records {
+    write_delete(&mut code, &record.id);
+  }
+
+  database.connection.execute(&code, textual_error).map_err(|_| ())
+}
+
+pub fn write_select_all(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "SELECT " {RECORD} " FROM " {TABLE} ";"
+  )
+}
+
+pub fn load_session_records(
+  database: &Database,
+  textual_error: &mut impl IsTextualError,
+) -> Result<Vec<SessionRecord>, ()> {
+  let mut code = SqlCode::new();
+  write_select_all(&mut code);
+
+  let rows = database.connection.query_rows(&code, |row| {
+    let record: String = row.get(0)?;
+    Ok(record)
+  }, textual_error)?;
+
+  let mut records = Vec::new();
+  for record in rows {
+    match serde_json::from_str(&record) {
+      Ok(record) => {
+        records.push(record);
+      }
+      Err(error) => {
+        textual_error.change_context("Loading session records");
+        textual_error.add_message("Failed to deserialize a saved record");
+        textual_error.add_attachement_display("Deserialization error", error);
+        return Err(());
+      }
+    }
+  }
+
+  Ok(records)
+}
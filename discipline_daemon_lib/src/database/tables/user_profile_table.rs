@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use crate::x::{Database, IsTextualError, UuidV4};
+use crate::x::launcher::UserProfile;
+use crate::x::database::*;
+use crate::sql;
+
+const TABLE: &str = "UserProfiles";
+
+const ID: &str = "id";
+// The whole profile as JSON, like group profiles.
+const PROFILE: &str = "profile";
+
+pub fn write_create_table(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "CREATE TABLE IF NOT EXISTS " {TABLE} "( "
+      {ID} " TEXT PRIMARY KEY, "
+      {PROFILE} " TEXT NOT NULL "
+    ") STRICT, WITHOUT ROWID;"
+  )
+}
+
+pub fn write_save(
+  code: &mut SqlCode,
+  user_profile_id: &UuidV4,
+  profile: &str,
+) {
+  sql!(
+    code,
+
+    "INSERT INTO " {TABLE} " VALUES ("
+      [&user_profile_id.to_string()] ", "
+      [&profile]
+    ") ON CONFLICT DO UPDATE SET "
+      {PROFILE} " = excluded." {PROFILE} ";"
+  )
+}
+
+// Inserts the profiles, or replaces the saved ones, in one go. Their
+// uptime clocks change all the time, so they're saved periodically
+// rather than on every change.
+pub fn save_user_profiles<'a>(
+  database: &Database,
+  user_profiles: impl Iterator<Item = (&'a UuidV4, &'a UserProfile)>,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let mut code = SqlCode::new();
+
+  for (user_profile_id, user_profile) in user_profiles {
+    let profile = match serde_json::to_string(user_profile) {
+      Ok(profile) => {
+        profile
+      }
+      Err(error) => {
+        textual_error.change_context("Saving user profiles");
+        textual_error.add_message("Failed to serialize a profile");
+        textual_error.add_attachement_display("Serialization error", error);
+        textual_error.add_attachement_display("Id", user_profile_id.to_string());
+        return Err(());
+      }
+    };
+
+    write_save(&mut code, user_profile_id, &profile);
+  }
+
+  if code.as_str().is_empty() {
+    return Ok(());
+  }
+
+  database.connection.execute(&code, textual_error).map_err(|_| ())
+}
+
+pub fn write_select_all(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "SELECT " {ID} ", " {PROFILE} " FROM " {TABLE} ";"
+  )
+}
+
+pub fn load_user_profiles(
+  database: &Database,
+  textual_error: &mut impl IsTextualError,
+) -> Result<HashMap<UuidV4, UserProfile>, ()> {
+  let mut code = SqlCode::new();
+  write_select_all(&mut code);
+
+  let rows = database.connection.query_rows(&code, |row| {
+    let user_profile_id: String = row.get(0)?;
+    let profile: String = row.get(1)?;
+    Ok((user_profile_id, profile))
+  }, textual_error)?;
+
+  let mut user_profiles = HashMap::new();
+  for (user_profile_id, profile) in rows {
+    let Ok(user_profile_id) = UuidV4::from_string(&user_profile_id) else {
+      textual_error.change_context("Loading user profiles");
+      textual_error.add_message("A saved profile's id is invalid");
+      textual_error.add_attachement_display("Id", user_profile_id);
+      return Err(());
+    };
+
+    let user_profile = match serde_json::from_str(&profile) {
+      Ok(user_profile) => {
+        user_profile
+      }
+      Err(error) => {
+        textual_error.change_context("Loading user profiles");
+        textual_error.add_message("Failed to deserialize a saved profile");
+        textual_error.add_attachement_display("Deserialization error", error);
+        textual_error.add_attachement_display("Id", user_profile_id.to_string());
+        return Err(());
+      }
+    };
+
+    user_profiles.insert(user_profile_id, user_profile);
+  }
+
+  Ok(user_profiles)
+}
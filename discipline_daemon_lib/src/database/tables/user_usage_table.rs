@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use crate::x::{Database, IsTextualError};
+use crate::x::launcher::{UserId, UserUsage};
+use crate::x::database::*;
+use crate::sql;
+
+const TABLE: &str = "UserUsage";
+
+// Usage is counted per account, whichever profiles regulate it, so
+// accounts that are only regulated through a group have it saved too.
+const USER_ID: &str = "user_id";
+// The whole usage as JSON, like profiles.
+const USAGE: &str = "usage";
+
+pub fn write_create_table(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "CREATE TABLE IF NOT EXISTS " {TABLE} "( "
+      {USER_ID} " INTEGER PRIMARY KEY, "
+      {USAGE} " TEXT NOT NULL "
+    ") STRICT;"
+  )
+}
+
+pub fn write_save(
+  code: &mut SqlCode,
+  user_id: UserId,
+  usage: &str,
+) {
+  sql!(
+    code,
+
+    "INSERT INTO " {TABLE} " VALUES ("
+      [&user_id.inner()] ", "
+      [&usage]
+    ") ON CONFLICT DO UPDATE SET "
+      {USAGE} " = excluded." {USAGE} ";"
+  )
+}
+
+// Inserts the usage, or replaces the saved one, in one go. It changes
+// all the time, so it's saved periodically rather than on every change.
+pub fn save_user_usage(
+  database: &Database,
+  user_usage: impl Iterator<Item = (UserId, UserUsage)>,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let mut code = SqlCode::new();
+
+  for (user_id, usage) in user_usage {
+    let usage = match serde_json::to_string(&usage) {
+      Ok(usage) => {
+        usage
+      }
+      Err(error) => {
+        textual_error.change_context("Saving user usage");
+        textual_error.add_message("Failed to serialize an account's usage");
+        textual_error.add_attachement_display("Serialization error", error);
+        textual_error.add_attachement_display("User id", user_id.inner());
+        return Err(());
+      }
+    };
+
+    write_save(&mut code, user_id, &usage);
+  }
+
+  if code.as_str().is_empty() {
+    return Ok(());
+  }
+
+  database.connection.execute(&code, textual_error).map_err(|_| ())
+}
+
+pub fn write_select_all(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "SELECT " {USER_ID} ", " {USAGE} " FROM " {TABLE} ";"
+  )
+}
+
+pub fn load_user_usage(
+  database: &Database,
+  textual_error: &mut impl IsTextualError,
+) -> Result<HashMap<UserId, UserUsage>, ()> {
+  let mut code = SqlCode::new();
+  write_select_all(&mut code);
+
+  let rows = database.connection.query_rows(&code, |row| {
+    let user_id: u32 = row.get(0)?;
+    let usage: String = row.get(1)?;
+    Ok((user_id, usage))
+  }, textual_error)?;
+
+  let mut user_usage = HashMap::new();
+  for (user_id, usage) in rows {
+    let usage = match serde_json::from_str(&usage) {
+      Ok(usage) => {
+        usage
+      }
+      Err(error) => {
+        textual_error.change_context("Loading user usage");
+        textual_error.add_message("Failed to deserialize an account's saved usage");
+        textual_error.add_attachement_display("Deserialization error", error);
+        textual_error.add_attachement_display("User id", user_id);
+        return Err(());
+      }
+    };
+
+    user_usage.insert(UserId::new(user_id), usage);
+  }
+
+  Ok(user_usage)
+}
@@ -0,0 +1,215 @@
+use crate::x::{Database, IsTextualError, UuidV4, VaultDatum, VaultDatumContent};
+use crate::x::database::*;
+use crate::sql;
+
+const TABLE: &str = "VaultData";
+
+const ID: &str = "id";
+const VAULT_ID: &str = "vault_id";
+const VARIANT: &str = "variant";
+const VALUE: &str = "value";
+
+pub fn write_create_table(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "CREATE TABLE IF NOT EXISTS " {TABLE} "( "
+      {ID} " TEXT PRIMARY KEY, "
+      {VAULT_ID} " TEXT NOT NULL, "
+      {VARIANT} " INTEGER NOT NULL, "
+      {VALUE} " BLOB NOT NULL "
+    ") STRICT, WITHOUT ROWID;"
+  )
+}
+
+// The content is stored as bincode, since its shape depends on the variant.
+fn encode_content(
+  datum: &VaultDatum,
+  textual_error: &mut impl IsTextualError,
+) -> Result<Vec<u8>, ()> {
+  bincode::serde::encode_to_vec(datum.get_content(), bincode::config::standard()).map_err(|error| {
+    textual_error.change_context("Encoding a vault datum's content using bincode");
+    textual_error.add_attachement_display("Error", error);
+  })
+}
+
+pub fn write_insert(
+  code: &mut SqlCode,
+  vault_id: &UuidV4,
+  datum_id: &UuidV4,
+  datum: &VaultDatum,
+  content: &Vec<u8>,
+) {
+  sql!(
+    code,
+
+    "INSERT INTO " {TABLE} " VALUES ("
+      [&datum_id.to_string()] ", "
+      [&vault_id.to_string()] ", "
+      [&datum.get_variant().to_number()] ", "
+      [content]
+    ");"
+  )
+}
+
+pub fn insert_datum(
+  database: &Database,
+  vault_id: &UuidV4,
+  datum_id: &UuidV4,
+  datum: &VaultDatum,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), InsertError> {
+  let Ok(content) = encode_content(datum, textual_error) else {
+    return Err(InsertError::Other);
+  };
+
+  let mut code = SqlCode::new();
+  write_insert(&mut code, vault_id, datum_id, datum, &content);
+  database.connection.execute(&code, textual_error).map_err(|error| match error {
+    DbExecuteError::PrimaryKeyViolation => {
+      InsertError::DuplicateDatumId
+    }
+    DbExecuteError::ForiegnKeyViolation => {
+      InsertError::Other
+    }
+    DbExecuteError::Other => {
+      InsertError::Other
+    }
+  })
+}
+
+pub fn write_update_value(
+  code: &mut SqlCode,
+  datum_id: &UuidV4,
+  datum: &VaultDatum,
+  content: &Vec<u8>,
+) {
+  sql!(
+    code,
+
+    "UPDATE " {TABLE} " SET " 
+      {VARIANT} " = " [&datum.get_variant().to_number()] ", "
+      {VALUE} " = " [content]
+    " WHERE " {ID} " = " [&datum_id.to_string()] ";"
+  )
+}
+
+pub fn update_value(
+  database: &Database,
+  datum_id: &UuidV4,
+  datum: &VaultDatum,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), UpdateError> {
+  let Ok(content) = encode_content(datum, textual_error) else {
+    return Err(UpdateError::Other);
+  };
+
+  let mut code = SqlCode::new();
+  write_update_value(&mut code, datum_id, datum, &content);
+  database.connection.execute(&code, textual_error).map_err(|_| {
+    UpdateError::Other
+  })
+}
+
+pub fn write_delete(
+  code: &mut SqlCode,
+  datum_id: &UuidV4,
+) {
+  sql!(
+    code,
+
+    "DELETE FROM " {TABLE} " WHERE " {ID} " = " [&datum_id.to_string()] ";"
+  )
+}
+
+pub fn delete_datum(
+  database: &Database,
+  datum_id: &UuidV4,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), DeleteError> {
+  let mut code = SqlCode::new();
+  write_delete(&mut code, datum_id);
+  database.connection.execute(&code, textual_error).map_err(|_| {
+    DeleteError::Other
+  })
+}
+
+pub fn write_select_all(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "SELECT " {ID} ", " {VAULT_ID} ", " {VALUE} " FROM " {TABLE} ";"
+  )
+}
+
+pub struct LoadedDatum {
+  pub vault_id: UuidV4,
+  pub datum_id: UuidV4,
+  pub datum: VaultDatum,
+}
+
+pub fn load_data(
+  database: &Database,
+  textual_error: &mut impl IsTextualError,
+) -> Result<Vec<LoadedDatum>, ()> {
+  let mut code = SqlCode::new();
+  write_select_all(&mut code);
+
+  let rows = database.connection.query_rows(&code, |row| {
+    let datum_id: String = row.get(0)?;
+    let vault_id: String = row.get(1)?;
+    let content: Vec<u8> = row.get(2)?;
+    Ok((datum_id, vault_id, content))
+  }, textual_error)?;
+
+  let mut data = Vec::new();
+  for (datum_id, vault_id, content) in rows {
+    let (Ok(datum_id), Ok(vault_id)) = (
+      UuidV4::from_string(&datum_id),
+      UuidV4::from_string(&vault_id),
+    ) else {
+      textual_error.change_context("Loading vault data");
+      textual_error.add_message("A saved datum's id or vault id is invalid");
+      textual_error.add_attachement_display("Id", datum_id);
+      return Err(());
+    };
+
+    let content: VaultDatumContent = match bincode::serde::decode_from_slice(
+      &content, 
+      bincode::config::standard(),
+    ) {
+      Ok((content, _)) => {
+        content
+      }
+      Err(error) => {
+        textual_error.change_context("Loading vault data");
+        textual_error.add_message("Failed to decode a saved datum's content using bincode");
+        textual_error.add_attachement_display("Error", error);
+        textual_error.add_attachement_display("Id", datum_id.to_string());
+        return Err(());
+      }
+    };
+
+    data.push(LoadedDatum {
+      vault_id,
+      datum_id,
+      // It was validated before it was saved.
+      datum: VaultDatum::construct(content),
+    });
+  }
+
+  Ok(data)
+}
+
+pub enum InsertError {
+  DuplicateDatumId,
+  Other,
+}
+
+pub enum UpdateError {
+  Other,
+}
+
+pub enum DeleteError {
+  Other,
+}
@@ -0,0 +1,96 @@
+use crate::x::{Database, IsTextualError, UuidV4, VaultReveal};
+use crate::x::database::*;
+use crate::sql;
+
+const TABLE: &str = "VaultReveals";
+
+const ID: &str = "id";
+const VAULT_ID: &str = "vault_id";
+const REVEALED_AT: &str = "revealed_at";
+const WINDOW_FROM: &str = "window_from";
+const WINDOW_DURATION: &str = "window_duration";
+
+pub fn write_create_table(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "CREATE TABLE IF NOT EXISTS " {TABLE} "( "
+      {ID} " TEXT PRIMARY KEY, "
+      {VAULT_ID} " TEXT NOT NULL, "
+      {REVEALED_AT} " INTEGER NOT NULL, "
+      {WINDOW_FROM} " INTEGER NOT NULL, "
+      {WINDOW_DURATION} " INTEGER NOT NULL "
+    ") STRICT, WITHOUT ROWID;"
+  )
+}
+
+pub fn write_insert(
+  code: &mut SqlCode,
+  reveal_id: &UuidV4,
+  reveal: &VaultReveal,
+) {
+  sql!(
+    code,
+
+    "INSERT INTO " {TABLE} " VALUES ("
+      [&reveal_id.to_string()] ", "
+      [&reveal.vault_id.to_string()] ", "
+      [&reveal.revealed_at.as_timestamp()] ", "
+      [&reveal.window.get_from().as_timestamp()] ", "
+      [&reveal.window.get_total_duration().as_total_milliseconds()]
+    ");"
+  )
+}
+
+pub fn insert_reveal(
+  database: &Database,
+  reveal_id: &UuidV4,
+  reveal: &VaultReveal,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), InsertError> {
+  let mut code = SqlCode::new();
+  write_insert(&mut code, reveal_id, reveal);
+  database.connection.execute(&code, textual_error).map_err(|error| match error {
+    DbExecuteError::PrimaryKeyViolation => {
+      InsertError::DuplicateRevealId
+    }
+    DbExecuteError::ForiegnKeyViolation => {
+      InsertError::Other
+    }
+    DbExecuteError::Other => {
+      InsertError::Other
+    }
+  })
+}
+
+pub fn write_delete_vault_reveals(
+  code: &mut SqlCode,
+  vault_id: &UuidV4,
+) {
+  sql!(
+    code,
+
+    "DELETE FROM " {TABLE} " WHERE " {VAULT_ID} " = " [&vault_id.to_string()] ";"
+  )
+}
+
+pub fn delete_vault_reveals(
+  database: &Database,
+  vault_id: &UuidV4,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), DeleteError> {
+  let mut code = SqlCode::new();
+  write_delete_vault_reveals(&mut code, vault_id);
+  database.connection.execute(&code, textual_error).map_err(|_| {
+    DeleteError::Other
+  })
+}
+
+pub enum InsertError {
+  DuplicateRevealId,
+  Other,
+}
+
+pub enum DeleteError {
+  Other,
+}
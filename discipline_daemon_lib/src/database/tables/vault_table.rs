@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use crate::x::{Countdown, CountdownAfterPleaConditional, Database, Duration, Instant, IsTextualError, UuidV4, Vault, VaultName, VaultProtector, VaultProtectorVariant};
+use crate::x::database::*;
+use crate::sql;
+
+const TABLE: &str = "Vaults";
+
+const ID: &str = "id";
+const NAME: &str = "name";
+const PROTECTOR_VARIANT: &str = "protector_variant";
+const PROTECTOR_DURATION: &str = "protector_duration";
+const PROTECTOR_COUNTDOWN_FROM: &str = "protector_countdown_from";
+const PROTECTOR_COUNTDOWN_DURATION: &str = "protector_countdown_duration";
+const REVEAL_DURATION: &str = "reveal_duration";
+const REVEAL_WINDOW_FROM: &str = "reveal_window_from";
+const REVEAL_WINDOW_DURATION: &str = "reveal_window_duration";
+
+// A vault's data is in the VaultData table.
+pub fn write_create_table(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "CREATE TABLE IF NOT EXISTS " {TABLE} "( "
+      {ID} " TEXT PRIMARY KEY, "
+      {NAME} " TEXT NOT NULL, "
+      {PROTECTOR_VARIANT} " INTEGER NOT NULL, "
+      {PROTECTOR_DURATION} " INTEGER NOT NULL, "
+      {PROTECTOR_COUNTDOWN_FROM} " INTEGER, "
+      {PROTECTOR_COUNTDOWN_DURATION} " INTEGER, "
+      {REVEAL_DURATION} " INTEGER NOT NULL, "
+      {REVEAL_WINDOW_FROM} " INTEGER, "
+      {REVEAL_WINDOW_DURATION} " INTEGER "
+    ") STRICT, WITHOUT ROWID;"
+  )
+}
+
+fn get_countdown_columns(countdown: Option<&Countdown>) -> (Option<u64>, Option<u64>) {
+  match countdown {
+    Some(countdown) => {
+      (
+        Some(countdown.get_from().as_timestamp()),
+        Some(countdown.get_total_duration().as_total_milliseconds()),
+      )
+    }
+    None => {
+      (None, None)
+    }
+  }
+}
+
+pub fn write_insert(
+  code: &mut SqlCode,
+  vault_id: &UuidV4,
+  vault: &Vault,
+) {
+  let (protector_variant, protector_duration, protector_countdown) = match &vault.protector {
+    VaultProtector::CountdownAfterPlea(conditional) => {
+      (
+        vault.protector.get_variant().to_number(),
+        conditional.duration.as_total_milliseconds(),
+        conditional.countdown.as_ref(),
+      )
+    }
+  };
+
+  let (protector_countdown_from, protector_countdown_duration) = get_countdown_columns(protector_countdown);
+  let (reveal_window_from, reveal_window_duration) = get_countdown_columns(vault.reveal_window.as_ref());
+
+  sql!(
+    code,
+
+    "INSERT INTO " {TABLE} " VALUES ("
+      [&vault_id.to_string()] ", "
+      [&vault.name.as_ref()] ", "
+      [&protector_variant] ", "
+      [&protector_duration] ", "
+      [&protector_countdown_from] ", "
+      [&protector_countdown_duration] ", "
+      [&vault.reveal_duration.as_total_milliseconds()] ", "
+      [&reveal_window_from] ", "
+      [&reveal_window_duration]
+    ");"
+  )
+}
+
+pub fn insert_vault(
+  database: &Database,
+  vault_id: &UuidV4,
+  vault: &Vault,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), InsertError> {
+  let mut code = SqlCode::new();
+  write_insert(&mut code, vault_id, vault);
+  database.connection.execute(&code, textual_error).map_err(|error| match error {
+    DbExecuteError::PrimaryKeyViolation => {
+      InsertError::DuplicateVaultId
+    }
+    DbExecuteError::ForiegnKeyViolation => {
+      InsertError::Other
+    }
+    DbExecuteError::Other => {
+      InsertError::Other
+    }
+  })
+}
+
+pub fn write_update_protector_countdown(
+  code: &mut SqlCode,
+  vault_id: &UuidV4,
+  countdown: Option<&Countdown>,
+) {
+  let (countdown_from, countdown_duration) = get_countdown_columns(countdown);
+
+  sql!(
+    code,
+
+    "UPDATE " {TABLE} " SET "
+      {PROTECTOR_COUNTDOWN_FROM} " = " [&countdown_from] ", "
+      {PROTECTOR_COUNTDOWN_DURATION} " = " [&countdown_duration]
+    " WHERE " {ID} " = " [&vault_id.to_string()] ";"
+  )
+}
+
+// None re-arms the protector.
+pub fn update_protector_countdown(
+  database: &Database,
+  vault_id: &UuidV4,
+  countdown: Option<&Countdown>,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), UpdateError> {
+  let mut code = SqlCode::new();
+  write_update_protector_countdown(&mut code, vault_id, countdown);
+  database.connection.execute(&code, textual_error).map_err(|_| {
+    UpdateError::Other
+  })
+}
+
+pub fn write_update_reveal_window(
+  code: &mut SqlCode,
+  vault_id: &UuidV4,
+  reveal_window: Option<&Countdown>,
+) {
+  let (reveal_window_from, reveal_window_duration) = get_countdown_columns(reveal_window);
+
+  sql!(
+    code,
+
+    "UPDATE " {TABLE} " SET "
+      {REVEAL_WINDOW_FROM} " = " [&reveal_window_from] ", "
+      {REVEAL_WINDOW_DURATION} " = " [&reveal_window_duration]
+    " WHERE " {ID} " = " [&vault_id.to_string()] ";"
+  )
+}
+
+pub fn update_reveal_window(
+  database: &Database,
+  vault_id: &UuidV4,
+  reveal_window: Option<&Countdown>,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), UpdateError> {
+  let mut code = SqlCode::new();
+  write_update_reveal_window(&mut code, vault_id, reveal_window);
+  database.connection.execute(&code, textual_error).map_err(|_| {
+    UpdateError::Other
+  })
+}
+
+pub fn write_select_all(code: &mut SqlCode) {
+  sql!(
+    code,
+
+    "SELECT "
+      {ID} ", "
+      {NAME} ", "
+      {PROTECTOR_VARIANT} ", "
+      {PROTECTOR_DURATION} ", "
+      {PROTECTOR_COUNTDOWN_FROM} ", "
+      {PROTECTOR_COUNTDOWN_DURATION} ", "
+      {REVEAL_DURATION} ", "
+      {REVEAL_WINDOW_FROM} ", "
+      {REVEAL_WINDOW_DURATION}
+    " FROM " {TABLE} ";"
+  )
+}
+
+struct VaultRow {
+  vault_id: String,
+  name: String,
+  protector_variant: i64,
+  protector_duration: i64,
+  protector_countdown_from: Option<i64>,
+  protector_countdown_duration: Option<i64>,
+  reveal_duration: i64,
+  reveal_window_from: Option<i64>,
+  reveal_window_duration: Option<i64>,
+}
+
+fn get_countdown(from: Option<i64>, duration: Option<i64>) -> Option<Countdown> {
+  match (from, duration) {
+    (Some(from), Some(duration)) => {
+      Some(Countdown::construct(
+        Instant::from_timestamp(from.max(0) as u64),
+        Duration::from_milliseconds(duration.max(0) as u64),
+      ))
+    }
+    _ => {
+      None
+    }
+  }
+}
+
+// The loaded vaults have no data. It's in the VaultData table.
+pub fn load_vaults(
+  database: &Database,
+  textual_error: &mut impl IsTextualError,
+) -> Result<HashMap<UuidV4, Vault>, ()> {
+  let mut code = SqlCode::new();
+  write_select_all(&mut code);
+
+  let rows = database.connection.query_rows(&code, |row| {
+    Ok(VaultRow {
+      vault_id: row.get(0)?,
+      name: row.get(1)?,
+      protector_variant: row.get(2)?,
+      protector_duration: row.get(3)?,
+      protector_countdown_from: row.get(4)?,
+      protector_countdown_duration: row.get(5)?,
+      reveal_duration: row.get(6)?,
+      reveal_window_from: row.get(7)?,
+      reveal_window_duration: row.get(8)?,
+    })
+  }, textual_error)?;
+
+  let mut vaults = HashMap::new();
+  for row in rows {
+    let Ok(vault_id) = UuidV4::from_string(&row.vault_id) else {
+      textual_error.change_context("Loading vaults");
+      textual_error.add_message("A saved vault's id is invalid");
+      textual_error.add_attachement_display("Id", row.vault_id);
+      return Err(());
+    };
+
+    let Ok(name) = VaultName::new(row.name) else {
+      textual_error.change_context("Loading vaults");
+      textual_error.add_message("A saved vault's name is invalid");
+      textual_error.add_attachement_display("Id", vault_id.to_string());
+      return Err(());
+    };
+
+    let protector_variant = VaultProtectorVariant::from_number(
+      row.protector_variant.clamp(0, u8::MAX as i64) as u8, 
+      textual_error,
+    )?;
+
+    let protector = match protector_variant {
+      VaultProtectorVariant::CountdownAfterPlea => {
+        VaultProtector::CountdownAfterPlea(CountdownAfterPleaConditional::construct(
+          Duration::from_milliseconds(row.protector_duration.max(0) as u64),
+          get_countdown(row.protector_countdown_from, row.protector_countdown_duration),
+        ))
+      }
+    };
+
+    vaults.insert(vault_id, Vault::construct(
+      name,
+      protector,
+      Duration::from_milliseconds(row.reveal_duration.max(0) as u64),
+      get_countdown(row.reveal_window_from, row.reveal_window_duration),
+      HashMap::new(),
+    ));
+  }
+
+  Ok(vaults)
+}
+
+pub enum InsertError {
+  DuplicateVaultId,
+  Other,
+}
+
+pub enum UpdateError {
+  Other,
+}
@@ -1,7 +1,7 @@
 use std::borrow::Borrow;
 use std::ffi::CString;
 use std::any::type_name;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use rusqlite::types::ValueRef;
@@ -59,9 +59,29 @@ impl SqlCode {
     }
   }
   
-  pub fn write_literal(&mut self, str: &str) {}
-  pub fn write_value<A: ScalarWrite>(&mut self, str: A) {}
-  pub fn write_value_ref<A: ScalarWrite>(&mut self, str: &A) {}
+  pub fn write_literal(&mut self, str: &str) {
+    self.value.push_str(str);
+  }
+
+  pub fn write_value<A: ScalarWrite>(&mut self, value: A) {
+    self.write_scalar_value(&value);
+  }
+
+  pub fn write_value_ref<A: ScalarWrite>(&mut self, value: &A) {
+    self.write_scalar_value(value);
+  }
+
+  pub fn write_identifier(&mut self, identifier: &str) {
+    self.value.push('"');
+    for char in identifier.chars() {
+      if char == '"' {
+        self.value.push_str("\"\"");
+      } else {
+        self.value.push(char);
+      }
+    }
+    self.value.push('"');
+  }
 
   pub fn write(&mut self, str: &str) {
     self.value.push_str(str);
@@ -660,6 +680,16 @@ impl ScalarWrite for CString {
   }
 }
 
+impl ScalarWrite for Vec<u8> {
+  fn write(value: &Self, writer: &mut ScalarValueWriteDestination) {
+    writer.code.write("X'");
+    for byte in value {
+      writer.code.write(&format!("{byte:02X}"));
+    }
+    writer.code.write_char('\'');
+  }
+}
+
 impl<T> ScalarWrite for Option<T>
 where 
   T: ScalarWrite
@@ -1114,30 +1144,161 @@ impl MyConnection {
     }
   }
 
+  pub fn open(file: &Path, textual_error: &mut impl IsTextualError) -> Result<Self, ()> {
+    let connection = match rusqlite::Connection::open(file) {
+      Ok(connection) => {
+        connection
+      }
+      Err(error) => {
+        textual_error.change_context("Opening connection to a SQLite database");
+        textual_error.add_message("An error occured while opening the connection");
+        textual_error.add_attachement_display("Error", error);
+        textual_error.add_attachement_display("Database file", file.display());
+        return Err(());
+      }
+    };
+
+    Ok(Self { connection })
+  }
+
   pub fn execute(&self, code: &SqlCode, textual_error: &mut impl IsTextualError) -> Result<(), DbExecuteError> {
-    todo!()
+    let Err(error) = self.connection.execute_batch(code.as_str()) else {
+      return Ok(());
+    };
 
-    // let Err(error) = self.connection.execute_batch(code.as_str()) else {
-    //   return Ok(());
-    // };
-    
-    // let sqlite_extended_error_code = match error {
-    //   rusqlite::Error::SqliteFailure(error, _) => {
-    //     error.extended_code
-    //   }
-    //   other => {
-    //     return Err(DbExecuteError::Other(other));
-    //   }
-    // };
+    let extended_code = match &error {
+      rusqlite::Error::SqliteFailure(error, _) => {
+        Some(error.extended_code)
+      }
+      _ => {
+        None
+      }
+    };
 
-    // match sqlite_extended_error_code {
-    //   libsqlite3_sys::SQLITE_CONSTRAINT_PRIMARYKEY => {
-    //     Err(DbExecuteError::PrimaryKeyViolation)
-    //   }
-    //   _ => {
-    //     Err(DbExecuteError::Other(error))
-    //   }
-    // }
+    match extended_code {
+      Some(libsqlite3_sys::SQLITE_CONSTRAINT_PRIMARYKEY) => {
+        Err(DbExecuteError::PrimaryKeyViolation)
+      }
+      Some(libsqlite3_sys::SQLITE_CONSTRAINT_FOREIGNKEY) => {
+        Err(DbExecuteError::ForiegnKeyViolation)
+      }
+      _ => {
+        textual_error.change_context("Executing SQLite code");
+        textual_error.add_message("A SQLite error occured");
+        textual_error.add_attachement_display("SQLite error", error);
+        textual_error.add_attachement_display("SQL code", code.as_str());
+        Err(DbExecuteError::Other)
+      }
+    }
+  }
+
+  // Runs a query that returns at most one row and maps that row.
+  pub fn query_optional_row<T>(
+    &self, 
+    code: &SqlCode, 
+    map: impl FnOnce(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Option<T>, ()> {
+    let mut statement = match self.connection.prepare(code.as_str()) {
+      Ok(statement) => {
+        statement
+      }
+      Err(error) => {
+        textual_error.change_context("Querying one row from a SQLite database");
+        textual_error.add_message("A SQLite error occured while prepareing a statement");
+        textual_error.add_attachement_display("SQLite error", error);
+        textual_error.add_attachement_display("SQL code", code.as_str());
+        return Err(());
+      }
+    };
+
+    let mut rows = match statement.query(()) {
+      Ok(rows) => {
+        rows
+      }
+      Err(error) => {
+        textual_error.change_context("Querying one row from a SQLite database");
+        textual_error.add_message("A SQLite error occured while running the statement");
+        textual_error.add_attachement_display("SQLite error", error);
+        textual_error.add_attachement_display("SQL code", code.as_str());
+        return Err(());
+      }
+    };
+
+    let row = match rows.next() {
+      Ok(Some(row)) => {
+        row
+      }
+      Ok(None) => {
+        return Ok(None);
+      }
+      Err(error) => {
+        textual_error.change_context("Querying one row from a SQLite database");
+        textual_error.add_message("A SQLite error occured while reading the row");
+        textual_error.add_attachement_display("SQLite error", error);
+        textual_error.add_attachement_display("SQL code", code.as_str());
+        return Err(());
+      }
+    };
+
+    match map(row) {
+      Ok(value) => {
+        Ok(Some(value))
+      }
+      Err(error) => {
+        textual_error.change_context("Querying one row from a SQLite database");
+        textual_error.add_message("Failed to read the row's columns");
+        textual_error.add_attachement_display("SQLite error", error);
+        textual_error.add_attachement_display("SQL code", code.as_str());
+        Err(())
+      }
+    }
+  }
+
+  pub fn query_rows<T>(
+    &self, 
+    code: &SqlCode, 
+    mut map: impl FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Vec<T>, ()> {
+    let mut statement = match self.connection.prepare(code.as_str()) {
+      Ok(statement) => {
+        statement
+      }
+      Err(error) => {
+        textual_error.change_context("Querying rows from a SQLite database");
+        textual_error.add_message("A SQLite error occured while prepareing a statement");
+        textual_error.add_attachement_display("SQLite error", error);
+        textual_error.add_attachement_display("SQL code", code.as_str());
+        return Err(());
+      }
+    };
+
+    let rows = match statement.query_map((), |row| map(row)) {
+      Ok(rows) => {
+        rows
+      }
+      Err(error) => {
+        textual_error.change_context("Querying rows from a SQLite database");
+        textual_error.add_message("A SQLite error occured while running the statement");
+        textual_error.add_attachement_display("SQLite error", error);
+        textual_error.add_attachement_display("SQL code", code.as_str());
+        return Err(());
+      }
+    };
+
+    match rows.collect() {
+      Ok(values) => {
+        Ok(values)
+      }
+      Err(error) => {
+        textual_error.change_context("Querying rows from a SQLite database");
+        textual_error.add_message("Failed to read a row's columns");
+        textual_error.add_attachement_display("SQLite error", error);
+        textual_error.add_attachement_display("SQL code", code.as_str());
+        Err(())
+      }
+    }
   }
 
   pub fn execute_with_textual_error(
@@ -1,11 +1,311 @@
-use crate::x::TextualErrorV2;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex, watch};
+use tokio::task::{JoinSet, spawn_blocking};
+use crate::x::{IsTextualError, TextualError};
+use super::procedures::application_usage::GetTopApplications;
+use super::procedures::group_profiles::{CreateGroupProfile, CreateGroupProfileReturn, DeleteGroupProfile, DeleteGroupProfileReturn};
+use super::procedures::password_escrow;
+use super::procedures::session_registry::GetSessions;
+use super::{ApplicationUsage, ChpasswdBackend, Daemon, IsPasswordBackend, SessionRecord, SocketProtocol, UserId, find_socket_owner};
 
-pub struct Api {
+// What an API client may ask for. Sent as one JSON encoded request per
+// line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ApiRequest {
+  CreatePasswordEscrow(password_escrow::Create),
+  RotatePasswordEscrow(password_escrow::Rotate),
+  DeletePasswordEscrow(password_escrow::Delete),
+  // Boxed, since a profile's regulations are much larger than any other
+  // request.
+  CreateGroupProfile(Box<CreateGroupProfile>),
+  DeleteGroupProfile(DeleteGroupProfile),
+  GetTopApplications(GetTopApplications),
+  GetSessions(GetSessions),
+}
 
+// Each request is answered with one JSON encoded response per line, in
+// the order the requests were received.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ApiResponse {
+  CreatePasswordEscrow(password_escrow::CreateReturn),
+  RotatePasswordEscrow(password_escrow::RotateReturn),
+  DeletePasswordEscrow(password_escrow::DeleteReturn),
+  CreateGroupProfile(CreateGroupProfileReturn),
+  DeleteGroupProfile(DeleteGroupProfileReturn),
+  GetTopApplications(Vec<ApplicationUsage>),
+  GetSessions(Vec<SessionRecord>),
+  MalformedRequest,
+  InternalError,
+}
+
+impl ApiRequest {
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    password_backend: &impl IsPasswordBackend,
+    textual_error: &mut impl IsTextualError,
+  ) -> ApiResponse {
+    match self {
+      ApiRequest::CreatePasswordEscrow(procedure) => {
+        ApiResponse::CreatePasswordEscrow(procedure.execute(daemon, password_backend, textual_error))
+      }
+      ApiRequest::RotatePasswordEscrow(procedure) => {
+        ApiResponse::RotatePasswordEscrow(procedure.execute(daemon, password_backend, textual_error))
+      }
+      ApiRequest::DeletePasswordEscrow(procedure) => {
+        ApiResponse::DeletePasswordEscrow(procedure.execute(daemon, textual_error))
+      }
+      ApiRequest::CreateGroupProfile(procedure) => {
+        ApiResponse::CreateGroupProfile((*procedure).execute(daemon, textual_error))
+      }
+      ApiRequest::DeleteGroupProfile(procedure) => {
+        ApiResponse::DeleteGroupProfile(procedure.execute(daemon, textual_error))
+      }
+      ApiRequest::GetTopApplications(procedure) => {
+        match procedure.execute(daemon, textual_error) {
+          Ok(usage) => {
+            ApiResponse::GetTopApplications(usage)
+          }
+          Err(()) => {
+            ApiResponse::InternalError
+          }
+        }
+      }
+      ApiRequest::GetSessions(procedure) => {
+        ApiResponse::GetSessions(procedure.execute(daemon))
+      }
+    }
+  }
+}
+
+impl ApiResponse {
+  fn is_internal_error(&self) -> bool {
+    matches!(
+      self,
+      ApiResponse::InternalError
+        | ApiResponse::CreatePasswordEscrow(password_escrow::CreateReturn::InternalError)
+        | ApiResponse::RotatePasswordEscrow(password_escrow::RotateReturn::InternalError)
+        | ApiResponse::DeletePasswordEscrow(password_escrow::DeleteReturn::InternalError)
+        | ApiResponse::CreateGroupProfile(CreateGroupProfileReturn::InternalError)
+        | ApiResponse::DeleteGroupProfile(DeleteGroupProfileReturn::InternalError)
+    )
+  }
+}
+
+pub struct Api {
+  listener: TcpListener,
 }
 
 impl Api {
-  pub fn create(textual_error: &mut impl TextualErrorV2) -> Result<Self, ()> {
-    todo!()
+  // The API is only reachable from this machine.
+  pub async fn bind(port: u16, textual_error: &mut impl IsTextualError) -> Result<Self, ()> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+
+    match TcpListener::bind(address).await {
+      Ok(listener) => {
+        Ok(Self { listener })
+      }
+      Err(error) => {
+        textual_error.change_context("Binding the Discipline Linux Daemon API server");
+        textual_error.add_message("An io error occured while binding the TcpListener");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Address", address);
+        Err(())
+      }
+    }
   }
-}
\ No newline at end of file
+
+  pub async fn serve(
+    self,
+    daemon: Arc<Mutex<Daemon>>,
+    mut shutdown: watch::Receiver<bool>,
+  ) {
+    let mut connections = JoinSet::new();
+
+    loop {
+      tokio::select! {
+        _ = shutdown.changed() => {
+          break;
+        }
+        connection = self.listener.accept() => {
+          match connection {
+            Ok((stream, peer)) => {
+              // Any local user may connect over TCP, but the API changes
+              // other users' passwords and profiles, so only root is served.
+              if find_socket_owner(SocketProtocol::Tcp, peer) != Some(UserId::new(0)) {
+                // TODO: Use a proper logging mechanism.
+                eprintln!("Discipline Linux Daemon API server: Refused a connection from {peer}, which isn't owned by root");
+                continue;
+              }
+
+              connections.spawn(handle_connection(
+                stream,
+                Arc::clone(&daemon),
+                shutdown.clone(),
+              ));
+            }
+            Err(error) => {
+              // TODO: Use a proper logging mechanism.
+              eprintln!("Discipline Linux Daemon API server: Failed to accept a connection: {error}");
+            }
+          }
+        }
+      }
+
+      // Reap connections that already finished.
+      while connections.try_join_next().is_some() {}
+    }
+
+    connections.shutdown().await;
+  }
+}
+
+async fn handle_connection(
+  stream: TcpStream,
+  daemon: Arc<Mutex<Daemon>>,
+  mut shutdown: watch::Receiver<bool>,
+) {
+  let (reader, mut writer) = stream.into_split();
+  let mut lines = BufReader::new(reader).lines();
+
+  loop {
+    let line = tokio::select! {
+      _ = shutdown.changed() => {
+        return;
+      }
+      line = lines.next_line() => {
+        match line {
+          Ok(Some(line)) => {
+            line
+          }
+          // The client hung up, or sent something that isn't text.
+          Ok(None) | Err(_) => {
+            return;
+          }
+        }
+      }
+    };
+
+    let response = match serde_json::from_str::<ApiRequest>(&line) {
+      Ok(request) => {
+        let daemon = Arc::clone(&daemon);
+
+        // Procedures may run commands, like chpasswd, so they run where
+        // blocking is fine.
+        spawn_blocking(move || execute_request(request, &daemon))
+          .await
+          .unwrap_or(ApiResponse::InternalError)
+      }
+      Err(_) => {
+        ApiResponse::MalformedRequest
+      }
+    };
+
+    let Ok(mut line) = serde_json::to_vec(&response) else {
+      return;
+    };
+    line.push(b'\n');
+
+    if writer.write_all(&line).await.is_err() {
+      return;
+    }
+  }
+}
+
+fn execute_request(request: ApiRequest, daemon: &Mutex<Daemon>) -> ApiResponse {
+  let mut textual_error = TextualError::new("Serving a Discipline Linux Daemon API request");
+  let mut daemon = daemon.blocking_lock();
+  let response = request.execute(&mut daemon, &ChpasswdBackend::default(), &mut textual_error);
+
+  if response.is_internal_error() {
+    // TODO: Use a proper logging mechanism.
+    eprintln!("{textual_error}");
+  }
+
+  response
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use crate::x::UuidV4;
+  use super::super::{SessionDetails, UserName};
+  use super::*;
+
+  async fn connect(daemon: Daemon) -> (TcpStream, watch::Sender<bool>) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    tokio::spawn(handle_connection(server, Arc::new(Mutex::new(daemon)), shutdown_receiver));
+    (client, shutdown_sender)
+  }
+
+  #[tokio::test]
+  async fn requests_are_answered_in_order() {
+    let mut textual_error = TextualError::new("Testing the API");
+    let mut daemon = Daemon::open_in_memory(&mut textual_error).unwrap();
+    let user_name = UserName::new(CString::new("alex").unwrap());
+    daemon.on_user_session_opened(&user_name, SessionDetails::default());
+
+    let (client, _shutdown) = connect(daemon).await;
+    let (reader, mut writer) = client.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let get_sessions = ApiRequest::GetSessions(GetSessions {
+      user_name: Some(user_name.clone()),
+      include_closed: false,
+    });
+    let delete_group_profile = ApiRequest::DeleteGroupProfile(DeleteGroupProfile {
+      group_profile_id: UuidV4::generate(),
+    });
+
+    let mut requests = Vec::new();
+    for request in [&get_sessions, &delete_group_profile] {
+      requests.extend(serde_json::to_vec(request).unwrap());
+      requests.push(b'\n');
+    }
+    requests.extend(b"{\"NoSuchProcedure\":null}\n");
+    writer.write_all(&requests).await.unwrap();
+
+    let response = lines.next_line().await.unwrap().unwrap();
+    match serde_json::from_str(&response).unwrap() {
+      ApiResponse::GetSessions(sessions) => {
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].user_name, user_name);
+      }
+      other => {
+        panic!("Unexpected response: {other:?}");
+      }
+    }
+
+    let response = lines.next_line().await.unwrap().unwrap();
+    assert!(matches!(
+      serde_json::from_str(&response).unwrap(),
+      ApiResponse::DeleteGroupProfile(DeleteGroupProfileReturn::NoSuchGroupProfile),
+    ));
+
+    let response = lines.next_line().await.unwrap().unwrap();
+    assert!(matches!(
+      serde_json::from_str(&response).unwrap(),
+      ApiResponse::MalformedRequest,
+    ));
+  }
+
+  #[tokio::test]
+  async fn connections_close_on_shutdown() {
+    let mut textual_error = TextualError::new("Testing the API");
+    let daemon = Daemon::open_in_memory(&mut textual_error).unwrap();
+
+    let (client, shutdown) = connect(daemon).await;
+    let mut lines = BufReader::new(client).lines();
+
+    shutdown.send(true).unwrap();
+    assert!(matches!(lines.next_line().await, Ok(None)));
+  }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::x::{DateTime, Duration, Instant};
+use super::{ProcessId, ProcessInfo, UserId};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplicationUsageConfiguration {
+  // How many days of usage are kept, today included.
+  pub retention_days: u32,
+}
+
+impl Default for ApplicationUsageConfiguration {
+  fn default() -> Self {
+    Self {
+      retention_days: 90,
+    }
+  }
+}
+
+// Identifies an application in usage statistics: its executable's
+// path, or the kernel's name for the process when the path is unknown.
+pub fn get_usage_key(process: &ProcessInfo) -> String {
+  match &process.executable_path {
+    Some(path) => {
+      path.to_string_lossy().into_owned()
+    }
+    None => {
+      process.name.clone()
+    }
+  }
+}
+
+// Days since January 1, 1970 UTC, which is what usage is bucketed by.
+pub fn get_day_number(date_time: DateTime) -> i64 {
+  date_time
+    .as_timestamp()
+    .div_euclid(Duration::MILLISECONDS_PER_DAY as i64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UsagePeriod {
+  Today,
+  // Today and the six days before it.
+  ThisWeek,
+}
+
+impl UsagePeriod {
+  pub fn get_first_day(self, today: i64) -> i64 {
+    match self {
+      UsagePeriod::Today => {
+        today
+      }
+      UsagePeriod::ThisWeek => {
+        today - 6
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApplicationUsage {
+  pub executable: String,
+  pub run_time: Duration,
+}
+
+#[derive(Debug)]
+struct RunningApplication {
+  process_ids: Vec<ProcessId>,
+  // Run time up to here is already counted.
+  counted_till: Instant,
+}
+
+// Follows which applications profiled users are running, counting
+// wall clock time during which at least one of an application's
+// processes was alive, so a browser with thirty processes doesn't
+// count thirty times.
+#[derive(Debug)]
+pub struct ApplicationUsageTracker {
+  configuration: ApplicationUsageConfiguration,
+  processes: HashMap<ProcessId, (UserId, String)>,
+  applications: HashMap<(UserId, String), RunningApplication>,
+  // Counted, but not yet saved.
+  pending_run_times: HashMap<(UserId, String), Duration>,
+}
+
+impl ApplicationUsageTracker {
+  pub fn create(configuration: ApplicationUsageConfiguration) -> Self {
+    Self {
+      configuration,
+      processes: HashMap::new(),
+      applications: HashMap::new(),
+      pending_run_times: HashMap::new(),
+    }
+  }
+
+  pub fn get_configuration(&self) -> &ApplicationUsageConfiguration {
+    &self.configuration
+  }
+
+  // Also called when a tracked process execs, since it may become
+  // another application.
+  pub fn on_process_started(&mut self, process: &ProcessInfo, now: Instant) {
+    let key = (process.user_id, get_usage_key(process));
+    if self.processes.get(&process.process_id) == Some(&key) {
+      return;
+    }
+
+    self.on_process_exited(process.process_id, now);
+    self.processes.insert(process.process_id, key.clone());
+
+    self
+      .applications
+      .entry(key)
+      .or_insert_with(|| RunningApplication {
+        process_ids: Vec::new(),
+        counted_till: now,
+      })
+      .process_ids
+      .push(process.process_id);
+  }
+
+  pub fn on_process_exited(&mut self, process_id: ProcessId, now: Instant) {
+    let Some(key) = self.processes.remove(&process_id) else {
+      return;
+    };
+
+    let Some(application) = self.applications.get_mut(&key) else {
+      return;
+    };
+
+    application.process_ids.retain(|id| *id != process_id);
+    if !application.process_ids.is_empty() {
+      return;
+    }
+
+    let run_time = application.counted_till.till_or_zero(now);
+    self.applications.remove(&key);
+    self.add_pending_run_time(key, run_time);
+  }
+
+  // Brings the tracked processes in line with a full listing, catching
+  // processes that started before us and events we missed.
+  pub fn synchronize(&mut self, processes: &[ProcessInfo], now: Instant) {
+    let exited_process_ids: Vec<ProcessId> = self
+      .processes
+      .keys()
+      .filter(|process_id| !processes.iter().any(|process| process.process_id == **process_id))
+      .copied()
+      .collect();
+
+    for process_id in exited_process_ids {
+      self.on_process_exited(process_id, now);
+    }
+
+    for process in processes {
+      self.on_process_started(process, now);
+    }
+  }
+
+  fn add_pending_run_time(&mut self, key: (UserId, String), run_time: Duration) {
+    if run_time.is_zero() {
+      return;
+    }
+
+    let pending_run_time = self.pending_run_times.entry(key).or_insert(Duration::zero());
+    *pending_run_time = pending_run_time.saturating_add(run_time);
+  }
+
+  // Hands over everything counted up to `now`, clearing it. The caller
+  // saves it to today's bucket, so a little run time from before
+  // midnight can land in the next day.
+  pub fn take_run_times(&mut self, now: Instant) -> Vec<(UserId, String, Duration)> {
+    let running: Vec<((UserId, String), Duration)> = self
+      .applications
+      .iter_mut()
+      .map(|(key, application)| {
+        let run_time = application.counted_till.till_or_zero(now);
+        application.counted_till = now;
+        (key.clone(), run_time)
+      })
+      .collect();
+
+    for (key, run_time) in running {
+      self.add_pending_run_time(key, run_time);
+    }
+
+    self
+      .pending_run_times
+      .drain()
+      .map(|((user_id, executable), run_time)| (user_id, executable, run_time))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+  use super::*;
+
+  fn create_process(process_id: ProcessId, executable_path: &str) -> ProcessInfo {
+    ProcessInfo {
+      process_id,
+      user_id: UserId::new(1000),
+      executable_path: Some(PathBuf::from(executable_path)),
+      name: "process".to_string(),
+    }
+  }
+
+  fn at_second(second: u64) -> Instant {
+    Instant::from_timestamp(second * 1000)
+  }
+
+  fn get_run_time(run_times: &[(UserId, String, Duration)], executable: &str) -> Option<Duration> {
+    run_times
+      .iter()
+      .find(|(_, key, _)| key == executable)
+      .map(|(_, _, run_time)| *run_time)
+  }
+
+  #[test]
+  fn an_application_counts_once_however_many_processes_it_has() {
+    let mut tracker = ApplicationUsageTracker::create(ApplicationUsageConfiguration::default());
+
+    tracker.on_process_started(&create_process(1, "/usr/bin/firefox"), at_second(0));
+    tracker.on_process_started(&create_process(2, "/usr/bin/firefox"), at_second(10));
+    tracker.on_process_exited(1, at_second(20));
+    tracker.on_process_exited(2, at_second(30));
+
+    let run_times = tracker.take_run_times(at_second(60));
+    assert_eq!(run_times.len(), 1);
+    assert_eq!(get_run_time(&run_times, "/usr/bin/firefox"), Some(Duration::from_milliseconds(30 * 1000)));
+  }
+
+  #[test]
+  fn taken_run_times_are_not_counted_again() {
+    let mut tracker = ApplicationUsageTracker::create(ApplicationUsageConfiguration::default());
+    tracker.on_process_started(&create_process(1, "/usr/bin/vim"), at_second(0));
+
+    let run_times = tracker.take_run_times(at_second(40));
+    assert_eq!(get_run_time(&run_times, "/usr/bin/vim"), Some(Duration::from_milliseconds(40 * 1000)));
+
+    let run_times = tracker.take_run_times(at_second(50));
+    assert_eq!(get_run_time(&run_times, "/usr/bin/vim"), Some(Duration::from_milliseconds(10 * 1000)));
+  }
+
+  #[test]
+  fn listings_catch_missed_starts_and_exits() {
+    let mut tracker = ApplicationUsageTracker::create(ApplicationUsageConfiguration::default());
+
+    tracker.synchronize(&[create_process(1, "/usr/bin/vim"), create_process(2, "/usr/bin/gimp")], at_second(0));
+    tracker.synchronize(&[create_process(2, "/usr/bin/gimp")], at_second(20));
+
+    let run_times = tracker.take_run_times(at_second(50));
+    assert_eq!(get_run_time(&run_times, "/usr/bin/vim"), Some(Duration::from_milliseconds(20 * 1000)));
+    assert_eq!(get_run_time(&run_times, "/usr/bin/gimp"), Some(Duration::from_milliseconds(50 * 1000)));
+  }
+}
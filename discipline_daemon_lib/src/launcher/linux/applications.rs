@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::x::{AlwaysRules, Duration, Instant, Time, TimeAllowanceRules, TimeRangeRules};
+use super::ProcessInfo;
+
+// Names the application a regulation applies to: either an absolute
+// executable path, like "/usr/bin/steam", or an executable name, like
+// "steam", which matches that executable wherever it's installed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ApplicationName {
+  inner: String,
+}
+
+impl ApplicationName {
+  pub fn new(inner: String) -> Self {
+    Self {
+      inner,
+    }
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.inner
+  }
+
+  pub fn matches(&self, process: &ProcessInfo) -> bool {
+    if self.inner.starts_with('/') {
+      return process
+        .executable_path
+        .as_deref()
+        .is_some_and(|path| path == Path::new(&self.inner));
+    }
+
+    process.get_executable_name() == self.inner
+  }
+}
+
+// How long an application ran today, counted while any of its
+// processes was seen running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplicationRuntime {
+  pub day_start: Instant,
+  pub day_runtime: Duration,
+  pub previous_synchronization_time: Option<Instant>,
+}
+
+impl Default for ApplicationRuntime {
+  fn default() -> Self {
+    Self {
+      day_start: Instant::from_timestamp(0),
+      day_runtime: Duration::zero(),
+      previous_synchronization_time: None,
+    }
+  }
+}
+
+impl ApplicationRuntime {
+  // Longer gaps between synchronizations, like while the machine was
+  // suspended, only count this much.
+  const MAXIMUM_SYNCHRONIZATION_INTERVAL: Duration = Duration::MINUTE;
+
+  pub fn synchronize(&mut self, time: Time, now: Instant, is_running: bool) {
+    if !now.is_eariler_than(self.day_start.saturating_add(Duration::DAY)) {
+      self.day_start = now.saturating_sub(time.as_elapsed_time());
+      self.day_runtime = Duration::zero();
+    }
+
+    if is_running && let Some(previous_synchronization_time) = self.previous_synchronization_time {
+      let elapsed = previous_synchronization_time
+        .till_or_zero(now)
+        .min(Self::MAXIMUM_SYNCHRONIZATION_INTERVAL);
+
+      self.day_runtime = self.day_runtime.saturating_add(elapsed);
+    }
+
+    self.previous_synchronization_time = Some(now);
+  }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplicationRegulation {
+  pub always_rules: AlwaysRules,
+  pub time_range_rules: TimeRangeRules,
+  pub daily_allowance_rules: TimeAllowanceRules,
+  #[serde(default)]
+  pub runtime: ApplicationRuntime,
+}
+
+impl ApplicationRegulation {
+  pub fn construct(
+    always_rules: AlwaysRules,
+    time_range_rules: TimeRangeRules,
+    daily_allowance_rules: TimeAllowanceRules,
+    runtime: ApplicationRuntime,
+  ) -> Self {
+    Self {
+      always_rules,
+      time_range_rules,
+      daily_allowance_rules,
+      runtime,
+    }
+  }
+
+  pub fn is_blocked(&self, time: Time, instant: Instant) -> bool {
+    self.always_rules.are_some_active(instant)
+    ||
+    self.time_range_rules.are_some_active(time, instant)
+    ||
+    self.daily_allowance_rules.are_some_active(instant, self.runtime.day_runtime)
+  }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplicationRegulations {
+  regulations: HashMap<ApplicationName, ApplicationRegulation>,
+}
+
+impl ApplicationRegulations {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn construct(regulations: HashMap<ApplicationName, ApplicationRegulation>) -> Self {
+    Self {
+      regulations,
+    }
+  }
+
+  pub fn get_regulations(&self) -> impl Iterator<Item = (&ApplicationName, &ApplicationRegulation)> {
+    self.regulations.iter()
+  }
+
+  pub fn get_regulations_mut(&mut self) -> impl Iterator<Item = (&ApplicationName, &mut ApplicationRegulation)> {
+    self.regulations.iter_mut()
+  }
+
+  pub fn add_regulation(&mut self, name: ApplicationName, regulation: ApplicationRegulation) {
+    self.regulations.insert(name, regulation);
+  }
+
+  pub fn delete_regulation(&mut self, name: &ApplicationName) {
+    self.regulations.remove(name);
+  }
+
+  // Adds the other regulations' rules. Where both regulate the same
+  // application, the rules add up and the runtime counted here is
+  // kept; other runtimes start from zero.
+  pub fn merge(&mut self, other: &Self) {
+    for (name, regulation) in &other.regulations {
+      match self.regulations.get_mut(name) {
+        Some(existing) => {
+          existing.always_rules.merge(&regulation.always_rules);
+          existing.time_range_rules.merge(&regulation.time_range_rules);
+          existing.daily_allowance_rules.merge(&regulation.daily_allowance_rules);
+        }
+        None => {
+          self.regulations.insert(name.clone(), ApplicationRegulation {
+            runtime: ApplicationRuntime::default(),
+            ..regulation.clone()
+          });
+        }
+      }
+    }
+  }
+
+  // The runtime counted for each regulated application. It's saved
+  // with the account's usage rather than with the profile.
+  pub fn get_runtimes(&self) -> HashMap<ApplicationName, ApplicationRuntime> {
+    self
+      .regulations
+      .iter()
+      .map(|(name, regulation)| (name.clone(), regulation.runtime.clone()))
+      .collect()
+  }
+
+  // Takes over runtimes counted earlier, by an older copy of these
+  // regulations or before a restart, so the day's count doesn't reset.
+  pub fn set_runtimes(&mut self, runtimes: &HashMap<ApplicationName, ApplicationRuntime>) {
+    for (name, regulation) in &mut self.regulations {
+      if let Some(runtime) = runtimes.get(name) {
+        regulation.runtime = runtime.clone();
+      }
+    }
+  }
+
+  // Whether some regulation that applies to the process blocks it.
+  pub fn is_process_blocked(&self, process: &ProcessInfo, time: Time, instant: Instant) -> bool {
+    self
+      .regulations
+      .iter()
+      .any(|(name, regulation)| name.matches(process) && regulation.is_blocked(time, instant))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+  use crate::x::{AlwaysRule, CountdownAfterPleaConditional, RuleEnabler, TimeAllowanceRule, UuidV4};
+  use super::super::UserId;
+  use super::*;
+
+  fn create_process(executable_path: Option<&str>, name: &str) -> ProcessInfo {
+    ProcessInfo {
+      process_id: 1,
+      user_id: UserId::new(1000),
+      executable_path: executable_path.map(PathBuf::from),
+      name: name.to_string(),
+    }
+  }
+
+  // Enabled until someone pleads and waits out the countdown.
+  fn create_enabler() -> RuleEnabler {
+    RuleEnabler::CountdownAfterPlea(CountdownAfterPleaConditional::create(Duration::HOUR))
+  }
+
+  fn create_locked_regulation() -> ApplicationRegulation {
+    let mut regulation = ApplicationRegulation::default();
+    regulation.always_rules.rules.insert(UuidV4::generate(), AlwaysRule::create(create_enabler()));
+    regulation
+  }
+
+  fn create_allowance_regulation(allowance: Duration) -> ApplicationRegulation {
+    let rule = TimeAllowanceRule::construct(create_enabler(), allowance);
+
+    ApplicationRegulation {
+      daily_allowance_rules: TimeAllowanceRules::construct(HashMap::from([(UuidV4::generate(), rule)])),
+      ..ApplicationRegulation::default()
+    }
+  }
+
+  fn at_noon() -> Time {
+    Time::from_timestamp(12 * 60 * 60 * 1000).unwrap()
+  }
+
+  fn at_minute(minute: u64) -> Instant {
+    Instant::from_timestamp(Duration::DAY.as_total_milliseconds() + minute * 60 * 1000)
+  }
+
+  #[test]
+  fn names_match_by_path_or_by_executable_name() {
+    let steam = create_process(Some("/usr/bin/steam"), "steam");
+    let other_steam = create_process(Some("/opt/steam/steam"), "steam");
+    let pathless_steam = create_process(None, "steam");
+
+    let by_path = ApplicationName::new("/usr/bin/steam".to_string());
+    assert!(by_path.matches(&steam));
+    assert!(!by_path.matches(&other_steam));
+    assert!(!by_path.matches(&pathless_steam));
+
+    let by_name = ApplicationName::new("steam".to_string());
+    assert!(by_name.matches(&steam));
+    assert!(by_name.matches(&other_steam));
+    assert!(by_name.matches(&pathless_steam));
+    assert!(!by_name.matches(&create_process(Some("/usr/bin/steamcmd"), "steamcmd")));
+  }
+
+  #[test]
+  fn runtime_is_only_counted_while_running() {
+    let mut runtime = ApplicationRuntime::default();
+
+    runtime.synchronize(at_noon(), at_minute(0), true);
+    assert_eq!(runtime.day_runtime, Duration::zero());
+
+    runtime.synchronize(at_noon(), at_minute(1), true);
+    assert_eq!(runtime.day_runtime, Duration::MINUTE);
+
+    runtime.synchronize(at_noon(), at_minute(2), false);
+    assert_eq!(runtime.day_runtime, Duration::MINUTE);
+  }
+
+  #[test]
+  fn long_gaps_only_count_a_minute() {
+    let mut runtime = ApplicationRuntime::default();
+
+    runtime.synchronize(at_noon(), at_minute(0), true);
+    runtime.synchronize(at_noon(), at_minute(30), true);
+
+    assert_eq!(runtime.day_runtime, Duration::MINUTE);
+  }
+
+  #[test]
+  fn runtime_starts_over_the_next_day() {
+    let mut runtime = ApplicationRuntime::default();
+
+    runtime.synchronize(at_noon(), at_minute(0), true);
+    runtime.synchronize(at_noon(), at_minute(1), true);
+    assert_eq!(runtime.day_runtime, Duration::MINUTE);
+
+    // Noon the next day.
+    runtime.synchronize(at_noon(), at_minute(24 * 60 + 1), false);
+    assert_eq!(runtime.day_runtime, Duration::zero());
+  }
+
+  #[test]
+  fn processes_of_locked_applications_are_blocked() {
+    let mut regulations = ApplicationRegulations::new();
+    regulations.add_regulation(ApplicationName::new("steam".to_string()), create_locked_regulation());
+
+    assert!(regulations.is_process_blocked(&create_process(Some("/usr/bin/steam"), "steam"), at_noon(), at_minute(0)));
+    assert!(!regulations.is_process_blocked(&create_process(Some("/usr/bin/vim"), "vim"), at_noon(), at_minute(0)));
+  }
+
+  #[test]
+  fn processes_are_blocked_once_the_daily_allowance_is_used_up() {
+    let name = ApplicationName::new("steam".to_string());
+    let process = create_process(Some("/usr/bin/steam"), "steam");
+
+    let mut regulations = ApplicationRegulations::new();
+    regulations.add_regulation(name.clone(), create_allowance_regulation(Duration::HOUR));
+
+    let mut runtime = ApplicationRuntime {
+      day_start: at_minute(0),
+      day_runtime: Duration::HOUR.saturating_sub(Duration::MINUTE),
+      previous_synchronization_time: None,
+    };
+    regulations.set_runtimes(&HashMap::from([(name.clone(), runtime.clone())]));
+    assert!(!regulations.is_process_blocked(&process, at_noon(), at_minute(0)));
+
+    runtime.day_runtime = Duration::HOUR;
+    regulations.set_runtimes(&HashMap::from([(name, runtime)]));
+    assert!(regulations.is_process_blocked(&process, at_noon(), at_minute(0)));
+  }
+
+  #[test]
+  fn merging_keeps_the_runtime_counted_so_far() {
+    let name = ApplicationName::new("steam".to_string());
+
+    let mut regulations = ApplicationRegulations::new();
+    let mut regulation = create_allowance_regulation(Duration::HOUR);
+    regulation.runtime.day_runtime = Duration::MINUTE;
+    regulations.add_regulation(name.clone(), regulation);
+
+    let mut other = ApplicationRegulations::new();
+    other.add_regulation(name.clone(), create_locked_regulation());
+    other.add_regulation(ApplicationName::new("vim".to_string()), create_allowance_regulation(Duration::HOUR));
+    regulations.merge(&other);
+
+    let runtimes = regulations.get_runtimes();
+    assert_eq!(runtimes[&name].day_runtime, Duration::MINUTE);
+    assert_eq!(runtimes[&ApplicationName::new("vim".to_string())].day_runtime, Duration::zero());
+    assert!(regulations.is_process_blocked(&create_process(Some("/usr/bin/steam"), "steam"), at_noon(), at_minute(0)));
+  }
+}
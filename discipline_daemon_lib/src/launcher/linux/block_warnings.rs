@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::x::{Duration, Instant};
+use super::{BlockReason, UpcomingBlock, UserName};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockWarningsConfiguration {
+  // How long before a block starts each warning is sent.
+  pub thresholds: Vec<Duration>,
+}
+
+impl Default for BlockWarningsConfiguration {
+  fn default() -> Self {
+    Self {
+      thresholds: vec![
+        Duration::from_minutes_or_panic(15),
+        Duration::from_minutes_or_panic(5),
+        Duration::from_minutes_or_panic(1),
+      ],
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+struct WarnedBlock {
+  starts_at: Instant,
+  sent_thresholds: Vec<Duration>,
+}
+
+// Remembers which warnings were sent for every user's upcoming block,
+// so each threshold is only crossed once.
+#[derive(Debug)]
+pub struct BlockWarnings {
+  configuration: BlockWarningsConfiguration,
+  warned_blocks: HashMap<UserName, WarnedBlock>,
+}
+
+impl BlockWarnings {
+  // The next block instant is recomputed on every tick from the wall
+  // clock, so it drifts a little. Blocks closer than this are the same.
+  const SAME_BLOCK_TOLERANCE: Duration = Duration::MINUTE;
+
+  pub fn create(configuration: BlockWarningsConfiguration) -> Self {
+    Self {
+      configuration,
+      warned_blocks: HashMap::new(),
+    }
+  }
+
+  pub fn get_configuration(&self) -> &BlockWarningsConfiguration {
+    &self.configuration
+  }
+
+  // Returns how long is left if a warning is due.
+  //
+  // When several thresholds were crossed since the last tick, as when
+  // someone logs in three minutes before a block, only one warning is
+  // sent for all of them.
+  pub fn take_due_warning(
+    &mut self,
+    user_name: &UserName,
+    upcoming_block: Option<UpcomingBlock>,
+    now: Instant,
+  ) -> Option<Duration> {
+    let Some(upcoming_block) = upcoming_block else {
+      self.warned_blocks.remove(user_name);
+      return None;
+    };
+
+    let remaining = now.till_or_zero(upcoming_block.starts_at);
+    if remaining.is_zero() {
+      return None;
+    }
+
+    let warned_block = self
+      .warned_blocks
+      .entry(user_name.clone())
+      .or_insert(WarnedBlock {
+        starts_at: upcoming_block.starts_at,
+        sent_thresholds: Vec::new(),
+      });
+
+    let drift = if warned_block.starts_at.is_later_than(upcoming_block.starts_at) {
+      upcoming_block.starts_at.till_or_zero(warned_block.starts_at)
+    } else {
+      warned_block.starts_at.till_or_zero(upcoming_block.starts_at)
+    };
+
+    if drift.is_longer_than(Self::SAME_BLOCK_TOLERANCE) {
+      warned_block.sent_thresholds.clear();
+    }
+    warned_block.starts_at = upcoming_block.starts_at;
+
+    let due_thresholds: Vec<Duration> = self
+      .configuration
+      .thresholds
+      .iter()
+      .copied()
+      .filter(|threshold| remaining.is_shorter_than_or_equal_to(*threshold))
+      .filter(|threshold| !warned_block.sent_thresholds.contains(threshold))
+      .collect();
+
+    if due_thresholds.is_empty() {
+      return None;
+    }
+
+    warned_block.sent_thresholds.extend(due_thresholds);
+    Some(remaining)
+  }
+
+  // Forgets users that no longer have live sessions.
+  pub fn retain(&mut self, mut has_open_sessions: impl FnMut(&UserName) -> bool) {
+    self.warned_blocks.retain(|user_name, _| has_open_sessions(user_name));
+  }
+}
+
+pub fn create_block_warning_message(reason: BlockReason, remaining: Duration) -> (String, String) {
+  // Round up, so "0 minutes left" is never shown.
+  let minutes = remaining
+    .as_total_milliseconds()
+    .div_ceil(Duration::MILLISECONDS_PER_MINUTE);
+
+  let summary = if minutes == 1 {
+    "1 minute left".to_string()
+  } else {
+    format!("{minutes} minutes left")
+  };
+
+  let body = match reason {
+    BlockReason::TimeRange => {
+      "A blocked time range is about to start. Save your work."
+    }
+    BlockReason::AllowanceExhaustion => {
+      "Your screen time allowance is about to run out. Save your work."
+    }
+    BlockReason::Lock => {
+      "A lock is about to take effect. Save your work."
+    }
+  };
+
+  (summary, body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use super::*;
+
+  fn create_warnings() -> BlockWarnings {
+    BlockWarnings::create(BlockWarningsConfiguration::default())
+  }
+
+  fn create_user_name() -> UserName {
+    UserName::new(CString::new("alex").unwrap())
+  }
+
+  fn minutes(minutes: u64) -> Duration {
+    Duration::from_minutes_or_panic(minutes)
+  }
+
+  fn create_block(starts_at: Instant) -> Option<UpcomingBlock> {
+    Some(UpcomingBlock {
+      reason: BlockReason::TimeRange,
+      starts_at,
+    })
+  }
+
+  #[test]
+  fn warnings_are_due_as_thresholds_are_crossed() {
+    let mut warnings = create_warnings();
+    let user_name = create_user_name();
+    let starts_at = Instant::from_elapsed_time(minutes(60));
+    let at = |minutes_left| starts_at.saturating_sub(minutes(minutes_left));
+
+    let cases = [
+      (20, None),
+      (15, Some(minutes(15))),
+      (10, None),
+      (5, Some(minutes(5))),
+      (1, Some(minutes(1))),
+    ];
+
+    for (minutes_left, warning) in cases {
+      assert_eq!(
+        warnings.take_due_warning(&user_name, create_block(starts_at), at(minutes_left)),
+        warning,
+        "{minutes_left} minutes before the block",
+      );
+    }
+  }
+
+  #[test]
+  fn warnings_are_not_repeated() {
+    let mut warnings = create_warnings();
+    let user_name = create_user_name();
+    let starts_at = Instant::from_elapsed_time(minutes(60));
+    let now = starts_at.saturating_sub(minutes(14));
+
+    assert_eq!(warnings.take_due_warning(&user_name, create_block(starts_at), now), Some(minutes(14)));
+    assert_eq!(warnings.take_due_warning(&user_name, create_block(starts_at), now), None);
+
+    // The block drifting a little is still the same block.
+    let drifted = starts_at.saturating_add(Duration::from_milliseconds(30 * 1000));
+    assert_eq!(warnings.take_due_warning(&user_name, create_block(drifted), now), None);
+
+    // A different block is warned about anew.
+    let sooner = starts_at.saturating_sub(minutes(10));
+    assert_eq!(warnings.take_due_warning(&user_name, create_block(sooner), now), Some(minutes(4)));
+  }
+
+  #[test]
+  fn blocks_sooner_than_the_first_threshold_get_one_warning() {
+    let mut warnings = create_warnings();
+    let user_name = create_user_name();
+    let starts_at = Instant::from_elapsed_time(minutes(60));
+    let now = starts_at.saturating_sub(minutes(3));
+
+    // The 15 and 5 minute thresholds were both crossed before anyone
+    // was around to be warned.
+    assert_eq!(warnings.take_due_warning(&user_name, create_block(starts_at), now), Some(minutes(3)));
+    assert_eq!(warnings.take_due_warning(&user_name, create_block(starts_at), now.saturating_add(minutes(1))), None);
+    assert_eq!(warnings.take_due_warning(&user_name, create_block(starts_at), now.saturating_add(minutes(2))), Some(minutes(1)));
+  }
+
+  #[test]
+  fn no_warning_is_due_without_an_upcoming_block() {
+    let mut warnings = create_warnings();
+    let user_name = create_user_name();
+    let starts_at = Instant::from_elapsed_time(minutes(60));
+
+    assert_eq!(warnings.take_due_warning(&user_name, None, starts_at), None);
+    // A block that already started is past warning about.
+    assert_eq!(warnings.take_due_warning(&user_name, create_block(starts_at), starts_at), None);
+  }
+}
@@ -0,0 +1,372 @@
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use super::{WebFilterAction, WebRules};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrowserPoliciesConfiguration {
+  pub firefox_policies_path: PathBuf,
+  // Chromium based browsers merge every file in their managed policies
+  // directory, so these are files of our own.
+  pub chromium_policies_paths: Vec<PathBuf>,
+}
+
+impl Default for BrowserPoliciesConfiguration {
+  fn default() -> Self {
+    Self {
+      firefox_policies_path: PathBuf::from("/etc/firefox/policies/policies.json"),
+      chromium_policies_paths: vec![
+        PathBuf::from("/etc/chromium/policies/managed/discipline.json"),
+        PathBuf::from("/etc/opt/chrome/policies/managed/discipline.json"),
+      ],
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlFilter {
+  // A domain, which covers its subdomains too, or "*".
+  pub host: String,
+  pub path_pattern: Option<String>,
+}
+
+impl UrlFilter {
+  fn everything() -> Self {
+    Self {
+      host: "*".into(),
+      path_pattern: None,
+    }
+  }
+
+  // Firefox takes match patterns, where "*.example.com" also matches
+  // example.com itself.
+  fn to_firefox_pattern(&self) -> String {
+    let path = self.path_pattern.as_deref().unwrap_or("/*");
+
+    match (self.host.as_str(), path) {
+      ("*", "/*") => {
+        "<all_urls>".into()
+      }
+      ("*", path) => {
+        format!("*://*{path}")
+      }
+      (host, path) => {
+        format!("*://*.{host}{path}")
+      }
+    }
+  }
+
+  // Chromium takes a host followed by a path prefix. Patterns are cut
+  // at their first "*", which makes them match more than they should,
+  // and any host with a path can't be expressed at all.
+  fn to_chromium_pattern(&self) -> Option<String> {
+    let Some(path_pattern) = &self.path_pattern else {
+      return Some(self.host.clone());
+    };
+
+    if self.host == "*" {
+      return None;
+    }
+
+    let prefix = path_pattern
+      .split('*')
+      .next()
+      .unwrap_or_default();
+
+    Some(format!("{}{prefix}", self.host))
+  }
+}
+
+// What browsers on this machine are made to enforce. Policy files are
+// machine wide, so this is the union of the web rules of everyone who's
+// logged in, and an exception one user's rules make applies to all.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BrowserPolicy {
+  pub blocked: Vec<UrlFilter>,
+  pub allowed: Vec<UrlFilter>,
+}
+
+impl BrowserPolicy {
+  pub fn create<'a>(web_rules: impl IntoIterator<Item = &'a WebRules>) -> Self {
+    let mut policy = Self::default();
+
+    for web_rules in web_rules {
+      if web_rules.internet_access_blocked {
+        policy.add_blocked(UrlFilter::everything());
+        continue;
+      }
+
+      if !web_rules.enabled {
+        continue;
+      }
+
+      for rule in &web_rules.rules {
+        let filter = UrlFilter {
+          host: rule.host.clone(),
+          path_pattern: rule.path_pattern.clone(),
+        };
+
+        match rule.action {
+          WebFilterAction::Allow => {
+            policy.add_allowed(filter);
+          }
+          WebFilterAction::Deny => {
+            policy.add_blocked(filter);
+          }
+        }
+      }
+
+      if web_rules.default_action == WebFilterAction::Deny {
+        policy.add_blocked(UrlFilter::everything());
+      }
+    }
+
+    policy
+  }
+
+  fn add_blocked(&mut self, filter: UrlFilter) {
+    if !self.blocked.contains(&filter) {
+      self.blocked.push(filter);
+    }
+  }
+
+  fn add_allowed(&mut self, filter: UrlFilter) {
+    if !self.allowed.contains(&filter) {
+      self.allowed.push(filter);
+    }
+  }
+
+  // Exceptions alone restrict nothing, so they don't count.
+  pub fn is_restrictive(&self) -> bool {
+    !self.blocked.is_empty()
+  }
+}
+
+pub fn generate_firefox_policies(policy: &BrowserPolicy) -> String {
+  let blocked: Vec<String> = policy.blocked.iter().map(UrlFilter::to_firefox_pattern).collect();
+  let allowed: Vec<String> = policy.allowed.iter().map(UrlFilter::to_firefox_pattern).collect();
+
+  let policies = json!({
+    "policies": {
+      "WebsiteFilter": {
+        "Block": blocked,
+        "Exceptions": allowed,
+      },
+      "DisablePrivateBrowsing": true,
+      "DisableDeveloperTools": true,
+    }
+  });
+
+  format!("{policies:#}\n")
+}
+
+pub fn generate_chromium_policies(policy: &BrowserPolicy) -> String {
+  let blocked: Vec<String> = policy.blocked.iter().filter_map(UrlFilter::to_chromium_pattern).collect();
+  let allowed: Vec<String> = policy.allowed.iter().filter_map(UrlFilter::to_chromium_pattern).collect();
+
+  let policies = json!({
+    "URLBlocklist": blocked,
+    "URLAllowlist": allowed,
+    // Incognito mode is disabled.
+    "IncognitoModeAvailability": 1,
+    // Developer tools are disallowed everywhere.
+    "DeveloperToolsAvailability": 2,
+  });
+
+  format!("{policies:#}\n")
+}
+
+// Keeps browser policy files in line with who's logged in and their
+// rules, rewriting them only when the policy changes.
+#[derive(Debug)]
+pub struct BrowserPolicies {
+  // None when policy files are disabled, in which case none are
+  // written.
+  configuration: Option<BrowserPoliciesConfiguration>,
+  applied_policy: Option<BrowserPolicy>,
+}
+
+impl BrowserPolicies {
+  pub fn create(configuration: Option<BrowserPoliciesConfiguration>) -> Self {
+    Self {
+      configuration,
+      applied_policy: None,
+    }
+  }
+
+  pub fn get_configuration(&self) -> Option<&BrowserPoliciesConfiguration> {
+    self.configuration.as_ref()
+  }
+
+  // Nothing is applied yet after the daemon starts, so the first
+  // reconciliation always writes, or removes, the files.
+  pub fn needs_applying(&self, policy: &BrowserPolicy) -> bool {
+    self.configuration.is_some() && self.applied_policy.as_ref() != Some(policy)
+  }
+
+  pub fn on_policy_applied(&mut self, policy: BrowserPolicy) {
+    self.applied_policy = Some(policy);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::native_messaging::ActiveWebRule;
+  use super::*;
+
+  fn create_filter(host: &str, path_pattern: Option<&str>) -> UrlFilter {
+    UrlFilter {
+      host: host.to_string(),
+      path_pattern: path_pattern.map(ToOwned::to_owned),
+    }
+  }
+
+  fn create_web_rules(rules: Vec<(WebFilterAction, &str, Option<&str>)>, default_action: WebFilterAction) -> WebRules {
+    WebRules {
+      internet_access_blocked: false,
+      enabled: true,
+      rules: rules
+        .into_iter()
+        .map(|(action, host, path_pattern)| ActiveWebRule {
+          action,
+          host: host.to_string(),
+          path_pattern: path_pattern.map(ToOwned::to_owned),
+        })
+        .collect(),
+      default_action,
+    }
+  }
+
+  fn create_policy() -> BrowserPolicy {
+    BrowserPolicy {
+      blocked: vec![
+        create_filter("youtube.com", Some("/shorts/*")),
+        create_filter("reddit.com", None),
+        UrlFilter::everything(),
+      ],
+      allowed: vec![
+        create_filter("wikipedia.org", None),
+        create_filter("*", Some("/docs/*")),
+      ],
+    }
+  }
+
+  #[test]
+  fn firefox_policies_are_generated() {
+    assert_eq!(generate_firefox_policies(&create_policy()), r#"{
+  "policies": {
+    "DisableDeveloperTools": true,
+    "DisablePrivateBrowsing": true,
+    "WebsiteFilter": {
+      "Block": [
+        "*://*.youtube.com/shorts/*",
+        "*://*.reddit.com/*",
+        "<all_urls>"
+      ],
+      "Exceptions": [
+        "*://*.wikipedia.org/*",
+        "*://*/docs/*"
+      ]
+    }
+  }
+}
+"#);
+  }
+
+  #[test]
+  fn chromium_policies_are_generated() {
+    // Chromium can't express a path on any host, so the last exception
+    // is left out.
+    assert_eq!(generate_chromium_policies(&create_policy()), r#"{
+  "DeveloperToolsAvailability": 2,
+  "IncognitoModeAvailability": 1,
+  "URLAllowlist": [
+    "wikipedia.org"
+  ],
+  "URLBlocklist": [
+    "youtube.com/shorts/",
+    "reddit.com",
+    "*"
+  ]
+}
+"#);
+  }
+
+  #[test]
+  fn empty_policies_are_generated() {
+    let policy = BrowserPolicy::default();
+
+    assert_eq!(generate_firefox_policies(&policy), r#"{
+  "policies": {
+    "DisableDeveloperTools": true,
+    "DisablePrivateBrowsing": true,
+    "WebsiteFilter": {
+      "Block": [],
+      "Exceptions": []
+    }
+  }
+}
+"#);
+    assert_eq!(generate_chromium_policies(&policy), r#"{
+  "DeveloperToolsAvailability": 2,
+  "IncognitoModeAvailability": 1,
+  "URLAllowlist": [],
+  "URLBlocklist": []
+}
+"#);
+  }
+
+  #[test]
+  fn policies_are_the_union_of_everyones_rules() {
+    let alex = create_web_rules(
+      vec![
+        (WebFilterAction::Deny, "youtube.com", Some("/shorts/*")),
+        (WebFilterAction::Allow, "wikipedia.org", None),
+      ],
+      WebFilterAction::Allow,
+    );
+    let sam = create_web_rules(
+      vec![
+        (WebFilterAction::Deny, "youtube.com", Some("/shorts/*")),
+        (WebFilterAction::Deny, "reddit.com", None),
+      ],
+      WebFilterAction::Deny,
+    );
+    let disabled = WebRules {
+      enabled: false,
+      ..create_web_rules(vec![(WebFilterAction::Deny, "example.com", None)], WebFilterAction::Deny)
+    };
+
+    let policy = BrowserPolicy::create([&alex, &sam, &disabled]);
+    assert_eq!(policy, BrowserPolicy {
+      blocked: vec![
+        create_filter("youtube.com", Some("/shorts/*")),
+        create_filter("reddit.com", None),
+        UrlFilter::everything(),
+      ],
+      allowed: vec![create_filter("wikipedia.org", None)],
+    });
+    assert!(policy.is_restrictive());
+  }
+
+  #[test]
+  fn blocked_internet_access_blocks_everything() {
+    let blocked = WebRules {
+      internet_access_blocked: true,
+      ..create_web_rules(vec![(WebFilterAction::Allow, "wikipedia.org", None)], WebFilterAction::Allow)
+    };
+
+    assert_eq!(BrowserPolicy::create([&blocked]), BrowserPolicy {
+      blocked: vec![UrlFilter::everything()],
+      allowed: Vec::new(),
+    });
+  }
+
+  #[test]
+  fn exceptions_alone_restrict_nothing() {
+    let rules = create_web_rules(vec![(WebFilterAction::Allow, "wikipedia.org", None)], WebFilterAction::Allow);
+
+    assert!(!BrowserPolicy::create([&rules]).is_restrictive());
+    assert!(!BrowserPolicy::create([&WebRules::unrestricted()]).is_restrictive());
+  }
+}
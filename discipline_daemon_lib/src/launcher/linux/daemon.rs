@@ -1,88 +1,627 @@
-use std::path::PathBuf;
-use crate::x::{DateTime, TextualErrorV2, Database};
-use super::{State, Api, UserName, pam};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::x::{DateTime, Database, Duration, Instant, IsTextualError, MonotonicClock, RulesStats, TextualError, Vaults, VaultsStats};
+use crate::x::database::{group_profile_table, monotonic_clock_table, password_escrow_table, session_record_table, user_profile_table, user_usage_table, vault_data_table, vault_table};
+use super::{ApplicationUsageConfiguration, ApplicationUsageTracker, LoginClass, LoginContext, LoginRefusal, LoginRefusalReason, State, UserName, UserProfiles, PasswordEscrows, BlockWarnings, BlockWarningsConfiguration, BrowserPolicies, BrowserPoliciesConfiguration, InternetBlocker, InternetBlockingConfiguration, DnsRedirector, DnsResolverConfiguration, DomainBlocklists, HttpProxyConfiguration, NativeMessagingConfiguration, SessionEnforcementConfiguration, SessionDeadline, SessionDetails, SessionEnforcer, SessionRecord, SessionRecords, pam};
+use super::{get_time_from_boottime_clock, get_time_from_realtime_clock};
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LaunchConfiguration {
   pub api_server_port: u16,
   pub database_directory: PathBuf,
   pub pam_server_path: PathBuf,
   pub pam_client_authentication_token: pam::AuthenticationToken,
+  // Touched every tick so the PAM module can tell how long the daemon
+  // has been gone. Only written when this is set.
+  #[serde(default)]
+  pub pam_heartbeat_path: Option<PathBuf>,
+  // The copy of everyone's block schedule the PAM module falls back on
+  // is only written when this is set.
+  #[serde(default)]
+  pub pam_policy_cache: Option<pam::PolicyCacheConfiguration>,
+  // How long in-flight requests may take to finish once we're asked to stop.
+  pub shutdown_grace_period: Duration,
+  // How often the clock is synchronized and expired state is cleaned up.
+  pub tick_interval: Duration,
+  // What happens to a user's live sessions when a block starts.
+  #[serde(default)]
+  pub session_enforcement: SessionEnforcementConfiguration,
+  // When users are warned that a block is about to start.
+  #[serde(default)]
+  pub block_warnings: BlockWarningsConfiguration,
+  // Which destinations stay reachable while a user's internet access
+  // is blocked.
+  #[serde(default)]
+  pub internet_blocking: InternetBlockingConfiguration,
+  // The filtering DNS resolver is only started when this is set.
+  #[serde(default)]
+  pub dns_resolver: Option<DnsResolverConfiguration>,
+  // The filtering HTTP proxy is only started when this is set.
+  #[serde(default)]
+  pub http_proxy: Option<HttpProxyConfiguration>,
+  // The server browser extensions get web rules from, through the
+  // native messaging host, is only started when this is set.
+  #[serde(default)]
+  pub native_messaging: Option<NativeMessagingConfiguration>,
+  // Browser policy files are only written when this is set.
+  #[serde(default)]
+  pub browser_policies: Option<BrowserPoliciesConfiguration>,
+  // How long per-application usage statistics are kept.
+  #[serde(default)]
+  pub application_usage: ApplicationUsageConfiguration,
+}
+
+impl LaunchConfiguration {
+  pub const DEFAULT_PATH: &'static str = "/etc/discipline/daemon.json";
+
+  pub fn load(
+    configuration_file_path: impl AsRef<Path>,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Self, ()> {
+    let configuration_file_path = configuration_file_path.as_ref();
+
+    // The file holds the token the PAM module authenticates with.
+    pam::AuthenticationToken::ensure_stored_privately(configuration_file_path, textual_error)?;
+
+    let configuration_file_content = match std::fs::read(configuration_file_path) {
+      Ok(value) => {
+        value
+      }
+      Err(error) => {
+        textual_error.change_context("Loading Discipline Linux Daemon Configuration from file");
+        textual_error.add_message("A filesystem error occured");
+        textual_error.add_attachement_display("Filesystem error", error);
+        textual_error.add_attachement_display("Configuration file path", configuration_file_path.display());
+        return Err(());
+      }
+    };
+
+    let configuration: Self = match serde_json::from_slice(&configuration_file_content) {
+      Ok(value) => {
+        value
+      }
+      Err(error) => {
+        textual_error.change_context("Deserializing the configuration file content, which is in JSON format");
+        textual_error.add_message("Deserialization failed");
+        textual_error.add_attachement_display("Deserializing error", error);
+        textual_error.add_attachement_display("Configuration file path", configuration_file_path.display());
+        return Err(());
+      }
+    };
+
+    configuration.pam_client_authentication_token.ensure_not_placeholder(textual_error)?;
+    Ok(configuration)
+  }
 }
 
 pub struct Daemon {
   pub state: State,
   pub database: Database,
-  pub api_server: Api,
-  pub pam_server: pam::Server,
 }
 
 impl Daemon {
+  // The largest jump the monotonic clock makes in one synchronization.
+  const MAXIMUM_CLOCK_SYNCHRONIZATION_INTERVAL: Duration = Duration::MINUTE;
+
   pub fn open(
-    configuration: LaunchConfiguration,
-    textual_error: &mut impl TextualErrorV2,
+    configuration: &LaunchConfiguration,
+    textual_error: &mut impl IsTextualError,
   ) -> Result<Self, ()> {
-    let mut textual_error_context = textual_error.context(
-      "Launchnig Discipline Linux Daemon",
-    );
+    let database = Database::open(&configuration.database_directory, textual_error)?;
+    let state = Self::load_state(configuration, &database, textual_error)?;
+
+    Ok(Self {
+      state,
+      database,
+    })
+  }
+
+  // A daemon whose state starts out empty and is saved nowhere.
+  #[cfg(test)]
+  pub fn open_in_memory(textual_error: &mut impl IsTextualError) -> Result<Self, ()> {
+    let configuration = create_test_configuration();
+    let database = Database::open_in_memory(textual_error)?;
+    let state = Self::load_state(&configuration, &database, textual_error)?;
+
+    Ok(Self {
+      state,
+      database,
+    })
+  }
+
+  // Loads the state back from the database, like a restart would.
+  #[cfg(test)]
+  pub fn reload_state(&mut self, textual_error: &mut impl IsTextualError) -> Result<(), ()> {
+    self.state = Self::load_state(&create_test_configuration(), &self.database, textual_error)?;
+    Ok(())
+  }
 
-    // let database = Database::open(
-    //   &mut textual_error_context, 
-    //   configuration.database_directory,
-    // )?;
+  fn load_state(
+    configuration: &LaunchConfiguration,
+    database: &Database,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<State, ()> {
+    let (Some(realtime), Some(boottime)) = (
+      get_time_from_realtime_clock(),
+      get_time_from_boottime_clock(),
+    ) else {
+      textual_error.change_context("Loading the daemon's state");
+      textual_error.add_message("Failed to read the system clocks");
+      return Err(());
+    };
 
-    // let state = database.load_state(
-    //   &mut textual_error_context,
-    // )?;
+    let monotonic_clock = match monotonic_clock_table::load_clock(
+      database, 
+      realtime, 
+      boottime, 
+      textual_error,
+    )? {
+      Some(monotonic_clock) => {
+        monotonic_clock
+      }
+      None => {
+        MonotonicClock::create(
+          realtime, 
+          boottime, 
+          Self::MAXIMUM_CLOCK_SYNCHRONIZATION_INTERVAL,
+        )
+      }
+    };
 
-    // let api = Api::create(
-    //   &mut textual_error_context,
-    // )?;
+    let group_profiles = group_profile_table::load_group_profiles(database, textual_error)?;
+    let user_profiles = user_profile_table::load_user_profiles(database, textual_error)?;
+    let user_usage = user_usage_table::load_user_usage(database, textual_error)?;
+    let session_records = session_record_table::load_session_records(database, textual_error)?;
+    let password_escrows = password_escrow_table::load_escrows(database, textual_error)?;
 
-    // let pam_server = pam::Server::new(
-    //   configuration.pam_server_path, 
-    //   configuration.pam_client_authentication_token, 
-    //   textual_error,
-    // )?;
+    let mut vaults = vault_table::load_vaults(database, textual_error)?;
+    for loaded_datum in vault_data_table::load_data(database, textual_error)? {
+      match vaults.get_mut(&loaded_datum.vault_id) {
+        Some(vault) => {
+          vault.data.insert(loaded_datum.datum_id, loaded_datum.datum);
+        }
+        None => {
+          // TODO: Use a proper logging mechanism.
+          eprintln!(
+            "Discipline Linux Daemon: Skipping saved datum {} of vault {}, which wasn't found", 
+            loaded_datum.datum_id.to_string(), 
+            loaded_datum.vault_id.to_string(),
+          );
+        }
+      }
+    }
 
-    // Ok(Self {
-    //   api_server: api,
-    //   state,
-    //   database,
-    //   pam_server,
-    // })
-    todo!()
+    let vaults = Vaults::construct(vaults);
+    let mut vaults_stats = VaultsStats::default();
+    vaults_stats.recount_usage(&vaults);
+
+    Ok(State {
+      user_profiles: UserProfiles::construct(user_profiles, group_profiles, user_usage),
+      monotonic_clock,
+      rules_stats: RulesStats::new(RulesStats::DEFAULT_MAXIMUM_RULES_NUMBER),
+      vaults,
+      vaults_stats,
+      password_escrows: PasswordEscrows::construct(password_escrows),
+      session_records: SessionRecords::construct(session_records),
+      session_enforcer: SessionEnforcer::create(configuration.session_enforcement.clone()),
+      block_warnings: BlockWarnings::create(configuration.block_warnings.clone()),
+      internet_blocker: InternetBlocker::create(
+        configuration.internet_blocking.clone(),
+        configuration.http_proxy.is_some(),
+      ),
+      domain_blocklists: DomainBlocklists::create(
+        configuration
+          .dns_resolver
+          .clone()
+          .unwrap_or_default()
+          .blocklists_directory,
+      ),
+      dns_redirector: DnsRedirector::create(
+        configuration
+          .dns_resolver
+          .as_ref()
+          .map(|dns_resolver| dns_resolver.port),
+      ),
+      browser_policies: BrowserPolicies::create(configuration.browser_policies.clone()),
+      application_usage_tracker: ApplicationUsageTracker::create(configuration.application_usage.clone()),
+      pam_policy_cache_writer: pam::PolicyCacheWriter::create(
+        configuration.pam_policy_cache.clone(),
+        configuration.pam_client_authentication_token.clone(),
+      ),
+    })
   }
 
-  pub fn is_user_session_open_blocked(&self, user_name: &UserName) -> bool {
-    self
-      .state
-      .user_profiles
-      .get_profile_given_user_name(user_name)
-      .map(|profile| {
-        let time = DateTime::now().time();
-        let instant = self.state.monotonic_clock.now();
-        profile.is_session_open_blocked(time, instant)
-      })
-      .unwrap_or(false)
+  pub fn synchronize_clock(&mut self) {
+    if let (Some(realtime), Some(boottime)) = (
+      get_time_from_realtime_clock(),
+      get_time_from_boottime_clock(),
+    ) {
+      self.state.monotonic_clock.synchronize(realtime, boottime);
+    }
   }
 
-  pub fn on_user_session_opened(&self, user_name: &UserName) {
-    self
-      .state
-      .user_profiles
-      .get_profile_given_user_name(user_name)
-      .map(|profile| {
-        profile.on_user_session_opened();
+  // Saves everything that isn't written to the database as it
+  // changes. Called periodically and once more before exiting.
+  pub fn persist(&mut self, textual_error: &mut impl IsTextualError) -> Result<(), ()> {
+    self.synchronize_clock();
+    monotonic_clock_table::save_clock(
+      &self.database, 
+      &self.state.monotonic_clock, 
+      textual_error,
+    )?;
+    user_profile_table::save_user_profiles(
+      &self.database, 
+      self.state.user_profiles.get_all_profiles(), 
+      textual_error,
+    )?;
+    // Usage is only counted on the effective profiles, not on the
+    // profiles saved above.
+    user_usage_table::save_user_usage(
+      &self.database,
+      self.state.user_profiles.get_all_usage(),
+      textual_error,
+    )
+  }
+
+  pub fn is_user_session_open_blocked(
+    &self, 
+    user_name: &UserName,
+    login_context: &LoginContext,
+  ) -> bool {
+    self.get_login_refusal(user_name, login_context).is_some()
+  }
+
+  // Why the login would be refused, or None if it's allowed.
+  pub fn get_login_refusal(
+    &self,
+    user_name: &UserName,
+    login_context: &LoginContext,
+  ) -> Option<LoginRefusal> {
+    let wall_now = DateTime::now();
+    let time = wall_now.time();
+    let instant = self.state.monotonic_clock.now();
+    let class = login_context.get_class();
+
+    let get_blocked_until = |ends_at: Option<Instant>| {
+      ends_at.map(|ends_at| get_wall_timestamp(ends_at, instant, wall_now))
+    };
+
+    // Someone blocked can't get around it by switching to an account
+    // that isn't regulated, or is regulated more loosely.
+    if let LoginClass::SwitchUser | LoginClass::Elevation = class {
+      let requesting_user_block = login_context
+        .requesting_user_id
+        .and_then(|user_id| self.state.user_profiles.get_profile_given_user_id(user_id))
+        .filter(|profile| profile.login_policy.refuse_switching_users_while_blocked)
+        .and_then(|profile| profile.get_current_block(time, instant));
+
+      if let Some(block) = requesting_user_block {
+        return Some(LoginRefusal {
+          reason: LoginRefusalReason::RequestingUserBlocked(block.reason),
+          blocked_until: get_blocked_until(block.ends_at),
+        });
+      }
+    }
+
+    let profile = self.state.user_profiles.get_profile_given_user_name(user_name)?;
+
+    if profile.login_policy.is_refused(class) {
+      return Some(LoginRefusal {
+        reason: LoginRefusalReason::RefusedLoginClass(class),
+        blocked_until: None,
       });
+    }
+    if profile.login_policy.is_exempt(class) {
+      return None;
+    }
+
+    profile
+      .get_current_block(time, instant)
+      .map(|block| LoginRefusal {
+        reason: LoginRefusalReason::Blocked(block.reason),
+        blocked_until: get_blocked_until(block.ends_at),
+      })
+  }
+
+  // None for users without a profile. Sessions of a login class the
+  // profile exempts from blocks aren't cut off, so they get no end.
+  // Without `login_class`, the user's next block is the end.
+  pub fn get_session_deadline(&self, user_name: &UserName, login_class: Option<LoginClass>) -> Option<SessionDeadline> {
+    let wall_now = DateTime::now();
+    let instant = self.state.monotonic_clock.now();
+
+    let profile = self.state.user_profiles.get_profile_given_user_name(user_name)?;
+
+    let is_exempt = login_class.is_some_and(|login_class| profile.login_policy.is_exempt(login_class));
+
+    let ends_at = profile
+      .get_next_block(wall_now.time(), instant)
+      .filter(|_| !is_exempt)
+      .map(|block| get_wall_timestamp(block.starts_at, instant, wall_now));
+
+    Some(SessionDeadline {
+      profile_name: profile.name.as_str().to_owned(),
+      ends_at,
+    })
+  }
+
+  pub fn on_user_session_opened(&mut self, user_name: &UserName, details: SessionDetails) {
+    let now = DateTime::now().as_timestamp();
+    let record = self.state.session_records.on_session_opened(user_name, details, now).clone();
+
+    self.save_session_records(&[record], &[]);
   }
 
-  pub fn on_user_session_closed(&self, user_name: &UserName) {
-    self
+  pub fn on_user_session_closed(&mut self, user_name: &UserName, details: &SessionDetails) {
+    let now = DateTime::now().as_timestamp();
+    let Some((record, forgotten_record)) = self
       .state
-      .user_profiles
-      .get_profile_given_user_name(user_name)
-      .map(|profile| {
-        profile.on_user_session_closed();
-      });
+      .session_records
+      .on_session_closed(user_name, details, now)
+    else {
+      return;
+    };
+
+    let forgotten_records: Vec<_> = forgotten_record.into_iter().collect();
+    self.save_session_records(&[record], &forgotten_records);
+  }
+
+  // Failing to save only loses track of the sessions if the daemon
+  // restarts before the next change, so it isn't worth failing over.
+  pub fn save_session_records(&self, saved_records: &[SessionRecord], deleted_records: &[SessionRecord]) {
+    let mut textual_error = TextualError::new("Saving session records");
+
+    if let Err(()) = session_record_table::update_session_records(
+      &self.database,
+      saved_records,
+      deleted_records,
+      &mut textual_error,
+    ) {
+      // TODO: Use a proper logging mechanism.
+      eprintln!("{textual_error}");
+    }
   }
-}
\ No newline at end of file
+}
+
+// The wall time at which the monotonic clock reaches `instant`, as a
+// UNIX timestamp in milliseconds.
+fn get_wall_timestamp(instant: Instant, now: Instant, wall_now: DateTime) -> i64 {
+  let offset = i64::try_from(instant.since_or_zero(now).as_total_milliseconds()).unwrap_or(i64::MAX);
+  wall_now.as_timestamp().saturating_add(offset)
+}
+
+// Only what's required, with everything optional left unset.
+#[cfg(test)]
+fn create_test_configuration() -> LaunchConfiguration {
+  serde_json::from_str(r#"{
+    "api_server_port": 9090,
+    "database_directory": "/var/lib/discipline",
+    "pam_server_path": "/run/discipline/pam.sock",
+    "pam_client_authentication_token": { "value": "token" },
+    "shutdown_grace_period": 10000,
+    "tick_interval": 5000
+  }"#).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use crate::x::{AlwaysRule, CountdownAfterPleaConditional, RuleEnabler, UserUptimeClock, UuidV4, Vault, VaultDatum, VaultName, VaultProtector};
+  use crate::x::launcher::{ApplicationRegulations, BlockReason, DeviceAccessRegulation, InternetAccessRegulation, LoginPolicy, PasswordEscrow, ScreenAccessRegulation, UserId, UserProfile, UserProfileName};
+  use super::*;
+
+  #[test]
+  fn saved_vaults_and_escrows_are_loaded_back_with_their_usage() {
+    let mut textual_error = TextualError::new("Testing loading the state");
+    let database = Database::open_in_memory(&mut textual_error).unwrap();
+
+    let mut vault = Vault::create(
+      VaultName::new("Recovery codes".to_string()).unwrap(),
+      VaultProtector::CountdownAfterPlea(CountdownAfterPleaConditional::construct(
+        Duration::HOUR,
+        None,
+      )),
+    );
+    let vault_id = UuidV4::generate();
+    assert!(vault_table::insert_vault(&database, &vault_id, &vault, &mut textual_error).is_ok());
+
+    let datum_id = UuidV4::generate();
+    let datum = VaultDatum::new("123-456".to_string()).unwrap();
+    assert!(vault_data_table::insert_datum(&database, &vault_id, &datum_id, &datum, &mut textual_error).is_ok());
+    vault.set_datum(datum_id.clone(), datum);
+
+    let escrow_id = UuidV4::generate();
+    let escrow = PasswordEscrow {
+      vault_id: vault_id.clone(),
+      datum_id: datum_id.clone(),
+      user_id: UserId::new(1000),
+      user_name: UserName::new(CString::new("alex").unwrap()),
+      rotate_after_reveal: true,
+    };
+    assert!(password_escrow_table::insert_escrow(&database, &escrow_id, &escrow, &mut textual_error).is_ok());
+
+    let state = Daemon::load_state(&create_test_configuration(), &database, &mut textual_error).unwrap();
+
+    let loaded_vault = state.vaults.get_vault_given_id(&vault_id).unwrap();
+    assert_eq!(loaded_vault.data.len(), 1);
+    assert!(loaded_vault.data.contains_key(&datum_id));
+
+    assert_eq!(state.vaults_stats.get_vault_number(), 1);
+    assert_eq!(state.vaults_stats.get_data_number(), 1);
+    assert_eq!(state.vaults_stats.get_data_size(), vault.get_data_size());
+
+    let loaded_escrow = state.password_escrows.get_escrow_given_id(&escrow_id).unwrap();
+    assert_eq!(loaded_escrow.vault_id, vault_id);
+    assert_eq!(loaded_escrow.user_id, UserId::new(1000));
+    assert!(loaded_escrow.rotate_after_reveal);
+  }
+
+  #[test]
+  fn saved_profiles_are_loaded_back_with_their_rules() {
+    let mut textual_error = TextualError::new("Testing loading the state");
+    let database = Database::open_in_memory(&mut textual_error).unwrap();
+
+    let mut profile = UserProfile {
+      name: UserProfileName::new("Alex".to_string()).unwrap(),
+      user_id: UserId::new(1000),
+      user_name: UserName::new(CString::new("alex").unwrap()),
+      uptime_clock: UserUptimeClock::construct(
+        false,
+        Instant::from_timestamp(0),
+        Duration::zero(),
+        Instant::from_timestamp(0),
+        Duration::zero(),
+        Instant::from_timestamp(0),
+        Duration::MINUTE,
+      ),
+      device_access_regulation: DeviceAccessRegulation::new(),
+      screen_access_regulation: ScreenAccessRegulation::default(),
+      internet_access_regulation: InternetAccessRegulation::new(),
+      application_regulations: ApplicationRegulations::new(),
+      login_policy: LoginPolicy::default(),
+      rules_stats: RulesStats::new(RulesStats::DEFAULT_MAXIMUM_RULES_NUMBER),
+      is_orphaned: false,
+    };
+
+    // Rules are kept in maps keyed by their ids.
+    let rule_id = UuidV4::generate();
+    let rule = AlwaysRule::create(RuleEnabler::CountdownAfterPlea(CountdownAfterPleaConditional::create(Duration::HOUR)));
+    profile.screen_access_regulation.always_rules.rules.insert(rule_id.clone(), rule);
+
+    let profile_id = UuidV4::generate();
+    assert!(user_profile_table::save_user_profiles(&database, [(&profile_id, &profile)].into_iter(), &mut textual_error).is_ok());
+
+    let state = Daemon::load_state(&create_test_configuration(), &database, &mut textual_error).unwrap();
+
+    let loaded_profile = state.user_profiles.get_profile_given_id(&profile_id).unwrap();
+    assert!(loaded_profile.screen_access_regulation.always_rules.rules.contains_key(&rule_id));
+  }
+
+  #[test]
+  fn an_empty_database_loads_an_empty_state() {
+    let mut textual_error = TextualError::new("Testing loading the state");
+    let database = Database::open_in_memory(&mut textual_error).unwrap();
+
+    let state = Daemon::load_state(&create_test_configuration(), &database, &mut textual_error).unwrap();
+
+    assert_eq!(state.vaults.get_all_vaults().count(), 0);
+    assert_eq!(state.user_profiles.get_all_profiles().count(), 0);
+    assert_eq!(state.vaults_stats.get_data_size(), 0);
+  }
+
+  fn create_locked_daemon(login_policy: LoginPolicy) -> Daemon {
+    let mut textual_error = TextualError::new("Creating a daemon for a test");
+    let mut daemon = Daemon::open_in_memory(&mut textual_error).unwrap();
+    let now = daemon.state.monotonic_clock.now();
+
+    let mut screen_access_regulation = ScreenAccessRegulation::default();
+    screen_access_regulation.always_rules.rules.insert(
+      UuidV4::generate(),
+      AlwaysRule::create(RuleEnabler::CountdownAfterPlea(CountdownAfterPleaConditional::create(Duration::HOUR))),
+    );
+
+    let profile = UserProfile {
+      name: UserProfileName::new("Alex".to_string()).unwrap(),
+      user_id: UserId::new(1000),
+      user_name: UserName::new(CString::new("alex").unwrap()),
+      uptime_clock: UserUptimeClock::construct(
+        false,
+        now,
+        Duration::zero(),
+        now,
+        Duration::zero(),
+        now,
+        Duration::HOUR,
+      ),
+      device_access_regulation: DeviceAccessRegulation::new(),
+      screen_access_regulation,
+      internet_access_regulation: InternetAccessRegulation::new(),
+      application_regulations: ApplicationRegulations::new(),
+      login_policy,
+      rules_stats: RulesStats::new(RulesStats::DEFAULT_MAXIMUM_RULES_NUMBER),
+      is_orphaned: false,
+    };
+
+    daemon.state.user_profiles.add_user(UuidV4::generate(), profile);
+    daemon
+  }
+
+  #[test]
+  fn exempt_sessions_get_a_deadline_without_an_end() {
+    let daemon = create_locked_daemon(LoginPolicy {
+      exempt_classes: vec![LoginClass::Remote],
+      ..LoginPolicy::default()
+    });
+    let user_name = UserName::new(CString::new("alex").unwrap());
+
+    let remote = daemon.get_session_deadline(&user_name, Some(LoginClass::Remote)).unwrap();
+    assert_eq!(remote.ends_at, None);
+
+    let graphical = daemon.get_session_deadline(&user_name, Some(LoginClass::Graphical)).unwrap();
+    assert!(graphical.ends_at.is_some());
+
+    // Older modules don't say.
+    let unknown = daemon.get_session_deadline(&user_name, None).unwrap();
+    assert!(unknown.ends_at.is_some());
+  }
+
+  fn create_login_context(service: &str, requesting_user_id: Option<u32>) -> LoginContext {
+    LoginContext {
+      service: service.to_string(),
+      tty: None,
+      remote_host: None,
+      requesting_user_id: requesting_user_id.map(UserId::new),
+    }
+  }
+
+  #[test]
+  fn logins_are_refused_according_to_the_login_policy() {
+    let alex = UserName::new(CString::new("alex").unwrap());
+    let root = UserName::new(CString::new("root").unwrap());
+
+    let exempting_remote = LoginPolicy {
+      exempt_classes: vec![LoginClass::Remote],
+      ..LoginPolicy::default()
+    };
+    let refusing_remote = LoginPolicy {
+      exempt_classes: vec![LoginClass::Remote],
+      refused_classes: vec![LoginClass::Remote],
+      ..LoginPolicy::default()
+    };
+    let refusing_switching = LoginPolicy {
+      refuse_switching_users_while_blocked: true,
+      ..LoginPolicy::default()
+    };
+
+    let blocked = Some(LoginRefusalReason::Blocked(BlockReason::Lock));
+    let requesting_user_blocked = Some(LoginRefusalReason::RequestingUserBlocked(BlockReason::Lock));
+
+    let cases = [
+      (LoginPolicy::default(), &alex, "gdm-password", None, blocked),
+      (LoginPolicy::default(), &alex, "sshd", None, blocked),
+      (exempting_remote.clone(), &alex, "sshd", None, None),
+      (exempting_remote, &alex, "gdm-password", None, blocked),
+      (refusing_remote.clone(), &alex, "sshd", None, Some(LoginRefusalReason::RefusedLoginClass(LoginClass::Remote))),
+      (refusing_remote, &alex, "login", None, blocked),
+      // Accounts without a profile aren't regulated.
+      (LoginPolicy::default(), &root, "gdm-password", None, None),
+      (LoginPolicy::default(), &root, "su", Some(1000), None),
+      (refusing_switching.clone(), &root, "su", Some(1000), requesting_user_blocked),
+      (refusing_switching.clone(), &root, "sudo", Some(1000), requesting_user_blocked),
+      // Only switching is refused for the requesting user's sake.
+      (refusing_switching.clone(), &root, "sshd", Some(1000), None),
+      (refusing_switching, &root, "su", Some(1001), None),
+    ];
+
+    for (login_policy, user_name, service, requesting_user_id, reason) in cases {
+      let daemon = create_locked_daemon(login_policy.clone());
+      let refusal = daemon.get_login_refusal(user_name, &create_login_context(service, requesting_user_id));
+
+      assert_eq!(
+        refusal.map(|refusal| refusal.reason),
+        reason,
+        "{login_policy:?}, {user_name:?} through {service}, requested by {requesting_user_id:?}",
+      );
+    }
+  }
+}
@@ -0,0 +1,243 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use crate::x::IsTextualError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockedDomainResponse {
+  NxDomain,
+  NullAddress,
+}
+
+// A profile's domain filtering settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainFilter {
+  // Names of the blocklist files, without their extension.
+  pub blocklists: Vec<String>,
+  pub blocked_domain_response: BlockedDomainResponse,
+  pub enforce_safe_search: bool,
+}
+
+impl Default for DomainFilter {
+  fn default() -> Self {
+    Self {
+      blocklists: Vec::new(),
+      blocked_domain_response: BlockedDomainResponse::NxDomain,
+      enforce_safe_search: false,
+    }
+  }
+}
+
+impl DomainFilter {
+  // Blocks what either filter blocks. The response to blocked domains
+  // stays this filter's.
+  pub fn merge(&mut self, other: &Self) {
+    for blocklist in &other.blocklists {
+      if !self.blocklists.contains(blocklist) {
+        self.blocklists.push(blocklist.clone());
+      }
+    }
+
+    self.enforce_safe_search |= other.enforce_safe_search;
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsDecision {
+  Forward,
+  Block(BlockedDomainResponse),
+  Rewrite(&'static str),
+}
+
+// Search engines and the endpoints that force SafeSearch on them.
+const SAFE_SEARCH_ENDPOINTS: &[(&str, &str)] = &[
+  ("google.com", "forcesafesearch.google.com"),
+  ("www.google.com", "forcesafesearch.google.com"),
+  ("bing.com", "strict.bing.com"),
+  ("www.bing.com", "strict.bing.com"),
+  ("duckduckgo.com", "safe.duckduckgo.com"),
+  ("www.duckduckgo.com", "safe.duckduckgo.com"),
+  ("start.duckduckgo.com", "safe.duckduckgo.com"),
+  ("www.youtube.com", "restrict.youtube.com"),
+  ("m.youtube.com", "restrict.youtube.com"),
+  ("youtubei.googleapis.com", "restrict.youtube.com"),
+  ("youtube.googleapis.com", "restrict.youtube.com"),
+  ("www.youtube-nocookie.com", "restrict.youtube.com"),
+];
+
+pub fn get_safe_search_endpoint(name: &str) -> Option<&'static str> {
+  SAFE_SEARCH_ENDPOINTS
+    .iter()
+    .find(|(domain, _)| *domain == name)
+    .map(|(_, endpoint)| *endpoint)
+}
+
+#[derive(Debug)]
+struct DomainBlocklist {
+  domains: HashSet<String>,
+  modified_at: SystemTime,
+}
+
+// Blocklist files from a directory, one domain per line. Hosts-file
+// lines like "0.0.0.0 example.com" and "#" comments are understood too.
+// A listed domain blocks its subdomains as well.
+#[derive(Debug)]
+pub struct DomainBlocklists {
+  directory: PathBuf,
+  blocklists: HashMap<String, DomainBlocklist>,
+}
+
+impl DomainBlocklists {
+  pub const EXTENSION: &'static str = "txt";
+
+  pub fn create(directory: PathBuf) -> Self {
+    Self {
+      directory,
+      blocklists: HashMap::new(),
+    }
+  }
+
+  pub fn get_directory(&self) -> &Path {
+    &self.directory
+  }
+
+  pub fn contains_blocklist(&self, name: &str) -> bool {
+    self.blocklists.contains_key(name)
+  }
+
+  pub fn is_blocked(&self, blocklist_names: &[String], name: &str) -> bool {
+    let blocklists: Vec<_> = blocklist_names
+      .iter()
+      .filter_map(|blocklist_name| self.blocklists.get(blocklist_name))
+      .collect();
+
+    if blocklists.is_empty() {
+      return false;
+    }
+
+    let mut suffix = name;
+    loop {
+      if blocklists.iter().any(|blocklist| blocklist.domains.contains(suffix)) {
+        return true;
+      }
+
+      match suffix.split_once('.') {
+        Some((_, parent)) => {
+          suffix = parent;
+        }
+        None => {
+          return false;
+        }
+      }
+    }
+  }
+
+  pub fn decide(&self, filter: &DomainFilter, name: &str) -> DnsDecision {
+    if self.is_blocked(&filter.blocklists, name) {
+      return DnsDecision::Block(filter.blocked_domain_response);
+    }
+
+    if filter.enforce_safe_search && let Some(endpoint) = get_safe_search_endpoint(name) {
+      return DnsDecision::Rewrite(endpoint);
+    }
+
+    DnsDecision::Forward
+  }
+
+  // Rereads the blocklists whose files changed since the last reload
+  // and forgets those whose files were removed. A missing directory
+  // just means there are no blocklists.
+  pub fn reload(&mut self, textual_error: &mut impl IsTextualError) -> Result<(), ()> {
+    let entries = match fs::read_dir(&self.directory) {
+      Ok(entries) => {
+        entries
+      }
+      Err(error) if error.kind() == ErrorKind::NotFound => {
+        self.blocklists.clear();
+        return Ok(());
+      }
+      Err(error) => {
+        textual_error.change_context("Reloading domain blocklists");
+        textual_error.add_message("Failed to read the blocklists directory");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Directory", self.directory.display());
+        return Err(());
+      }
+    };
+
+    let mut present = HashSet::new();
+    let mut failed = false;
+
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if path.extension().and_then(|extension| extension.to_str()) != Some(Self::EXTENSION) {
+        continue;
+      }
+      let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+        continue;
+      };
+
+      let name = name.to_string();
+      present.insert(name.clone());
+
+      let Ok(modified_at) = entry.metadata().and_then(|metadata| metadata.modified()) else {
+        continue;
+      };
+
+      let is_up_to_date = self
+        .blocklists
+        .get(&name)
+        .is_some_and(|blocklist| blocklist.modified_at == modified_at);
+
+      if is_up_to_date {
+        continue;
+      }
+
+      match fs::read_to_string(&path) {
+        Ok(content) => {
+          self.blocklists.insert(name, DomainBlocklist {
+            domains: parse_blocklist(&content),
+            modified_at,
+          });
+        }
+        Err(error) => {
+          // Keep the previous version of the list, if any.
+          textual_error.change_context("Reloading domain blocklists");
+          textual_error.add_message("Failed to read a blocklist file");
+          textual_error.add_attachement_display("Io error", error);
+          textual_error.add_attachement_display("Path", path.display());
+          failed = true;
+        }
+      }
+    }
+
+    self.blocklists.retain(|name, _| present.contains(name));
+
+    if failed {
+      Err(())
+    } else {
+      Ok(())
+    }
+  }
+}
+
+fn parse_blocklist(content: &str) -> HashSet<String> {
+  content
+    .lines()
+    .filter_map(|line| {
+      let line = line.split('#').next()?.trim();
+      // The domain is the last field of hosts-file lines.
+      let domain = line.split_whitespace().last()?;
+      let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+
+      if domain.is_empty() || domain == "localhost" {
+        None
+      } else {
+        Some(domain)
+      }
+    })
+    .collect()
+}
@@ -0,0 +1,288 @@
+// Just enough of the DNS wire format (RFC 1035) to filter queries:
+// we parse the question of incoming queries and build responses for the
+// ones we answer ourselves. Everything else is forwarded untouched.
+
+pub const RECORD_TYPE_A: u16 = 1;
+pub const RECORD_TYPE_AAAA: u16 = 28;
+pub const RECORD_CLASS_IN: u16 = 1;
+
+const HEADER_LENGTH: usize = 12;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_OPCODE: u16 = 0x7800;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const FLAG_RECURSION_AVAILABLE: u16 = 0x0080;
+
+pub const RESPONSE_CODE_NO_ERROR: u16 = 0;
+pub const RESPONSE_CODE_SERVER_FAILURE: u16 = 2;
+pub const RESPONSE_CODE_NAME_ERROR: u16 = 3;
+
+const MAXIMUM_NAME_LENGTH: usize = 255;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsQuestion {
+  // Lowercase, without the trailing dot.
+  pub name: String,
+  pub record_type: u16,
+  pub record_class: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct DnsQuery {
+  pub id: u16,
+  pub flags: u16,
+  pub question: DnsQuestion,
+  // The question's bytes, copied into our responses.
+  question_bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsAddressRecord {
+  pub record_type: u16,
+  pub time_to_live: u32,
+  pub data: Vec<u8>,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+  let bytes = bytes.get(offset..offset + 2)?;
+  Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+  let bytes = bytes.get(offset..offset + 4)?;
+  Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Reads an uncompressed name, as found in the question of a query.
+fn read_name(bytes: &[u8], mut offset: usize) -> Option<(String, usize)> {
+  let mut name = String::new();
+
+  loop {
+    let length = *bytes.get(offset)? as usize;
+    offset += 1;
+
+    if length == 0 {
+      break;
+    }
+    // Compression pointers and the reserved label types.
+    if length & 0xC0 != 0 {
+      return None;
+    }
+
+    let label = bytes.get(offset..offset + length)?;
+    offset += length;
+
+    if !name.is_empty() {
+      name.push('.');
+    }
+    for byte in label {
+      name.push(byte.to_ascii_lowercase() as char);
+    }
+
+    if name.len() > MAXIMUM_NAME_LENGTH {
+      return None;
+    }
+  }
+
+  Some((name, offset))
+}
+
+// Skips a possibly compressed name, as found in resource records.
+fn skip_name(bytes: &[u8], mut offset: usize) -> Option<usize> {
+  loop {
+    let length = *bytes.get(offset)? as usize;
+
+    if length == 0 {
+      return Some(offset + 1);
+    }
+    if length & 0xC0 == 0xC0 {
+      bytes.get(offset + 1)?;
+      return Some(offset + 2);
+    }
+    if length & 0xC0 != 0 {
+      return None;
+    }
+
+    offset += 1 + length;
+  }
+}
+
+fn write_name(buffer: &mut Vec<u8>, name: &str) -> Option<()> {
+  if name.len() > MAXIMUM_NAME_LENGTH {
+    return None;
+  }
+
+  for label in name.split('.').filter(|label| !label.is_empty()) {
+    if label.len() > 63 {
+      return None;
+    }
+    buffer.push(label.len() as u8);
+    buffer.extend_from_slice(label.as_bytes());
+  }
+
+  buffer.push(0);
+  Some(())
+}
+
+fn write_header(
+  buffer: &mut Vec<u8>,
+  id: u16,
+  flags: u16,
+  answer_count: u16,
+) {
+  buffer.extend_from_slice(&id.to_be_bytes());
+  buffer.extend_from_slice(&flags.to_be_bytes());
+  // One question, the answers, no authority and no additional records.
+  buffer.extend_from_slice(&1u16.to_be_bytes());
+  buffer.extend_from_slice(&answer_count.to_be_bytes());
+  buffer.extend_from_slice(&0u16.to_be_bytes());
+  buffer.extend_from_slice(&0u16.to_be_bytes());
+}
+
+impl DnsQuery {
+  // Only standard queries with exactly one question are accepted.
+  pub fn parse(bytes: &[u8]) -> Option<Self> {
+    let id = read_u16(bytes, 0)?;
+    let flags = read_u16(bytes, 2)?;
+    let question_count = read_u16(bytes, 4)?;
+
+    if flags & FLAG_RESPONSE != 0 || flags & FLAG_OPCODE != 0 || question_count != 1 {
+      return None;
+    }
+
+    let (name, offset) = read_name(bytes, HEADER_LENGTH)?;
+    let record_type = read_u16(bytes, offset)?;
+    let record_class = read_u16(bytes, offset + 2)?;
+
+    Some(Self {
+      id,
+      flags,
+      question: DnsQuestion {
+        name,
+        record_type,
+        record_class,
+      },
+      question_bytes: bytes[HEADER_LENGTH..offset + 4].to_vec(),
+    })
+  }
+
+  fn response_flags(&self, response_code: u16) -> u16 {
+    FLAG_RESPONSE
+    | (self.flags & FLAG_RECURSION_DESIRED)
+    | FLAG_RECURSION_AVAILABLE
+    | response_code
+  }
+
+  pub fn create_error_response(&self, response_code: u16) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(HEADER_LENGTH + self.question_bytes.len());
+    write_header(&mut buffer, self.id, self.response_flags(response_code), 0);
+    buffer.extend_from_slice(&self.question_bytes);
+    buffer
+  }
+
+  // Answers the question with the given addresses, keeping only those
+  // of the type that was asked for. Answering an A query with no
+  // addresses yields an empty NOERROR response.
+  pub fn create_address_response(&self, records: &[DnsAddressRecord]) -> Vec<u8> {
+    let records: Vec<_> = records
+      .iter()
+      .filter(|record| record.record_type == self.question.record_type)
+      .take(u16::MAX as usize)
+      .collect();
+
+    let mut buffer = Vec::new();
+    write_header(
+      &mut buffer,
+      self.id,
+      self.response_flags(RESPONSE_CODE_NO_ERROR),
+      records.len() as u16,
+    );
+    buffer.extend_from_slice(&self.question_bytes);
+
+    for record in records {
+      // A pointer to the name in the question.
+      buffer.extend_from_slice(&[0xC0, HEADER_LENGTH as u8]);
+      buffer.extend_from_slice(&record.record_type.to_be_bytes());
+      buffer.extend_from_slice(&RECORD_CLASS_IN.to_be_bytes());
+      buffer.extend_from_slice(&record.time_to_live.to_be_bytes());
+      buffer.extend_from_slice(&(record.data.len() as u16).to_be_bytes());
+      buffer.extend_from_slice(&record.data);
+    }
+
+    buffer
+  }
+
+  // The response that points a blocked name nowhere.
+  pub fn create_null_address_response(&self) -> Vec<u8> {
+    let data = match self.question.record_type {
+      RECORD_TYPE_A => {
+        vec![0; 4]
+      }
+      RECORD_TYPE_AAAA => {
+        vec![0; 16]
+      }
+      _ => {
+        return self.create_address_response(&[]);
+      }
+    };
+
+    self.create_address_response(&[DnsAddressRecord {
+      record_type: self.question.record_type,
+      time_to_live: 60,
+      data,
+    }])
+  }
+}
+
+// Creates a recursive query for another name, used to resolve the
+// endpoints we rewrite names to.
+pub fn create_query(id: u16, name: &str, record_type: u16) -> Option<Vec<u8>> {
+  let mut buffer = Vec::new();
+  write_header(&mut buffer, id, FLAG_RECURSION_DESIRED, 0);
+  write_name(&mut buffer, name)?;
+  buffer.extend_from_slice(&record_type.to_be_bytes());
+  buffer.extend_from_slice(&RECORD_CLASS_IN.to_be_bytes());
+  Some(buffer)
+}
+
+pub fn get_response_id(bytes: &[u8]) -> Option<u16> {
+  read_u16(bytes, 0)
+}
+
+// Collects the A and AAAA records of a response's answer section,
+// whatever name they're for, since following CNAME chains is the
+// upstream's job.
+pub fn read_address_records(bytes: &[u8]) -> Option<Vec<DnsAddressRecord>> {
+  let flags = read_u16(bytes, 2)?;
+  if flags & FLAG_RESPONSE == 0 || flags & 0x000F != RESPONSE_CODE_NO_ERROR {
+    return None;
+  }
+
+  let question_count = read_u16(bytes, 4)?;
+  let answer_count = read_u16(bytes, 6)?;
+
+  let mut offset = HEADER_LENGTH;
+  for _ in 0..question_count {
+    offset = skip_name(bytes, offset)? + 4;
+  }
+
+  let mut records = Vec::new();
+  for _ in 0..answer_count {
+    offset = skip_name(bytes, offset)?;
+    let record_type = read_u16(bytes, offset)?;
+    let time_to_live = read_u32(bytes, offset + 4)?;
+    let data_length = read_u16(bytes, offset + 8)? as usize;
+    let data = bytes.get(offset + 10..offset + 10 + data_length)?;
+    offset += 10 + data_length;
+
+    if record_type == RECORD_TYPE_A || record_type == RECORD_TYPE_AAAA {
+      records.push(DnsAddressRecord {
+        record_type,
+        time_to_live,
+        data: data.to_vec(),
+      });
+    }
+  }
+
+  Some(records)
+}
@@ -0,0 +1,11 @@
+mod message;
+pub use message::*;
+
+mod filter;
+pub use filter::*;
+
+mod resolver;
+pub use resolver::*;
+
+mod redirector;
+pub use redirector::*;
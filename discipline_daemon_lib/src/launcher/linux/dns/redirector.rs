@@ -0,0 +1,42 @@
+use super::super::{UserId, generate_dns_redirect_ruleset};
+
+// Keeps the nftables rules that send profiled users' DNS queries to
+// our resolver in line with the profiles, applying them only when they
+// change.
+#[derive(Debug)]
+pub struct DnsRedirector {
+  // None when the resolver is disabled, in which case nothing is
+  // redirected.
+  port: Option<u16>,
+  applied_ruleset: Option<String>,
+}
+
+impl DnsRedirector {
+  pub const TABLE_NAME: &'static str = "discipline_dns";
+
+  pub fn create(port: Option<u16>) -> Self {
+    Self {
+      port,
+      applied_ruleset: None,
+    }
+  }
+
+  pub fn generate_ruleset(&self, user_ids: &[UserId]) -> String {
+    match self.port {
+      Some(port) => {
+        generate_dns_redirect_ruleset(Self::TABLE_NAME, port, user_ids)
+      }
+      None => {
+        generate_dns_redirect_ruleset(Self::TABLE_NAME, 0, &[])
+      }
+    }
+  }
+
+  pub fn needs_applying(&self, ruleset: &str) -> bool {
+    self.applied_ruleset.as_deref() != Some(ruleset)
+  }
+
+  pub fn on_ruleset_applied(&mut self, ruleset: String) {
+    self.applied_ruleset = Some(ruleset);
+  }
+}
@@ -0,0 +1,587 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::{Mutex, Semaphore, watch};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use crate::x::{Duration, IsTextualError};
+use super::super::{Daemon, SocketProtocol, UserId, find_socket_owner};
+use super::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsResolverConfiguration {
+  // The loopback port profiled users' DNS traffic is redirected to.
+  pub port: u16,
+  pub upstream: SocketAddr,
+  pub upstream_timeout: Duration,
+  pub blocklists_directory: PathBuf,
+}
+
+impl Default for DnsResolverConfiguration {
+  fn default() -> Self {
+    Self {
+      port: 5300,
+      upstream: SocketAddr::from((Ipv4Addr::new(127, 0, 0, 53), 53)),
+      upstream_timeout: Duration::from_milliseconds(3000),
+      blocklists_directory: PathBuf::from("/etc/discipline/blocklists"),
+    }
+  }
+}
+
+// How a query reached us, which is how it's forwarded too. Clients
+// retry over TCP when a UDP response comes back truncated, so their
+// query going upstream over UDP would only be truncated again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsTransport {
+  Udp,
+  Tcp,
+}
+
+// A forwarding resolver that filters queries according to the profile
+// of the user who sent them.
+//
+// The firewall redirects the port-53 traffic of profiled users to us,
+// UDP and TCP alike, which keeps their source address, so the querying
+// socket's owner can be looked up in /proc/net.
+pub struct Resolver {
+  socket: Arc<UdpSocket>,
+  listener: TcpListener,
+  upstream: SocketAddr,
+  upstream_timeout: Duration,
+  // Shared by UDP queries and TCP connections.
+  semaphore: Arc<Semaphore>,
+  queries: JoinSet<()>,
+  connections: JoinSet<()>,
+}
+
+impl Resolver {
+  const MAXIMUM_CONCURRENT_QUERIES: usize = 256;
+  const MAXIMUM_MESSAGE_LENGTH: usize = 4096;
+  // Clients may keep a TCP connection open for more queries, but not
+  // forever.
+  const TCP_IDLE_TIMEOUT: Duration = Duration::from_milliseconds(10 * 1000);
+
+  pub async fn bind(
+    configuration: &DnsResolverConfiguration,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Self, ()> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, configuration.port));
+
+    let socket = match UdpSocket::bind(address).await {
+      Ok(socket) => {
+        socket
+      }
+      Err(error) => {
+        textual_error.change_context("Binding the Discipline Linux Daemon DNS resolver");
+        textual_error.add_message("An io error occured while binding the UdpSocket");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Address", address);
+        return Err(());
+      }
+    };
+
+    let listener = match TcpListener::bind(address).await {
+      Ok(listener) => {
+        listener
+      }
+      Err(error) => {
+        textual_error.change_context("Binding the Discipline Linux Daemon DNS resolver");
+        textual_error.add_message("An io error occured while binding the TcpListener");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Address", address);
+        return Err(());
+      }
+    };
+
+    Ok(Self {
+      socket: Arc::new(socket),
+      listener,
+      upstream: configuration.upstream,
+      upstream_timeout: configuration.upstream_timeout,
+      semaphore: Arc::new(Semaphore::new(Self::MAXIMUM_CONCURRENT_QUERIES)),
+      queries: JoinSet::new(),
+      connections: JoinSet::new(),
+    })
+  }
+
+  // Answers queries until `shutdown` changes, then waits for the UDP
+  // queries in flight. TCP connections may sit idle, so they're cut.
+  pub async fn serve(
+    mut self,
+    daemon: Arc<Mutex<Daemon>>,
+    mut shutdown: watch::Receiver<bool>,
+  ) {
+    let mut buffer = vec![0; Self::MAXIMUM_MESSAGE_LENGTH];
+
+    loop {
+      tokio::select! {
+        _ = shutdown.changed() => {
+          break;
+        }
+        // Reap finished queries so the sets don't grow unbounded.
+        Some(_) = self.queries.join_next(), if !self.queries.is_empty() => {}
+        Some(_) = self.connections.join_next(), if !self.connections.is_empty() => {}
+        received = self.socket.recv_from(&mut buffer) => {
+          let (length, client) = match received {
+            Ok(received) => {
+              received
+            }
+            Err(error) => {
+              // TODO: Use a proper logging mechanism.
+              eprintln!("Discipline Linux Daemon DNS resolver: Failed to receive a query: {error}");
+              continue;
+            }
+          };
+
+          // When we're saturated, the client retries.
+          let Ok(permit) = Arc::clone(&self.semaphore).try_acquire_owned() else {
+            continue;
+          };
+
+          let bytes = buffer[..length].to_vec();
+          let daemon = Arc::clone(&daemon);
+          let socket = Arc::clone(&self.socket);
+          let upstream = self.upstream;
+          let upstream_timeout = self.upstream_timeout;
+
+          self.queries.spawn(async move {
+            let _permit = permit;
+            handle_query(&daemon, &socket, client, &bytes, upstream, upstream_timeout).await;
+          });
+        }
+        connection = self.listener.accept() => {
+          let (stream, client) = match connection {
+            Ok(connection) => {
+              connection
+            }
+            Err(error) => {
+              // TODO: Use a proper logging mechanism.
+              eprintln!("Discipline Linux Daemon DNS resolver: Failed to accept a connection: {error}");
+              continue;
+            }
+          };
+
+          let Ok(permit) = Arc::clone(&self.semaphore).try_acquire_owned() else {
+            continue;
+          };
+
+          let daemon = Arc::clone(&daemon);
+          let upstream = self.upstream;
+          let upstream_timeout = self.upstream_timeout;
+
+          self.connections.spawn(async move {
+            let _permit = permit;
+            handle_connection(&daemon, stream, client, upstream, upstream_timeout).await;
+          });
+        }
+      }
+    }
+
+    while self.queries.join_next().await.is_some() {}
+    self.connections.shutdown().await;
+  }
+}
+
+async fn handle_query(
+  daemon: &Mutex<Daemon>,
+  socket: &UdpSocket,
+  client: SocketAddr,
+  bytes: &[u8],
+  upstream: SocketAddr,
+  upstream_timeout: Duration,
+) {
+  let Some(query) = DnsQuery::parse(bytes) else {
+    return;
+  };
+
+  let user_id = find_socket_owner(SocketProtocol::Udp, client);
+  let decision = decide(daemon, user_id, &query).await;
+
+  let response = resolve(&query, bytes, &decision, DnsTransport::Udp, upstream, upstream_timeout).await;
+  let _ = socket.send_to(&response, client).await;
+}
+
+// Answers queries on a TCP connection one after another, each framed
+// by a two byte length, until the client hangs up or goes quiet.
+async fn handle_connection(
+  daemon: &Mutex<Daemon>,
+  mut stream: TcpStream,
+  client: SocketAddr,
+  upstream: SocketAddr,
+  upstream_timeout: Duration,
+) {
+  let user_id = find_socket_owner(SocketProtocol::Tcp, client);
+
+  loop {
+    let Ok(Some(bytes)) = timeout(
+      Resolver::TCP_IDLE_TIMEOUT.to_std_duration(),
+      read_tcp_message(&mut stream),
+    ).await else {
+      return;
+    };
+
+    let Some(query) = DnsQuery::parse(&bytes) else {
+      return;
+    };
+
+    let decision = decide(daemon, user_id, &query).await;
+    let response = resolve(&query, &bytes, &decision, DnsTransport::Tcp, upstream, upstream_timeout).await;
+
+    if write_tcp_message(&mut stream, &response).await.is_none() {
+      return;
+    }
+  }
+}
+
+async fn decide(
+  daemon: &Mutex<Daemon>,
+  user_id: Option<UserId>,
+  query: &DnsQuery,
+) -> DnsDecision {
+  let daemon = daemon.lock().await;
+
+  user_id
+    .and_then(|user_id| daemon.state.user_profiles.get_profile_given_user_id(user_id))
+    .map(|profile| {
+      daemon
+        .state
+        .domain_blocklists
+        .decide(&profile.internet_access_regulation.domain_filter, &query.question.name)
+    })
+    .unwrap_or(DnsDecision::Forward)
+}
+
+pub async fn resolve(
+  query: &DnsQuery,
+  bytes: &[u8],
+  decision: &DnsDecision,
+  transport: DnsTransport,
+  upstream: SocketAddr,
+  upstream_timeout: Duration,
+) -> Vec<u8> {
+  match decision {
+    DnsDecision::Block(BlockedDomainResponse::NxDomain) => {
+      query.create_error_response(RESPONSE_CODE_NAME_ERROR)
+    }
+    DnsDecision::Block(BlockedDomainResponse::NullAddress) => {
+      query.create_null_address_response()
+    }
+    DnsDecision::Forward => {
+      forward(bytes, query.id, transport, upstream, upstream_timeout)
+        .await
+        .unwrap_or_else(|| query.create_error_response(RESPONSE_CODE_SERVER_FAILURE))
+    }
+    DnsDecision::Rewrite(endpoint) => {
+      let record_type = query.question.record_type;
+      if record_type != RECORD_TYPE_A && record_type != RECORD_TYPE_AAAA {
+        return query.create_address_response(&[]);
+      }
+
+      // The endpoint's addresses are served as the original name's,
+      // so clients keep talking to the name they asked for.
+      let records = match create_query(query.id, endpoint, record_type) {
+        Some(endpoint_query) => {
+          forward(&endpoint_query, query.id, transport, upstream, upstream_timeout)
+            .await
+            .and_then(|response| read_address_records(&response))
+        }
+        None => {
+          None
+        }
+      };
+
+      match records {
+        Some(records) => {
+          query.create_address_response(&records)
+        }
+        None => {
+          query.create_error_response(RESPONSE_CODE_SERVER_FAILURE)
+        }
+      }
+    }
+  }
+}
+
+async fn forward(
+  bytes: &[u8],
+  id: u16,
+  transport: DnsTransport,
+  upstream: SocketAddr,
+  upstream_timeout: Duration,
+) -> Option<Vec<u8>> {
+  match transport {
+    DnsTransport::Udp => {
+      forward_over_udp(bytes, id, upstream, upstream_timeout).await
+    }
+    DnsTransport::Tcp => {
+      forward_over_tcp(bytes, id, upstream, upstream_timeout).await
+    }
+  }
+}
+
+// Sends a query upstream from a fresh socket, so each query gets a new
+// random source port, and waits for the matching response.
+async fn forward_over_udp(
+  bytes: &[u8],
+  id: u16,
+  upstream: SocketAddr,
+  upstream_timeout: Duration,
+) -> Option<Vec<u8>> {
+  let local_address: SocketAddr = match upstream {
+    SocketAddr::V4(_) => {
+      (Ipv4Addr::UNSPECIFIED, 0).into()
+    }
+    SocketAddr::V6(_) => {
+      (Ipv6Addr::UNSPECIFIED, 0).into()
+    }
+  };
+
+  let socket = UdpSocket::bind(local_address).await.ok()?;
+  socket.send_to(bytes, upstream).await.ok()?;
+
+  let mut buffer = vec![0; Resolver::MAXIMUM_MESSAGE_LENGTH];
+
+  timeout(upstream_timeout.to_std_duration(), async {
+    loop {
+      let (length, sender) = socket.recv_from(&mut buffer).await.ok()?;
+      if sender == upstream && get_response_id(&buffer[..length]) == Some(id) {
+        return Some(buffer[..length].to_vec());
+      }
+    }
+  })
+  .await
+  .ok()
+  .flatten()
+}
+
+// Sends a query upstream over a connection of its own.
+async fn forward_over_tcp(
+  bytes: &[u8],
+  id: u16,
+  upstream: SocketAddr,
+  upstream_timeout: Duration,
+) -> Option<Vec<u8>> {
+  timeout(upstream_timeout.to_std_duration(), async {
+    let mut stream = TcpStream::connect(upstream).await.ok()?;
+    write_tcp_message(&mut stream, bytes).await?;
+
+    let response = read_tcp_message(&mut stream).await?;
+    if get_response_id(&response) == Some(id) {
+      Some(response)
+    } else {
+      None
+    }
+  })
+  .await
+  .ok()
+  .flatten()
+}
+
+async fn read_tcp_message(stream: &mut (impl AsyncRead + Unpin)) -> Option<Vec<u8>> {
+  let length = stream.read_u16().await.ok()?;
+
+  let mut message = vec![0; usize::from(length)];
+  stream.read_exact(&mut message).await.ok()?;
+  Some(message)
+}
+
+async fn write_tcp_message(stream: &mut (impl AsyncWrite + Unpin), message: &[u8]) -> Option<()> {
+  let length = u16::try_from(message.len()).ok()?;
+
+  let mut framed = Vec::with_capacity(2 + message.len());
+  framed.extend_from_slice(&length.to_be_bytes());
+  framed.extend_from_slice(message);
+  stream.write_all(&framed).await.ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use super::*;
+
+  const UPSTREAM_ADDRESS: [u8; 4] = [93, 184, 216, 34];
+
+  // Answers every A query with the same address.
+  async fn spawn_upstream() -> SocketAddr {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let address = socket.local_addr().unwrap();
+
+    tokio::spawn(async move {
+      let mut buffer = vec![0; 512];
+      loop {
+        let Ok((length, client)) = socket.recv_from(&mut buffer).await else {
+          return;
+        };
+        let query = DnsQuery::parse(&buffer[..length]).unwrap();
+        let response = query.create_address_response(&[DnsAddressRecord {
+          record_type: RECORD_TYPE_A,
+          time_to_live: 300,
+          data: UPSTREAM_ADDRESS.to_vec(),
+        }]);
+        let _ = socket.send_to(&response, client).await;
+      }
+    });
+
+    address
+  }
+
+  // Like `spawn_upstream`, over TCP.
+  async fn spawn_tcp_upstream() -> SocketAddr {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+      loop {
+        let Ok((mut stream, _)) = listener.accept().await else {
+          return;
+        };
+        let Some(bytes) = read_tcp_message(&mut stream).await else {
+          continue;
+        };
+        let query = DnsQuery::parse(&bytes).unwrap();
+        let response = query.create_address_response(&[DnsAddressRecord {
+          record_type: RECORD_TYPE_A,
+          time_to_live: 300,
+          data: UPSTREAM_ADDRESS.to_vec(),
+        }]);
+        let _ = write_tcp_message(&mut stream, &response).await;
+      }
+    });
+
+    address
+  }
+
+  fn create_test_query(name: &str) -> (DnsQuery, Vec<u8>) {
+    let bytes = create_query(0x1234, name, RECORD_TYPE_A).unwrap();
+    (DnsQuery::parse(&bytes).unwrap(), bytes)
+  }
+
+  fn get_response_code(response: &[u8]) -> u16 {
+    u16::from_be_bytes([response[2], response[3]]) & 0x000F
+  }
+
+  #[tokio::test]
+  async fn allowed_names_are_forwarded() {
+    let upstream = spawn_upstream().await;
+    let (query, bytes) = create_test_query("example.com");
+
+    let response = resolve(&query, &bytes, &DnsDecision::Forward, DnsTransport::Udp, upstream, Duration::SECOND).await;
+
+    assert_eq!(get_response_id(&response), Some(0x1234));
+    assert_eq!(
+      read_address_records(&response).unwrap()[0].data,
+      UPSTREAM_ADDRESS,
+    );
+  }
+
+  #[tokio::test]
+  async fn tcp_queries_are_forwarded_over_tcp() {
+    // Only a TCP upstream listens, so forwarding over UDP would fail.
+    let upstream = spawn_tcp_upstream().await;
+    let (query, bytes) = create_test_query("example.com");
+
+    let response = resolve(&query, &bytes, &DnsDecision::Forward, DnsTransport::Tcp, upstream, Duration::SECOND).await;
+
+    assert_eq!(get_response_id(&response), Some(0x1234));
+    assert_eq!(
+      read_address_records(&response).unwrap()[0].data,
+      UPSTREAM_ADDRESS,
+    );
+  }
+
+  #[tokio::test]
+  async fn tcp_connections_answer_length_prefixed_queries() {
+    let upstream = spawn_tcp_upstream().await;
+    let mut textual_error = crate::x::TextualError::new("Test");
+    let daemon = Mutex::new(Daemon::open_in_memory(&mut textual_error).unwrap());
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+    let (stream, peer) = listener.accept().await.unwrap();
+
+    let (_, bytes) = create_test_query("example.com");
+    let (_, other_bytes) = create_test_query("example.org");
+
+    let mut framed = (bytes.len() as u16).to_be_bytes().to_vec();
+    framed.extend(&bytes);
+    framed.extend((other_bytes.len() as u16).to_be_bytes());
+    framed.extend(&other_bytes);
+    client.write_all(&framed).await.unwrap();
+    client.shutdown().await.unwrap();
+
+    handle_connection(&daemon, stream, peer, upstream, Duration::SECOND).await;
+
+    for query in [bytes, other_bytes] {
+      let length = client.read_u16().await.unwrap();
+      let mut response = vec![0; usize::from(length)];
+      client.read_exact(&mut response).await.unwrap();
+
+      assert_eq!(&response[12..query.len()], &query[12..]);
+      assert_eq!(read_address_records(&response).unwrap()[0].data, UPSTREAM_ADDRESS);
+    }
+    assert!(read_tcp_message(&mut client).await.is_none());
+  }
+
+  #[tokio::test]
+  async fn blocked_names_are_answered_locally() {
+    // Nothing listens there, so forwarding would fail.
+    let upstream = SocketAddr::from((Ipv4Addr::LOCALHOST, 9));
+    let (query, bytes) = create_test_query("ads.example.com");
+
+    let decision = DnsDecision::Block(BlockedDomainResponse::NxDomain);
+    let response = resolve(&query, &bytes, &decision, DnsTransport::Udp, upstream, Duration::SECOND).await;
+    assert_eq!(get_response_code(&response), RESPONSE_CODE_NAME_ERROR);
+
+    let decision = DnsDecision::Block(BlockedDomainResponse::NullAddress);
+    let response = resolve(&query, &bytes, &decision, DnsTransport::Udp, upstream, Duration::SECOND).await;
+    assert_eq!(get_response_code(&response), RESPONSE_CODE_NO_ERROR);
+    assert_eq!(read_address_records(&response).unwrap()[0].data, [0, 0, 0, 0]);
+  }
+
+  #[tokio::test]
+  async fn safe_search_names_are_rewritten() {
+    let upstream = spawn_upstream().await;
+    let (query, bytes) = create_test_query("www.google.com");
+
+    let decision = DnsDecision::Rewrite("forcesafesearch.google.com");
+    let response = resolve(&query, &bytes, &decision, DnsTransport::Udp, upstream, Duration::SECOND).await;
+
+    // The answer is for the name that was asked for.
+    assert_eq!(DnsQuery::parse(&bytes).unwrap().question.name, "www.google.com");
+    assert_eq!(&response[12..bytes.len()], &bytes[12..]);
+    assert_eq!(
+      read_address_records(&response).unwrap()[0].data,
+      UPSTREAM_ADDRESS,
+    );
+  }
+
+  #[test]
+  fn blocklists_block_subdomains() {
+    let directory = std::env::temp_dir().join(format!("discipline-blocklists-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("ads.txt"), "# Ads\n0.0.0.0 ads.example.com\ntracker.net.\n").unwrap();
+
+    let mut blocklists = DomainBlocklists::create(directory.clone());
+    blocklists.reload(&mut crate::x::TextualError::new("Test")).unwrap();
+
+    let filter = DomainFilter {
+      blocklists: vec!["ads".into()],
+      blocked_domain_response: BlockedDomainResponse::NxDomain,
+      enforce_safe_search: true,
+    };
+
+    assert_eq!(blocklists.decide(&filter, "ads.example.com"), DnsDecision::Block(BlockedDomainResponse::NxDomain));
+    assert_eq!(blocklists.decide(&filter, "x.tracker.net"), DnsDecision::Block(BlockedDomainResponse::NxDomain));
+    assert_eq!(blocklists.decide(&filter, "example.com"), DnsDecision::Forward);
+    assert_eq!(blocklists.decide(&filter, "bing.com"), DnsDecision::Rewrite("strict.bing.com"));
+
+    fs::remove_file(directory.join("ads.txt")).unwrap();
+    blocklists.reload(&mut crate::x::TextualError::new("Test")).unwrap();
+    assert_eq!(blocklists.decide(&filter, "ads.example.com"), DnsDecision::Forward);
+
+    let _ = fs::remove_dir_all(directory);
+  }
+}
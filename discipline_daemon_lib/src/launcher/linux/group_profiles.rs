@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use super::{ApplicationRegulations, DeviceAccessRegulation, GroupId, GroupName, InternetAccessRegulation, LoginPolicy, ScreenAccessRegulation, UserId, UserName, UserProfileName};
+
+// Regulates every member of a Unix group, whether they're listed in the
+// group database or have it as their primary group.
+//
+// A user covered by several profiles, their own and their groups', is
+// regulated by all of them at once: they're blocked whenever any of the
+// profiles blocks them, every blocklist applies, and allowances are
+// counted against their own usage. Web filter rules are tried in order,
+// their own profile's first, then their groups' in order of group id,
+// so an individual profile can make exceptions for one member.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupProfile {
+  pub name: UserProfileName,
+  pub group_id: GroupId,
+  pub group_name: GroupName,
+  pub device_access_regulation: DeviceAccessRegulation,
+  pub screen_access_regulation: ScreenAccessRegulation,
+  pub internet_access_regulation: InternetAccessRegulation,
+  #[serde(default)]
+  pub application_regulations: ApplicationRegulations,
+  #[serde(default)]
+  pub login_policy: LoginPolicy,
+  // Set when the group no longer exists, like UserProfile::is_orphaned.
+  #[serde(default)]
+  pub is_orphaned: bool,
+  // Refreshed from the account databases, so never saved.
+  #[serde(skip)]
+  pub members: Vec<(UserId, UserName)>,
+}
+
+impl GroupProfile {
+  pub fn construct(
+    name: UserProfileName,
+    group_id: GroupId,
+    group_name: GroupName,
+    device_access_regulation: DeviceAccessRegulation,
+    screen_access_regulation: ScreenAccessRegulation,
+    internet_access_regulation: InternetAccessRegulation,
+    application_regulations: ApplicationRegulations,
+  ) -> Self {
+    Self {
+      name,
+      group_id,
+      group_name,
+      device_access_regulation,
+      screen_access_regulation,
+      internet_access_regulation,
+      application_regulations,
+      login_policy: LoginPolicy::default(),
+      is_orphaned: false,
+      members: Vec::new(),
+    }
+  }
+
+  pub fn has_member(&self, user_id: UserId) -> bool {
+    self
+      .members
+      .iter()
+      .any(|(member_user_id, _)| *member_user_id == user_id)
+  }
+}
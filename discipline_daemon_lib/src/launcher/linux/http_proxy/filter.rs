@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize};
+use crate::x::{Time, TimeRange};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebFilterAction {
+  Allow,
+  Deny,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebFilterRule {
+  pub action: WebFilterAction,
+  // "example.com" matches example.com and its subdomains, "*" matches
+  // every host.
+  pub host: String,
+  // A pattern where "*" matches any run of characters, like
+  // "/watch*". Rules with a path pattern are skipped for HTTPS, whose
+  // paths the proxy can't see.
+  pub path_pattern: Option<String>,
+  // When set, the rule only applies within this time of day.
+  pub time_range: Option<TimeRange>,
+}
+
+impl WebFilterRule {
+  fn matches(&self, host: &str, path: Option<&str>, time: Time) -> bool {
+    if let Some(time_range) = &self.time_range && !time_range.contains(time) {
+      return false;
+    }
+
+    if !matches_host(&self.host, host) {
+      return false;
+    }
+
+    match (&self.path_pattern, path) {
+      (None, _) => {
+        true
+      }
+      (Some(pattern), Some(path)) => {
+        matches_pattern(pattern.as_bytes(), path.as_bytes())
+      }
+      (Some(_), None) => {
+        false
+      }
+    }
+  }
+}
+
+// A profile's web filtering settings, applied by the HTTP proxy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebFilter {
+  // Enabled filters confine the user's traffic to the proxy.
+  pub enabled: bool,
+  // The first matching rule decides.
+  pub rules: Vec<WebFilterRule>,
+  pub default_action: WebFilterAction,
+}
+
+impl Default for WebFilter {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      rules: Vec::new(),
+      default_action: WebFilterAction::Allow,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebFilterDecision {
+  Allow,
+  Deny { reason: String },
+}
+
+impl WebFilter {
+  // Adds another enabled filter's rules after this filter's own, so
+  // this filter's rules still get the first say. Unmatched requests are
+  // denied if either filter denies them by default.
+  pub fn merge(&mut self, other: &Self) {
+    if !other.enabled {
+      return;
+    }
+
+    if !self.enabled {
+      *self = other.clone();
+      return;
+    }
+
+    self.rules.extend(other.rules.iter().cloned());
+    if other.default_action == WebFilterAction::Deny {
+      self.default_action = WebFilterAction::Deny;
+    }
+  }
+
+  pub fn decide(&self, host: &str, path: Option<&str>, time: Time) -> WebFilterDecision {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+    let rule = self
+      .rules
+      .iter()
+      .find(|rule| rule.matches(&host, path, time));
+
+    let action = rule
+      .map(|rule| rule.action)
+      .unwrap_or(self.default_action);
+
+    match (action, rule) {
+      (WebFilterAction::Allow, _) => {
+        WebFilterDecision::Allow
+      }
+      (WebFilterAction::Deny, Some(rule)) => {
+        let mut reason = format!("A rule blocks \"{}\"", rule.host);
+        if let Some(path_pattern) = &rule.path_pattern {
+          reason.push_str(&format!(" for paths matching \"{path_pattern}\""));
+        }
+        if rule.time_range.is_some() {
+          reason.push_str(" at this time of day");
+        }
+        reason.push('.');
+
+        WebFilterDecision::Deny { reason }
+      }
+      (WebFilterAction::Deny, None) => {
+        WebFilterDecision::Deny {
+          reason: "Only allowed websites can be visited.".into(),
+        }
+      }
+    }
+  }
+}
+
+fn matches_host(rule_host: &str, host: &str) -> bool {
+  if rule_host == "*" {
+    return true;
+  }
+
+  let rule_host = rule_host.trim_end_matches('.');
+
+  host.eq_ignore_ascii_case(rule_host)
+  ||
+  host
+    .strip_suffix(rule_host)
+    .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+// Matches `text` against a pattern where "*" matches any run of bytes.
+fn matches_pattern(pattern: &[u8], text: &[u8]) -> bool {
+  let (mut pattern_index, mut text_index) = (0, 0);
+  // Where to resume when a mismatch happens after a "*".
+  let mut backtrack: Option<(usize, usize)> = None;
+
+  while text_index < text.len() {
+    if pattern_index < pattern.len() && pattern[pattern_index] == b'*' {
+      pattern_index += 1;
+      backtrack = Some((pattern_index, text_index));
+    } else if pattern_index < pattern.len() && pattern[pattern_index] == text[text_index] {
+      pattern_index += 1;
+      text_index += 1;
+    } else if let Some((star_pattern_index, star_text_index)) = backtrack {
+      pattern_index = star_pattern_index;
+      text_index = star_text_index + 1;
+      backtrack = Some((star_pattern_index, star_text_index + 1));
+    } else {
+      return false;
+    }
+  }
+
+  pattern[pattern_index..].iter().all(|byte| *byte == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn create_rule(action: WebFilterAction, host: &str, path_pattern: Option<&str>) -> WebFilterRule {
+    WebFilterRule {
+      action,
+      host: host.to_string(),
+      path_pattern: path_pattern.map(ToOwned::to_owned),
+      time_range: None,
+    }
+  }
+
+  fn create_filter(rules: Vec<WebFilterRule>, default_action: WebFilterAction) -> WebFilter {
+    WebFilter {
+      enabled: true,
+      rules,
+      default_action,
+    }
+  }
+
+  fn noon() -> Time {
+    Time::from_timestamp(12 * 60 * 60 * 1000).unwrap()
+  }
+
+  #[test]
+  fn blocked_domains_block_their_subdomains() {
+    let filter = create_filter(
+      vec![create_rule(WebFilterAction::Deny, "example.com", None)],
+      WebFilterAction::Allow,
+    );
+
+    let cases = [
+      ("example.com", false),
+      ("www.example.com", false),
+      ("a.b.example.com", false),
+      ("WWW.Example.COM", false),
+      // A trailing dot names the same host.
+      ("www.example.com.", false),
+      ("notexample.com", true),
+      ("example.com.evil", true),
+      ("example.org", true),
+    ];
+
+    for (host, is_allowed) in cases {
+      let decision = filter.decide(host, Some("/"), noon());
+      assert_eq!(decision == WebFilterDecision::Allow, is_allowed, "{host}");
+    }
+  }
+
+  #[test]
+  fn the_first_matching_rule_decides() {
+    let filter = create_filter(
+      vec![
+        create_rule(WebFilterAction::Allow, "docs.example.com", None),
+        create_rule(WebFilterAction::Deny, "example.com", Some("/watch*")),
+        create_rule(WebFilterAction::Allow, "example.com", None),
+      ],
+      WebFilterAction::Deny,
+    );
+
+    let cases = [
+      ("docs.example.com", Some("/watch?v=1"), true),
+      ("example.com", Some("/watch?v=1"), false),
+      ("example.com", Some("/about"), true),
+      // HTTPS paths aren't seen, so path rules are skipped for them.
+      ("example.com", None, true),
+      ("example.org", Some("/"), false),
+    ];
+
+    for (host, path, is_allowed) in cases {
+      let decision = filter.decide(host, path, noon());
+      assert_eq!(decision == WebFilterDecision::Allow, is_allowed, "{host} {path:?}");
+    }
+  }
+
+  #[test]
+  fn denials_say_why() {
+    let mut rule = create_rule(WebFilterAction::Deny, "example.com", Some("/watch*"));
+    rule.time_range = Some(TimeRange::from_times(
+      Time::from_timestamp(0).unwrap(),
+      Time::from_timestamp(Time::MAXIMUM_TIMESTAMP).unwrap(),
+    ));
+    let filter = create_filter(vec![rule], WebFilterAction::Deny);
+
+    assert_eq!(
+      filter.decide("example.com", Some("/watch"), noon()),
+      WebFilterDecision::Deny {
+        reason: "A rule blocks \"example.com\" for paths matching \"/watch*\" at this time of day.".into(),
+      },
+    );
+    assert_eq!(
+      filter.decide("example.org", Some("/"), noon()),
+      WebFilterDecision::Deny {
+        reason: "Only allowed websites can be visited.".into(),
+      },
+    );
+  }
+
+  #[test]
+  fn rules_only_apply_within_their_time_range() {
+    let mut rule = create_rule(WebFilterAction::Deny, "*", None);
+    rule.time_range = Some(TimeRange::from_times(
+      Time::from_timestamp(22 * 60 * 60 * 1000).unwrap(),
+      Time::from_timestamp(6 * 60 * 60 * 1000).unwrap(),
+    ));
+    let filter = create_filter(vec![rule], WebFilterAction::Allow);
+
+    let at = |hour: u32| Time::from_timestamp(hour * 60 * 60 * 1000).unwrap();
+    assert_eq!(filter.decide("example.com", None, at(12)), WebFilterDecision::Allow);
+    assert_ne!(filter.decide("example.com", None, at(23)), WebFilterDecision::Allow);
+    assert_ne!(filter.decide("example.com", None, at(2)), WebFilterDecision::Allow);
+  }
+
+  #[test]
+  fn patterns_match_runs_of_characters() {
+    let cases = [
+      ("/watch*", "/watch", true),
+      ("/watch*", "/watch?v=1", true),
+      ("/watch*", "/about", false),
+      ("*/live/*", "/channel/live/now", true),
+      ("*/live/*", "/channel/live", false),
+      ("/a*b*c", "/aXbYc", true),
+      ("/a*b*c", "/aXcYb", false),
+      ("*", "", true),
+      ("", "/", false),
+    ];
+
+    for (pattern, text, matches) in cases {
+      assert_eq!(matches_pattern(pattern.as_bytes(), text.as_bytes()), matches, "{pattern} against {text}");
+    }
+  }
+
+  #[test]
+  fn merged_filters_keep_the_first_say_and_the_stricter_default() {
+    let mut filter = create_filter(
+      vec![create_rule(WebFilterAction::Allow, "example.com", None)],
+      WebFilterAction::Allow,
+    );
+
+    filter.merge(&WebFilter {
+      enabled: false,
+      ..create_filter(Vec::new(), WebFilterAction::Deny)
+    });
+    assert_eq!(filter.default_action, WebFilterAction::Allow);
+
+    filter.merge(&create_filter(
+      vec![create_rule(WebFilterAction::Deny, "example.com", None)],
+      WebFilterAction::Deny,
+    ));
+    assert_eq!(filter.default_action, WebFilterAction::Deny);
+    assert_eq!(filter.decide("example.com", None, noon()), WebFilterDecision::Allow);
+    assert_ne!(filter.decide("example.org", None, noon()), WebFilterDecision::Allow);
+  }
+}
@@ -0,0 +1,11 @@
+mod filter;
+pub use filter::*;
+
+mod request;
+pub use request::*;
+
+mod tls;
+pub use tls::*;
+
+mod proxy;
+pub use proxy::*;
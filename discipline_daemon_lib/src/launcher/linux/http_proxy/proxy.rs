@@ -0,0 +1,327 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, copy_bidirectional};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex, Semaphore, watch};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use crate::x::{DateTime, Duration, IsTextualError};
+use super::super::{Daemon, SocketProtocol, find_socket_owner};
+use super::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpProxyConfiguration {
+  // The loopback port the proxy listens on.
+  pub port: u16,
+  // How long reading a request head or reaching a server may take.
+  pub timeout: Duration,
+}
+
+impl Default for HttpProxyConfiguration {
+  fn default() -> Self {
+    Self {
+      port: 3128,
+      timeout: Duration::from_milliseconds(10 * 1000),
+    }
+  }
+}
+
+// A forward proxy that filters requests according to the profile of
+// the user who sent them.
+//
+// Users whose profile enables web filtering may only reach the network
+// through us, so it's enough to filter the first request of every
+// connection: plain HTTP connections are closed after it, and CONNECT
+// tunnels are opaque.
+pub struct Proxy {
+  listener: TcpListener,
+  timeout: Duration,
+  semaphore: Arc<Semaphore>,
+  connections: JoinSet<()>,
+}
+
+enum ProxyDecision {
+  Allow,
+  Deny { reason: String },
+}
+
+impl Proxy {
+  const MAXIMUM_CONCURRENT_CONNECTIONS: usize = 512;
+  const MAXIMUM_HEAD_LENGTH: usize = 64 * 1024;
+
+  pub async fn bind(
+    configuration: &HttpProxyConfiguration,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Self, ()> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, configuration.port));
+
+    match TcpListener::bind(address).await {
+      Ok(listener) => {
+        Ok(Self {
+          listener,
+          timeout: configuration.timeout,
+          semaphore: Arc::new(Semaphore::new(Self::MAXIMUM_CONCURRENT_CONNECTIONS)),
+          connections: JoinSet::new(),
+        })
+      }
+      Err(error) => {
+        textual_error.change_context("Binding the Discipline Linux Daemon HTTP proxy");
+        textual_error.add_message("An io error occured while binding the TcpListener");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Address", address);
+        Err(())
+      }
+    }
+  }
+
+  // Accepts connections until `shutdown` changes. Tunnels can stay open
+  // for hours, so they're cut rather than waited for.
+  pub async fn serve(
+    mut self,
+    daemon: Arc<Mutex<Daemon>>,
+    mut shutdown: watch::Receiver<bool>,
+  ) {
+    loop {
+      tokio::select! {
+        _ = shutdown.changed() => {
+          break;
+        }
+        Some(_) = self.connections.join_next(), if !self.connections.is_empty() => {}
+        connection = self.listener.accept() => {
+          let (stream, peer) = match connection {
+            Ok(connection) => {
+              connection
+            }
+            Err(error) => {
+              // TODO: Use a proper logging mechanism.
+              eprintln!("Discipline Linux Daemon HTTP proxy: Failed to accept a connection: {error}");
+              continue;
+            }
+          };
+
+          let Ok(permit) = Arc::clone(&self.semaphore).try_acquire_owned() else {
+            continue;
+          };
+
+          let daemon = Arc::clone(&daemon);
+          let connection_timeout = self.timeout;
+
+          self.connections.spawn(async move {
+            let _permit = permit;
+            handle_connection(&daemon, stream, peer, connection_timeout).await;
+          });
+        }
+      }
+    }
+
+    self.connections.shutdown().await;
+  }
+}
+
+async fn handle_connection(
+  daemon: &Mutex<Daemon>,
+  mut client: TcpStream,
+  peer: SocketAddr,
+  connection_timeout: Duration,
+) {
+  let Some((head, buffer)) = read_request_head(&mut client, connection_timeout).await else {
+    let _ = client.write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 0\r\n\r\n").await;
+    return;
+  };
+
+  let user_id = find_socket_owner(SocketProtocol::Tcp, peer);
+
+  match head.target {
+    RequestTarget::Tunnel { host, port } => {
+      if let ProxyDecision::Deny { .. } = decide(daemon, user_id, &host, None).await {
+        // Browsers don't show bodies of failed CONNECT requests.
+        let _ = client.write_all(b"HTTP/1.1 403 Forbidden\r\nConnection: close\r\nContent-Length: 0\r\n\r\n").await;
+        return;
+      }
+
+      let Some(mut server) = connect(&host, port, connection_timeout).await else {
+        let _ = client.write_all(b"HTTP/1.1 502 Bad Gateway\r\nConnection: close\r\nContent-Length: 0\r\n\r\n").await;
+        return;
+      };
+
+      if client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").await.is_err() {
+        return;
+      }
+
+      // The client may have sent its ClientHello along with the head.
+      let mut first_bytes = buffer[head.length..].to_vec();
+      if first_bytes.is_empty() {
+        let mut chunk = vec![0; 16 * 1024];
+        match timeout(connection_timeout.to_std_duration(), client.read(&mut chunk)).await {
+          Ok(Ok(length)) if length > 0 => {
+            first_bytes.extend_from_slice(&chunk[..length]);
+          }
+          _ => {
+            return;
+          }
+        }
+      }
+
+      // The tunnel is for one host, but the TLS handshake may name
+      // another one sharing the same servers.
+      if let Some(server_name) = read_server_name_indication(&first_bytes)
+        && server_name != host
+        && let ProxyDecision::Deny { .. } = decide(daemon, user_id, &server_name, None).await
+      {
+        return;
+      }
+
+      if server.write_all(&first_bytes).await.is_err() {
+        return;
+      }
+
+      let _ = copy_bidirectional(&mut client, &mut server).await;
+    }
+    RequestTarget::Resource { host, port, path } => {
+      if let ProxyDecision::Deny { reason } = decide(daemon, user_id, &host, Some(&path)).await {
+        let _ = client.write_all(&create_block_page(&host, &path, &reason)).await;
+        return;
+      }
+
+      let Some(mut server) = connect(&host, port, connection_timeout).await else {
+        let _ = client.write_all(b"HTTP/1.1 502 Bad Gateway\r\nConnection: close\r\nContent-Length: 0\r\n\r\n").await;
+        return;
+      };
+
+      let mut request = rewrite_request_head(&buffer[..head.length]);
+      request.extend_from_slice(&buffer[head.length..]);
+
+      if server.write_all(&request).await.is_err() {
+        return;
+      }
+
+      let _ = copy_bidirectional(&mut client, &mut server).await;
+    }
+  }
+}
+
+async fn read_request_head(
+  client: &mut TcpStream,
+  connection_timeout: Duration,
+) -> Option<(RequestHead, Vec<u8>)> {
+  let mut buffer = Vec::new();
+  let mut chunk = vec![0; 8 * 1024];
+
+  timeout(connection_timeout.to_std_duration(), async {
+    loop {
+      let length = client.read(&mut chunk).await.ok()?;
+      if length == 0 {
+        return None;
+      }
+      buffer.extend_from_slice(&chunk[..length]);
+
+      match parse_request_head(&buffer) {
+        ParseRequestHeadReturn::Complete(head) => {
+          return Some(head);
+        }
+        ParseRequestHeadReturn::Invalid => {
+          return None;
+        }
+        ParseRequestHeadReturn::Incomplete if buffer.len() > Proxy::MAXIMUM_HEAD_LENGTH => {
+          return None;
+        }
+        ParseRequestHeadReturn::Incomplete => {}
+      }
+    }
+  })
+  .await
+  .ok()
+  .flatten()
+  .map(|head| (head, buffer))
+}
+
+async fn decide(
+  daemon: &Mutex<Daemon>,
+  user_id: Option<super::super::UserId>,
+  host: &str,
+  path: Option<&str>,
+) -> ProxyDecision {
+  let daemon = daemon.lock().await;
+
+  // Users without a profile aren't filtered.
+  let Some(profile) = user_id.and_then(|user_id| daemon.state.user_profiles.get_profile_given_user_id(user_id)) else {
+    return ProxyDecision::Allow;
+  };
+
+  let time = DateTime::now().time();
+  let now = daemon.state.monotonic_clock.now();
+
+  if profile.is_internet_access_blocked(time, now) {
+    return ProxyDecision::Deny {
+      reason: "Internet access is blocked right now.".into(),
+    };
+  }
+
+  let web_filter = &profile.internet_access_regulation.web_filter;
+  if !web_filter.enabled {
+    return ProxyDecision::Allow;
+  }
+
+  match web_filter.decide(host, path, time) {
+    WebFilterDecision::Allow => {
+      ProxyDecision::Allow
+    }
+    WebFilterDecision::Deny { reason } => {
+      ProxyDecision::Deny { reason }
+    }
+  }
+}
+
+async fn connect(host: &str, port: u16, connection_timeout: Duration) -> Option<TcpStream> {
+  timeout(connection_timeout.to_std_duration(), TcpStream::connect((host, port)))
+    .await
+    .ok()?
+    .ok()
+}
+
+fn create_block_page(host: &str, path: &str, reason: &str) -> Vec<u8> {
+  let body = format!(
+    "<!DOCTYPE html>\n\
+     <html>\n\
+     <head><meta charset=\"utf-8\"><title>Blocked by Discipline</title></head>\n\
+     <body>\n\
+     <h1>This page is blocked</h1>\n\
+     <p>Discipline blocked <code>{}{}</code>.</p>\n\
+     <p>{}</p>\n\
+     </body>\n\
+     </html>\n",
+    escape_html(host),
+    escape_html(path),
+    escape_html(reason),
+  );
+
+  let mut response = format!(
+    "HTTP/1.1 403 Forbidden\r\n\
+     Content-Type: text/html; charset=utf-8\r\n\
+     Content-Length: {}\r\n\
+     Cache-Control: no-store\r\n\
+     Connection: close\r\n\
+     \r\n",
+    body.len(),
+  )
+  .into_bytes();
+
+  response.extend_from_slice(body.as_bytes());
+  response
+}
+
+fn escape_html(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for character in text.chars() {
+    match character {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      character => escaped.push(character),
+    }
+  }
+  escaped
+}
@@ -0,0 +1,89 @@
+// Reads the server name a TLS client asks for from its ClientHello
+// (RFC 8446, section 4.1.2, and RFC 6066, section 3), so tunnels can be
+// checked against the host the client really talks to, not just the
+// one named in the CONNECT request.
+//
+// Only a ClientHello that fits in the first record is understood.
+pub fn read_server_name_indication(bytes: &[u8]) -> Option<String> {
+  let mut reader = Reader { bytes, offset: 0 };
+
+  // Record header: handshake content type, version and length.
+  if reader.read_u8()? != 0x16 {
+    return None;
+  }
+  reader.skip(2)?;
+  let record_length = reader.read_u16()? as usize;
+  let mut reader = Reader { bytes: reader.read_slice(record_length)?, offset: 0 };
+
+  // Handshake header: ClientHello type and length.
+  if reader.read_u8()? != 0x01 {
+    return None;
+  }
+  reader.skip(3)?;
+
+  // Version and random.
+  reader.skip(2 + 32)?;
+  // Session id, cipher suites and compression methods.
+  let length = reader.read_u8()? as usize;
+  reader.skip(length)?;
+  let length = reader.read_u16()? as usize;
+  reader.skip(length)?;
+  let length = reader.read_u8()? as usize;
+  reader.skip(length)?;
+
+  let extensions_length = reader.read_u16()? as usize;
+  let mut extensions = Reader { bytes: reader.read_slice(extensions_length)?, offset: 0 };
+
+  while let Some(extension_type) = extensions.read_u16() {
+    let length = extensions.read_u16()? as usize;
+    let data = extensions.read_slice(length)?;
+
+    if extension_type != 0x0000 {
+      continue;
+    }
+
+    let mut names = Reader { bytes: data, offset: 0 };
+    let list_length = names.read_u16()? as usize;
+    let mut names = Reader { bytes: names.read_slice(list_length)?, offset: 0 };
+
+    while let Some(name_type) = names.read_u8() {
+      let length = names.read_u16()? as usize;
+      let name = names.read_slice(length)?;
+
+      // host_name
+      if name_type == 0 {
+        let name = std::str::from_utf8(name).ok()?;
+        return Some(name.to_ascii_lowercase());
+      }
+    }
+
+    return None;
+  }
+
+  None
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn read_slice(&mut self, length: usize) -> Option<&'a [u8]> {
+    let slice = self.bytes.get(self.offset..self.offset.checked_add(length)?)?;
+    self.offset += length;
+    Some(slice)
+  }
+
+  fn skip(&mut self, length: usize) -> Option<()> {
+    self.read_slice(length).map(|_| ())
+  }
+
+  fn read_u8(&mut self) -> Option<u8> {
+    self.read_slice(1).map(|bytes| bytes[0])
+  }
+
+  fn read_u16(&mut self) -> Option<u16> {
+    self.read_slice(2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+  }
+}
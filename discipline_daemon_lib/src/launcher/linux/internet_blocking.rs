@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use super::{NetworkAddress, UserId, generate_ruleset};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InternetBlockingConfiguration {
+  // Destinations blocked users can still reach. Loopback is always
+  // reachable.
+  pub allowlist: Vec<NetworkAddress>,
+}
+
+// Keeps the nftables ruleset in line with which users are blocked,
+// applying it only when it changes.
+#[derive(Debug)]
+pub struct InternetBlocker {
+  configuration: InternetBlockingConfiguration,
+  // Whether users whose web filter is enabled are blocked too, leaving
+  // them only the HTTP proxy, which is reached over loopback.
+  confines_web_filtered_users: bool,
+  applied_ruleset: Option<String>,
+}
+
+impl InternetBlocker {
+  pub const TABLE_NAME: &'static str = "discipline";
+
+  pub fn create(
+    configuration: InternetBlockingConfiguration,
+    confines_web_filtered_users: bool,
+  ) -> Self {
+    Self {
+      configuration,
+      confines_web_filtered_users,
+      applied_ruleset: None,
+    }
+  }
+
+  pub fn get_configuration(&self) -> &InternetBlockingConfiguration {
+    &self.configuration
+  }
+
+  pub fn confines_web_filtered_users(&self) -> bool {
+    self.confines_web_filtered_users
+  }
+
+  pub fn generate_ruleset(&self, blocked_user_ids: &[UserId]) -> String {
+    generate_ruleset(
+      Self::TABLE_NAME,
+      &self.configuration.allowlist,
+      blocked_user_ids,
+    )
+  }
+
+  // Nothing is applied yet after the daemon starts, so the first
+  // reconciliation always applies the ruleset.
+  pub fn needs_applying(&self, ruleset: &str) -> bool {
+    self.applied_ruleset.as_deref() != Some(ruleset)
+  }
+
+  pub fn on_ruleset_applied(&mut self, ruleset: String) {
+    self.applied_ruleset = Some(ruleset);
+  }
+}
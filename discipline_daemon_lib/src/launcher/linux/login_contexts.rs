@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+use super::{BlockReason, UserId};
+
+// What the PAM module knows about a login besides whose it is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoginContext {
+  // PAM_SERVICE, like "sshd" or "gdm-password".
+  pub service: String,
+  // PAM_TTY, like "/dev/tty2" or ":0".
+  pub tty: Option<String>,
+  // PAM_RHOST, set for logins from another machine.
+  pub remote_host: Option<String>,
+  // The real user id of the process that asked, which for su and sudo
+  // is the user who's switching, not the one switched to.
+  pub requesting_user_id: Option<UserId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LoginClass {
+  // Display managers and screen lockers.
+  Graphical,
+  // Text consoles.
+  Console,
+  // SSH, and anything else that names a remote host.
+  Remote,
+  // su and runuser.
+  SwitchUser,
+  // sudo, pkexec and doas.
+  Elevation,
+  Other,
+}
+
+const GRAPHICAL_SERVICES: &[&str] = &[
+  "gdm-password",
+  "gdm-autologin",
+  "gdm-fingerprint",
+  "gdm-smartcard",
+  "sddm",
+  "sddm-autologin",
+  "sddm-greeter",
+  "lightdm",
+  "lightdm-autologin",
+  "lxdm",
+  "xdm",
+  "kde",
+  "kscreensaver",
+  "xscreensaver",
+  "cinnamon-screensaver",
+  "mate-screensaver",
+  "xfce4-screensaver",
+  "i3lock",
+  "swaylock",
+];
+
+const SWITCH_USER_SERVICES: &[&str] = &["su", "su-l", "runuser", "runuser-l"];
+
+const ELEVATION_SERVICES: &[&str] = &["sudo", "sudo-i", "pkexec", "polkit-1", "doas"];
+
+impl LoginContext {
+  pub fn get_class(&self) -> LoginClass {
+    let service = self.service.as_str();
+
+    if SWITCH_USER_SERVICES.contains(&service) {
+      return LoginClass::SwitchUser;
+    }
+    if ELEVATION_SERVICES.contains(&service) {
+      return LoginClass::Elevation;
+    }
+    if service == "sshd" || self.remote_host.as_deref().is_some_and(|host| !host.is_empty()) {
+      return LoginClass::Remote;
+    }
+    if GRAPHICAL_SERVICES.contains(&service) {
+      return LoginClass::Graphical;
+    }
+    if service == "login" {
+      return LoginClass::Console;
+    }
+
+    // Unknown services are told apart by their terminal: X displays
+    // look like ":0", text consoles like "/dev/tty2" or "tty2".
+    match self.tty.as_deref() {
+      Some(tty) if tty.starts_with(':') => {
+        LoginClass::Graphical
+      }
+      Some(tty) if tty.starts_with("/dev/tty") || tty.starts_with("tty") => {
+        LoginClass::Console
+      }
+      _ => {
+        LoginClass::Other
+      }
+    }
+  }
+}
+
+// How a profile treats the different kinds of login.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoginPolicy {
+  // Logins the screen regulation's blocks don't apply to, like Remote,
+  // so someone can still help over SSH during a block.
+  pub exempt_classes: Vec<LoginClass>,
+  // Logins that are always refused, blocked or not.
+  pub refused_classes: Vec<LoginClass>,
+  // Refuses su and sudo while the user asking is blocked, whichever
+  // account they're switching to, so a block can't be escaped through
+  // an unregulated account.
+  pub refuse_switching_users_while_blocked: bool,
+}
+
+impl LoginPolicy {
+  // Exemptions and refusals add up. Where a class is both, refusal
+  // wins.
+  pub fn merge(&mut self, other: &Self) {
+    for class in &other.exempt_classes {
+      if !self.exempt_classes.contains(class) {
+        self.exempt_classes.push(*class);
+      }
+    }
+    for class in &other.refused_classes {
+      if !self.refused_classes.contains(class) {
+        self.refused_classes.push(*class);
+      }
+    }
+
+    self.refuse_switching_users_while_blocked |= other.refuse_switching_users_while_blocked;
+  }
+
+  pub fn is_refused(&self, class: LoginClass) -> bool {
+    self.refused_classes.contains(&class)
+  }
+
+  pub fn is_exempt(&self, class: LoginClass) -> bool {
+    !self.is_refused(class) && self.exempt_classes.contains(&class)
+  }
+}
+
+// Why a login was refused, for the PAM module to tell the user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoginRefusal {
+  pub reason: LoginRefusalReason,
+  // When logging in will be allowed again if nobody acts, as a UNIX
+  // timestamp in milliseconds. None when that isn't known.
+  pub blocked_until: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoginRefusalReason {
+  // The screen regulation of the user logging in blocks them.
+  Blocked(BlockReason),
+  // The login policy refuses this kind of login outright.
+  RefusedLoginClass(LoginClass),
+  // The user switching accounts is blocked themselves.
+  RequestingUserBlocked(BlockReason),
+  // The daemon couldn't be asked, and the module's failure policy
+  // refused the login.
+  Unverifiable,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn create_context(service: &str, tty: Option<&str>, remote_host: Option<&str>) -> LoginContext {
+    LoginContext {
+      service: service.to_string(),
+      tty: tty.map(ToOwned::to_owned),
+      remote_host: remote_host.map(ToOwned::to_owned),
+      requesting_user_id: None,
+    }
+  }
+
+  #[test]
+  fn logins_are_classified() {
+    let cases = [
+      ("su", None, None, LoginClass::SwitchUser),
+      ("su-l", Some("/dev/pts/0"), None, LoginClass::SwitchUser),
+      ("runuser", None, None, LoginClass::SwitchUser),
+      ("sudo", Some("/dev/pts/0"), None, LoginClass::Elevation),
+      ("pkexec", None, None, LoginClass::Elevation),
+      // su over SSH is still su.
+      ("su", None, Some("192.0.2.7"), LoginClass::SwitchUser),
+      ("sshd", None, None, LoginClass::Remote),
+      ("sshd", Some("ssh"), Some("192.0.2.7"), LoginClass::Remote),
+      ("my-service", Some("/dev/tty2"), Some("192.0.2.7"), LoginClass::Remote),
+      // An empty RHOST isn't a remote host.
+      ("my-service", Some("/dev/tty2"), Some(""), LoginClass::Console),
+      ("gdm-password", Some(":0"), None, LoginClass::Graphical),
+      ("login", Some("/dev/tty2"), None, LoginClass::Console),
+      ("my-service", Some(":0"), None, LoginClass::Graphical),
+      ("my-service", Some("/dev/tty2"), None, LoginClass::Console),
+      ("my-service", Some("tty2"), None, LoginClass::Console),
+      ("my-service", Some("/dev/pts/0"), None, LoginClass::Other),
+      ("my-service", None, None, LoginClass::Other),
+      ("", None, None, LoginClass::Other),
+    ];
+
+    for (service, tty, remote_host, class) in cases {
+      assert_eq!(
+        create_context(service, tty, remote_host).get_class(),
+        class,
+        "service {service:?}, tty {tty:?}, remote host {remote_host:?}",
+      );
+    }
+  }
+
+  #[test]
+  fn merged_policies_add_up() {
+    let mut policy = LoginPolicy {
+      exempt_classes: vec![LoginClass::Remote],
+      refused_classes: Vec::new(),
+      refuse_switching_users_while_blocked: false,
+    };
+
+    policy.merge(&LoginPolicy {
+      exempt_classes: vec![LoginClass::Remote, LoginClass::Console],
+      refused_classes: vec![LoginClass::Elevation],
+      refuse_switching_users_while_blocked: true,
+    });
+
+    assert_eq!(policy.exempt_classes, vec![LoginClass::Remote, LoginClass::Console]);
+    assert_eq!(policy.refused_classes, vec![LoginClass::Elevation]);
+    assert!(policy.refuse_switching_users_while_blocked);
+
+    // Merging in a looser policy doesn't loosen it.
+    policy.merge(&LoginPolicy::default());
+    assert!(policy.refuse_switching_users_while_blocked);
+  }
+
+  #[test]
+  fn refusal_beats_exemption() {
+    let mut policy = LoginPolicy {
+      exempt_classes: vec![LoginClass::Remote, LoginClass::Console],
+      ..LoginPolicy::default()
+    };
+
+    policy.merge(&LoginPolicy {
+      refused_classes: vec![LoginClass::Remote],
+      ..LoginPolicy::default()
+    });
+
+    let cases = [
+      (LoginClass::Remote, false, true),
+      (LoginClass::Console, true, false),
+      (LoginClass::Graphical, false, false),
+    ];
+
+    for (class, is_exempt, is_refused) in cases {
+      assert_eq!(policy.is_exempt(class), is_exempt, "{class:?}");
+      assert_eq!(policy.is_refused(class), is_refused, "{class:?}");
+    }
+  }
+}
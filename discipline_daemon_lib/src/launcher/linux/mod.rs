@@ -2,15 +2,57 @@ mod system;
 pub use system::*;
 
 mod daemon;
-pub use daemon::Daemon;
+pub use daemon::{Daemon, LaunchConfiguration};
+
+mod service;
+pub use service::{run, remove_browser_policies};
 
 
 mod profiles;
 pub use profiles::*;
 
+mod group_profiles;
+pub use group_profiles::*;
+
+mod login_contexts;
+pub use login_contexts::*;
+
 mod state;
 pub use state::State;
 
+mod password_escrow;
+pub use password_escrow::*;
+
+mod sessions;
+pub use sessions::*;
+
+mod session_enforcement;
+pub use session_enforcement::*;
+
+mod block_warnings;
+pub use block_warnings::*;
+
+mod internet_blocking;
+pub use internet_blocking::*;
+
+mod applications;
+pub use applications::*;
+
+mod application_usage;
+pub use application_usage::*;
+
+pub mod dns;
+pub use dns::{DnsRedirector, DnsResolverConfiguration, DomainBlocklists, DomainFilter};
+
+pub mod http_proxy;
+pub use http_proxy::{HttpProxyConfiguration, WebFilter, WebFilterAction};
+
+pub mod native_messaging;
+pub use native_messaging::{NativeMessagingConfiguration, WebRules};
+
+mod browser_policies;
+pub use browser_policies::*;
+
 mod api;
 pub use api::Api;
 
@@ -0,0 +1,132 @@
+use std::io::{self, ErrorKind, Read, Write};
+use serde::{Deserialize, Serialize};
+use super::WebRules;
+
+// Messages the browser extension sends to the native messaging host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExtensionMessage {
+  // Asks for the latest rules, like after the extension reloads.
+  GetRules,
+}
+
+// Messages the native messaging host sends to the browser extension.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostMessage {
+  Rules { rules: WebRules },
+  // The daemon can't be reached. The extension keeps enforcing the
+  // last rules it got.
+  DaemonUnavailable,
+}
+
+// Browsers refuse larger messages from native messaging hosts.
+pub const MAXIMUM_HOST_MESSAGE_LENGTH: usize = 1024 * 1024;
+// Browsers never send messages larger than this.
+pub const MAXIMUM_EXTENSION_MESSAGE_LENGTH: usize = 64 * 1024 * 1024;
+
+// Reads a message framed the way browsers do it: a 32-bit length in
+// native byte order followed by that many bytes of JSON. Returns None
+// once the browser closes the stream.
+pub fn read_native_message(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+  let mut length = [0; 4];
+  match reader.read_exact(&mut length) {
+    Ok(()) => {}
+    Err(error) if error.kind() == ErrorKind::UnexpectedEof => {
+      return Ok(None);
+    }
+    Err(error) => {
+      return Err(error);
+    }
+  }
+
+  let length = u32::from_ne_bytes(length) as usize;
+  if length > MAXIMUM_EXTENSION_MESSAGE_LENGTH {
+    return Err(io::Error::new(ErrorKind::InvalidData, "The message is too long"));
+  }
+
+  let mut message = vec![0; length];
+  reader.read_exact(&mut message)?;
+  Ok(Some(message))
+}
+
+pub fn write_native_message(writer: &mut impl Write, message: &[u8]) -> io::Result<()> {
+  if message.len() > MAXIMUM_HOST_MESSAGE_LENGTH {
+    return Err(io::Error::new(ErrorKind::InvalidData, "The message is too long"));
+  }
+
+  writer.write_all(&(message.len() as u32).to_ne_bytes())?;
+  writer.write_all(message)?;
+  writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+  use super::*;
+
+  fn frame(message: &[u8]) -> Vec<u8> {
+    let mut framed = (message.len() as u32).to_ne_bytes().to_vec();
+    framed.extend_from_slice(message);
+    framed
+  }
+
+  #[test]
+  fn messages_are_prefixed_with_their_length_in_native_byte_order() {
+    let mut written = Vec::new();
+    write_native_message(&mut written, br#"{"type":"daemon_unavailable"}"#).unwrap();
+
+    assert_eq!(&written[..4], &29u32.to_ne_bytes());
+    assert_eq!(&written[4..], br#"{"type":"daemon_unavailable"}"#);
+  }
+
+  #[test]
+  fn messages_are_read_one_at_a_time() {
+    let mut stream = frame(br#"{"type":"get_rules"}"#);
+    stream.extend(frame(b""));
+    stream.extend(frame(b"[]"));
+    let mut reader = Cursor::new(stream);
+
+    assert_eq!(read_native_message(&mut reader).unwrap(), Some(br#"{"type":"get_rules"}"#.to_vec()));
+    assert_eq!(read_native_message(&mut reader).unwrap(), Some(Vec::new()));
+    assert_eq!(read_native_message(&mut reader).unwrap(), Some(b"[]".to_vec()));
+    assert_eq!(read_native_message(&mut reader).unwrap(), None);
+  }
+
+  #[test]
+  fn truncated_messages_are_errors() {
+    let mut framed = frame(b"[1, 2, 3]");
+    framed.pop();
+
+    let error = read_native_message(&mut Cursor::new(framed)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+  }
+
+  #[test]
+  fn oversize_messages_are_refused() {
+    let length = (MAXIMUM_EXTENSION_MESSAGE_LENGTH as u32 + 1).to_ne_bytes();
+    let error = read_native_message(&mut Cursor::new(length)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+    let mut written = Vec::new();
+    let error = write_native_message(&mut written, &vec![b' '; MAXIMUM_HOST_MESSAGE_LENGTH + 1]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert!(written.is_empty());
+
+    write_native_message(&mut written, &vec![b' '; MAXIMUM_HOST_MESSAGE_LENGTH]).unwrap();
+    assert_eq!(written.len(), 4 + MAXIMUM_HOST_MESSAGE_LENGTH);
+  }
+
+  #[test]
+  fn messages_are_tagged_by_type() {
+    assert!(matches!(
+      serde_json::from_slice(br#"{"type":"get_rules"}"#),
+      Ok(ExtensionMessage::GetRules),
+    ));
+    assert!(serde_json::from_slice::<ExtensionMessage>(br#"{"type":"block_everything"}"#).is_err());
+    assert_eq!(
+      serde_json::to_string(&HostMessage::DaemonUnavailable).unwrap(),
+      r#"{"type":"daemon_unavailable"}"#,
+    );
+  }
+}
@@ -0,0 +1,8 @@
+mod rules;
+pub use rules::*;
+
+mod host;
+pub use host::*;
+
+mod server;
+pub use server::*;
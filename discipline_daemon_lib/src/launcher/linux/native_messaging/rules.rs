@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use crate::x::{Instant, Time};
+use super::super::{UserProfile, WebFilterAction};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActiveWebRule {
+  pub action: WebFilterAction,
+  pub host: String,
+  pub path_pattern: Option<String>,
+}
+
+// The web rules in effect for a user right now, which the browser
+// extension enforces page by page. Rules outside their time range are
+// left out, so the extension needn't know about schedules and a new
+// snapshot is sent whenever a rule activates or deactivates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebRules {
+  pub internet_access_blocked: bool,
+  pub enabled: bool,
+  // The first matching rule decides.
+  pub rules: Vec<ActiveWebRule>,
+  pub default_action: WebFilterAction,
+}
+
+impl WebRules {
+  // What users without a profile get.
+  pub fn unrestricted() -> Self {
+    Self {
+      internet_access_blocked: false,
+      enabled: false,
+      rules: Vec::new(),
+      default_action: WebFilterAction::Allow,
+    }
+  }
+
+  pub fn create(profile: &UserProfile, time: Time, instant: Instant) -> Self {
+    let web_filter = &profile.internet_access_regulation.web_filter;
+
+    let rules = web_filter
+      .rules
+      .iter()
+      .filter(|rule| {
+        rule
+          .time_range
+          .as_ref()
+          .is_none_or(|time_range| time_range.contains(time))
+      })
+      .map(|rule| ActiveWebRule {
+        action: rule.action,
+        host: rule.host.clone(),
+        path_pattern: rule.path_pattern.clone(),
+      })
+      .collect();
+
+    Self {
+      internet_access_blocked: profile.is_internet_access_blocked(time, instant),
+      enabled: web_filter.enabled,
+      rules,
+      default_action: web_filter.default_action,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use crate::x::{AlwaysRule, CountdownAfterPleaConditional, Duration, RuleEnabler, RulesStats, TimeRange, UserUptimeClock, UuidV4};
+  use super::super::super::*;
+  use super::super::super::http_proxy::WebFilterRule;
+  use super::*;
+
+  fn at(hour: u32) -> Time {
+    Time::from_timestamp(hour * 60 * 60 * 1000).unwrap()
+  }
+
+  fn create_profile(web_filter: WebFilter) -> UserProfile {
+    let now = Instant::from_timestamp(0);
+
+    UserProfile {
+      name: UserProfileName::new("Alex".to_string()).unwrap(),
+      user_id: UserId::new(1000),
+      user_name: UserName::new(CString::new("alex").unwrap()),
+      uptime_clock: UserUptimeClock::construct(
+        false,
+        now,
+        Duration::zero(),
+        now,
+        Duration::zero(),
+        now,
+        Duration::HOUR,
+      ),
+      device_access_regulation: DeviceAccessRegulation::new(),
+      screen_access_regulation: ScreenAccessRegulation::default(),
+      internet_access_regulation: InternetAccessRegulation {
+        web_filter,
+        ..InternetAccessRegulation::new()
+      },
+      application_regulations: ApplicationRegulations::new(),
+      login_policy: LoginPolicy::default(),
+      rules_stats: RulesStats::new(RulesStats::DEFAULT_MAXIMUM_RULES_NUMBER),
+      is_orphaned: false,
+    }
+  }
+
+  fn create_rule(action: WebFilterAction, host: &str, path_pattern: Option<&str>, time_range: Option<(u32, u32)>) -> WebFilterRule {
+    WebFilterRule {
+      action,
+      host: host.to_string(),
+      path_pattern: path_pattern.map(ToOwned::to_owned),
+      time_range: time_range.map(|(from, till)| TimeRange::from_times(at(from), at(till))),
+    }
+  }
+
+  fn create_active_rule(action: WebFilterAction, host: &str, path_pattern: Option<&str>) -> ActiveWebRule {
+    ActiveWebRule {
+      action,
+      host: host.to_string(),
+      path_pattern: path_pattern.map(ToOwned::to_owned),
+    }
+  }
+
+  #[test]
+  fn only_rules_in_their_time_range_are_sent() {
+    let profile = create_profile(WebFilter {
+      enabled: true,
+      rules: vec![
+        create_rule(WebFilterAction::Deny, "youtube.com", Some("/shorts/*"), None),
+        create_rule(WebFilterAction::Deny, "youtube.com", None, Some((20, 6))),
+        create_rule(WebFilterAction::Allow, "*", None, Some((9, 17))),
+      ],
+      default_action: WebFilterAction::Deny,
+    });
+
+    let cases = [
+      (at(12), vec![
+        create_active_rule(WebFilterAction::Deny, "youtube.com", Some("/shorts/*")),
+        create_active_rule(WebFilterAction::Allow, "*", None),
+      ]),
+      (at(18), vec![
+        create_active_rule(WebFilterAction::Deny, "youtube.com", Some("/shorts/*")),
+      ]),
+      (at(23), vec![
+        create_active_rule(WebFilterAction::Deny, "youtube.com", Some("/shorts/*")),
+        create_active_rule(WebFilterAction::Deny, "youtube.com", None),
+      ]),
+    ];
+
+    for (time, rules) in cases {
+      assert_eq!(
+        WebRules::create(&profile, time, Instant::from_timestamp(0)),
+        WebRules {
+          internet_access_blocked: false,
+          enabled: true,
+          rules,
+          default_action: WebFilterAction::Deny,
+        },
+        "{time:?}",
+      );
+    }
+  }
+
+  #[test]
+  fn blocked_internet_access_is_sent() {
+    let mut profile = create_profile(WebFilter::default());
+    profile.internet_access_regulation.always_rules.rules.insert(
+      UuidV4::generate(),
+      AlwaysRule::create(RuleEnabler::CountdownAfterPlea(CountdownAfterPleaConditional::create(Duration::HOUR))),
+    );
+
+    let rules = WebRules::create(&profile, at(12), Instant::from_timestamp(0));
+    assert!(rules.internet_access_blocked);
+  }
+
+  #[test]
+  fn disabled_filters_are_sent_as_disabled() {
+    let profile = create_profile(WebFilter {
+      enabled: false,
+      rules: vec![create_rule(WebFilterAction::Deny, "example.com", None, None)],
+      default_action: WebFilterAction::Allow,
+    });
+
+    let rules = WebRules::create(&profile, at(12), Instant::from_timestamp(0));
+    assert!(!rules.enabled);
+    assert!(!rules.internet_access_blocked);
+  }
+}
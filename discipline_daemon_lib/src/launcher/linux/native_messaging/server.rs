@@ -0,0 +1,221 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{Mutex, Semaphore, watch};
+use tokio::task::JoinSet;
+use tokio::time::{MissedTickBehavior, interval};
+use crate::x::{DateTime, Duration, IsTextualError};
+use super::super::{Daemon, UserId};
+use super::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NativeMessagingConfiguration {
+  // How often each connected host is checked for rules that activated
+  // or deactivated.
+  pub update_interval: Duration,
+}
+
+impl Default for NativeMessagingConfiguration {
+  fn default() -> Self {
+    Self {
+      update_interval: Duration::from_milliseconds(5 * 1000),
+    }
+  }
+}
+
+// Streams each connected native messaging host the web rules of the
+// user running it, as one JSON encoded `WebRules` per line, sent on
+// connecting and whenever they change.
+pub struct Server {
+  listener: UnixListener,
+  update_interval: Duration,
+  semaphore: Arc<Semaphore>,
+  connections: JoinSet<()>,
+}
+
+impl Server {
+  // Not configurable, since the host runs as the user and can't read
+  // the daemon's configuration.
+  pub const PATH: &'static str = "/run/discipline/web-rules.sock";
+
+  const MAXIMUM_CONCURRENT_CONNECTIONS: usize = 64;
+
+  pub async fn new(
+    configuration: &NativeMessagingConfiguration,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Self, ()> {
+    // A socket file left behind by a previous run makes binding fail.
+    match fs::remove_file(Self::PATH) {
+      Ok(()) => {}
+      Err(error) if error.kind() == ErrorKind::NotFound => {}
+      Err(error) => {
+        textual_error.change_context("Creating Discipline Linux Daemon native messaging server");
+        textual_error.add_message("Failed to remove a stale socket file");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Path", Self::PATH);
+        return Err(());
+      }
+    }
+
+    let listener = match UnixListener::bind(Self::PATH) {
+      Ok(value) => {
+        value
+      }
+      Err(error) => {
+        textual_error.change_context("Creating Discipline Linux Daemon native messaging server");
+        textual_error.add_message("An io error occured while binding the UnixListener");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Path", Self::PATH);
+        return Err(());
+      }
+    };
+
+    // Every user's browser may connect. Each one only ever gets its own
+    // rules, since users are told apart by their socket credentials.
+    if let Err(error) = fs::set_permissions(Self::PATH, fs::Permissions::from_mode(0o666)) {
+      textual_error.change_context("Creating Discipline Linux Daemon native messaging server");
+      textual_error.add_message("Failed to change the socket file's permissions");
+      textual_error.add_attachement_display("Io error", error);
+      textual_error.add_attachement_display("Path", Self::PATH);
+      return Err(());
+    }
+
+    Ok(Self {
+      listener,
+      update_interval: configuration.update_interval,
+      semaphore: Arc::new(Semaphore::new(Self::MAXIMUM_CONCURRENT_CONNECTIONS)),
+      connections: JoinSet::new(),
+    })
+  }
+
+  // Accepts connections until `shutdown` changes. Connections never
+  // finish on their own, so they're closed on shutdown too.
+  pub async fn serve(
+    mut self,
+    daemon: Arc<Mutex<Daemon>>,
+    mut shutdown: watch::Receiver<bool>,
+  ) {
+    loop {
+      let permit = tokio::select! {
+        _ = shutdown.changed() => {
+          break;
+        }
+        permit = Arc::clone(&self.semaphore).acquire_owned() => {
+          match permit {
+            Ok(permit) => {
+              permit
+            }
+            Err(_) => {
+              // The semaphore is never closed.
+              break;
+            }
+          }
+        }
+      };
+
+      let connection = tokio::select! {
+        _ = shutdown.changed() => {
+          break;
+        }
+        connection = self.listener.accept() => {
+          match connection {
+            Ok((connection, _)) => {
+              connection
+            }
+            Err(error) => {
+              // TODO: Use a proper logging mechanism.
+              eprintln!("Discipline Linux Daemon native messaging server: Failed to accept a connection: {error}");
+              continue;
+            }
+          }
+        }
+      };
+
+      let daemon = Arc::clone(&daemon);
+      let update_interval = self.update_interval;
+      let shutdown = shutdown.clone();
+
+      self.connections.spawn(async move {
+        handle_connection(connection, daemon, update_interval, shutdown).await;
+        drop(permit);
+      });
+
+      // Reap connections that already finished.
+      while self.connections.try_join_next().is_some() {}
+    }
+
+    self.connections.shutdown().await;
+    let _ = fs::remove_file(Self::PATH);
+  }
+}
+
+async fn handle_connection(
+  connection: UnixStream,
+  daemon: Arc<Mutex<Daemon>>,
+  update_interval: Duration,
+  mut shutdown: watch::Receiver<bool>,
+) {
+  let user_id = match connection.peer_cred() {
+    Ok(credentials) => {
+      UserId::new(credentials.uid())
+    }
+    Err(error) => {
+      // TODO: Use a proper logging mechanism.
+      eprintln!("Discipline Linux Daemon native messaging server: Failed to read a connection's credentials: {error}");
+      return;
+    }
+  };
+
+  let (mut reader, mut writer) = connection.into_split();
+  let mut discarded = [0; 64];
+  let mut sent_rules: Option<WebRules> = None;
+  let mut ticks = interval(update_interval.to_std_duration());
+  ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+  loop {
+    tokio::select! {
+      _ = shutdown.changed() => {
+        return;
+      }
+      // Hosts never write. This only notices them going away.
+      length = reader.read(&mut discarded) => {
+        if !matches!(length, Ok(length) if length > 0) {
+          return;
+        }
+      }
+      _ = ticks.tick() => {
+        let rules = {
+          let daemon = daemon.lock().await;
+
+          match daemon.state.user_profiles.get_profile_given_user_id(user_id) {
+            Some(profile) => {
+              WebRules::create(profile, DateTime::now().time(), daemon.state.monotonic_clock.now())
+            }
+            None => {
+              WebRules::unrestricted()
+            }
+          }
+        };
+
+        if sent_rules.as_ref() == Some(&rules) {
+          continue;
+        }
+
+        let Ok(mut line) = serde_json::to_vec(&rules) else {
+          return;
+        };
+        line.push(b'\n');
+
+        if writer.write_all(&line).await.is_err() {
+          return;
+        }
+
+        sent_rules = Some(rules);
+      }
+    }
+  }
+}
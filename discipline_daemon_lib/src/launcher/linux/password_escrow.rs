@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::x::UuidV4;
+use super::{UserId, UserName};
+
+// A local account whose password only the daemon knows. The password
+// is kept in a vault, so the account is only usable once the vault's
+// protector lets it be revealed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordEscrow {
+  pub vault_id: UuidV4,
+  pub datum_id: UuidV4,
+  pub user_id: UserId,
+  pub user_name: UserName,
+  // Whether the password is changed again once a reveal window is over,
+  // so that a revealed password can't be reused later.
+  pub rotate_after_reveal: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PasswordEscrows {
+  escrows: HashMap<UuidV4, PasswordEscrow>,
+}
+
+impl PasswordEscrows {
+  pub fn new() -> Self {
+    Self {
+      escrows: HashMap::new(),
+    }
+  }
+
+  pub fn construct(escrows: HashMap<UuidV4, PasswordEscrow>) -> Self {
+    Self {
+      escrows,
+    }
+  }
+
+  pub fn get_escrow_given_id(&self, escrow_id: &UuidV4) -> Option<&PasswordEscrow> {
+    self.escrows.get(escrow_id)
+  }
+
+  pub fn get_escrow_given_user_id(&self, user_id: UserId) -> Option<(&UuidV4, &PasswordEscrow)> {
+    self
+      .escrows
+      .iter()
+      .find(|(_, escrow)| escrow.user_id == user_id)
+  }
+
+  pub fn get_escrows_to_rotate_after_reveal(&self, vault_id: &UuidV4) -> Vec<UuidV4> {
+    self
+      .escrows
+      .iter()
+      .filter(|(_, escrow)| escrow.rotate_after_reveal && escrow.vault_id == *vault_id)
+      .map(|(escrow_id, _)| escrow_id.clone())
+      .collect()
+  }
+
+  pub fn add_escrow(&mut self, escrow_id: UuidV4, escrow: PasswordEscrow) {
+    self.escrows.insert(escrow_id, escrow);
+  }
+
+  pub fn delete_escrow(&mut self, escrow_id: &UuidV4) -> Option<PasswordEscrow> {
+    self.escrows.remove(escrow_id)
+  }
+}
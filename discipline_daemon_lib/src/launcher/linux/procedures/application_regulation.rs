@@ -0,0 +1,231 @@
+use crate::x::{DateTime, IsTextualError};
+use super::*;
+
+pub struct EnforceApplicationRegulations {
+  // Scanning /proc is slow, so the caller does it without the daemon
+  // locked.
+  pub processes: Vec<ProcessInfo>,
+}
+
+impl EnforceApplicationRegulations {
+  // Counts the runtime of regulated applications, then kills every
+  // process of an application that's blocked for its user. This also
+  // catches processes that started before their application became
+  // blocked, and ones whose exec events were missed.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    process_backend: &impl IsProcessBackend,
+    textual_error: &mut impl IsTextualError,
+  ) {
+    let processes = self.processes;
+
+    let time = DateTime::now().time();
+    let now = daemon.state.monotonic_clock.now();
+
+    for profile in daemon.state.user_profiles.get_profiles_mut() {
+      let user_id = profile.user_id;
+
+      for (name, regulation) in profile.application_regulations.get_regulations_mut() {
+        let is_running = processes
+          .iter()
+          .any(|process| process.user_id == user_id && name.matches(process));
+
+        regulation.runtime.synchronize(time, now, is_running);
+      }
+    }
+
+    for process in &processes {
+      let Some(profile) = daemon.state.user_profiles.get_profile_given_user_id(process.user_id) else {
+        continue;
+      };
+
+      if !profile.application_regulations.is_process_blocked(process, time, now) {
+        continue;
+      }
+
+      if let Err(()) = process_backend.kill_process(process.process_id, textual_error) {
+        // TODO: Use a proper logging mechanism.
+        eprintln!("Discipline Linux Daemon: Failed to kill a process of a blocked application");
+      }
+    }
+  }
+}
+
+pub struct HandleProcessExecution {
+  pub process_id: ProcessId,
+}
+
+impl HandleProcessExecution {
+  // Kills a process that just started if its application is blocked
+  // for its user, before it gets the chance to do much.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    process_backend: &impl IsProcessBackend,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    // It may have exited already.
+    let Some(process) = process_backend.get_process(self.process_id) else {
+      return Ok(());
+    };
+
+    let Some(profile) = daemon.state.user_profiles.get_profile_given_user_id(process.user_id) else {
+      return Ok(());
+    };
+
+    let time = DateTime::now().time();
+    let now = daemon.state.monotonic_clock.now();
+
+    if !profile.application_regulations.is_process_blocked(&process, time, now) {
+      return Ok(());
+    }
+
+    process_backend.kill_process(process.process_id, textual_error)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use std::ffi::CString;
+  use std::path::PathBuf;
+  use crate::x::{AlwaysRule, CountdownAfterPleaConditional, Duration, RuleEnabler, RulesStats, TextualError, TimeAllowanceRule, TimeAllowanceRules, UserUptimeClock, UuidV4};
+  use super::*;
+
+  const REGULATED_USER_ID: u32 = 1000;
+
+  fn create_daemon() -> Daemon {
+    let mut textual_error = TextualError::new("Creating a daemon for a test");
+    Daemon::open_in_memory(&mut textual_error).unwrap()
+  }
+
+  fn create_process(process_id: ProcessId, user_id: u32, executable_path: &str) -> ProcessInfo {
+    let executable_path = PathBuf::from(executable_path);
+
+    ProcessInfo {
+      process_id,
+      user_id: UserId::new(user_id),
+      name: executable_path.file_name().unwrap().to_string_lossy().into_owned(),
+      executable_path: Some(executable_path),
+    }
+  }
+
+  fn create_enabler() -> RuleEnabler {
+    RuleEnabler::CountdownAfterPlea(CountdownAfterPleaConditional::create(Duration::HOUR))
+  }
+
+  fn add_profile(daemon: &mut Daemon, application_regulations: ApplicationRegulations) {
+    let now = daemon.state.monotonic_clock.now();
+
+    let profile = UserProfile {
+      name: UserProfileName::new("Alex".to_string()).unwrap(),
+      user_id: UserId::new(REGULATED_USER_ID),
+      user_name: UserName::new(CString::new("alex").unwrap()),
+      uptime_clock: UserUptimeClock::construct(
+        false,
+        now,
+        Duration::zero(),
+        now,
+        Duration::zero(),
+        now,
+        Duration::HOUR,
+      ),
+      device_access_regulation: DeviceAccessRegulation::new(),
+      screen_access_regulation: ScreenAccessRegulation::default(),
+      internet_access_regulation: InternetAccessRegulation::new(),
+      application_regulations,
+      login_policy: LoginPolicy::default(),
+      rules_stats: RulesStats::new(RulesStats::DEFAULT_MAXIMUM_RULES_NUMBER),
+      is_orphaned: false,
+    };
+
+    daemon.state.user_profiles.add_user(UuidV4::generate(), profile);
+  }
+
+  fn add_steam_locked_profile(daemon: &mut Daemon) {
+    let mut regulation = ApplicationRegulation::default();
+    regulation.always_rules.rules.insert(UuidV4::generate(), AlwaysRule::create(create_enabler()));
+
+    let mut application_regulations = ApplicationRegulations::new();
+    application_regulations.add_regulation(ApplicationName::new("steam".to_string()), regulation);
+    add_profile(daemon, application_regulations);
+  }
+
+  fn advance_clock(daemon: &mut Daemon, duration: Duration) {
+    let clock = &mut daemon.state.monotonic_clock;
+    clock.total_elapsed_duration = clock.total_elapsed_duration.saturating_add(duration);
+  }
+
+  #[test]
+  fn only_blocked_applications_of_regulated_users_are_killed() {
+    let mut daemon = create_daemon();
+    add_steam_locked_profile(&mut daemon);
+
+    let processes = vec![
+      create_process(1, REGULATED_USER_ID, "/usr/bin/steam"),
+      create_process(2, REGULATED_USER_ID, "/usr/bin/vim"),
+      create_process(3, REGULATED_USER_ID + 1, "/usr/bin/steam"),
+    ];
+    let process_backend = MockProcessBackend::new(processes.clone());
+
+    let mut textual_error = TextualError::new("Testing application regulations");
+    EnforceApplicationRegulations { processes }.execute(&mut daemon, &process_backend, &mut textual_error);
+
+    assert_eq!(process_backend.get_killed_process_ids(), vec![1]);
+  }
+
+  #[test]
+  fn blocked_applications_are_killed_as_they_start() {
+    let mut daemon = create_daemon();
+    add_steam_locked_profile(&mut daemon);
+
+    let process_backend = MockProcessBackend::new(vec![
+      create_process(1, REGULATED_USER_ID, "/usr/bin/steam"),
+      create_process(2, REGULATED_USER_ID, "/usr/bin/vim"),
+    ]);
+
+    let mut textual_error = TextualError::new("Testing application regulations");
+    HandleProcessExecution { process_id: 1 }.execute(&mut daemon, &process_backend, &mut textual_error).unwrap();
+    HandleProcessExecution { process_id: 2 }.execute(&mut daemon, &process_backend, &mut textual_error).unwrap();
+    // Exited before we got to it.
+    HandleProcessExecution { process_id: 3 }.execute(&mut daemon, &process_backend, &mut textual_error).unwrap();
+
+    assert_eq!(process_backend.get_killed_process_ids(), vec![1]);
+  }
+
+  #[test]
+  fn the_days_runtime_survives_a_restart() {
+    let mut daemon = create_daemon();
+    let name = ApplicationName::new("steam".to_string());
+
+    let rule = TimeAllowanceRule::construct(create_enabler(), Duration::HOUR);
+    let regulation = ApplicationRegulation {
+      daily_allowance_rules: TimeAllowanceRules::construct(HashMap::from([(UuidV4::generate(), rule)])),
+      ..ApplicationRegulation::default()
+    };
+    let mut application_regulations = ApplicationRegulations::new();
+    application_regulations.add_regulation(name.clone(), regulation);
+    add_profile(&mut daemon, application_regulations);
+
+    let processes = vec![create_process(1, REGULATED_USER_ID, "/usr/bin/steam")];
+    let process_backend = MockProcessBackend::new(processes.clone());
+    let mut textual_error = TextualError::new("Testing application regulations");
+
+    EnforceApplicationRegulations { processes: processes.clone() }.execute(&mut daemon, &process_backend, &mut textual_error);
+    advance_clock(&mut daemon, Duration::MINUTE);
+    EnforceApplicationRegulations { processes }.execute(&mut daemon, &process_backend, &mut textual_error);
+
+    daemon.persist(&mut textual_error).unwrap();
+    daemon.reload_state(&mut textual_error).unwrap();
+
+    let runtimes = daemon
+      .state
+      .user_profiles
+      .get_profile_given_user_id(UserId::new(REGULATED_USER_ID))
+      .unwrap()
+      .application_regulations
+      .get_runtimes();
+    assert_eq!(runtimes[&name].day_runtime, Duration::MINUTE);
+  }
+}
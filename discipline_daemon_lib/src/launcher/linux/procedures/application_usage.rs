@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use crate::x::{DateTime, IsTextualError};
+use crate::x::database::application_usage_table;
+use super::*;
+
+pub struct RecordProcessEvent {
+  pub event: ProcessEvent,
+}
+
+impl RecordProcessEvent {
+  // Starts or stops counting a process's run time, for users who have
+  // a profile.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    process_backend: &impl IsProcessBackend,
+  ) {
+    let now = daemon.state.monotonic_clock.now();
+
+    match self.event {
+      ProcessEvent::Execution(process_id) => {
+        let Some(process) = process_backend.get_process(process_id) else {
+          return;
+        };
+
+        if daemon.state.user_profiles.get_profile_given_user_id(process.user_id).is_none() {
+          return;
+        }
+
+        daemon.state.application_usage_tracker.on_process_started(&process, now);
+      }
+      ProcessEvent::Exit(process_id) => {
+        daemon.state.application_usage_tracker.on_process_exited(process_id, now);
+      }
+    }
+  }
+}
+
+pub struct SaveApplicationUsage {
+  // Like EnforceApplicationRegulations, the caller scans /proc.
+  pub processes: Vec<ProcessInfo>,
+}
+
+impl SaveApplicationUsage {
+  // Adds the run time counted since the last save to today's buckets
+  // and forgets the days past the retention limit.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let now = daemon.state.monotonic_clock.now();
+
+    let processes: Vec<ProcessInfo> = self
+      .processes
+      .into_iter()
+      .filter(|process| {
+        daemon
+          .state
+          .user_profiles
+          .get_profile_given_user_id(process.user_id)
+          .is_some()
+      })
+      .collect();
+
+    let tracker = &mut daemon.state.application_usage_tracker;
+    tracker.synchronize(&processes, now);
+
+    let today = get_day_number(DateTime::now());
+    let retention_days = tracker.get_configuration().retention_days.max(1);
+    let oldest_kept_day = today - (retention_days as i64 - 1);
+
+    let run_times: Vec<_> = tracker
+      .take_run_times(now)
+      .into_iter()
+      .map(|(user_id, executable, run_time)| (user_id, executable, today, run_time))
+      .collect();
+
+    application_usage_table::add_run_times(
+      &daemon.database,
+      &run_times,
+      oldest_kept_day,
+      textual_error,
+    )
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetTopApplications {
+  pub user_id: UserId,
+  pub period: UsagePeriod,
+  pub limit: usize,
+}
+
+impl GetTopApplications {
+  // The applications the user ran the longest within the period,
+  // longest first. Run time that isn't saved yet isn't included.
+  pub fn execute(
+    self,
+    daemon: &Daemon,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Vec<ApplicationUsage>, ()> {
+    let today = get_day_number(DateTime::now());
+
+    let usage = application_usage_table::select_top(
+      &daemon.database,
+      self.user_id,
+      self.period.get_first_day(today),
+      self.limit,
+      textual_error,
+    )?;
+
+    Ok(
+      usage
+        .into_iter()
+        .map(|(executable, run_time)| ApplicationUsage { executable, run_time })
+        .collect()
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::x::{Duration, TextualError};
+  use super::*;
+
+  fn create_daemon() -> Daemon {
+    let mut textual_error = TextualError::new("Creating a daemon for a test");
+    Daemon::open_in_memory(&mut textual_error).unwrap()
+  }
+
+  fn minutes(minutes: u64) -> Duration {
+    Duration::from_milliseconds(minutes * 60 * 1000)
+  }
+
+  fn get_top_applications(daemon: &Daemon, user_id: UserId, period: UsagePeriod, limit: usize) -> Vec<ApplicationUsage> {
+    let mut textual_error = TextualError::new("Testing application usage");
+    GetTopApplications { user_id, period, limit }
+      .execute(daemon, &mut textual_error)
+      .unwrap()
+  }
+
+  fn create_usage(executable: &str, run_time: Duration) -> ApplicationUsage {
+    ApplicationUsage { executable: executable.to_string(), run_time }
+  }
+
+  #[test]
+  fn usage_is_summed_over_the_period_longest_first() {
+    let daemon = create_daemon();
+    let today = get_day_number(DateTime::now());
+    let user_id = UserId::new(1000);
+    let other_user_id = UserId::new(1001);
+
+    let mut textual_error = TextualError::new("Saving application usage for a test");
+    application_usage_table::add_run_times(
+      &daemon.database,
+      &[
+        (user_id, "/usr/bin/firefox".to_string(), today, minutes(10)),
+        (user_id, "/usr/bin/firefox".to_string(), today - 1, minutes(30)),
+        (user_id, "/usr/bin/vim".to_string(), today, minutes(20)),
+        (user_id, "/usr/bin/gimp".to_string(), today - 7, minutes(90)),
+        (other_user_id, "/usr/bin/steam".to_string(), today, minutes(600)),
+      ],
+      today - 30,
+      &mut textual_error,
+    ).unwrap();
+
+    assert_eq!(
+      get_top_applications(&daemon, user_id, UsagePeriod::Today, 10),
+      vec![create_usage("/usr/bin/vim", minutes(20)), create_usage("/usr/bin/firefox", minutes(10))],
+    );
+    assert_eq!(
+      get_top_applications(&daemon, user_id, UsagePeriod::ThisWeek, 10),
+      vec![create_usage("/usr/bin/firefox", minutes(40)), create_usage("/usr/bin/vim", minutes(20))],
+    );
+    assert_eq!(
+      get_top_applications(&daemon, user_id, UsagePeriod::ThisWeek, 1),
+      vec![create_usage("/usr/bin/firefox", minutes(40))],
+    );
+  }
+
+  #[test]
+  fn saving_adds_to_todays_usage_and_forgets_old_days() {
+    let mut daemon = create_daemon();
+    let today = get_day_number(DateTime::now());
+    let user_id = UserId::new(1000);
+
+    let mut textual_error = TextualError::new("Saving application usage for a test");
+    application_usage_table::add_run_times(
+      &daemon.database,
+      &[
+        (user_id, "/usr/bin/vim".to_string(), today, minutes(5)),
+        (user_id, "/usr/bin/gimp".to_string(), today - 1000, minutes(5)),
+      ],
+      today - 2000,
+      &mut textual_error,
+    ).unwrap();
+
+    // Nobody has a profile, so nothing running is counted, but the
+    // retention limit still applies.
+    SaveApplicationUsage { processes: Vec::new() }
+      .execute(&mut daemon, &mut textual_error)
+      .unwrap();
+
+    let mut textual_error = TextualError::new("Reading application usage for a test");
+    let usage = application_usage_table::select_top(&daemon.database, user_id, today - 2000, 10, &mut textual_error).unwrap();
+    assert_eq!(usage, vec![("/usr/bin/vim".to_string(), minutes(5))]);
+  }
+}
@@ -0,0 +1,65 @@
+use tokio::sync::Mutex;
+use crate::x::{DateTime, IsTextualError};
+use super::*;
+
+pub struct SendBlockWarnings;
+
+impl SendBlockWarnings {
+  // Warns users with live sessions that a block is about to start.
+  //
+  // Notifications are sent after the daemon is unlocked, since each
+  // one runs a command in the user's session. Call it where blocking
+  // is fine.
+  pub fn execute(
+    self,
+    daemon: &Mutex<Daemon>,
+    notification_backend: &impl IsNotificationBackend,
+    textual_error: &mut impl IsTextualError,
+  ) {
+    let mut warnings = Vec::new();
+
+    {
+      let mut daemon = daemon.blocking_lock();
+      let daemon = &mut *daemon;
+      let time = DateTime::now().time();
+      let now = daemon.state.monotonic_clock.now();
+
+      let session_records = &daemon.state.session_records;
+      daemon
+        .state
+        .block_warnings
+        .retain(|user_name| session_records.has_open_sessions(user_name));
+
+      for user_name in daemon.state.session_records.get_users_with_open_sessions() {
+        let Some(profile) = daemon.state.user_profiles.get_profile_given_user_name(&user_name) else {
+          continue;
+        };
+
+        let user_id = profile.user_id;
+        let upcoming_block = profile.get_next_block(time, now);
+
+        let Some(remaining) = daemon
+          .state
+          .block_warnings
+          .take_due_warning(&user_name, upcoming_block, now)
+        else {
+          continue;
+        };
+
+        let Some(upcoming_block) = upcoming_block else {
+          continue;
+        };
+
+        let (summary, body) = create_block_warning_message(upcoming_block.reason, remaining);
+        warnings.push((user_id, user_name, summary, body));
+      }
+    }
+
+    for (user_id, user_name, summary, body) in warnings {
+      if let Err(()) = notification_backend.notify(user_id, &user_name, &summary, &body, textual_error) {
+        // TODO: Use a proper logging mechanism.
+        eprintln!("Discipline Linux Daemon: Failed to warn a user of an upcoming block");
+      }
+    }
+  }
+}
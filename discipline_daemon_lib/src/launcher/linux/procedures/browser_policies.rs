@@ -0,0 +1,81 @@
+use crate::x::{DateTime, IsTextualError};
+use super::*;
+
+pub struct ReconcileBrowserPolicies;
+
+impl ReconcileBrowserPolicies {
+  // Writes browser policy files enforcing the web rules of everyone
+  // who's logged in, or puts back whatever was there before once
+  // nobody's rules restrict anything.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let time = DateTime::now().time();
+    let now = daemon.state.monotonic_clock.now();
+
+    let web_rules: Vec<WebRules> = daemon
+      .state
+      .session_records
+      .get_users_with_open_sessions()
+      .iter()
+      .filter_map(|user_name| daemon.state.user_profiles.get_profile_given_user_name(user_name))
+      .map(|profile| WebRules::create(profile, time, now))
+      .collect();
+
+    let policy = BrowserPolicy::create(&web_rules);
+    if !daemon.state.browser_policies.needs_applying(&policy) {
+      return Ok(());
+    }
+
+    let Some(configuration) = daemon.state.browser_policies.get_configuration() else {
+      return Ok(());
+    };
+
+    if policy.is_restrictive() {
+      write_file_atomically(
+        &configuration.firefox_policies_path,
+        generate_firefox_policies(&policy).as_bytes(),
+        textual_error,
+      )?;
+
+      let chromium_policies = generate_chromium_policies(&policy);
+      for path in &configuration.chromium_policies_paths {
+        write_file_atomically(path, chromium_policies.as_bytes(), textual_error)?;
+      }
+    } else {
+      restore_browser_policy_files(configuration, textual_error)?;
+    }
+
+    daemon.state.browser_policies.on_policy_applied(policy);
+    Ok(())
+  }
+}
+
+pub struct RemoveBrowserPolicies;
+
+impl RemoveBrowserPolicies {
+  // Puts back whatever policy files were there before we wrote ours,
+  // for when the daemon is uninstalled.
+  pub fn execute(
+    self,
+    configuration: &BrowserPoliciesConfiguration,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    restore_browser_policy_files(configuration, textual_error)
+  }
+}
+
+fn restore_browser_policy_files(
+  configuration: &BrowserPoliciesConfiguration,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  restore_file(&configuration.firefox_policies_path, textual_error)?;
+
+  for path in &configuration.chromium_policies_paths {
+    restore_file(path, textual_error)?;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,52 @@
+use tokio::sync::Mutex;
+use crate::x::IsTextualError;
+use super::*;
+
+pub struct ReconcileDnsRedirects;
+
+impl ReconcileDnsRedirects {
+  // Makes the firewall send the DNS queries of every profiled user,
+  // and only theirs, to our resolver. Like ReconcileInternetBlocking,
+  // it doesn't keep the daemon locked while nft runs.
+  pub fn execute(
+    self,
+    daemon: &Mutex<Daemon>,
+    firewall_backend: &impl IsFirewallBackend,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let ruleset = {
+      let daemon = daemon.blocking_lock();
+
+      let user_ids: Vec<UserId> = daemon
+        .state
+        .user_profiles
+        .get_profiles()
+        .map(|profile| profile.user_id)
+        .collect();
+
+      let ruleset = daemon.state.dns_redirector.generate_ruleset(&user_ids);
+      if !daemon.state.dns_redirector.needs_applying(&ruleset) {
+        return Ok(());
+      }
+
+      ruleset
+    };
+
+    firewall_backend.apply_ruleset(&ruleset, textual_error)?;
+    daemon.blocking_lock().state.dns_redirector.on_ruleset_applied(ruleset);
+    Ok(())
+  }
+}
+
+pub struct ReloadBlocklists;
+
+impl ReloadBlocklists {
+  // Picks up blocklist files that were added, changed or removed.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    daemon.state.domain_blocklists.reload(textual_error)
+  }
+}
@@ -0,0 +1,472 @@
+use serde::{Deserialize, Serialize};
+use crate::x::{IsTextualError, UuidV4};
+use crate::x::database::group_profile_table;
+use super::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateGroupProfile {
+  pub group_profile_id: Option<UuidV4>,
+  pub name: UserProfileName,
+  pub group_name: GroupName,
+  pub device_access_regulation: DeviceAccessRegulation,
+  pub screen_access_regulation: ScreenAccessRegulation,
+  pub internet_access_regulation: InternetAccessRegulation,
+  pub application_regulations: ApplicationRegulations,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CreateGroupProfileReturn {
+  NoSuchGroup,
+  GroupAlreadyRegulated,
+  DuplicateGroupProfileId,
+  InternalError,
+  Success(UuidV4),
+}
+
+impl CreateGroupProfile {
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    textual_error: &mut impl IsTextualError,
+  ) -> CreateGroupProfileReturn {
+    let group = match get_group_file_entry_with_group_name(
+      &self.group_name,
+      &AllocationConfig::default(),
+    ) {
+      Ok(group) => {
+        group
+      }
+      Err(GetGroupFileEntryError::NoSuchGroup) => {
+        return CreateGroupProfileReturn::NoSuchGroup;
+      }
+      Err(GetGroupFileEntryError::NotEnoughMemory) => {
+        textual_error.change_context("Looking up the group to be regulated");
+        textual_error.add_message("Not enough memory");
+        return CreateGroupProfileReturn::InternalError;
+      }
+      Err(GetGroupFileEntryError::SystemCallFailed) => {
+        textual_error.change_context("Looking up the group to be regulated");
+        textual_error.add_message("getgrnam_r failed");
+        return CreateGroupProfileReturn::InternalError;
+      }
+    };
+
+    let is_group_regulated = daemon
+      .state
+      .user_profiles
+      .get_all_group_profiles()
+      .any(|(_, group_profile)| !group_profile.is_orphaned && group_profile.group_id == group.group_id);
+
+    if is_group_regulated {
+      return CreateGroupProfileReturn::GroupAlreadyRegulated;
+    }
+
+    let group_profile_id = self.group_profile_id.unwrap_or_else(UuidV4::generate);
+    if daemon.state.user_profiles.get_group_profile_given_id(&group_profile_id).is_some() {
+      return CreateGroupProfileReturn::DuplicateGroupProfileId;
+    }
+
+    let Ok(members) = get_group_members(&group, textual_error) else {
+      return CreateGroupProfileReturn::InternalError;
+    };
+
+    let mut group_profile = GroupProfile::construct(
+      self.name,
+      group.group_id,
+      group.group_name,
+      self.device_access_regulation,
+      self.screen_access_regulation,
+      self.internet_access_regulation,
+      self.application_regulations,
+    );
+
+    if let Err(()) = group_profile_table::save_group_profile(
+      &daemon.database,
+      &group_profile_id,
+      &group_profile,
+      textual_error,
+    ) {
+      return CreateGroupProfileReturn::InternalError;
+    }
+
+    group_profile.members = members;
+    daemon.state.user_profiles.add_group_profile(group_profile_id.clone(), group_profile);
+
+    CreateGroupProfileReturn::Success(group_profile_id)
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteGroupProfile {
+  pub group_profile_id: UuidV4,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DeleteGroupProfileReturn {
+  NoSuchGroupProfile,
+  InternalError,
+  Success,
+}
+
+impl DeleteGroupProfile {
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    textual_error: &mut impl IsTextualError,
+  ) -> DeleteGroupProfileReturn {
+    if daemon.state.user_profiles.get_group_profile_given_id(&self.group_profile_id).is_none() {
+      return DeleteGroupProfileReturn::NoSuchGroupProfile;
+    }
+
+    if let Err(()) = group_profile_table::delete_group_profile(
+      &daemon.database,
+      &self.group_profile_id,
+      textual_error,
+    ) {
+      return DeleteGroupProfileReturn::InternalError;
+    }
+
+    daemon.state.user_profiles.delete_group_profile(&self.group_profile_id);
+    DeleteGroupProfileReturn::Success
+  }
+}
+
+pub struct SaveGroupProfile {
+  pub group_profile_id: UuidV4,
+}
+
+impl SaveGroupProfile {
+  // Saves a group profile that was changed in place, through
+  // `get_group_profile_given_id_mut`, and starts enforcing the change.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    daemon.state.user_profiles.rebuild_effective_profiles();
+
+    let Some(group_profile) = daemon.state.user_profiles.get_group_profile_given_id(&self.group_profile_id) else {
+      return Ok(());
+    };
+
+    group_profile_table::save_group_profile(
+      &daemon.database,
+      &self.group_profile_id,
+      group_profile,
+      textual_error,
+    )
+  }
+}
+
+pub struct RefreshGroupMemberships;
+
+impl RefreshGroupMemberships {
+  // Reads every regulated group's members from the account databases.
+  // Groups are identified by their group id, so a renamed group stays
+  // regulated. When a profile's group id no longer exists but its group
+  // name does, the group was deleted and created again, and the profile
+  // follows it. Profiles left without a group are marked orphaned.
+  //
+  // Profiles whose group couldn't be looked up keep their members, and
+  // an error is returned once all the others are done.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let now = daemon.state.monotonic_clock.now();
+
+    let group_profiles: Vec<(UuidV4, GroupId, GroupName)> = daemon
+      .state
+      .user_profiles
+      .get_all_group_profiles()
+      .map(|(group_profile_id, group_profile)| {
+        (group_profile_id.clone(), group_profile.group_id, group_profile.group_name.clone())
+      })
+      .collect();
+
+    let mut lookups_failed = false;
+
+    for (group_profile_id, group_id, group_name) in group_profiles {
+      let group = match get_group_file_entry_with_group_id(group_id, &AllocationConfig::default()) {
+        Ok(group) => {
+          Some(group)
+        }
+        Err(GetGroupFileEntryError::NoSuchGroup) => {
+          match get_group_file_entry_with_group_name(&group_name, &AllocationConfig::default()) {
+            Ok(group) => {
+              // TODO: Use a proper logging mechanism.
+              eprintln!("Discipline Linux Daemon: A regulated group was re-created with a new group id, moved its profile to it");
+              Some(group)
+            }
+            Err(GetGroupFileEntryError::NoSuchGroup) => {
+              None
+            }
+            Err(_) => {
+              textual_error.change_context("Looking up a regulated group given its group name");
+              textual_error.add_message("getgrnam_r failed");
+              lookups_failed = true;
+              continue;
+            }
+          }
+        }
+        Err(_) => {
+          textual_error.change_context("Looking up a regulated group given its group id");
+          textual_error.add_message("getgrgid_r failed");
+          textual_error.add_attachement_display("Group id", group_id.inner());
+          lookups_failed = true;
+          continue;
+        }
+      };
+
+      let Some(group) = group else {
+        if daemon.state.user_profiles.mark_group_profile_orphaned(&group_profile_id) {
+          // TODO: Use a proper logging mechanism.
+          eprintln!("Discipline Linux Daemon: A regulated group was deleted, marked its profile orphaned");
+        }
+        continue;
+      };
+
+      let Ok(members) = get_group_members(&group, textual_error) else {
+        lookups_failed = true;
+        continue;
+      };
+
+      let is_group_changed = group.group_id != group_id || group.group_name != group_name;
+
+      daemon.state.user_profiles.set_group(
+        &group_profile_id,
+        group.group_id,
+        group.group_name,
+        members,
+      );
+
+      if is_group_changed {
+        let procedure = SaveGroupProfile { group_profile_id };
+        if let Err(()) = procedure.execute(daemon, textual_error) {
+          lookups_failed = true;
+        }
+      }
+    }
+
+    daemon.state.user_profiles.on_group_memberships_refreshed(now);
+
+    if lookups_failed {
+      return Err(());
+    }
+
+    Ok(())
+  }
+}
+
+// The users listed as members of the group, and the users whose primary
+// group it is.
+fn get_group_members(
+  group: &GroupFileEntry,
+  textual_error: &mut impl IsTextualError,
+) -> Result<Vec<(UserId, UserName)>, ()> {
+  let mut members: Vec<(UserId, UserName)> = Vec::new();
+
+  for member_name in &group.member_names {
+    match get_password_file_entry_with_user_name(member_name, &AllocationConfig::default()) {
+      Ok(account) => {
+        members.push((account.user_id, account.user_name));
+      }
+      // Group databases often list accounts that are long gone.
+      Err(GetPasswordFileEntryError::NoSuchUser) => {}
+      Err(_) => {
+        textual_error.change_context("Looking up a member of a regulated group");
+        textual_error.add_message("getpwnam_r failed");
+        return Err(());
+      }
+    }
+  }
+
+  // Safety: Nothing else in the daemon enumerates the password
+  // database, and this runs with the daemon locked.
+  for account in unsafe { all_users() } {
+    if account.primary_group_id == group.group_id {
+      members.push((account.user_id, account.user_name));
+    }
+  }
+
+  members.sort_by_key(|(user_id, _)| user_id.inner());
+  members.dedup_by_key(|(user_id, _)| *user_id);
+  Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use crate::x::{Duration, TextualError};
+  use crate::x::database::group_profile_table;
+  use super::*;
+
+  fn create_daemon() -> Daemon {
+    let mut textual_error = TextualError::new("Creating a daemon for a test");
+    Daemon::open_in_memory(&mut textual_error).unwrap()
+  }
+
+  fn create_procedure(group_name: &str) -> CreateGroupProfile {
+    CreateGroupProfile {
+      group_profile_id: None,
+      name: UserProfileName::new("Everyone in the group".to_string()).unwrap(),
+      group_name: GroupName::new(CString::new(group_name).unwrap()),
+      device_access_regulation: DeviceAccessRegulation::default(),
+      screen_access_regulation: ScreenAccessRegulation::default(),
+      internet_access_regulation: InternetAccessRegulation::new(),
+      application_regulations: ApplicationRegulations::default(),
+    }
+  }
+
+  // The root group exists everywhere, and root's primary group is it.
+  fn create_root_group_profile(daemon: &mut Daemon) -> UuidV4 {
+    let mut textual_error = TextualError::new("Creating a group profile for a test");
+
+    match create_procedure("root").execute(daemon, &mut textual_error) {
+      CreateGroupProfileReturn::Success(group_profile_id) => {
+        group_profile_id
+      }
+      other => {
+        panic!("Unexpected return: {other:?}");
+      }
+    }
+  }
+
+  #[test]
+  fn created_group_profiles_are_saved_with_their_members() {
+    let mut daemon = create_daemon();
+    let group_profile_id = create_root_group_profile(&mut daemon);
+
+    let group_profile = daemon.state.user_profiles.get_group_profile_given_id(&group_profile_id).unwrap();
+    assert_eq!(group_profile.group_id, GroupId::new(0));
+    assert!(group_profile.has_member(UserId::new(0)));
+
+    let mut textual_error = TextualError::new("Loading group profiles for a test");
+    let saved = group_profile_table::load_group_profiles(&daemon.database, &mut textual_error).unwrap();
+    assert!(saved.contains_key(&group_profile_id));
+  }
+
+  #[test]
+  fn a_group_is_only_regulated_once() {
+    let mut daemon = create_daemon();
+    create_root_group_profile(&mut daemon);
+
+    let mut textual_error = TextualError::new("Testing group profiles");
+    assert!(matches!(
+      create_procedure("root").execute(&mut daemon, &mut textual_error),
+      CreateGroupProfileReturn::GroupAlreadyRegulated,
+    ));
+  }
+
+  #[test]
+  fn missing_groups_are_refused() {
+    let mut daemon = create_daemon();
+    let mut textual_error = TextualError::new("Testing group profiles");
+
+    assert!(matches!(
+      create_procedure("discipline-no-such-group").execute(&mut daemon, &mut textual_error),
+      CreateGroupProfileReturn::NoSuchGroup,
+    ));
+  }
+
+  #[test]
+  fn taken_ids_are_refused() {
+    let mut daemon = create_daemon();
+    let group_profile_id = create_root_group_profile(&mut daemon);
+
+    // Any group but root, which is regulated already.
+    let groups = std::fs::read_to_string("/etc/group").unwrap();
+    let group_name = groups
+      .lines()
+      .filter_map(|line| line.split(':').next())
+      .find(|group_name| *group_name != "root")
+      .unwrap();
+
+    let mut procedure = create_procedure(group_name);
+    procedure.group_profile_id = Some(group_profile_id);
+
+    let mut textual_error = TextualError::new("Testing group profiles");
+    assert!(matches!(
+      procedure.execute(&mut daemon, &mut textual_error),
+      CreateGroupProfileReturn::DuplicateGroupProfileId,
+    ));
+  }
+
+  #[test]
+  fn deleted_group_profiles_are_forgotten() {
+    let mut daemon = create_daemon();
+    let group_profile_id = create_root_group_profile(&mut daemon);
+    let mut textual_error = TextualError::new("Testing group profiles");
+
+    let procedure = DeleteGroupProfile { group_profile_id: group_profile_id.clone() };
+    assert!(matches!(
+      procedure.execute(&mut daemon, &mut textual_error),
+      DeleteGroupProfileReturn::Success,
+    ));
+    assert!(daemon.state.user_profiles.get_group_profile_given_id(&group_profile_id).is_none());
+
+    let saved = group_profile_table::load_group_profiles(&daemon.database, &mut textual_error).unwrap();
+    assert!(!saved.contains_key(&group_profile_id));
+
+    // The root group may be regulated again.
+    create_root_group_profile(&mut daemon);
+
+    let procedure = DeleteGroupProfile { group_profile_id };
+    assert!(matches!(
+      procedure.execute(&mut daemon, &mut textual_error),
+      DeleteGroupProfileReturn::NoSuchGroupProfile,
+    ));
+  }
+
+  fn set_root_day_uptime(daemon: &mut Daemon, day_uptime: Duration) {
+    for profile in daemon.state.user_profiles.get_profiles_mut() {
+      if profile.user_id == UserId::new(0) {
+        profile.uptime_clock.day_uptime = day_uptime;
+      }
+    }
+  }
+
+  fn get_root_day_uptime(daemon: &Daemon) -> Duration {
+    daemon
+      .state
+      .user_profiles
+      .get_profile_given_user_id(UserId::new(0))
+      .unwrap()
+      .uptime_clock
+      .day_uptime
+  }
+
+  #[test]
+  fn the_usage_of_members_without_a_profile_of_their_own_survives_a_restart() {
+    let mut daemon = create_daemon();
+    create_root_group_profile(&mut daemon);
+    set_root_day_uptime(&mut daemon, Duration::HOUR);
+
+    let mut textual_error = TextualError::new("Restarting the daemon for a test");
+    daemon.persist(&mut textual_error).unwrap();
+    daemon.reload_state(&mut textual_error).unwrap();
+    // Memberships aren't saved, but read from the system at startup.
+    RefreshGroupMemberships.execute(&mut daemon, &mut textual_error).unwrap();
+
+    assert_eq!(get_root_day_uptime(&daemon), Duration::HOUR);
+  }
+
+  #[test]
+  fn members_regulated_again_pick_up_their_usage() {
+    let mut daemon = create_daemon();
+    let group_profile_id = create_root_group_profile(&mut daemon);
+    set_root_day_uptime(&mut daemon, Duration::HOUR);
+
+    let mut textual_error = TextualError::new("Testing group profiles");
+    let procedure = DeleteGroupProfile { group_profile_id };
+    assert!(matches!(
+      procedure.execute(&mut daemon, &mut textual_error),
+      DeleteGroupProfileReturn::Success,
+    ));
+    assert!(daemon.state.user_profiles.get_profile_given_user_id(UserId::new(0)).is_none());
+
+    create_root_group_profile(&mut daemon);
+    assert_eq!(get_root_day_uptime(&daemon), Duration::HOUR);
+  }
+}
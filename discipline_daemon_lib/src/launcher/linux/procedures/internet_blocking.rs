@@ -0,0 +1,52 @@
+use tokio::sync::Mutex;
+use crate::x::{DateTime, IsTextualError};
+use super::*;
+
+pub struct ReconcileInternetBlocking;
+
+impl ReconcileInternetBlocking {
+  // Makes the firewall drop the outbound traffic of exactly the users
+  // whose internet rules are active, and of web filtered users when
+  // the HTTP proxy is running.
+  //
+  // Applying a ruleset runs nft, so the daemon is only locked while
+  // the ruleset is generated and recorded. Call it where blocking is
+  // fine.
+  pub fn execute(
+    self,
+    daemon: &Mutex<Daemon>,
+    firewall_backend: &impl IsFirewallBackend,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let ruleset = {
+      let daemon = daemon.blocking_lock();
+
+      let time = DateTime::now().time();
+      let now = daemon.state.monotonic_clock.now();
+      let confines_web_filtered_users = daemon.state.internet_blocker.confines_web_filtered_users();
+
+      let blocked_user_ids: Vec<UserId> = daemon
+        .state
+        .user_profiles
+        .get_profiles()
+        .filter(|profile| {
+          profile.is_internet_access_blocked(time, now)
+          ||
+          confines_web_filtered_users && profile.internet_access_regulation.web_filter.enabled
+        })
+        .map(|profile| profile.user_id)
+        .collect();
+
+      let ruleset = daemon.state.internet_blocker.generate_ruleset(&blocked_user_ids);
+      if !daemon.state.internet_blocker.needs_applying(&ruleset) {
+        return Ok(());
+      }
+
+      ruleset
+    };
+
+    firewall_backend.apply_ruleset(&ruleset, textual_error)?;
+    daemon.blocking_lock().state.internet_blocker.on_ruleset_applied(ruleset);
+    Ok(())
+  }
+}
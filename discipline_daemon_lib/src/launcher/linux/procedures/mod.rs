@@ -1,4 +1,17 @@
 use super::*;
 
 // pub mod user_profile_screen_regulation_always_rules;
-// mod always_rule_procedures;
\ No newline at end of file
+// mod always_rule_procedures;
+
+pub mod password_escrow;
+pub mod session_enforcement;
+pub mod block_warnings;
+pub mod internet_blocking;
+pub mod dns_filtering;
+pub mod browser_policies;
+pub mod application_regulation;
+pub mod application_usage;
+pub mod user_accounts;
+pub mod group_profiles;
+pub mod pam_policy_cache;
+pub mod session_registry;
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use crate::x::{DateTime, IsTextualError};
+use super::*;
+
+pub struct WritePamPolicyCache;
+
+impl WritePamPolicyCache {
+  // Writes every regulated user's block schedule for the PAM module to
+  // fall back on, when it changed or is due for a refresh.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let now = daemon.state.monotonic_clock.now();
+    let wall_now = DateTime::now();
+
+    let schedules: HashMap<String, pam::BlockSchedule> = daemon
+      .state
+      .user_profiles
+      .get_profiles()
+      .filter(|profile| !profile.is_orphaned)
+      .filter_map(|profile| {
+        // Names that aren't UTF-8 can't be JSON keys. The module then
+        // treats the user as unregulated, and its failure policy
+        // decides.
+        let user_name = profile.user_name.inner().to_str().ok()?;
+        Some((user_name.to_owned(), pam::BlockSchedule::create(profile, now, wall_now)))
+      })
+      .collect();
+
+    let writer = &daemon.state.pam_policy_cache_writer;
+    if !writer.needs_writing(&schedules, now) {
+      return Ok(());
+    }
+
+    let Some(configuration) = writer.get_configuration() else {
+      return Ok(());
+    };
+
+    let cache = pam::PolicyCache {
+      written_at: wall_now.as_timestamp(),
+      schedules,
+    };
+
+    let encoded = match cache.encode(writer.get_authentication_token()) {
+      Ok(encoded) => {
+        encoded
+      }
+      Err(error) => {
+        textual_error.change_context("Writing the PAM policy cache");
+        textual_error.add_message("Failed to serialize the cache");
+        textual_error.add_attachement_display("Serialization error", error);
+        return Err(());
+      }
+    };
+
+    write_file_atomically_with_mode(&configuration.path, &encoded, 0o600, textual_error)?;
+
+    daemon.state.pam_policy_cache_writer.on_written(cache.schedules, now);
+    Ok(())
+  }
+}
@@ -0,0 +1,483 @@
+use serde::{Deserialize, Serialize};
+use crate::x::{IsTextualError, UuidV4, VaultDatum, VaultQuotaViolation};
+use crate::x::database::{password_escrow_table, vault_data_table, CountdownAfterPleaConditionalDbAdapter};
+use crate::x::procedures::vault;
+use super::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Create {
+  pub user_name: UserName,
+  pub vault_id: UuidV4,
+  pub escrow_id: Option<UuidV4>,
+  pub rotate_after_reveal: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CreateReturn {
+  NoSuchVault,
+  NoSuchUser,
+  RefusingToEscrowRootPassword,
+  PasswordAlreadyEscrowed,
+  QuotaViolation(VaultQuotaViolation),
+  DuplicateEscrowId,
+  PasswordChangeFailed,
+  InternalError,
+  Success,
+}
+
+impl Create {
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    password_backend: &impl IsPasswordBackend,
+    textual_error: &mut impl IsTextualError,
+  ) -> CreateReturn {
+    let Some(vault) = daemon.state.vaults.get_vault_given_id(&self.vault_id) else {
+      return CreateReturn::NoSuchVault;
+    };
+
+    let account = match get_password_file_entry_with_user_name(
+      &self.user_name,
+      &AllocationConfig::default(),
+    ) {
+      Ok(account) => {
+        account
+      }
+      Err(GetPasswordFileEntryError::NoSuchUser) => {
+        return CreateReturn::NoSuchUser;
+      }
+      Err(GetPasswordFileEntryError::NotEnoughMemory) => {
+        textual_error.change_context("Looking up the account whose password is to be escrowed");
+        textual_error.add_message("Not enough memory");
+        return CreateReturn::InternalError;
+      }
+      Err(GetPasswordFileEntryError::SystemCallFailed) => {
+        textual_error.change_context("Looking up the account whose password is to be escrowed");
+        textual_error.add_message("getpwnam_r failed");
+        return CreateReturn::InternalError;
+      }
+    };
+
+    // Losing the root password may leave the system unrecoverable.
+    if account.user_id.inner() == 0 {
+      return CreateReturn::RefusingToEscrowRootPassword;
+    }
+
+    if daemon.state.password_escrows.get_escrow_given_user_id(account.user_id).is_some() {
+      return CreateReturn::PasswordAlreadyEscrowed;
+    }
+
+    let Ok(password) = generate_password(DEFAULT_PASSWORD_LENGTH, textual_error) else {
+      return CreateReturn::InternalError;
+    };
+
+    let Ok(datum) = VaultDatum::new(password.clone()) else {
+      return CreateReturn::InternalError;
+    };
+
+    if let Err(violation) = daemon.state.vaults_stats.check_datum_quotas(vault, &datum, None) {
+      return CreateReturn::QuotaViolation(violation);
+    }
+
+    let client_created_escrow_id = self.escrow_id.is_some();
+    let escrow_id = self.escrow_id.unwrap_or_else(UuidV4::generate);
+    let datum_id = UuidV4::generate();
+    let escrow = PasswordEscrow {
+      vault_id: self.vault_id.clone(),
+      datum_id: datum_id.clone(),
+      user_id: account.user_id,
+      user_name: account.user_name,
+      rotate_after_reveal: self.rotate_after_reveal,
+    };
+
+    if vault_data_table::insert_datum(
+      &daemon.database,
+      &self.vault_id,
+      &datum_id,
+      &datum,
+      textual_error,
+    ).is_err() {
+      return CreateReturn::InternalError;
+    }
+
+    if let Err(error) = password_escrow_table::insert_escrow(
+      &daemon.database,
+      &escrow_id,
+      &escrow,
+      textual_error,
+    ) {
+      let _ = vault_data_table::delete_datum(&daemon.database, &datum_id, textual_error);
+
+      return match error {
+        password_escrow_table::InsertError::DuplicateEscrowId if client_created_escrow_id => {
+          CreateReturn::DuplicateEscrowId
+        }
+        password_escrow_table::InsertError::DuplicateEscrowId => {
+          CreateReturn::InternalError
+        }
+        password_escrow_table::InsertError::Other => {
+          CreateReturn::InternalError
+        }
+      };
+    }
+
+    // The password is stored before it's set, so that there's never
+    // a moment where the account has a password nobody knows.
+    if password_backend.set_password(
+      &escrow.user_name,
+      &password,
+      textual_error,
+    ).is_err() {
+      let _ = password_escrow_table::delete_escrow(&daemon.database, &escrow_id, textual_error);
+      let _ = vault_data_table::delete_datum(&daemon.database, &datum_id, textual_error);
+      return CreateReturn::PasswordChangeFailed;
+    }
+
+    daemon.state.vaults_stats.update_after_datum_added(datum.get_size());
+    if let Some(vault) = daemon.state.vaults.get_vault_given_id_mut(&self.vault_id) {
+      vault.set_datum(datum_id, datum);
+    }
+    daemon.state.password_escrows.add_escrow(escrow_id, escrow);
+
+    CreateReturn::Success
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rotate {
+  pub escrow_id: UuidV4,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RotateReturn {
+  NoSuchEscrow,
+  NoSuchVault,
+  PasswordChangeFailed,
+  InternalError,
+  Success,
+}
+
+impl Rotate {
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    password_backend: &impl IsPasswordBackend,
+    textual_error: &mut impl IsTextualError,
+  ) -> RotateReturn {
+    rotate_password(daemon, password_backend, &self.escrow_id, textual_error)
+  }
+}
+
+fn rotate_password(
+  daemon: &mut Daemon,
+  password_backend: &impl IsPasswordBackend,
+  escrow_id: &UuidV4,
+  textual_error: &mut impl IsTextualError,
+) -> RotateReturn {
+  let Some(escrow) = daemon.state.password_escrows.get_escrow_given_id(escrow_id) else {
+    return RotateReturn::NoSuchEscrow;
+  };
+
+  let Some(vault) = daemon.state.vaults.get_vault_given_id_mut(&escrow.vault_id) else {
+    return RotateReturn::NoSuchVault;
+  };
+
+  let Ok(password) = generate_password(DEFAULT_PASSWORD_LENGTH, textual_error) else {
+    return RotateReturn::InternalError;
+  };
+
+  let Ok(datum) = VaultDatum::new(password.clone()) else {
+    return RotateReturn::InternalError;
+  };
+
+  if vault_data_table::update_value(
+    &daemon.database,
+    &escrow.datum_id,
+    &datum,
+    textual_error,
+  ).is_err() {
+    return RotateReturn::InternalError;
+  }
+
+  if password_backend.set_password(
+    &escrow.user_name,
+    &password,
+    textual_error,
+  ).is_err() {
+    // The account still has the previous password, so put it back.
+    if let Some(previous_datum) = vault.data.get(&escrow.datum_id) {
+      let _ = vault_data_table::update_value(
+        &daemon.database,
+        &escrow.datum_id,
+        previous_datum,
+        textual_error,
+      );
+    }
+    return RotateReturn::PasswordChangeFailed;
+  }
+
+  let previous_datum_size = vault
+    .data
+    .get(&escrow.datum_id)
+    .map(VaultDatum::get_size)
+    .unwrap_or(0);
+
+  daemon.state.vaults_stats.update_after_datum_replaced(previous_datum_size, datum.get_size());
+  vault.set_datum(escrow.datum_id.clone(), datum);
+  RotateReturn::Success
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delete {
+  pub escrow_id: UuidV4,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DeleteReturn {
+  NoSuchEscrow,
+  InternalError,
+  Success,
+}
+
+impl Delete {
+  // The password stays in the vault. Only the link between the
+  // account and the vault is removed, so it's no longer rotated.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    textual_error: &mut impl IsTextualError,
+  ) -> DeleteReturn {
+    if daemon.state.password_escrows.get_escrow_given_id(&self.escrow_id).is_none() {
+      return DeleteReturn::NoSuchEscrow;
+    }
+
+    if password_escrow_table::delete_escrow(
+      &daemon.database,
+      &self.escrow_id,
+      textual_error,
+    ).is_err() {
+      return DeleteReturn::InternalError;
+    }
+
+    daemon.state.password_escrows.delete_escrow(&self.escrow_id);
+    DeleteReturn::Success
+  }
+}
+
+pub struct RelockVaults;
+
+impl RelockVaults {
+  // Relocks vaults whose reveal window is over, then rotates the
+  // escrowed passwords that were revealed in them.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    password_backend: &impl IsPasswordBackend,
+    textual_error: &mut impl IsTextualError,
+  ) {
+    let relocked_vaults = vault::relock_finished_reveals(
+      &daemon.database,
+      &CountdownAfterPleaConditionalDbAdapter {},
+      &mut daemon.state.vaults,
+      &daemon.state.monotonic_clock,
+      textual_error,
+    );
+
+    for vault_id in relocked_vaults {
+      for escrow_id in daemon.state.password_escrows.get_escrows_to_rotate_after_reveal(&vault_id) {
+        let _ = rotate_password(daemon, password_backend, &escrow_id, textual_error);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use crate::x::{Countdown, CountdownAfterPleaConditional, Duration, TextualError, Vault, VaultDatumContent, VaultName, VaultProtector};
+  use crate::x::database::vault_table;
+  use super::*;
+
+  // Any account but root's, since its password is never escrowed.
+  fn find_regular_user_name() -> UserName {
+    let accounts = std::fs::read_to_string("/etc/passwd").unwrap();
+
+    accounts
+      .lines()
+      .filter_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let user_id = fields.nth(1)?;
+        (user_id != "0").then(|| UserName::new(CString::new(name).unwrap()))
+      })
+      .next()
+      .unwrap()
+  }
+
+  fn create_daemon() -> Daemon {
+    let mut textual_error = TextualError::new("Creating a daemon for a test");
+    Daemon::open_in_memory(&mut textual_error).unwrap()
+  }
+
+  // A vault whose protector already counted down, so it may be opened.
+  fn add_unlocked_vault(daemon: &mut Daemon) -> UuidV4 {
+    let mut textual_error = TextualError::new("Adding a vault for a test");
+    let vault = Vault::create(
+      VaultName::new("Passwords".to_string()).unwrap(),
+      VaultProtector::CountdownAfterPlea(CountdownAfterPleaConditional::construct(
+        Duration::HOUR,
+        Some(Countdown::construct(daemon.state.monotonic_clock.now(), Duration::HOUR)),
+      )),
+    );
+
+    let vault_id = UuidV4::generate();
+    assert!(vault_table::insert_vault(&daemon.database, &vault_id, &vault, &mut textual_error).is_ok());
+    daemon.state.vaults.add_vault(vault_id.clone(), vault);
+    daemon.state.vaults_stats.recount_usage(&daemon.state.vaults);
+    vault_id
+  }
+
+  fn advance_clock(daemon: &mut Daemon, duration: Duration) {
+    let clock = &mut daemon.state.monotonic_clock;
+    clock.total_elapsed_duration = clock.total_elapsed_duration.saturating_add(duration);
+  }
+
+  fn create_escrow(
+    daemon: &mut Daemon,
+    password_backend: &impl IsPasswordBackend,
+    user_name: UserName,
+    vault_id: &UuidV4,
+    escrow_id: &UuidV4,
+  ) -> CreateReturn {
+    let mut textual_error = TextualError::new("Creating a password escrow for a test");
+    let procedure = Create {
+      user_name,
+      vault_id: vault_id.clone(),
+      escrow_id: Some(escrow_id.clone()),
+      rotate_after_reveal: true,
+    };
+
+    procedure.execute(daemon, password_backend, &mut textual_error)
+  }
+
+  fn get_escrowed_password(daemon: &Daemon, escrow_id: &UuidV4) -> String {
+    let escrow = daemon.state.password_escrows.get_escrow_given_id(escrow_id).unwrap();
+    let vault = daemon.state.vaults.get_vault_given_id(&escrow.vault_id).unwrap();
+
+    match vault.data.get(&escrow.datum_id).unwrap().get_content() {
+      VaultDatumContent::Text(password) => {
+        password.clone()
+      }
+      other => {
+        panic!("Escrowed passwords are text, but found {other:?}");
+      }
+    }
+  }
+
+  #[test]
+  fn creating_an_escrow_sets_and_stores_the_same_password() {
+    let mut daemon = create_daemon();
+    let password_backend = MockPasswordBackend::default();
+    let vault_id = add_unlocked_vault(&mut daemon);
+    let escrow_id = UuidV4::generate();
+    let user_name = find_regular_user_name();
+
+    let result = create_escrow(&mut daemon, &password_backend, user_name.clone(), &vault_id, &escrow_id);
+    assert!(matches!(result, CreateReturn::Success));
+
+    let changes = password_backend.get_changes();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].0, user_name);
+    assert_eq!(changes[0].1, get_escrowed_password(&daemon, &escrow_id));
+    assert_eq!(daemon.state.vaults_stats.get_data_number(), 1);
+
+    // Saved too.
+    let mut textual_error = TextualError::new("Loading escrows for a test");
+    let saved_escrows = password_escrow_table::load_escrows(&daemon.database, &mut textual_error).unwrap();
+    assert!(saved_escrows.contains_key(&escrow_id));
+
+    let result = create_escrow(&mut daemon, &password_backend, user_name, &vault_id, &UuidV4::generate());
+    assert!(matches!(result, CreateReturn::PasswordAlreadyEscrowed));
+  }
+
+  #[test]
+  fn creating_an_escrow_refuses_root_and_undoes_failed_password_changes() {
+    let mut daemon = create_daemon();
+    let vault_id = add_unlocked_vault(&mut daemon);
+
+    let root = UserName::new(CString::new("root").unwrap());
+    let result = create_escrow(&mut daemon, &MockPasswordBackend::default(), root, &vault_id, &UuidV4::generate());
+    assert!(matches!(result, CreateReturn::RefusingToEscrowRootPassword));
+
+    let escrow_id = UuidV4::generate();
+    let result = create_escrow(
+      &mut daemon,
+      &MockPasswordBackend::failing(),
+      find_regular_user_name(),
+      &vault_id,
+      &escrow_id,
+    );
+    assert!(matches!(result, CreateReturn::PasswordChangeFailed));
+    assert!(daemon.state.password_escrows.get_escrow_given_id(&escrow_id).is_none());
+
+    let mut textual_error = TextualError::new("Loading escrows for a test");
+    assert!(password_escrow_table::load_escrows(&daemon.database, &mut textual_error).unwrap().is_empty());
+  }
+
+  #[test]
+  fn rotating_an_escrow_replaces_the_stored_password() {
+    let mut daemon = create_daemon();
+    let password_backend = MockPasswordBackend::default();
+    let vault_id = add_unlocked_vault(&mut daemon);
+    let escrow_id = UuidV4::generate();
+
+    create_escrow(&mut daemon, &password_backend, find_regular_user_name(), &vault_id, &escrow_id);
+    let previous_password = get_escrowed_password(&daemon, &escrow_id);
+
+    let mut textual_error = TextualError::new("Rotating a password for a test");
+    let result = Rotate { escrow_id: escrow_id.clone() }.execute(&mut daemon, &password_backend, &mut textual_error);
+    assert!(matches!(result, RotateReturn::Success));
+
+    let password = get_escrowed_password(&daemon, &escrow_id);
+    assert_ne!(password, previous_password);
+    assert_eq!(password_backend.get_changes().last().unwrap().1, password);
+
+    // A failed change keeps the password the account still has.
+    let result = Rotate { escrow_id: escrow_id.clone() }.execute(&mut daemon, &MockPasswordBackend::failing(), &mut textual_error);
+    assert!(matches!(result, RotateReturn::PasswordChangeFailed));
+    assert_eq!(get_escrowed_password(&daemon, &escrow_id), password);
+  }
+
+  #[test]
+  fn revealed_passwords_are_rotated_once_the_vault_relocks() {
+    let mut daemon = create_daemon();
+    let password_backend = MockPasswordBackend::default();
+    let vault_id = add_unlocked_vault(&mut daemon);
+    let escrow_id = UuidV4::generate();
+
+    create_escrow(&mut daemon, &password_backend, find_regular_user_name(), &vault_id, &escrow_id);
+    let revealed_password = get_escrowed_password(&daemon, &escrow_id);
+
+    advance_clock(&mut daemon, Duration::HOUR);
+    let mut textual_error = TextualError::new("Revealing a vault for a test");
+    let result = vault::open(
+      &daemon.database,
+      &mut daemon.state.vaults,
+      &vault_id,
+      &daemon.state.monotonic_clock,
+      &mut textual_error,
+    );
+    assert!(matches!(result, vault::OpenReturn::Success));
+
+    // Nothing changes while the window is open.
+    RelockVaults.execute(&mut daemon, &password_backend, &mut textual_error);
+    assert_eq!(get_escrowed_password(&daemon, &escrow_id), revealed_password);
+
+    advance_clock(&mut daemon, Vault::DEFAULT_REVEAL_DURATION);
+    RelockVaults.execute(&mut daemon, &password_backend, &mut textual_error);
+
+    let password = get_escrowed_password(&daemon, &escrow_id);
+    assert_ne!(password, revealed_password);
+    assert_eq!(password_backend.get_changes().last().unwrap().1, password);
+  }
+}
@@ -0,0 +1,254 @@
+use tokio::sync::Mutex;
+use crate::x::{DateTime, IsTextualError};
+use super::*;
+
+pub struct EnforceBlocks;
+
+// Whose sessions a block is enforced on.
+enum EnforcementTarget {
+  // All of the user's sessions.
+  User(UserId),
+  // Some of them, by logind session id, when the others are exempt.
+  Sessions(Vec<String>),
+}
+
+impl EnforceBlocks {
+  // Acts on the live sessions of users whose profiles became blocked
+  // after they logged in, once the grace period is over. Sessions of
+  // login classes the profile exempts from blocks are left alone.
+  //
+  // The actions run loginctl, so they're decided with the daemon
+  // locked and taken after it's unlocked. Call it where blocking is
+  // fine.
+  pub fn execute(
+    self,
+    daemon: &Mutex<Daemon>,
+    session_backend: &impl IsSessionBackend,
+    textual_error: &mut impl IsTextualError,
+  ) {
+    let actions = {
+      let mut daemon = daemon.blocking_lock();
+      self.decide(&mut daemon)
+    };
+
+    for (target, action) in actions {
+      let result = match target {
+        EnforcementTarget::User(user_id) => {
+          match action {
+            SessionEnforcementAction::LockScreen => {
+              session_backend.lock_sessions(user_id, textual_error)
+            }
+            SessionEnforcementAction::TerminateSessions => {
+              session_backend.terminate_sessions(user_id, textual_error)
+            }
+            SessionEnforcementAction::KillProcesses => {
+              session_backend.kill_processes(user_id, textual_error)
+            }
+          }
+        }
+        EnforcementTarget::Sessions(session_ids) => {
+          let mut result = Ok(());
+
+          for session_id in &session_ids {
+            let session_result = match action {
+              SessionEnforcementAction::LockScreen => {
+                session_backend.lock_session(session_id, textual_error)
+              }
+              SessionEnforcementAction::TerminateSessions => {
+                session_backend.terminate_session(session_id, textual_error)
+              }
+              SessionEnforcementAction::KillProcesses => {
+                session_backend.kill_session_processes(session_id, textual_error)
+              }
+            };
+
+            result = result.and(session_result);
+          }
+
+          result
+        }
+      };
+
+      if let Err(()) = result {
+        // TODO: Use a proper logging mechanism.
+        eprintln!("Discipline Linux Daemon: Failed to enforce a block on a user's sessions");
+      }
+    }
+  }
+
+  fn decide(&self, daemon: &mut Daemon) -> Vec<(EnforcementTarget, SessionEnforcementAction)> {
+    let mut actions = Vec::new();
+
+    let time = DateTime::now().time();
+    let now = daemon.state.monotonic_clock.now();
+
+    let session_records = &daemon.state.session_records;
+    daemon
+      .state
+      .session_enforcer
+      .retain(|user_name| session_records.has_open_sessions(user_name));
+
+    for user_name in daemon.state.session_records.get_users_with_open_sessions() {
+      let Some(profile) = daemon.state.user_profiles.get_profile_given_user_name(&user_name) else {
+        continue;
+      };
+
+      let sessions = daemon.state.session_records.get_sessions(&user_name);
+      let enforced_sessions: Vec<_> = sessions
+        .iter()
+        .filter(|record| !profile.login_policy.is_exempt(record.details.get_login_class()))
+        .collect();
+
+      // A user whose sessions are all exempt counts as not blocked, so
+      // the grace period starts over once they open one that isn't.
+      let is_blocked = !enforced_sessions.is_empty() && profile.is_session_open_blocked(time, now);
+
+      let target = if enforced_sessions.len() == sessions.len() {
+        EnforcementTarget::User(profile.user_id)
+      } else {
+        // Sessions the module couldn't name can't be told apart from
+        // the exempt ones, so they're spared rather than risk those.
+        EnforcementTarget::Sessions(
+          enforced_sessions
+            .iter()
+            .filter_map(|record| record.details.logind_session_id.clone())
+            .collect()
+        )
+      };
+
+      let EnforcementDecision::Enforce(action) = daemon
+        .state
+        .session_enforcer
+        .decide(&user_name, is_blocked, now)
+      else {
+        continue;
+      };
+
+      // Failed attempts count too, so a broken backend isn't retried
+      // on every tick.
+      daemon.state.session_enforcer.on_enforced(&user_name, now);
+      actions.push((target, action));
+    }
+
+    actions
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use crate::x::{AlwaysRule, CountdownAfterPleaConditional, Duration, RuleEnabler, RulesStats, TextualError, UserUptimeClock, UuidV4};
+  use super::*;
+
+  const USER_ID: u32 = 1000;
+
+  fn create_user_name() -> UserName {
+    UserName::new(CString::new("alex").unwrap())
+  }
+
+  fn create_locked_daemon(exempt_classes: Vec<LoginClass>) -> Daemon {
+    let mut textual_error = TextualError::new("Creating a daemon for a test");
+    let mut daemon = Daemon::open_in_memory(&mut textual_error).unwrap();
+    let now = daemon.state.monotonic_clock.now();
+
+    let mut screen_access_regulation = ScreenAccessRegulation::default();
+    screen_access_regulation.always_rules.rules.insert(
+      UuidV4::generate(),
+      AlwaysRule::create(RuleEnabler::CountdownAfterPlea(CountdownAfterPleaConditional::create(Duration::HOUR))),
+    );
+
+    let profile = UserProfile {
+      name: UserProfileName::new("Alex".to_string()).unwrap(),
+      user_id: UserId::new(USER_ID),
+      user_name: create_user_name(),
+      uptime_clock: UserUptimeClock::construct(
+        false,
+        now,
+        Duration::zero(),
+        now,
+        Duration::zero(),
+        now,
+        Duration::HOUR,
+      ),
+      device_access_regulation: DeviceAccessRegulation::new(),
+      screen_access_regulation,
+      internet_access_regulation: InternetAccessRegulation::new(),
+      application_regulations: ApplicationRegulations::new(),
+      login_policy: LoginPolicy {
+        exempt_classes,
+        ..LoginPolicy::default()
+      },
+      rules_stats: RulesStats::new(RulesStats::DEFAULT_MAXIMUM_RULES_NUMBER),
+      is_orphaned: false,
+    };
+
+    daemon.state.user_profiles.add_user(UuidV4::generate(), profile);
+    daemon
+  }
+
+  fn open_session(daemon: &mut Daemon, logind_session_id: &str, service: &str) {
+    let details = SessionDetails {
+      logind_session_id: Some(logind_session_id.to_string()),
+      service: Some(service.to_string()),
+      ..SessionDetails::default()
+    };
+
+    daemon.on_user_session_opened(&create_user_name(), details);
+  }
+
+  // Runs enforcement once as the block starts, and once after its grace
+  // period.
+  fn enforce_after_grace_period(daemon: Daemon, session_backend: &MockSessionBackend) {
+    let daemon = Mutex::new(daemon);
+    let mut textual_error = TextualError::new("Testing enforcing blocks");
+
+    EnforceBlocks.execute(&daemon, session_backend, &mut textual_error);
+
+    {
+      let mut daemon = daemon.blocking_lock();
+      let grace_period = daemon.state.session_enforcer.get_configuration().grace_period;
+      let clock = &mut daemon.state.monotonic_clock;
+      clock.total_elapsed_duration = clock.total_elapsed_duration.saturating_add(grace_period);
+    }
+
+    EnforceBlocks.execute(&daemon, session_backend, &mut textual_error);
+  }
+
+  #[test]
+  fn blocks_are_enforced_on_all_sessions_without_exemptions() {
+    let mut daemon = create_locked_daemon(Vec::new());
+    open_session(&mut daemon, "c1", "gdm-password");
+    open_session(&mut daemon, "c2", "sshd");
+
+    let session_backend = MockSessionBackend::default();
+    enforce_after_grace_period(daemon, &session_backend);
+
+    assert_eq!(session_backend.get_actions(), vec![(UserId::new(USER_ID), MockSessionAction::LockSessions)]);
+    assert!(session_backend.get_session_actions().is_empty());
+  }
+
+  #[test]
+  fn exempt_sessions_are_left_alone() {
+    let mut daemon = create_locked_daemon(vec![LoginClass::Remote]);
+    open_session(&mut daemon, "c1", "gdm-password");
+    open_session(&mut daemon, "c2", "sshd");
+
+    let session_backend = MockSessionBackend::default();
+    enforce_after_grace_period(daemon, &session_backend);
+
+    assert!(session_backend.get_actions().is_empty());
+    assert_eq!(session_backend.get_session_actions(), vec![("c1".to_string(), MockSessionAction::LockSessions)]);
+  }
+
+  #[test]
+  fn users_with_only_exempt_sessions_are_not_enforced_on() {
+    let mut daemon = create_locked_daemon(vec![LoginClass::Remote]);
+    open_session(&mut daemon, "c2", "sshd");
+
+    let session_backend = MockSessionBackend::default();
+    enforce_after_grace_period(daemon, &session_backend);
+
+    assert!(session_backend.get_actions().is_empty());
+    assert!(session_backend.get_session_actions().is_empty());
+  }
+}
@@ -0,0 +1,234 @@
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::x::{DateTime, IsTextualError};
+use crate::x::database::session_record_table;
+use super::*;
+
+pub struct ReconcileSessions;
+
+impl ReconcileSessions {
+  // Brings the registry in line with the sessions logind knows about,
+  // for when sessions opened or closed while the daemon wasn't running,
+  // or the module couldn't reach it.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    session_backend: &impl IsSessionBackend,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let logind_sessions = session_backend.list_sessions(textual_error)?;
+    let now = DateTime::now().as_timestamp();
+
+    let (changed_records, forgotten_records) = daemon.state.session_records.reconcile(
+      &logind_sessions,
+      |process_id| Path::new(&format!("/proc/{process_id}")).exists(),
+      now,
+    );
+
+    if changed_records.is_empty() && forgotten_records.is_empty() {
+      return Ok(());
+    }
+
+    session_record_table::update_session_records(
+      &daemon.database,
+      &changed_records,
+      &forgotten_records,
+      textual_error,
+    )
+  }
+}
+
+pub struct SynchronizeUptimeClocks;
+
+impl SynchronizeUptimeClocks {
+  // Users' uptime only runs while they have a session open.
+  pub fn execute(self, daemon: &mut Daemon) {
+    let now = daemon.state.monotonic_clock.now();
+
+    for profile in daemon.state.user_profiles.get_profiles_mut() {
+      profile.uptime_clock.is_running = daemon
+        .state
+        .session_records
+        .has_open_sessions(&profile.user_name);
+
+      profile.uptime_clock.synchronize(now);
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetSessions {
+  // Every user's sessions when None.
+  pub user_name: Option<UserName>,
+  pub include_closed: bool,
+}
+
+impl GetSessions {
+  // Open sessions first, then closed ones from the oldest.
+  pub fn execute(self, daemon: &Daemon) -> Vec<SessionRecord> {
+    let session_records = &daemon.state.session_records;
+
+    let open_sessions = session_records.get_open_sessions();
+    let closed_sessions = session_records
+      .get_closed_sessions()
+      .filter(|_| self.include_closed);
+
+    open_sessions
+      .chain(closed_sessions)
+      .filter(|record| {
+        self
+          .user_name
+          .as_ref()
+          .is_none_or(|user_name| &record.user_name == user_name)
+      })
+      .cloned()
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use crate::x::{Duration, RulesStats, TextualError, UserUptimeClock, UuidV4};
+  use crate::x::database::session_record_table;
+  use super::*;
+
+  fn create_daemon() -> Daemon {
+    let mut textual_error = TextualError::new("Creating a daemon for a test");
+    Daemon::open_in_memory(&mut textual_error).unwrap()
+  }
+
+  fn create_user_name(name: &str) -> UserName {
+    UserName::new(CString::new(name).unwrap())
+  }
+
+  fn create_details(logind_session_id: &str) -> SessionDetails {
+    SessionDetails {
+      logind_session_id: Some(logind_session_id.to_string()),
+      ..SessionDetails::default()
+    }
+  }
+
+  fn advance_clock(daemon: &mut Daemon, duration: Duration) {
+    let clock = &mut daemon.state.monotonic_clock;
+    clock.total_elapsed_duration = clock.total_elapsed_duration.saturating_add(duration);
+  }
+
+  fn add_profile(daemon: &mut Daemon, user_name: &UserName) {
+    let now = daemon.state.monotonic_clock.now();
+
+    let profile = UserProfile {
+      name: UserProfileName::new("Alex".to_string()).unwrap(),
+      user_id: UserId::new(1000),
+      user_name: user_name.clone(),
+      uptime_clock: UserUptimeClock::construct(
+        false,
+        now,
+        Duration::zero(),
+        now,
+        Duration::zero(),
+        now,
+        Duration::HOUR,
+      ),
+      device_access_regulation: DeviceAccessRegulation::new(),
+      screen_access_regulation: ScreenAccessRegulation::default(),
+      internet_access_regulation: InternetAccessRegulation::new(),
+      application_regulations: ApplicationRegulations::new(),
+      login_policy: LoginPolicy::default(),
+      rules_stats: RulesStats::new(RulesStats::DEFAULT_MAXIMUM_RULES_NUMBER),
+      is_orphaned: false,
+    };
+
+    daemon.state.user_profiles.add_user(UuidV4::generate(), profile);
+  }
+
+  fn get_day_uptime(daemon: &Daemon, user_name: &UserName) -> Duration {
+    daemon
+      .state
+      .user_profiles
+      .get_profile_given_user_name(user_name)
+      .unwrap()
+      .uptime_clock
+      .day_uptime
+  }
+
+  #[test]
+  fn opened_and_closed_sessions_are_recorded_and_saved() {
+    let mut daemon = create_daemon();
+    let user_name = create_user_name("alex");
+
+    daemon.on_user_session_opened(&user_name, create_details("1"));
+    daemon.on_user_session_opened(&user_name, create_details("2"));
+    daemon.on_user_session_closed(&user_name, &create_details("2"));
+
+    let open_sessions = GetSessions { user_name: None, include_closed: false }.execute(&daemon);
+    assert_eq!(open_sessions.len(), 1);
+    assert_eq!(open_sessions[0].details.logind_session_id.as_deref(), Some("1"));
+
+    let sessions = GetSessions { user_name: None, include_closed: true }.execute(&daemon);
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions[0].is_open());
+    assert_eq!(sessions[1].details.logind_session_id.as_deref(), Some("2"));
+    assert!(!sessions[1].is_open());
+
+    let mut textual_error = TextualError::new("Loading session records for a test");
+    let saved = session_record_table::load_session_records(&daemon.database, &mut textual_error).unwrap();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved.iter().filter(|record| record.is_open()).count(), 1);
+  }
+
+  #[test]
+  fn sessions_may_be_asked_for_by_user() {
+    let mut daemon = create_daemon();
+    let alex = create_user_name("alex");
+    let sam = create_user_name("sam");
+
+    daemon.on_user_session_opened(&alex, create_details("1"));
+    daemon.on_user_session_opened(&sam, create_details("2"));
+
+    let sessions = GetSessions { user_name: Some(sam.clone()), include_closed: true }.execute(&daemon);
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].user_name, sam);
+  }
+
+  #[test]
+  fn uptime_only_runs_while_a_session_is_open() {
+    let mut daemon = create_daemon();
+    let user_name = create_user_name("alex");
+    add_profile(&mut daemon, &user_name);
+
+    advance_clock(&mut daemon, Duration::MINUTE);
+    SynchronizeUptimeClocks.execute(&mut daemon);
+    assert_eq!(get_day_uptime(&daemon, &user_name), Duration::zero());
+
+    daemon.on_user_session_opened(&user_name, create_details("1"));
+    advance_clock(&mut daemon, Duration::MINUTE);
+    SynchronizeUptimeClocks.execute(&mut daemon);
+    assert_eq!(get_day_uptime(&daemon, &user_name), Duration::MINUTE);
+
+    daemon.on_user_session_closed(&user_name, &create_details("1"));
+    SynchronizeUptimeClocks.execute(&mut daemon);
+    advance_clock(&mut daemon, Duration::MINUTE);
+    SynchronizeUptimeClocks.execute(&mut daemon);
+    assert_eq!(get_day_uptime(&daemon, &user_name), Duration::MINUTE);
+  }
+
+  #[test]
+  fn uptime_survives_a_restart() {
+    let mut daemon = create_daemon();
+    let user_name = create_user_name("alex");
+    add_profile(&mut daemon, &user_name);
+
+    daemon.on_user_session_opened(&user_name, create_details("1"));
+    SynchronizeUptimeClocks.execute(&mut daemon);
+    advance_clock(&mut daemon, Duration::MINUTE);
+    SynchronizeUptimeClocks.execute(&mut daemon);
+    assert_eq!(get_day_uptime(&daemon, &user_name), Duration::MINUTE);
+
+    let mut textual_error = TextualError::new("Restarting the daemon for a test");
+    daemon.persist(&mut textual_error).unwrap();
+    daemon.reload_state(&mut textual_error).unwrap();
+
+    assert_eq!(get_day_uptime(&daemon, &user_name), Duration::MINUTE);
+  }
+}
@@ -0,0 +1,298 @@
+use crate::x::{IsTextualError, UuidV4};
+use super::*;
+
+pub struct ReconcileUserProfiles;
+
+impl ReconcileUserProfiles {
+  // Brings every profile in line with the account database. An account
+  // is identified by its user id, so a renamed user keeps their
+  // profile. When a profile's user id no longer exists but its user
+  // name does, the account was deleted and created again, and the
+  // profile follows it, unless another profile already regulates the
+  // new account. Profiles left without an account are marked orphaned.
+  //
+  // Profiles whose account couldn't be looked up are left as they are,
+  // and an error is returned once all the others are done.
+  pub fn execute(
+    self,
+    daemon: &mut Daemon,
+    account_backend: &impl IsAccountBackend,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let profiles: Vec<(UuidV4, UserId, UserName)> = daemon
+      .state
+      .user_profiles
+      .get_all_profiles()
+      .map(|(profile_id, profile)| {
+        (profile_id.clone(), profile.user_id, profile.user_name.clone())
+      })
+      .collect();
+
+    let mut lookups_failed = false;
+    let mut missing = Vec::new();
+
+    // Renames are settled first, so an account that was renamed can't
+    // be taken for a re-created one by the second pass.
+    for (profile_id, user_id, user_name) in profiles {
+      match account_backend.get_account_given_user_id(user_id) {
+        Ok(account) => {
+          let is_orphaned = daemon
+            .state
+            .user_profiles
+            .get_profile_given_id(&profile_id)
+            .is_some_and(|profile| profile.is_orphaned);
+
+          // An orphaned profile doesn't get its account back if another
+          // profile took it over in the meantime.
+          if is_orphaned && daemon.state.user_profiles.get_profile_id_given_user_id(account.user_id).is_some() {
+            continue;
+          }
+
+          if daemon.state.user_profiles.set_account(&profile_id, account.user_id, account.user_name) {
+            // TODO: Use a proper logging mechanism.
+            eprintln!("Discipline Linux Daemon: A regulated account was renamed or came back, updated its profile");
+          }
+        }
+        Err(GetPasswordFileEntryError::NoSuchUser) => {
+          missing.push((profile_id, user_name));
+        }
+        Err(GetPasswordFileEntryError::NotEnoughMemory) => {
+          textual_error.change_context("Looking up a regulated account given its user id");
+          textual_error.add_message("Not enough memory");
+          textual_error.add_attachement_display("User id", user_id.inner());
+          lookups_failed = true;
+        }
+        Err(GetPasswordFileEntryError::SystemCallFailed) => {
+          textual_error.change_context("Looking up a regulated account given its user id");
+          textual_error.add_message("getpwuid_r failed");
+          textual_error.add_attachement_display("User id", user_id.inner());
+          lookups_failed = true;
+        }
+      }
+    }
+
+    for (profile_id, user_name) in missing {
+      match account_backend.get_account_given_user_name(&user_name) {
+        Ok(account) => {
+          if daemon.state.user_profiles.get_profile_id_given_user_id(account.user_id).is_some() {
+            daemon.state.user_profiles.mark_orphaned(&profile_id);
+          } else {
+            daemon.state.user_profiles.set_account(&profile_id, account.user_id, account.user_name);
+            // TODO: Use a proper logging mechanism.
+            eprintln!("Discipline Linux Daemon: A regulated account was re-created with a new user id, moved its profile to it");
+          }
+        }
+        Err(GetPasswordFileEntryError::NoSuchUser) => {
+          if daemon.state.user_profiles.mark_orphaned(&profile_id) {
+            // TODO: Use a proper logging mechanism.
+            eprintln!("Discipline Linux Daemon: A regulated account was deleted, marked its profile orphaned");
+          }
+        }
+        Err(GetPasswordFileEntryError::NotEnoughMemory) => {
+          textual_error.change_context("Looking up a regulated account given its user name");
+          textual_error.add_message("Not enough memory");
+          lookups_failed = true;
+        }
+        Err(GetPasswordFileEntryError::SystemCallFailed) => {
+          textual_error.change_context("Looking up a regulated account given its user name");
+          textual_error.add_message("getpwnam_r failed");
+          lookups_failed = true;
+        }
+      }
+    }
+
+    if lookups_failed {
+      return Err(());
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use crate::x::{Duration, Instant, RulesStats, TextualError, UserUptimeClock};
+  use super::*;
+
+  fn create_user_name(name: &str) -> UserName {
+    UserName::new(CString::new(name).unwrap())
+  }
+
+  fn create_account(user_id: u32, name: &str) -> PasswordFileEntry {
+    PasswordFileEntry {
+      user_id: UserId::new(user_id),
+      user_name: create_user_name(name),
+      primary_group_id: GroupId::new(user_id),
+    }
+  }
+
+  fn create_profile(user_id: u32, name: &str, is_orphaned: bool) -> UserProfile {
+    let now = Instant::from_timestamp(0);
+
+    UserProfile {
+      name: UserProfileName::new(name.to_string()).unwrap(),
+      user_id: UserId::new(user_id),
+      user_name: create_user_name(name),
+      uptime_clock: UserUptimeClock::construct(
+        false,
+        now,
+        Duration::zero(),
+        now,
+        Duration::zero(),
+        now,
+        Duration::HOUR,
+      ),
+      device_access_regulation: DeviceAccessRegulation::new(),
+      screen_access_regulation: ScreenAccessRegulation::default(),
+      internet_access_regulation: InternetAccessRegulation::new(),
+      application_regulations: ApplicationRegulations::new(),
+      login_policy: LoginPolicy::default(),
+      rules_stats: RulesStats::new(RulesStats::DEFAULT_MAXIMUM_RULES_NUMBER),
+      is_orphaned,
+    }
+  }
+
+  fn create_daemon(profiles: Vec<UserProfile>) -> (Daemon, Vec<UuidV4>) {
+    let mut textual_error = TextualError::new("Creating a daemon for a test");
+    let mut daemon = Daemon::open_in_memory(&mut textual_error).unwrap();
+
+    let profile_ids = profiles
+      .into_iter()
+      .map(|profile| {
+        let profile_id = UuidV4::generate();
+        daemon.state.user_profiles.add_user(profile_id.clone(), profile);
+        profile_id
+      })
+      .collect();
+
+    (daemon, profile_ids)
+  }
+
+  fn reconcile(daemon: &mut Daemon, accounts: Vec<PasswordFileEntry>) {
+    let mut textual_error = TextualError::new("Reconciling user profiles in a test");
+    ReconcileUserProfiles
+      .execute(daemon, &MockAccountBackend::new(accounts), &mut textual_error)
+      .unwrap();
+  }
+
+  // The profile's account, and whether it's orphaned.
+  fn get_account(daemon: &Daemon, profile_id: &UuidV4) -> (u32, String, bool) {
+    let profile = daemon.state.user_profiles.get_profile_given_id(profile_id).unwrap();
+
+    (
+      profile.user_id.inner(),
+      profile.user_name.inner().to_str().unwrap().to_string(),
+      profile.is_orphaned,
+    )
+  }
+
+  fn account(user_id: u32, name: &str, is_orphaned: bool) -> (u32, String, bool) {
+    (user_id, name.to_string(), is_orphaned)
+  }
+
+  #[test]
+  fn unchanged_accounts_keep_their_profiles() {
+    let (mut daemon, profile_ids) = create_daemon(vec![create_profile(1000, "alex", false)]);
+
+    reconcile(&mut daemon, vec![create_account(1000, "alex"), create_account(1001, "sam")]);
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1000, "alex", false));
+    assert_eq!(daemon.state.user_profiles.get_users_number(), 1);
+    assert!(daemon.state.user_profiles.get_profile_given_user_id(UserId::new(1001)).is_none());
+  }
+
+  #[test]
+  fn renamed_accounts_keep_their_profiles() {
+    let (mut daemon, profile_ids) = create_daemon(vec![create_profile(1000, "alex", false)]);
+
+    reconcile(&mut daemon, vec![create_account(1000, "alexandra")]);
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1000, "alexandra", false));
+    assert!(daemon.state.user_profiles.get_profile_given_user_name(&create_user_name("alexandra")).is_some());
+    assert!(daemon.state.user_profiles.get_profile_given_user_name(&create_user_name("alex")).is_none());
+  }
+
+  #[test]
+  fn swapped_names_follow_user_ids() {
+    let (mut daemon, profile_ids) = create_daemon(vec![
+      create_profile(1000, "alex", false),
+      create_profile(1001, "sam", false),
+    ]);
+
+    reconcile(&mut daemon, vec![create_account(1000, "sam"), create_account(1001, "alex")]);
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1000, "sam", false));
+    assert_eq!(get_account(&daemon, &profile_ids[1]), account(1001, "alex", false));
+  }
+
+  #[test]
+  fn removed_accounts_orphan_their_profiles() {
+    let (mut daemon, profile_ids) = create_daemon(vec![create_profile(1000, "alex", false)]);
+
+    reconcile(&mut daemon, vec![create_account(1001, "sam")]);
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1000, "alex", true));
+    assert!(daemon.state.user_profiles.get_profile_given_user_id(UserId::new(1000)).is_none());
+    assert!(daemon.state.user_profiles.get_profile_given_user_name(&create_user_name("alex")).is_none());
+
+    // Orphaned profiles stay orphaned while the account is gone.
+    reconcile(&mut daemon, Vec::new());
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1000, "alex", true));
+  }
+
+  #[test]
+  fn orphaned_profiles_get_their_accounts_back() {
+    let (mut daemon, profile_ids) = create_daemon(vec![create_profile(1000, "alex", true)]);
+
+    reconcile(&mut daemon, vec![create_account(1000, "alex")]);
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1000, "alex", false));
+    assert!(daemon.state.user_profiles.get_profile_given_user_id(UserId::new(1000)).is_some());
+  }
+
+  #[test]
+  fn orphaned_profiles_dont_take_accounts_regulated_by_others() {
+    let (mut daemon, profile_ids) = create_daemon(vec![
+      create_profile(1000, "alex", true),
+      create_profile(1000, "sam", false),
+    ]);
+
+    reconcile(&mut daemon, vec![create_account(1000, "sam")]);
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1000, "alex", true));
+    assert_eq!(get_account(&daemon, &profile_ids[1]), account(1000, "sam", false));
+  }
+
+  #[test]
+  fn recreated_accounts_keep_their_profiles() {
+    let (mut daemon, profile_ids) = create_daemon(vec![create_profile(1000, "alex", false)]);
+
+    reconcile(&mut daemon, vec![create_account(1002, "alex")]);
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1002, "alex", false));
+    assert!(daemon.state.user_profiles.get_profile_given_user_id(UserId::new(1002)).is_some());
+
+    // The same goes for orphaned profiles.
+    let (mut daemon, profile_ids) = create_daemon(vec![create_profile(1000, "alex", true)]);
+
+    reconcile(&mut daemon, vec![create_account(1002, "alex")]);
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1002, "alex", false));
+  }
+
+  #[test]
+  fn recreated_accounts_regulated_by_others_orphan_the_profile() {
+    let (mut daemon, profile_ids) = create_daemon(vec![
+      create_profile(1000, "alex", false),
+      create_profile(1002, "sam", false),
+    ]);
+
+    reconcile(&mut daemon, vec![create_account(1002, "alex")]);
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1000, "alex", true));
+    assert_eq!(get_account(&daemon, &profile_ids[1]), account(1002, "alex", false));
+  }
+
+  #[test]
+  fn failed_lookups_leave_profiles_alone() {
+    let (mut daemon, profile_ids) = create_daemon(vec![create_profile(1000, "alex", false)]);
+
+    let mut textual_error = TextualError::new("Reconciling user profiles in a test");
+    let result = ReconcileUserProfiles.execute(&mut daemon, &MockAccountBackend::failing(), &mut textual_error);
+
+    assert!(result.is_err());
+    assert_eq!(get_account(&daemon, &profile_ids[0]), account(1000, "alex", false));
+  }
+}
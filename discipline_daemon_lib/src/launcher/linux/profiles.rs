@@ -1,8 +1,8 @@
 use std::any::type_name;
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
-use crate::x::{AlwaysRules, Instant, RulesStats, TextualErrorContext, Time, TimeAllowanceRules, TimeRangeRules, ToTextualError, UserUptimeClock, UuidV4};
-use super::{UserId, UserName};
+use crate::x::{AlwaysRules, Duration, Instant, RulesStats, TextualErrorContext, Time, TimeAllowanceRules, TimeRangeRules, ToTextualError, UserUptimeClock, UuidV4};
+use super::{ApplicationName, ApplicationRegulations, ApplicationRuntime, DomainFilter, GroupId, GroupName, GroupProfile, LoginPolicy, UserId, UserName, WebFilter};
 
 
 #[derive(Debug, Clone)]
@@ -65,6 +65,13 @@ pub struct DeviceAccessRegulation {
 }
 
 impl DeviceAccessRegulation {
+  pub fn merge(&mut self, other: &Self) {
+    self.always_rules.merge(&other.always_rules);
+    self.time_range_rules.merge(&other.time_range_rules);
+    self.daily_uptime_allowance_rules.merge(&other.daily_uptime_allowance_rules);
+    self.weekly_uptime_allowance_rules.merge(&other.weekly_uptime_allowance_rules);
+  }
+
   pub fn new() -> Self {
     Self {
       always_rules: AlwaysRules::default(),
@@ -98,6 +105,13 @@ pub struct ScreenAccessRegulation {
 }
 
 impl ScreenAccessRegulation {
+  pub fn merge(&mut self, other: &Self) {
+    self.always_rules.merge(&other.always_rules);
+    self.time_range_rules.merge(&other.time_range_rules);
+    self.daily_allowance_rules.merge(&other.daily_allowance_rules);
+    self.weekly_allowance_rules.merge(&other.weekly_allowance_rules);
+  }
+
   pub fn construct(
     always_rules: AlwaysRules,
     time_range_rules: TimeRangeRules,
@@ -117,28 +131,75 @@ impl ScreenAccessRegulation {
 pub struct InternetAccessRegulation {
   pub always_rules: AlwaysRules,
   pub time_range_rules: TimeRangeRules,
+  #[serde(default)]
+  pub domain_filter: DomainFilter,
+  #[serde(default)]
+  pub web_filter: WebFilter,
   // traffic_allowance_rules
 }
 
 impl InternetAccessRegulation {
+  pub fn merge(&mut self, other: &Self) {
+    self.always_rules.merge(&other.always_rules);
+    self.time_range_rules.merge(&other.time_range_rules);
+    self.domain_filter.merge(&other.domain_filter);
+    self.web_filter.merge(&other.web_filter);
+  }
+
   pub fn new() -> Self {
     Self {
       always_rules: AlwaysRules::new(),
       time_range_rules: TimeRangeRules::new(),
+      domain_filter: DomainFilter::default(),
+      web_filter: WebFilter::default(),
     }
   }
 
   pub fn construct(
     always_rules: AlwaysRules,
     time_range_rules: TimeRangeRules,
+    domain_filter: DomainFilter,
+    web_filter: WebFilter,
   ) -> Self {
     Self {
       always_rules,
       time_range_rules,
+      domain_filter,
+      web_filter,
     }
   }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockReason {
+  TimeRange,
+  AllowanceExhaustion,
+  Lock,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UpcomingBlock {
+  pub reason: BlockReason,
+  pub starts_at: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CurrentBlock {
+  pub reason: BlockReason,
+  // None when the block lasts until someone acts, or until an allowance
+  // is renewed.
+  pub ends_at: Option<Instant>,
+}
+
+// What's counted for an account, as opposed to the regulation authored
+// for it. It's kept per account, whichever profiles regulate it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserUsage {
+  pub uptime_clock: UserUptimeClock,
+  #[serde(default)]
+  pub application_runtimes: HashMap<ApplicationName, ApplicationRuntime>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserProfile {
   pub name: UserProfileName,
@@ -148,7 +209,16 @@ pub struct UserProfile {
   pub device_access_regulation: DeviceAccessRegulation,
   pub screen_access_regulation: ScreenAccessRegulation,
   pub internet_access_regulation: InternetAccessRegulation,
+  #[serde(default)]
+  pub application_regulations: ApplicationRegulations,
+  #[serde(default)]
+  pub login_policy: LoginPolicy,
   pub rules_stats: RulesStats,
+  // Set when the account the profile regulates no longer exists. Such
+  // profiles are kept for an administrator to delete or reassign, but
+  // can't be found by user id or user name.
+  #[serde(default)]
+  pub is_orphaned: bool,
 }
 
 impl UserProfile {
@@ -193,22 +263,140 @@ impl UserProfile {
     todo!()
   } 
 
+  pub fn get_usage(&self) -> UserUsage {
+    UserUsage {
+      uptime_clock: self.uptime_clock.clone(),
+      application_runtimes: self.application_regulations.get_runtimes(),
+    }
+  }
+
+  pub fn set_usage(&mut self, usage: UserUsage) {
+    self.uptime_clock = usage.uptime_clock;
+    self.application_regulations.set_runtimes(&usage.application_runtimes);
+  }
+
+  // Adds a group profile's regulation to this profile's, as described
+  // on GroupProfile.
+  pub fn merge_group_profile(&mut self, group_profile: &GroupProfile) {
+    self.device_access_regulation.merge(&group_profile.device_access_regulation);
+    self.screen_access_regulation.merge(&group_profile.screen_access_regulation);
+    self.internet_access_regulation.merge(&group_profile.internet_access_regulation);
+    self.application_regulations.merge(&group_profile.application_regulations);
+    self.login_policy.merge(&group_profile.login_policy);
+  }
+
   pub fn is_session_open_blocked(
     &self, 
     time: Time,
     instant: Instant,
   ) -> bool {
-    self.screen_access_regulation.always_rules.are_some_active(instant)
+    let regulation = &self.screen_access_regulation;
+
+    regulation.always_rules.are_some_active(instant)
+    ||
+    regulation.time_range_rules.are_some_active(time, instant)
     ||
-    self.screen_access_regulation.time_range_rules.are_some_active(time, instant)
+    regulation.daily_allowance_rules.are_some_active(instant, self.uptime_clock.day_uptime)
+    ||
+    regulation.weekly_allowance_rules.are_some_active(instant, self.uptime_clock.week_uptime)
   }
 
-  pub fn on_user_session_opened(&self) {
+  pub fn is_internet_access_blocked(
+    &self,
+    time: Time,
+    instant: Instant,
+  ) -> bool {
+    let regulation = &self.internet_access_regulation;
+
+    regulation.always_rules.are_some_active(instant)
+    ||
+    regulation.time_range_rules.are_some_active(time, instant)
+  }
 
+  // The earliest instant at which the screen regulation will block the
+  // user if nobody acts, assuming they keep using the device.
+  pub fn get_next_block(
+    &self,
+    time: Time,
+    instant: Instant,
+  ) -> Option<UpcomingBlock> {
+    let regulation = &self.screen_access_regulation;
+
+    let candidates: [(BlockReason, Option<Duration>); 4] = [
+      (
+        BlockReason::Lock,
+        regulation.always_rules.get_duration_till_some_activate(instant),
+      ),
+      (
+        BlockReason::TimeRange,
+        regulation.time_range_rules.get_duration_till_some_activate(time, instant),
+      ),
+      (
+        BlockReason::AllowanceExhaustion,
+        regulation.daily_allowance_rules.get_duration_till_some_exhaust(instant, self.uptime_clock.day_uptime),
+      ),
+      (
+        BlockReason::AllowanceExhaustion,
+        regulation.weekly_allowance_rules.get_duration_till_some_exhaust(instant, self.uptime_clock.week_uptime),
+      ),
+    ];
+
+    candidates
+      .into_iter()
+      .filter_map(|(reason, duration)| duration.map(|duration| (reason, duration)))
+      .min_by_key(|(_, duration)| *duration)
+      .map(|(reason, duration)| UpcomingBlock {
+        reason,
+        starts_at: instant.saturating_add(duration),
+      })
   }
 
-  pub fn on_user_session_closed(&self) {
+  // Why the screen regulation blocks the user right now, and when it
+  // stops if nobody acts. A lock is reported before a time range, and a
+  // time range before an exhausted allowance, but the block only ends
+  // once none of them applies.
+  pub fn get_current_block(
+    &self,
+    time: Time,
+    instant: Instant,
+  ) -> Option<CurrentBlock> {
+    let regulation = &self.screen_access_regulation;
+
+    let is_locked = regulation.always_rules.are_some_active(instant);
+    let is_in_time_range = regulation.time_range_rules.are_some_active(time, instant);
+    let is_allowance_exhausted =
+      regulation.daily_allowance_rules.are_some_active(instant, self.uptime_clock.day_uptime)
+      ||
+      regulation.weekly_allowance_rules.are_some_active(instant, self.uptime_clock.week_uptime);
+
+    let reason = if is_locked {
+      BlockReason::Lock
+    } else if is_in_time_range {
+      BlockReason::TimeRange
+    } else if is_allowance_exhausted {
+      BlockReason::AllowanceExhaustion
+    } else {
+      return None;
+    };
+
+    let mut duration = Duration::zero();
+    if is_allowance_exhausted {
+      return Some(CurrentBlock { reason, ends_at: None });
+    }
+    if is_locked {
+      let Some(till_unlocked) = regulation.always_rules.get_duration_till_all_deactivate(instant) else {
+        return Some(CurrentBlock { reason, ends_at: None });
+      };
+      duration = duration.max(till_unlocked);
+    }
+    if let Some(till_range_end) = regulation.time_range_rules.get_duration_till_all_deactivate(time, instant) {
+      duration = duration.max(till_range_end);
+    }
 
+    Some(CurrentBlock {
+      reason,
+      ends_at: Some(instant.saturating_add(duration)),
+    })
   }
 }
 
@@ -245,27 +433,98 @@ impl UserProfilesStats {
 #[derive(Debug)]
 pub struct UserProfiles {
   user_profiles: HashMap<UuidV4, UserProfile>,
-  user_names_to_profile_ids: HashMap<UserName, UuidV4>,
+  group_profiles: HashMap<UuidV4, GroupProfile>,
+  // What's enforced on each regulated account: its own profile merged
+  // with its groups' profiles. Orphaned profiles are left out. Rebuilt
+  // whenever the profiles above change, keeping the usage counted so
+  // far.
+  effective_profiles: HashMap<UserId, UserProfile>,
+  // The usage of accounts that aren't regulated right now, as saved or
+  // as last counted, for when they are again.
+  stored_usage: HashMap<UserId, UserUsage>,
+  user_names_to_user_ids: HashMap<UserName, UserId>,
+  group_memberships_refreshed_at: Option<Instant>,
 }
 
 impl UserProfiles {
+  // Memberships also change in places we can't watch, like LDAP.
+  pub const GROUP_MEMBERSHIPS_REFRESH_INTERVAL: Duration = Duration::MINUTE;
+
   pub fn new() -> Self {
     Self {
       user_profiles: HashMap::new(),
-      user_names_to_profile_ids: HashMap::new(),
+      group_profiles: HashMap::new(),
+      effective_profiles: HashMap::new(),
+      stored_usage: HashMap::new(),
+      user_names_to_user_ids: HashMap::new(),
+      group_memberships_refreshed_at: None,
     }
   }
 
-  pub fn construct(user_profiles: HashMap<UuidV4, UserProfile>) -> Self {
-    let mut user_names_to_profile_ids = HashMap::new();
-    for (key, value) in &user_profiles {
-      user_names_to_profile_ids.insert(value.user_name.clone(), key.clone());
+  pub fn construct(
+    user_profiles: HashMap<UuidV4, UserProfile>,
+    group_profiles: HashMap<UuidV4, GroupProfile>,
+    usage: HashMap<UserId, UserUsage>,
+  ) -> Self {
+    let mut user_profiles = Self {
+      user_profiles,
+      group_profiles,
+      effective_profiles: HashMap::new(),
+      stored_usage: usage,
+      user_names_to_user_ids: HashMap::new(),
+      group_memberships_refreshed_at: None,
+    };
+
+    user_profiles.rebuild_effective_profiles();
+    user_profiles
+  }
+
+  // Needs calling after changing a profile through one of the `_mut`
+  // getters, for the change to be enforced.
+  pub fn rebuild_effective_profiles(&mut self) {
+    let mut previous_effective_profiles = std::mem::take(&mut self.effective_profiles);
+
+    for profile in self.user_profiles.values() {
+      if !profile.is_orphaned {
+        self.effective_profiles.insert(profile.user_id, profile.clone());
+      }
     }
 
-    Self {
-      user_profiles,
-      user_names_to_profile_ids,
+    let mut group_profiles: Vec<&GroupProfile> = self
+      .group_profiles
+      .values()
+      .filter(|group_profile| !group_profile.is_orphaned)
+      .collect();
+
+    group_profiles.sort_by_key(|group_profile| group_profile.group_id.inner());
+
+    for group_profile in group_profiles {
+      for (user_id, user_name) in &group_profile.members {
+        self
+          .effective_profiles
+          .entry(*user_id)
+          .or_insert_with(|| create_member_profile(group_profile, *user_id, user_name.clone()))
+          .merge_group_profile(group_profile);
+      }
+    }
+
+    for (user_id, profile) in &mut self.effective_profiles {
+      if let Some(previous) = previous_effective_profiles.remove(user_id) {
+        profile.set_usage(previous.get_usage());
+      } else if let Some(usage) = self.stored_usage.remove(user_id) {
+        profile.set_usage(usage);
+      }
     }
+
+    for (user_id, previous) in previous_effective_profiles {
+      self.stored_usage.insert(user_id, previous.get_usage());
+    }
+
+    self.user_names_to_user_ids = self
+      .effective_profiles
+      .values()
+      .map(|profile| (profile.user_name.clone(), profile.user_id))
+      .collect();
   }
 
   pub fn get_profile_given_id(&self, user_profile_id: &UuidV4) -> Option<&UserProfile> {
@@ -276,10 +535,56 @@ impl UserProfiles {
     self.user_profiles.get_mut(user_profile_id)
   }
 
+  // The individual profile that regulates the account, if any.
+  pub fn get_profile_id_given_user_id(&self, user_id: UserId) -> Option<&UuidV4> {
+    self
+      .user_profiles
+      .iter()
+      .find(|(_, profile)| !profile.is_orphaned && profile.user_id == user_id)
+      .map(|(profile_id, _)| profile_id)
+  }
+
+  // The account's effective profile.
   pub fn get_profile_given_user_name(&self, user_name: &UserName) -> Option<&UserProfile> {
-    let profile_id = self.user_names_to_profile_ids.get(user_name)?;
+    let user_id = self.user_names_to_user_ids.get(user_name)?;
+
+    self.effective_profiles.get(user_id)
+  }
+
+  // The account's effective profile.
+  pub fn get_profile_given_user_id(&self, user_id: UserId) -> Option<&UserProfile> {
+    self.effective_profiles.get(&user_id)
+  }
+
+  // The effective profile of every regulated account.
+  pub fn get_profiles(&self) -> impl Iterator<Item = &UserProfile> {
+    self.effective_profiles.values()
+  }
+
+  // For counting usage, which is kept across rebuilds. Changes to
+  // regulations made through here are lost on the next rebuild.
+  pub fn get_profiles_mut(&mut self) -> impl Iterator<Item = &mut UserProfile> {
+    self.effective_profiles.values_mut()
+  }
+
+  // The usage of every account counted so far, regulated or not.
+  pub fn get_all_usage(&self) -> impl Iterator<Item = (UserId, UserUsage)> {
+    let effective_usage = self
+      .effective_profiles
+      .iter()
+      .map(|(user_id, profile)| (*user_id, profile.get_usage()));
+
+    let stored_usage = self
+      .stored_usage
+      .iter()
+      .map(|(user_id, usage)| (*user_id, usage.clone()));
+
+    effective_usage.chain(stored_usage)
+  }
 
-    self.user_profiles.get(profile_id)
+  // Every individual profile, orphaned ones included.
+  pub fn get_all_profiles(&self) -> impl Iterator<Item = (&UuidV4, &UserProfile)> {
+    self.user_profiles.iter()
   }
 
   pub fn get_users_number(&self) -> usize {
@@ -288,13 +593,157 @@ impl UserProfiles {
 
   pub fn add_user(&mut self, user_id: UuidV4, user: UserProfile) {
     self.user_profiles.insert(user_id, user);
+    self.rebuild_effective_profiles();
   }
 
   pub fn delete_user(&mut self, user_id: &UuidV4) {
     self.user_profiles.remove(user_id);
+    self.rebuild_effective_profiles();
   }
 
   pub fn contains_user(&self, user_id: &UuidV4) -> bool {
     self.user_profiles.contains_key(user_id)
   }
-}
\ No newline at end of file
+
+  // Points the profile at the account as it is now, which clears its
+  // orphaned mark. Returns whether anything changed.
+  pub fn set_account(
+    &mut self,
+    user_profile_id: &UuidV4,
+    user_id: UserId,
+    user_name: UserName,
+  ) -> bool {
+    let Some(profile) = self.user_profiles.get_mut(user_profile_id) else {
+      return false;
+    };
+
+    if profile.user_id == user_id && profile.user_name == user_name && !profile.is_orphaned {
+      return false;
+    }
+
+    profile.user_id = user_id;
+    profile.user_name = user_name;
+    profile.is_orphaned = false;
+    self.rebuild_effective_profiles();
+    true
+  }
+
+  // Returns whether the profile wasn't orphaned already.
+  pub fn mark_orphaned(&mut self, user_profile_id: &UuidV4) -> bool {
+    let Some(profile) = self.user_profiles.get_mut(user_profile_id) else {
+      return false;
+    };
+
+    if profile.is_orphaned {
+      return false;
+    }
+
+    profile.is_orphaned = true;
+    self.rebuild_effective_profiles();
+    true
+  }
+
+  pub fn get_group_profile_given_id(&self, group_profile_id: &UuidV4) -> Option<&GroupProfile> {
+    self.group_profiles.get(group_profile_id)
+  }
+
+  pub fn get_group_profile_given_id_mut(&mut self, group_profile_id: &UuidV4) -> Option<&mut GroupProfile> {
+    self.group_profiles.get_mut(group_profile_id)
+  }
+
+  // Every group profile, orphaned ones included.
+  pub fn get_all_group_profiles(&self) -> impl Iterator<Item = (&UuidV4, &GroupProfile)> {
+    self.group_profiles.iter()
+  }
+
+  pub fn add_group_profile(&mut self, group_profile_id: UuidV4, group_profile: GroupProfile) {
+    self.group_profiles.insert(group_profile_id, group_profile);
+    self.rebuild_effective_profiles();
+  }
+
+  pub fn delete_group_profile(&mut self, group_profile_id: &UuidV4) {
+    self.group_profiles.remove(group_profile_id);
+    self.rebuild_effective_profiles();
+  }
+
+  // Points the profile at the group as it is now, with its current
+  // members, which clears its orphaned mark.
+  pub fn set_group(
+    &mut self,
+    group_profile_id: &UuidV4,
+    group_id: GroupId,
+    group_name: GroupName,
+    members: Vec<(UserId, UserName)>,
+  ) {
+    let Some(group_profile) = self.group_profiles.get_mut(group_profile_id) else {
+      return;
+    };
+
+    group_profile.group_id = group_id;
+    group_profile.group_name = group_name;
+    group_profile.members = members;
+    group_profile.is_orphaned = false;
+    self.rebuild_effective_profiles();
+  }
+
+  // Returns whether the profile wasn't orphaned already.
+  pub fn mark_group_profile_orphaned(&mut self, group_profile_id: &UuidV4) -> bool {
+    let Some(group_profile) = self.group_profiles.get_mut(group_profile_id) else {
+      return false;
+    };
+
+    if group_profile.is_orphaned {
+      return false;
+    }
+
+    group_profile.is_orphaned = true;
+    group_profile.members.clear();
+    self.rebuild_effective_profiles();
+    true
+  }
+
+  pub fn is_group_memberships_refresh_due(&self, now: Instant) -> bool {
+    match self.group_memberships_refreshed_at {
+      Some(refreshed_at) => {
+        !now.is_eariler_than(refreshed_at.saturating_add(Self::GROUP_MEMBERSHIPS_REFRESH_INTERVAL))
+      }
+      None => {
+        true
+      }
+    }
+  }
+
+  pub fn on_group_memberships_refreshed(&mut self, now: Instant) {
+    self.group_memberships_refreshed_at = Some(now);
+  }
+}
+
+// The profile of a group member who has no profile of their own, before
+// the group's regulation is merged in. Their usage starts from zero.
+fn create_member_profile(
+  group_profile: &GroupProfile,
+  user_id: UserId,
+  user_name: UserName,
+) -> UserProfile {
+  UserProfile {
+    name: group_profile.name.clone(),
+    user_id,
+    user_name,
+    uptime_clock: UserUptimeClock::construct(
+      false,
+      Instant::from_timestamp(0),
+      Duration::zero(),
+      Instant::from_timestamp(0),
+      Duration::zero(),
+      Instant::from_timestamp(0),
+      Duration::MINUTE,
+    ),
+    device_access_regulation: DeviceAccessRegulation::new(),
+    screen_access_regulation: ScreenAccessRegulation::default(),
+    internet_access_regulation: InternetAccessRegulation::new(),
+    application_regulations: ApplicationRegulations::new(),
+    login_policy: LoginPolicy::default(),
+    rules_stats: RulesStats::new(RulesStats::DEFAULT_MAXIMUM_RULES_NUMBER),
+    is_orphaned: false,
+  }
+}
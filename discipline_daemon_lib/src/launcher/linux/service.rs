@@ -0,0 +1,472 @@
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{Mutex, watch};
+use tokio::task::spawn_blocking;
+use tokio::time::{interval, timeout, MissedTickBehavior};
+use crate::x::TextualError;
+use super::{AccountChanges, PasswdAccountBackend, IsProcessBackend, Api, ChpasswdBackend, ProcessEvent, ProcessEvents, ProcfsBackend, dns, http_proxy, native_messaging, Daemon, DesktopNotificationBackend, LaunchConfiguration, LoginctlBackend, NftBackend, SystemdNotifier, pam};
+use super::procedures::password_escrow::RelockVaults;
+use super::procedures::session_enforcement::EnforceBlocks;
+use super::procedures::block_warnings::SendBlockWarnings;
+use super::procedures::internet_blocking::ReconcileInternetBlocking;
+use super::procedures::dns_filtering::{ReconcileDnsRedirects, ReloadBlocklists};
+use super::procedures::browser_policies::{ReconcileBrowserPolicies, RemoveBrowserPolicies};
+use super::procedures::application_regulation::{EnforceApplicationRegulations, HandleProcessExecution};
+use super::procedures::application_usage::{RecordProcessEvent, SaveApplicationUsage};
+use super::procedures::user_accounts::ReconcileUserProfiles;
+use super::procedures::group_profiles::RefreshGroupMemberships;
+use super::procedures::pam_policy_cache::WritePamPolicyCache;
+use super::procedures::session_registry::{ReconcileSessions, SynchronizeUptimeClocks};
+
+// Runs the daemon until it receives SIGTERM or SIGINT.
+//
+// Startup is reported to systemd only once everything is listening,
+// so units ordered after ours can rely on the sockets existing. On
+// shutdown, the servers stop accepting connections, the ones in flight
+// get `shutdown_grace_period` to finish, and the state is persisted
+// one last time.
+pub async fn run(configuration_path: &Path) -> ExitCode {
+  let notifier = SystemdNotifier::from_environment();
+  let mut textual_error = TextualError::new("Running Discipline Linux Daemon");
+
+  let Ok(configuration) = LaunchConfiguration::load(configuration_path, &mut textual_error) else {
+    return exit_with_error(&notifier, textual_error);
+  };
+
+  let Ok(daemon) = Daemon::open(&configuration, &mut textual_error) else {
+    return exit_with_error(&notifier, textual_error);
+  };
+
+  let daemon = Arc::new(Mutex::new(daemon));
+
+  let Ok(api) = Api::bind(configuration.api_server_port, &mut textual_error).await else {
+    return exit_with_error(&notifier, textual_error);
+  };
+
+  let Ok(pam_server) = pam::Server::new(
+    &configuration.pam_server_path,
+    configuration.pam_client_authentication_token.clone(),
+    &mut textual_error,
+  ).await else {
+    return exit_with_error(&notifier, textual_error);
+  };
+
+  let dns_resolver = match &configuration.dns_resolver {
+    Some(dns_resolver_configuration) => {
+      let Ok(dns_resolver) = dns::Resolver::bind(dns_resolver_configuration, &mut textual_error).await else {
+        return exit_with_error(&notifier, textual_error);
+      };
+
+      Some(dns_resolver)
+    }
+    None => {
+      None
+    }
+  };
+
+  let http_proxy = match &configuration.http_proxy {
+    Some(http_proxy_configuration) => {
+      let Ok(http_proxy) = http_proxy::Proxy::bind(http_proxy_configuration, &mut textual_error).await else {
+        return exit_with_error(&notifier, textual_error);
+      };
+
+      Some(http_proxy)
+    }
+    None => {
+      None
+    }
+  };
+
+  let native_messaging_server = match &configuration.native_messaging {
+    Some(native_messaging_configuration) => {
+      let Ok(native_messaging_server) = native_messaging::Server::new(native_messaging_configuration, &mut textual_error).await else {
+        return exit_with_error(&notifier, textual_error);
+      };
+
+      Some(native_messaging_server)
+    }
+    None => {
+      None
+    }
+  };
+
+  // Without process events, blocked applications are still killed and
+  // application usage is still counted by the periodic scan, just later
+  // and less precisely.
+  let process_events = match ProcessEvents::open(&mut TextualError::new("Watching process events")) {
+    Ok(process_events) => {
+      Some(process_events)
+    }
+    Err(()) => {
+      // TODO: Use a proper logging mechanism.
+      eprintln!("Discipline Linux Daemon: Process events are unavailable, falling back to scanning /proc");
+      None
+    }
+  };
+
+  // Without account change events, renamed and deleted accounts are
+  // only noticed the next time the daemon starts.
+  let account_changes = match AccountChanges::open(&mut TextualError::new("Watching account changes")) {
+    Ok(account_changes) => {
+      Some(account_changes)
+    }
+    Err(()) => {
+      // TODO: Use a proper logging mechanism.
+      eprintln!("Discipline Linux Daemon: Account change events are unavailable, accounts are only reconciled at startup");
+      None
+    }
+  };
+
+  let (mut sigterm, mut sigint) = match (
+    signal(SignalKind::terminate()),
+    signal(SignalKind::interrupt()),
+  ) {
+    (Ok(sigterm), Ok(sigint)) => {
+      (sigterm, sigint)
+    }
+    (Err(error), _) | (_, Err(error)) => {
+      textual_error.change_context("Installing signal handlers");
+      textual_error.add_attachement_display("Io error", error);
+      return exit_with_error(&notifier, textual_error);
+    }
+  };
+
+  // Blocks that were in effect when we stopped must be back in place
+  // before anyone can log in.
+  let startup_daemon = Arc::clone(&daemon);
+  let _ = spawn_blocking(move || reconcile_at_startup(&startup_daemon)).await;
+
+  let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+  let process_events_task = process_events.map(|process_events| {
+    tokio::spawn(watch_process_events(process_events, Arc::clone(&daemon), shutdown_receiver.clone()))
+  });
+  let account_changes_task = account_changes.map(|account_changes| {
+    tokio::spawn(watch_account_changes(account_changes, Arc::clone(&daemon), shutdown_receiver.clone()))
+  });
+  let api_task = tokio::spawn(api.serve(Arc::clone(&daemon), shutdown_receiver.clone()));
+  let pam_server_task = tokio::spawn(pam_server.serve(Arc::clone(&daemon), shutdown_receiver.clone()));
+  let dns_resolver_task = dns_resolver.map(|dns_resolver| {
+    tokio::spawn(dns_resolver.serve(Arc::clone(&daemon), shutdown_receiver.clone()))
+  });
+  let http_proxy_task = http_proxy.map(|http_proxy| {
+    tokio::spawn(http_proxy.serve(Arc::clone(&daemon), shutdown_receiver.clone()))
+  });
+  let native_messaging_server_task = native_messaging_server.map(|native_messaging_server| {
+    tokio::spawn(native_messaging_server.serve(Arc::clone(&daemon), shutdown_receiver))
+  });
+
+  let _ = notifier.notify_ready(&mut textual_error);
+
+  let mut ticks = interval(configuration.tick_interval.to_std_duration());
+  ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+  loop {
+    tokio::select! {
+      _ = sigterm.recv() => {
+        break;
+      }
+      _ = sigint.recv() => {
+        break;
+      }
+      _ = ticks.tick() => {
+        tick(&daemon).await;
+
+        if let Some(pam_heartbeat_path) = &configuration.pam_heartbeat_path {
+          let mut textual_error = TextualError::new("Running the daemon's periodic tasks");
+          if let Err(()) = pam::Heartbeat::beat(pam_heartbeat_path, &mut textual_error) {
+            // TODO: Use a proper logging mechanism.
+            eprintln!("{textual_error}");
+          }
+        }
+      }
+    }
+  }
+
+  let _ = notifier.notify_stopping(&mut textual_error);
+  let _ = shutdown_sender.send(true);
+
+  let drained = timeout(
+    configuration.shutdown_grace_period.to_std_duration(),
+    async {
+      let _ = api_task.await;
+      let _ = pam_server_task.await;
+      if let Some(process_events_task) = process_events_task {
+        let _ = process_events_task.await;
+      }
+      if let Some(account_changes_task) = account_changes_task {
+        let _ = account_changes_task.await;
+      }
+      if let Some(dns_resolver_task) = dns_resolver_task {
+        let _ = dns_resolver_task.await;
+      }
+      if let Some(http_proxy_task) = http_proxy_task {
+        let _ = http_proxy_task.await;
+      }
+      if let Some(native_messaging_server_task) = native_messaging_server_task {
+        let _ = native_messaging_server_task.await;
+      }
+    },
+  ).await;
+
+  if drained.is_err() {
+    // TODO: Use a proper logging mechanism.
+    eprintln!("Discipline Linux Daemon: Some connections didn't finish within the shutdown grace period");
+  }
+
+  let mut textual_error = TextualError::new("Stopping Discipline Linux Daemon");
+  if let Err(()) = daemon.lock().await.persist(&mut textual_error) {
+    eprintln!("{textual_error}");
+    return ExitCode::FAILURE;
+  }
+
+  ExitCode::SUCCESS
+}
+
+// Many of these run commands or scan /proc, so they run where blocking
+// is fine.
+fn reconcile_at_startup(daemon: &Mutex<Daemon>) {
+  {
+    let mut daemon = daemon.blocking_lock();
+
+    // Everything below finds profiles by user id or user name.
+    let mut textual_error = TextualError::new("Reconciling user profiles at startup");
+    if let Err(()) = ReconcileUserProfiles.execute(&mut daemon, &PasswdAccountBackend::default(), &mut textual_error) {
+      // TODO: Use a proper logging mechanism.
+      eprintln!("{textual_error}");
+    }
+
+    let mut textual_error = TextualError::new("Refreshing group memberships at startup");
+    if let Err(()) = RefreshGroupMemberships.execute(&mut daemon, &mut textual_error) {
+      eprintln!("{textual_error}");
+    }
+
+    let mut textual_error = TextualError::new("Reconciling sessions at startup");
+    if let Err(()) = ReconcileSessions.execute(&mut daemon, &LoginctlBackend::default(), &mut textual_error) {
+      eprintln!("{textual_error}");
+    }
+
+    // Reveal windows may have ended while we were down.
+    let mut textual_error = TextualError::new("Relocking vaults at startup");
+    RelockVaults.execute(&mut daemon, &ChpasswdBackend::default(), &mut textual_error);
+
+    let mut textual_error = TextualError::new("Loading blocklists at startup");
+    if let Err(()) = ReloadBlocklists.execute(&mut daemon, &mut textual_error) {
+      eprintln!("{textual_error}");
+    }
+  }
+
+  let mut textual_error = TextualError::new("Reconciling internet blocking at startup");
+  if let Err(()) = ReconcileInternetBlocking.execute(daemon, &NftBackend::default(), &mut textual_error) {
+    // TODO: Use a proper logging mechanism.
+    eprintln!("{textual_error}");
+  }
+
+  let mut textual_error = TextualError::new("Reconciling DNS redirects at startup");
+  if let Err(()) = ReconcileDnsRedirects.execute(daemon, &NftBackend::default(), &mut textual_error) {
+    eprintln!("{textual_error}");
+  }
+
+  let mut textual_error = TextualError::new("Reconciling browser policies at startup");
+  if let Err(()) = ReconcileBrowserPolicies.execute(&mut daemon.blocking_lock(), &mut textual_error) {
+    eprintln!("{textual_error}");
+  }
+}
+
+async fn tick(daemon: &Arc<Mutex<Daemon>>) {
+  let daemon = Arc::clone(daemon);
+  let _ = spawn_blocking(move || run_periodic_tasks(&daemon)).await;
+}
+
+// The daemon is locked once per task rather than for the whole run, and
+// not at all while nft, loginctl and the like run or /proc is scanned,
+// so the servers keep answering in the meantime.
+fn run_periodic_tasks(daemon: &Mutex<Daemon>) {
+  {
+    let mut daemon = daemon.blocking_lock();
+    daemon.synchronize_clock();
+
+    let now = daemon.state.monotonic_clock.now();
+    if daemon.state.user_profiles.is_group_memberships_refresh_due(now) {
+      let mut textual_error = TextualError::new("Refreshing group memberships");
+      if let Err(()) = RefreshGroupMemberships.execute(&mut daemon, &mut textual_error) {
+        // TODO: Use a proper logging mechanism.
+        eprintln!("{textual_error}");
+      }
+    }
+
+    // Before anything that looks at allowances.
+    SynchronizeUptimeClocks.execute(&mut daemon);
+  }
+
+  // Rotating a password changes it and saves it together, so this one
+  // keeps the daemon locked while chpasswd runs. It's rare.
+  let mut textual_error = TextualError::new("Relocking vaults");
+  RelockVaults.execute(&mut daemon.blocking_lock(), &ChpasswdBackend::default(), &mut textual_error);
+
+  let mut textual_error = TextualError::new("Sending block warnings");
+  SendBlockWarnings.execute(daemon, &DesktopNotificationBackend::default(), &mut textual_error);
+
+  let mut textual_error = TextualError::new("Enforcing blocks on sessions");
+  EnforceBlocks.execute(daemon, &LoginctlBackend::default(), &mut textual_error);
+
+  let mut textual_error = TextualError::new("Reconciling internet blocking");
+  if let Err(()) = ReconcileInternetBlocking.execute(daemon, &NftBackend::default(), &mut textual_error) {
+    // TODO: Use a proper logging mechanism.
+    eprintln!("{textual_error}");
+  }
+
+  let mut textual_error = TextualError::new("Reloading blocklists");
+  if let Err(()) = ReloadBlocklists.execute(&mut daemon.blocking_lock(), &mut textual_error) {
+    eprintln!("{textual_error}");
+  }
+
+  let mut textual_error = TextualError::new("Reconciling DNS redirects");
+  if let Err(()) = ReconcileDnsRedirects.execute(daemon, &NftBackend::default(), &mut textual_error) {
+    eprintln!("{textual_error}");
+  }
+
+  let mut textual_error = TextualError::new("Reconciling browser policies");
+  if let Err(()) = ReconcileBrowserPolicies.execute(&mut daemon.blocking_lock(), &mut textual_error) {
+    eprintln!("{textual_error}");
+  }
+
+  let mut textual_error = TextualError::new("Writing the PAM policy cache");
+  if let Err(()) = WritePamPolicyCache.execute(&mut daemon.blocking_lock(), &mut textual_error) {
+    eprintln!("{textual_error}");
+  }
+
+  let process_backend = ProcfsBackend::default();
+  let mut textual_error = TextualError::new("Scanning processes");
+  match process_backend.list_processes(&mut textual_error) {
+    Ok(processes) => {
+      let mut daemon = daemon.blocking_lock();
+
+      let mut textual_error = TextualError::new("Enforcing application regulations");
+      EnforceApplicationRegulations { processes: processes.clone() }
+        .execute(&mut daemon, &process_backend, &mut textual_error);
+
+      let mut textual_error = TextualError::new("Saving application usage");
+      if let Err(()) = (SaveApplicationUsage { processes }).execute(&mut daemon, &mut textual_error) {
+        eprintln!("{textual_error}");
+      }
+    }
+    Err(()) => {
+      eprintln!("{textual_error}");
+    }
+  }
+
+  let mut textual_error = TextualError::new("Saving the daemon's state");
+  if let Err(()) = daemon.blocking_lock().persist(&mut textual_error) {
+    eprintln!("{textual_error}");
+  }
+}
+
+async fn watch_process_events(
+  mut process_events: ProcessEvents,
+  daemon: Arc<Mutex<Daemon>>,
+  mut shutdown: watch::Receiver<bool>,
+) {
+  loop {
+    let event = tokio::select! {
+      _ = shutdown.changed() => {
+        return;
+      }
+      event = process_events.next() => {
+        match event {
+          Ok(event) => {
+            event
+          }
+          Err(error) => {
+            // TODO: Use a proper logging mechanism.
+            eprintln!("Discipline Linux Daemon: Stopped receiving process events, falling back to scanning /proc: {error}");
+            return;
+          }
+        }
+      }
+    };
+
+    let mut daemon = daemon.lock().await;
+    // So run time is counted from when the event happened rather than
+    // from the last tick.
+    daemon.synchronize_clock();
+
+    RecordProcessEvent { event }.execute(&mut daemon, &ProcfsBackend::default());
+
+    if let ProcessEvent::Execution(process_id) = event {
+      let mut textual_error = TextualError::new("Handling a process execution");
+
+      let procedure = HandleProcessExecution { process_id };
+      if let Err(()) = procedure.execute(&mut daemon, &ProcfsBackend::default(), &mut textual_error) {
+        // TODO: Use a proper logging mechanism.
+        eprintln!("{textual_error}");
+      }
+    }
+  }
+}
+
+async fn watch_account_changes(
+  mut account_changes: AccountChanges,
+  daemon: Arc<Mutex<Daemon>>,
+  mut shutdown: watch::Receiver<bool>,
+) {
+  loop {
+    tokio::select! {
+      _ = shutdown.changed() => {
+        return;
+      }
+      result = account_changes.next() => {
+        if let Err(error) = result {
+          // TODO: Use a proper logging mechanism.
+          eprintln!("Discipline Linux Daemon: Stopped watching account changes, accounts are only reconciled at startup: {error}");
+          return;
+        }
+      }
+    }
+
+    let mut daemon = daemon.lock().await;
+    let mut textual_error = TextualError::new("Reconciling profiles after an account change");
+
+    if let Err(()) = ReconcileUserProfiles.execute(&mut daemon, &PasswdAccountBackend::default(), &mut textual_error) {
+      // TODO: Use a proper logging mechanism.
+      eprintln!("{textual_error}");
+    }
+    if let Err(()) = RefreshGroupMemberships.execute(&mut daemon, &mut textual_error) {
+      eprintln!("{textual_error}");
+    }
+  }
+}
+
+// Puts back the browser policy files that were there before the daemon
+// wrote its own, for package managers to run when uninstalling it.
+pub fn remove_browser_policies(configuration_path: &Path) -> ExitCode {
+  // Files we never wrote are left alone, so the default paths are safe
+  // to try when the configuration is gone or lacks the section.
+  let browser_policies_configuration = LaunchConfiguration::load(
+    configuration_path,
+    &mut TextualError::new("Loading the configuration"),
+  )
+    .ok()
+    .and_then(|configuration| configuration.browser_policies)
+    .unwrap_or_default();
+
+  let mut textual_error = TextualError::new("Removing Discipline's browser policy files");
+  match RemoveBrowserPolicies.execute(&browser_policies_configuration, &mut textual_error) {
+    Ok(()) => {
+      ExitCode::SUCCESS
+    }
+    Err(()) => {
+      // TODO: Use a proper logging mechanism.
+      eprintln!("{textual_error}");
+      ExitCode::FAILURE
+    }
+  }
+}
+
+fn exit_with_error(notifier: &SystemdNotifier, mut textual_error: TextualError) -> ExitCode {
+  let _ = notifier.notify_status("Failed to start", &mut textual_error);
+  // TODO: Use a proper logging mechanism.
+  eprintln!("{textual_error}");
+  ExitCode::FAILURE
+}
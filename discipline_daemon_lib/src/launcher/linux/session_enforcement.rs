@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::x::{Duration, Instant};
+use super::UserName;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SessionEnforcementAction {
+  LockScreen,
+  TerminateSessions,
+  KillProcesses,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEnforcementConfiguration {
+  pub action: SessionEnforcementAction,
+  // How long a user may stay logged in after a block starts, so they
+  // get a chance to save their work.
+  pub grace_period: Duration,
+}
+
+impl Default for SessionEnforcementConfiguration {
+  fn default() -> Self {
+    Self {
+      action: SessionEnforcementAction::LockScreen,
+      grace_period: Duration::MINUTE,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PendingEnforcement {
+  blocked_since: Instant,
+  last_enforced_at: Option<Instant>,
+}
+
+impl PendingEnforcement {
+  pub fn get_blocked_since(&self) -> Instant {
+    self.blocked_since
+  }
+
+  pub fn get_last_enforced_at(&self) -> Option<Instant> {
+    self.last_enforced_at
+  }
+}
+
+pub enum EnforcementDecision {
+  NotBlocked,
+  WithinGracePeriod { remaining: Duration },
+  RecentlyEnforced,
+  Enforce(SessionEnforcementAction),
+}
+
+// Tracks, for every user with live sessions, when we first noticed
+// their profile was blocked and when we last acted on it.
+#[derive(Debug)]
+pub struct SessionEnforcer {
+  configuration: SessionEnforcementConfiguration,
+  pending: HashMap<UserName, PendingEnforcement>,
+}
+
+impl SessionEnforcer {
+  // How long to wait before acting again on a user who is still
+  // blocked, for instance because they unlocked their screen or
+  // because the previous attempt failed.
+  const REENFORCEMENT_INTERVAL: Duration = Duration::from_milliseconds(30 * 1000);
+
+  pub fn create(configuration: SessionEnforcementConfiguration) -> Self {
+    Self {
+      configuration,
+      pending: HashMap::new(),
+    }
+  }
+
+  pub fn get_configuration(&self) -> &SessionEnforcementConfiguration {
+    &self.configuration
+  }
+
+  pub fn get_pending(&self, user_name: &UserName) -> Option<&PendingEnforcement> {
+    self.pending.get(user_name)
+  }
+
+  pub fn decide(
+    &mut self,
+    user_name: &UserName,
+    is_blocked: bool,
+    now: Instant,
+  ) -> EnforcementDecision {
+    if !is_blocked {
+      self.pending.remove(user_name);
+      return EnforcementDecision::NotBlocked;
+    }
+
+    let pending = self
+      .pending
+      .entry(user_name.clone())
+      .or_insert(PendingEnforcement {
+        blocked_since: now,
+        last_enforced_at: None,
+      });
+
+    let grace_period_end = pending
+      .blocked_since
+      .saturating_add(self.configuration.grace_period);
+
+    if now.is_eariler_than(grace_period_end) {
+      return EnforcementDecision::WithinGracePeriod {
+        remaining: now.till_or_zero(grace_period_end),
+      };
+    }
+
+    if let Some(last_enforced_at) = pending.last_enforced_at
+      && now.since_or_zero(last_enforced_at) < Self::REENFORCEMENT_INTERVAL
+    {
+      return EnforcementDecision::RecentlyEnforced;
+    }
+
+    EnforcementDecision::Enforce(self.configuration.action)
+  }
+
+  pub fn on_enforced(&mut self, user_name: &UserName, now: Instant) {
+    if let Some(pending) = self.pending.get_mut(user_name) {
+      pending.last_enforced_at = Some(now);
+    }
+  }
+
+  // Forgets users that no longer have live sessions.
+  pub fn retain(&mut self, mut has_open_sessions: impl FnMut(&UserName) -> bool) {
+    self.pending.retain(|user_name, _| has_open_sessions(user_name));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use super::*;
+
+  const GRACE_PERIOD: Duration = Duration::MINUTE;
+
+  fn create_enforcer() -> SessionEnforcer {
+    SessionEnforcer::create(SessionEnforcementConfiguration {
+      action: SessionEnforcementAction::TerminateSessions,
+      grace_period: GRACE_PERIOD,
+    })
+  }
+
+  fn create_user_name() -> UserName {
+    UserName::new(CString::new("alex").unwrap())
+  }
+
+  fn at_second(second: u64) -> Instant {
+    Instant::from_timestamp(second * 1000)
+  }
+
+  #[test]
+  fn users_get_a_grace_period_once_blocked() {
+    let mut enforcer = create_enforcer();
+    let user_name = create_user_name();
+
+    assert!(matches!(
+      enforcer.decide(&user_name, true, at_second(100)),
+      EnforcementDecision::WithinGracePeriod { remaining } if remaining == GRACE_PERIOD,
+    ));
+    assert!(matches!(
+      enforcer.decide(&user_name, true, at_second(130)),
+      EnforcementDecision::WithinGracePeriod { remaining } if remaining == Duration::from_milliseconds(30 * 1000),
+    ));
+    assert!(matches!(
+      enforcer.decide(&user_name, true, at_second(160)),
+      EnforcementDecision::Enforce(SessionEnforcementAction::TerminateSessions),
+    ));
+  }
+
+  #[test]
+  fn users_still_blocked_are_enforced_on_again_after_30_seconds() {
+    let mut enforcer = create_enforcer();
+    let user_name = create_user_name();
+
+    enforcer.decide(&user_name, true, at_second(0));
+    assert!(matches!(enforcer.decide(&user_name, true, at_second(60)), EnforcementDecision::Enforce(_)));
+    enforcer.on_enforced(&user_name, at_second(60));
+
+    assert!(matches!(
+      enforcer.decide(&user_name, true, at_second(89)),
+      EnforcementDecision::RecentlyEnforced,
+    ));
+    assert!(matches!(
+      enforcer.decide(&user_name, true, at_second(90)),
+      EnforcementDecision::Enforce(_),
+    ));
+  }
+
+  #[test]
+  fn being_unblocked_starts_the_grace_period_over() {
+    let mut enforcer = create_enforcer();
+    let user_name = create_user_name();
+
+    enforcer.decide(&user_name, true, at_second(0));
+    enforcer.on_enforced(&user_name, at_second(60));
+
+    assert!(matches!(
+      enforcer.decide(&user_name, false, at_second(70)),
+      EnforcementDecision::NotBlocked,
+    ));
+    assert!(enforcer.get_pending(&user_name).is_none());
+
+    assert!(matches!(
+      enforcer.decide(&user_name, true, at_second(80)),
+      EnforcementDecision::WithinGracePeriod { remaining } if remaining == GRACE_PERIOD,
+    ));
+    assert_eq!(enforcer.get_pending(&user_name).unwrap().get_last_enforced_at(), None);
+  }
+
+  #[test]
+  fn users_without_sessions_are_forgotten() {
+    let mut enforcer = create_enforcer();
+    let user_name = create_user_name();
+
+    enforcer.decide(&user_name, true, at_second(0));
+    enforcer.retain(|_| false);
+
+    assert!(enforcer.get_pending(&user_name).is_none());
+  }
+}
@@ -0,0 +1,286 @@
+use std::collections::{HashMap, VecDeque};
+use serde::{Deserialize, Serialize};
+use crate::x::UuidV4;
+use super::{LoginClass, LoginContext, LogindSession, UserName};
+
+// What the PAM module knows about a session when it opens or closes
+// it. Modules that predate the session registry send none of it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionDetails {
+  // XDG_SESSION_ID, set when pam_systemd ran before the module.
+  pub logind_session_id: Option<String>,
+  pub service: Option<String>,
+  pub tty: Option<String>,
+  pub remote_host: Option<String>,
+  // The process that opened the session through PAM, which usually
+  // stays around to close it.
+  pub process_id: Option<u32>,
+  // Worked out by the daemon from the rest when it records the session,
+  // so it's neither sent by the module nor saved.
+  #[serde(skip)]
+  pub login_class: Option<LoginClass>,
+}
+
+impl SessionDetails {
+  pub fn get_login_class(&self) -> LoginClass {
+    if let Some(login_class) = self.login_class {
+      return login_class;
+    }
+
+    LoginContext {
+      service: self.service.clone().unwrap_or_default(),
+      tty: self.tty.clone(),
+      remote_host: self.remote_host.clone(),
+      requesting_user_id: None,
+    }
+    .get_class()
+  }
+}
+
+// When a regulated user's session will be cut off, for the PAM module
+// to put in the session's environment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionDeadline {
+  pub profile_name: String,
+  // A UNIX timestamp in milliseconds, assuming the user keeps using the
+  // device. None when nothing is going to block them.
+  pub ends_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRecord {
+  pub id: UuidV4,
+  pub user_name: UserName,
+  pub details: SessionDetails,
+  // UNIX timestamps in milliseconds.
+  pub opened_at: i64,
+  // None while the session is open.
+  pub closed_at: Option<i64>,
+}
+
+impl SessionRecord {
+  pub fn is_open(&self) -> bool {
+    self.closed_at.is_none()
+  }
+
+  // Whether `details` describe this session, for telling which of a
+  // user's sessions PAM closed.
+  fn matches(&self, details: &SessionDetails) -> bool {
+    if let (Some(ours), Some(theirs)) = (&self.details.logind_session_id, &details.logind_session_id) {
+      return ours == theirs;
+    }
+    if let (Some(ours), Some(theirs)) = (self.details.process_id, details.process_id) {
+      return ours == theirs;
+    }
+
+    false
+  }
+}
+
+// Every session the PAM module told us it opened and hasn't closed yet,
+// and the latest ones that were closed.
+#[derive(Debug)]
+pub struct SessionRecords {
+  records: HashMap<UserName, Vec<SessionRecord>>,
+  closed_records: VecDeque<SessionRecord>,
+}
+
+impl Default for SessionRecords {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl SessionRecords {
+  // Closed sessions are only kept for looking back on recent activity,
+  // so the oldest are forgotten past this many.
+  pub const MAXIMUM_CLOSED_RECORDS: usize = 256;
+
+  pub fn new() -> Self {
+    Self {
+      records: HashMap::new(),
+      closed_records: VecDeque::new(),
+    }
+  }
+
+  // Restores the records saved before the daemon stopped. They still
+  // need reconciling with logind, since sessions open and close while
+  // the daemon isn't running.
+  pub fn construct(saved_records: Vec<SessionRecord>) -> Self {
+    let mut records = Self::new();
+    let (open_records, mut closed_records): (Vec<_>, Vec<_>) = saved_records
+      .into_iter()
+      .partition(SessionRecord::is_open);
+
+    for mut record in open_records {
+      record.details.login_class = Some(record.details.get_login_class());
+      records.records.entry(record.user_name.clone()).or_default().push(record);
+    }
+
+    closed_records.sort_by_key(|record| record.closed_at);
+    for record in closed_records {
+      records.push_closed_record(record);
+    }
+
+    records
+  }
+
+  pub fn on_session_opened(
+    &mut self,
+    user_name: &UserName,
+    mut details: SessionDetails,
+    now: i64,
+  ) -> &SessionRecord {
+    details.login_class = Some(details.get_login_class());
+    let records = self.records.entry(user_name.clone()).or_default();
+
+    records.push(SessionRecord {
+      id: UuidV4::generate(),
+      user_name: user_name.clone(),
+      details,
+      opened_at: now,
+      closed_at: None,
+    });
+
+    // Just pushed.
+    records.last().unwrap()
+  }
+
+  // Closes the session `details` describe. When they don't tell, like
+  // with older modules, the user's oldest session goes first.
+  //
+  // Returns the closed record, and the record that was forgotten to
+  // make room for it, if any.
+  pub fn on_session_closed(
+    &mut self,
+    user_name: &UserName,
+    details: &SessionDetails,
+    now: i64,
+  ) -> Option<(SessionRecord, Option<SessionRecord>)> {
+    let records = self.records.get_mut(user_name)?;
+
+    let index = records
+      .iter()
+      .position(|record| record.matches(details))
+      .unwrap_or(0);
+
+    if index >= records.len() {
+      return None;
+    }
+
+    let mut record = records.remove(index);
+    if records.is_empty() {
+      self.records.remove(user_name);
+    }
+
+    record.closed_at = Some(now);
+    let forgotten = self.push_closed_record(record.clone());
+    Some((record, forgotten))
+  }
+
+  fn push_closed_record(&mut self, record: SessionRecord) -> Option<SessionRecord> {
+    self.closed_records.push_back(record);
+
+    if self.closed_records.len() > Self::MAXIMUM_CLOSED_RECORDS {
+      self.closed_records.pop_front()
+    } else {
+      None
+    }
+  }
+
+  // Closes the records of sessions that ended while nobody told us, and
+  // opens records for logind sessions we weren't told about. A record
+  // still stands when logind knows its session, or, when it doesn't
+  // know logind's id, while the process that opened it is alive.
+  //
+  // Returns the records that changed, and those that were forgotten.
+  pub fn reconcile(
+    &mut self,
+    logind_sessions: &[LogindSession],
+    is_process_alive: impl Fn(u32) -> bool,
+    now: i64,
+  ) -> (Vec<SessionRecord>, Vec<SessionRecord>) {
+    let mut changed_records = Vec::new();
+    let mut forgotten_records = Vec::new();
+
+    let is_session_alive = |record: &SessionRecord| {
+      match (&record.details.logind_session_id, record.details.process_id) {
+        (Some(logind_session_id), _) => {
+          logind_sessions.iter().any(|session| &session.id == logind_session_id)
+        }
+        (None, Some(process_id)) => {
+          is_process_alive(process_id)
+        }
+        // Nothing to check it against.
+        (None, None) => {
+          true
+        }
+      }
+    };
+
+    let mut ended_records = Vec::new();
+    for records in self.records.values_mut() {
+      let (alive, ended): (Vec<_>, Vec<_>) = records.drain(..).partition(|record| is_session_alive(record));
+      *records = alive;
+      ended_records.extend(ended);
+    }
+    self.records.retain(|_, records| !records.is_empty());
+
+    for mut record in ended_records {
+      record.closed_at = Some(now);
+      changed_records.push(record.clone());
+      forgotten_records.extend(self.push_closed_record(record));
+    }
+
+    for session in logind_sessions {
+      let is_known = self
+        .records
+        .values()
+        .flatten()
+        .any(|record| record.details.logind_session_id.as_ref() == Some(&session.id));
+
+      if is_known {
+        continue;
+      }
+
+      let details = SessionDetails {
+        logind_session_id: Some(session.id.clone()),
+        service: session.service.clone(),
+        tty: session.tty.clone(),
+        remote_host: session.remote_host.clone(),
+        process_id: session.leader,
+        login_class: None,
+      };
+
+      let record = self.on_session_opened(&session.user_name, details, now);
+      changed_records.push(record.clone());
+    }
+
+    (changed_records, forgotten_records)
+  }
+
+  pub fn get_sessions(&self, user_name: &UserName) -> &[SessionRecord] {
+    self
+      .records
+      .get(user_name)
+      .map(Vec::as_slice)
+      .unwrap_or_default()
+  }
+
+  pub fn get_open_sessions(&self) -> impl Iterator<Item = &SessionRecord> {
+    self.records.values().flatten()
+  }
+
+  // Oldest first.
+  pub fn get_closed_sessions(&self) -> impl Iterator<Item = &SessionRecord> {
+    self.closed_records.iter()
+  }
+
+  pub fn has_open_sessions(&self, user_name: &UserName) -> bool {
+    self.records.contains_key(user_name)
+  }
+
+  pub fn get_users_with_open_sessions(&self) -> Vec<UserName> {
+    self.records.keys().cloned().collect()
+  }
+}
@@ -1,8 +1,20 @@
-use crate::x::{MonotonicClock, RulesStats};
-use super::UserProfiles;
+use crate::x::{MonotonicClock, RulesStats, Vaults, VaultsStats};
+use super::{ApplicationUsageTracker, BlockWarnings, BrowserPolicies, DnsRedirector, DomainBlocklists, InternetBlocker, PasswordEscrows, SessionEnforcer, SessionRecords, UserProfiles, pam};
 
 pub struct State {
   pub user_profiles: UserProfiles,
   pub monotonic_clock: MonotonicClock,
   pub rules_stats: RulesStats,
-}
\ No newline at end of file
+  pub vaults: Vaults,
+  pub vaults_stats: VaultsStats,
+  pub password_escrows: PasswordEscrows,
+  pub session_records: SessionRecords,
+  pub session_enforcer: SessionEnforcer,
+  pub block_warnings: BlockWarnings,
+  pub internet_blocker: InternetBlocker,
+  pub domain_blocklists: DomainBlocklists,
+  pub dns_redirector: DnsRedirector,
+  pub browser_policies: BrowserPolicies,
+  pub application_usage_tracker: ApplicationUsageTracker,
+  pub pam_policy_cache_writer: pam::PolicyCacheWriter,
+}
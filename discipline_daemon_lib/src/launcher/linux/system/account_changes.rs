@@ -0,0 +1,193 @@
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use tokio::io::unix::AsyncFd;
+use crate::x::IsTextualError;
+
+// Hears about changes to the account databases in /etc through
+// inotify. The directory is watched rather than the files because
+// tools like useradd and vipw replace them by renaming a new file over
+// the old one, which a watch on the file itself wouldn't survive.
+pub struct AccountChanges {
+  inotify: AsyncFd<OwnedFd>,
+}
+
+impl AccountChanges {
+  pub const DIRECTORY: &'static str = "/etc";
+  pub const WATCHED_FILE_NAMES: &'static [&'static str] = &["passwd", "group"];
+
+  pub fn open(textual_error: &mut impl IsTextualError) -> Result<Self, ()> {
+    textual_error.change_context("Watching the account databases for changes");
+
+    let inotify = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    if inotify < 0 {
+      textual_error.add_message("Failed to create an inotify instance");
+      textual_error.add_attachement_display("Io error", io::Error::last_os_error());
+      return Err(());
+    }
+
+    let inotify = unsafe { OwnedFd::from_raw_fd(inotify) };
+
+    // The constant has no interior nul bytes.
+    let directory = CString::new(Self::DIRECTORY).unwrap();
+    let watch = unsafe {
+      libc::inotify_add_watch(
+        inotify.as_raw_fd(),
+        directory.as_ptr(),
+        libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO,
+      )
+    };
+
+    if watch < 0 {
+      textual_error.add_message("Failed to watch the directory");
+      textual_error.add_attachement_display("Io error", io::Error::last_os_error());
+      textual_error.add_attachement_display("Directory", Self::DIRECTORY);
+      return Err(());
+    }
+
+    match AsyncFd::new(inotify) {
+      Ok(inotify) => {
+        Ok(Self { inotify })
+      }
+      Err(error) => {
+        textual_error.add_message("Failed to register the inotify instance with the runtime");
+        textual_error.add_attachement_display("Io error", error);
+        Err(())
+      }
+    }
+  }
+
+  // Waits until one of the watched files changes. A single edit often
+  // produces several events, so callers should expect to be woken more
+  // than once per change.
+  pub async fn next(&mut self) -> io::Result<()> {
+    let mut buffer = [0u8; 4096];
+
+    loop {
+      let mut guard = self.inotify.readable().await?;
+
+      let result = guard.try_io(|inotify| {
+        let length = unsafe {
+          libc::read(
+            inotify.as_raw_fd(),
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            buffer.len(),
+          )
+        };
+
+        if length < 0 {
+          Err(io::Error::last_os_error())
+        } else {
+          Ok(length as usize)
+        }
+      });
+
+      let length = match result {
+        Ok(Ok(length)) => {
+          length
+        }
+        Ok(Err(error)) => {
+          return Err(error);
+        }
+        Err(_would_block) => {
+          continue;
+        }
+      };
+
+      if has_watched_file_changed(&buffer[..length]) {
+        return Ok(());
+      }
+    }
+  }
+}
+
+const EVENT_HEADER_LENGTH: usize = mem::size_of::<libc::inotify_event>();
+
+// Walks the struct inotify_event records in a read, each followed by
+// its nul padded file name.
+fn has_watched_file_changed(events: &[u8]) -> bool {
+  let mut offset = 0;
+
+  while let Some(header) = events.get(offset..offset + EVENT_HEADER_LENGTH) {
+    let mask = u32::from_ne_bytes(header[4..8].try_into().unwrap());
+    let name_length = u32::from_ne_bytes(header[12..16].try_into().unwrap()) as usize;
+
+    // Events were dropped, so any of them may have been a change.
+    if mask & libc::IN_Q_OVERFLOW != 0 {
+      return true;
+    }
+
+    let name_start = offset + EVENT_HEADER_LENGTH;
+    let Some(name) = events.get(name_start..name_start + name_length) else {
+      return false;
+    };
+
+    let name = name.split(|byte| *byte == 0).next().unwrap_or_default();
+    if AccountChanges::WATCHED_FILE_NAMES
+      .iter()
+      .any(|watched| watched.as_bytes() == name)
+    {
+      return true;
+    }
+
+    offset = name_start + name_length;
+  }
+
+  false
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // A struct inotify_event followed by its name, nul padded to a
+  // multiple of 16 bytes like the kernel does.
+  fn create_event(mask: u32, name: &str) -> Vec<u8> {
+    let name_length = if name.is_empty() { 0 } else { (name.len() + 1).next_multiple_of(16) };
+
+    let mut event = Vec::new();
+    event.extend(1i32.to_ne_bytes());
+    event.extend(mask.to_ne_bytes());
+    event.extend(0u32.to_ne_bytes());
+    event.extend((name_length as u32).to_ne_bytes());
+    event.extend(name.as_bytes());
+    event.resize(EVENT_HEADER_LENGTH + name_length, 0);
+    event
+  }
+
+  #[test]
+  fn changes_to_watched_files_are_noticed() {
+    let cases = [
+      (create_event(libc::IN_CLOSE_WRITE, "passwd"), true),
+      (create_event(libc::IN_MOVED_TO, "group"), true),
+      (create_event(libc::IN_MOVED_TO, "shadow"), false),
+      (create_event(libc::IN_CLOSE_WRITE, "passwd-"), false),
+      (create_event(libc::IN_CLOSE_WRITE, "passwd.lock"), false),
+      (create_event(libc::IN_Q_OVERFLOW, ""), true),
+      (Vec::new(), false),
+    ];
+
+    for (events, has_changed) in cases {
+      assert_eq!(has_watched_file_changed(&events), has_changed, "{events:?}");
+    }
+  }
+
+  #[test]
+  fn every_event_in_a_read_is_looked_at() {
+    let mut events = create_event(libc::IN_CLOSE_WRITE, "passwd.lock");
+    events.extend(create_event(libc::IN_MOVED_TO, "shadow"));
+    events.extend(create_event(libc::IN_MOVED_TO, "passwd"));
+
+    assert!(has_watched_file_changed(&events));
+  }
+
+  #[test]
+  fn truncated_events_are_ignored() {
+    let mut events = create_event(libc::IN_MOVED_TO, "shadow");
+    events.extend(create_event(libc::IN_MOVED_TO, "passwd"));
+    events.truncate(events.len() - 1);
+
+    assert!(!has_watched_file_changed(&events));
+  }
+}
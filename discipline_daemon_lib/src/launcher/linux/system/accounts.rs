@@ -0,0 +1,99 @@
+use super::{
+  AllocationConfig, GetPasswordFileEntryError, PasswordFileEntry, UserId, UserName,
+  get_password_file_entry_with_user_id, get_password_file_entry_with_user_name,
+};
+
+pub trait IsAccountBackend {
+  fn get_account_given_user_id(
+    &self,
+    user_id: UserId,
+  ) -> Result<PasswordFileEntry, GetPasswordFileEntryError>;
+
+  fn get_account_given_user_name(
+    &self,
+    user_name: &UserName,
+  ) -> Result<PasswordFileEntry, GetPasswordFileEntryError>;
+}
+
+// Looks accounts up with getpwuid_r and getpwnam_r, so they come from
+// wherever NSS is configured to look, not just /etc/passwd.
+#[derive(Default)]
+pub struct PasswdAccountBackend {
+  allocation_config: AllocationConfig,
+}
+
+impl IsAccountBackend for PasswdAccountBackend {
+  fn get_account_given_user_id(
+    &self,
+    user_id: UserId,
+  ) -> Result<PasswordFileEntry, GetPasswordFileEntryError> {
+    get_password_file_entry_with_user_id(user_id, &self.allocation_config)
+  }
+
+  fn get_account_given_user_name(
+    &self,
+    user_name: &UserName,
+  ) -> Result<PasswordFileEntry, GetPasswordFileEntryError> {
+    get_password_file_entry_with_user_name(user_name, &self.allocation_config)
+  }
+}
+
+// A fixed list of accounts in place of the account database.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockAccountBackend {
+  accounts: Vec<PasswordFileEntry>,
+  fails: bool,
+}
+
+#[cfg(test)]
+impl MockAccountBackend {
+  pub fn new(accounts: Vec<PasswordFileEntry>) -> Self {
+    Self {
+      accounts,
+      fails: false,
+    }
+  }
+
+  pub fn failing() -> Self {
+    Self {
+      accounts: Vec::new(),
+      fails: true,
+    }
+  }
+}
+
+#[cfg(test)]
+impl IsAccountBackend for MockAccountBackend {
+  fn get_account_given_user_id(
+    &self,
+    user_id: UserId,
+  ) -> Result<PasswordFileEntry, GetPasswordFileEntryError> {
+    if self.fails {
+      return Err(GetPasswordFileEntryError::SystemCallFailed);
+    }
+
+    self
+      .accounts
+      .iter()
+      .find(|account| account.user_id == user_id)
+      .cloned()
+      .ok_or(GetPasswordFileEntryError::NoSuchUser)
+  }
+
+  fn get_account_given_user_name(
+    &self,
+    user_name: &UserName,
+  ) -> Result<PasswordFileEntry, GetPasswordFileEntryError> {
+    if self.fails {
+      return Err(GetPasswordFileEntryError::SystemCallFailed);
+    }
+
+    self
+      .accounts
+      .iter()
+      .find(|account| account.user_name == *user_name)
+      .cloned()
+      .ok_or(GetPasswordFileEntryError::NoSuchUser)
+  }
+}
@@ -0,0 +1,342 @@
+use std::ffi::OsString;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use crate::x::IsTextualError;
+
+// Files we write into directories other programs read from, like
+// browser policy files, are "taken over": whatever was there first is
+// moved aside, to be put back by `restore_file`. The backup's existence
+// is what marks the file as ours, and an empty backup means there was
+// nothing there.
+const BACKUP_SUFFIX: &str = ".discipline-backup";
+const TEMPORARY_SUFFIX: &str = ".discipline-temporary";
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+  let mut path = OsString::from(path);
+  path.push(suffix);
+  PathBuf::from(path)
+}
+
+// Replaces `path`'s content so readers see either the old or the new
+// content, never a mix, even if we crash halfway.
+//
+// Users mustn't be able to swap the file for one of their own, so the
+// directory has to be root's and not writable by anyone else, and the
+// file must not be a symbolic link.
+pub fn write_file_atomically(
+  path: &Path,
+  contents: &[u8],
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  write_file_atomically_with_mode(path, contents, 0o644, textual_error)
+}
+
+// Like `write_file_atomically`, for files not everyone may read.
+pub fn write_file_atomically_with_mode(
+  path: &Path,
+  contents: &[u8],
+  mode: u32,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let Some(directory) = path.parent() else {
+    textual_error.change_context("Writing a file atomically");
+    textual_error.add_message("The path has no parent directory");
+    textual_error.add_attachement_display("Path", path.display());
+    return Err(());
+  };
+
+  create_trusted_directory(directory, textual_error)?;
+  take_over_file(path, textual_error)?;
+
+  let temporary_path = with_suffix(path, TEMPORARY_SUFFIX);
+  let _ = fs::remove_file(&temporary_path);
+
+  let result = OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .mode(mode)
+    .open(&temporary_path)
+    .and_then(|mut file| {
+      file.write_all(contents)?;
+      file.sync_all()
+    })
+    .and_then(|()| fs::rename(&temporary_path, path))
+    .and_then(|()| File::open(directory)?.sync_all());
+
+  if let Err(error) = result {
+    let _ = fs::remove_file(&temporary_path);
+    textual_error.change_context("Writing a file atomically");
+    textual_error.add_message("An io error occured");
+    textual_error.add_attachement_display("Io error", error);
+    textual_error.add_attachement_display("Path", path.display());
+    return Err(());
+  }
+
+  Ok(())
+}
+
+// Removes a file we took over, putting back what was there before.
+// Files we never took over are left alone.
+pub fn restore_file(
+  path: &Path,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let backup_path = with_suffix(path, BACKUP_SUFFIX);
+
+  let backup_length = match fs::symlink_metadata(&backup_path) {
+    Ok(metadata) => {
+      metadata.len()
+    }
+    Err(error) if error.kind() == ErrorKind::NotFound => {
+      return Ok(());
+    }
+    Err(error) => {
+      textual_error.change_context("Restoring a file we took over");
+      textual_error.add_message("Failed to read the backup's metadata");
+      textual_error.add_attachement_display("Io error", error);
+      textual_error.add_attachement_display("Path", backup_path.display());
+      return Err(());
+    }
+  };
+
+  let result = if backup_length == 0 {
+    remove_file_if_exists(path).and_then(|()| fs::remove_file(&backup_path))
+  } else {
+    fs::rename(&backup_path, path)
+  };
+
+  if let Err(error) = result {
+    textual_error.change_context("Restoring a file we took over");
+    textual_error.add_message("An io error occured");
+    textual_error.add_attachement_display("Io error", error);
+    textual_error.add_attachement_display("Path", path.display());
+    return Err(());
+  }
+
+  Ok(())
+}
+
+fn take_over_file(
+  path: &Path,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let backup_path = with_suffix(path, BACKUP_SUFFIX);
+  if fs::symlink_metadata(&backup_path).is_ok() {
+    return Ok(());
+  }
+
+  let result = match fs::symlink_metadata(path) {
+    Ok(metadata) if metadata.file_type().is_symlink() => {
+      textual_error.change_context("Taking over a file");
+      textual_error.add_message("Refusing to write through a symbolic link");
+      textual_error.add_attachement_display("Path", path.display());
+      return Err(());
+    }
+    Ok(_) => {
+      fs::rename(path, &backup_path)
+    }
+    Err(error) if error.kind() == ErrorKind::NotFound => {
+      OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&backup_path)
+        .map(|_| ())
+    }
+    Err(error) => {
+      Err(error)
+    }
+  };
+
+  if let Err(error) = result {
+    textual_error.change_context("Taking over a file");
+    textual_error.add_message("Failed to back up the file");
+    textual_error.add_attachement_display("Io error", error);
+    textual_error.add_attachement_display("Path", path.display());
+    return Err(());
+  }
+
+  Ok(())
+}
+
+fn create_trusted_directory(
+  path: &Path,
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  if let Err(error) = DirBuilder::new().recursive(true).mode(0o755).create(path) {
+    textual_error.change_context("Creating a directory only root can write to");
+    textual_error.add_message("An io error occured");
+    textual_error.add_attachement_display("Io error", error);
+    textual_error.add_attachement_display("Path", path.display());
+    return Err(());
+  }
+
+  let metadata = match fs::metadata(path) {
+    Ok(metadata) => {
+      metadata
+    }
+    Err(error) => {
+      textual_error.change_context("Creating a directory only root can write to");
+      textual_error.add_message("Failed to read the directory's metadata");
+      textual_error.add_attachement_display("Io error", error);
+      textual_error.add_attachement_display("Path", path.display());
+      return Err(());
+    }
+  };
+
+  if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+    textual_error.change_context("Creating a directory only root can write to");
+    textual_error.add_message("The directory exists but isn't owned by root, or others can write to it");
+    textual_error.add_attachement_display("Path", path.display());
+    textual_error.add_attachement_display("Owner", metadata.uid());
+    textual_error.add_attachement_display("Mode", format!("{:o}", metadata.mode() & 0o7777));
+    return Err(());
+  }
+
+  Ok(())
+}
+
+fn remove_file_if_exists(path: &Path) -> std::io::Result<()> {
+  match fs::remove_file(path) {
+    Ok(()) => {
+      Ok(())
+    }
+    Err(error) if error.kind() == ErrorKind::NotFound => {
+      Ok(())
+    }
+    Err(error) => {
+      Err(error)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::os::unix::fs::{PermissionsExt, symlink};
+  use crate::x::{TextualError, UuidV4};
+  use super::*;
+
+  // Inside a directory of its own, since the temporary directory itself
+  // is writable by everyone.
+  fn create_path() -> PathBuf {
+    std::env::temp_dir()
+      .join(format!("discipline-files-{}", UuidV4::generate().to_string()))
+      .join("policies")
+      .join("policies.json")
+  }
+
+  fn remove_path(path: &Path) {
+    let _ = fs::remove_dir_all(path.parent().unwrap().parent().unwrap());
+  }
+
+  fn list_directory(path: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(path.parent().unwrap())
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+      .collect();
+    names.sort();
+    names
+  }
+
+  #[test]
+  fn new_files_are_written_and_removed_on_restore() {
+    let path = create_path();
+    let mut textual_error = TextualError::new("Testing atomic writes");
+
+    write_file_atomically(&path, b"first", &mut textual_error).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"first");
+    assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o644);
+    assert_eq!(fs::metadata(path.parent().unwrap()).unwrap().mode() & 0o022, 0);
+    assert_eq!(list_directory(&path), ["policies.json", "policies.json.discipline-backup"]);
+    assert_eq!(fs::metadata(with_suffix(&path, BACKUP_SUFFIX)).unwrap().len(), 0);
+
+    write_file_atomically(&path, b"second", &mut textual_error).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"second");
+
+    restore_file(&path, &mut textual_error).unwrap();
+    assert_eq!(list_directory(&path), Vec::<String>::new());
+
+    remove_path(&path);
+  }
+
+  #[test]
+  fn existing_files_are_backed_up_once_and_put_back() {
+    let path = create_path();
+    let mut textual_error = TextualError::new("Testing atomic writes");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::set_permissions(path.parent().unwrap(), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(&path, b"the administrator's").unwrap();
+
+    write_file_atomically(&path, b"first", &mut textual_error).unwrap();
+    write_file_atomically(&path, b"second", &mut textual_error).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"second");
+    assert_eq!(fs::read(with_suffix(&path, BACKUP_SUFFIX)).unwrap(), b"the administrator's");
+
+    restore_file(&path, &mut textual_error).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"the administrator's");
+    assert_eq!(list_directory(&path), ["policies.json"]);
+
+    // Files we don't own are left alone.
+    restore_file(&path, &mut textual_error).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"the administrator's");
+
+    remove_path(&path);
+  }
+
+  #[test]
+  fn modes_are_applied() {
+    let path = create_path();
+    let mut textual_error = TextualError::new("Testing atomic writes");
+
+    write_file_atomically_with_mode(&path, b"secret", 0o600, &mut textual_error).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+
+    remove_path(&path);
+  }
+
+  #[test]
+  fn leftover_temporary_files_are_replaced() {
+    let path = create_path();
+    let mut textual_error = TextualError::new("Testing atomic writes");
+    write_file_atomically(&path, b"first", &mut textual_error).unwrap();
+    fs::write(with_suffix(&path, TEMPORARY_SUFFIX), b"from a crash").unwrap();
+
+    write_file_atomically(&path, b"second", &mut textual_error).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"second");
+    assert_eq!(list_directory(&path), ["policies.json", "policies.json.discipline-backup"]);
+
+    remove_path(&path);
+  }
+
+  #[test]
+  fn symbolic_links_are_refused() {
+    let path = create_path();
+    let mut textual_error = TextualError::new("Testing atomic writes");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::set_permissions(path.parent().unwrap(), fs::Permissions::from_mode(0o755)).unwrap();
+    let target = path.parent().unwrap().join("target");
+    fs::write(&target, b"untouched").unwrap();
+    symlink(&target, &path).unwrap();
+
+    assert!(write_file_atomically(&path, b"written", &mut textual_error).is_err());
+    assert_eq!(fs::read(&target).unwrap(), b"untouched");
+    assert!(fs::symlink_metadata(&path).unwrap().file_type().is_symlink());
+
+    remove_path(&path);
+  }
+
+  #[test]
+  fn directories_others_can_write_to_are_refused() {
+    let path = create_path();
+    let mut textual_error = TextualError::new("Testing atomic writes");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::set_permissions(path.parent().unwrap(), fs::Permissions::from_mode(0o777)).unwrap();
+
+    assert!(write_file_atomically(&path, b"written", &mut textual_error).is_err());
+    assert!(fs::symlink_metadata(&path).is_err());
+
+    remove_path(&path);
+  }
+}
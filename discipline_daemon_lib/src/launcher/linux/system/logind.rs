@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+#[cfg(test)]
+use std::sync::Mutex;
+use crate::x::IsTextualError;
+use super::{UserId, UserName};
+
+// A session as systemd-logind knows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogindSession {
+  pub id: String,
+  pub user_name: UserName,
+  pub service: Option<String>,
+  pub tty: Option<String>,
+  pub remote_host: Option<String>,
+  // The session's leader process.
+  pub leader: Option<u32>,
+}
+
+pub trait IsSessionBackend {
+  fn lock_sessions(
+    &self,
+    user_id: UserId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()>;
+
+  fn terminate_sessions(
+    &self,
+    user_id: UserId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()>;
+
+  fn kill_processes(
+    &self,
+    user_id: UserId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()>;
+
+  fn list_sessions(
+    &self,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Vec<LogindSession>, ()>;
+
+  // Like the above, for one of a user's sessions, when others of theirs
+  // must be left alone.
+  fn lock_session(
+    &self,
+    session_id: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()>;
+
+  fn terminate_session(
+    &self,
+    session_id: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()>;
+
+  fn kill_session_processes(
+    &self,
+    session_id: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()>;
+}
+
+// Acts on a user's sessions through systemd-logind, using loginctl
+// rather than talking D-Bus ourselves.
+pub struct LoginctlBackend {
+  program: PathBuf,
+}
+
+impl Default for LoginctlBackend {
+  fn default() -> Self {
+    Self {
+      program: PathBuf::from("/usr/bin/loginctl"),
+    }
+  }
+}
+
+impl LoginctlBackend {
+  pub fn new(program: PathBuf) -> Self {
+    Self {
+      program,
+    }
+  }
+
+  fn run(
+    &self,
+    arguments: &[&str],
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Output, ()> {
+    textual_error.add_attachement_display("Program", self.program.display());
+    textual_error.add_attachement_display("Arguments", arguments.join(" "));
+
+    let output = match Command::new(&self.program).args(arguments).output() {
+      Ok(output) => {
+        output
+      }
+      Err(error) => {
+        textual_error.add_message("Failed to run loginctl");
+        textual_error.add_attachement_display("Error", error);
+        return Err(());
+      }
+    };
+
+    if !output.status.success() {
+      textual_error.add_message("loginctl exited unsuccessfully");
+      textual_error.add_attachement_display("Exit status", output.status);
+      textual_error.add_attachement_display("Standard error", String::from_utf8_lossy(&output.stderr));
+      return Err(());
+    }
+
+    Ok(output)
+  }
+}
+
+impl IsSessionBackend for LoginctlBackend {
+  fn lock_sessions(
+    &self,
+    user_id: UserId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    textual_error.change_context("Locking a user's sessions using loginctl");
+
+    let user_id = user_id.inner().to_string();
+    let output = self.run(
+      &["show-user", &user_id, "--property=Sessions", "--value"],
+      textual_error,
+    )?;
+
+    // "lock-sessions" would lock everyone's sessions, so we lock the
+    // user's sessions one by one instead.
+    let sessions = String::from_utf8_lossy(&output.stdout).into_owned();
+    let mut arguments = vec!["lock-session"];
+    arguments.extend(sessions.split_whitespace());
+
+    if arguments.len() == 1 {
+      return Ok(());
+    }
+
+    self.run(&arguments, textual_error)?;
+    Ok(())
+  }
+
+  fn terminate_sessions(
+    &self,
+    user_id: UserId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    textual_error.change_context("Terminating a user's sessions using loginctl");
+
+    let user_id = user_id.inner().to_string();
+    self.run(&["terminate-user", &user_id], textual_error)?;
+    Ok(())
+  }
+
+  fn kill_processes(
+    &self,
+    user_id: UserId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    textual_error.change_context("Killing a user's processes using loginctl");
+
+    let user_id = user_id.inner().to_string();
+    self.run(&["kill-user", "--signal=SIGKILL", &user_id], textual_error)?;
+    Ok(())
+  }
+
+  fn list_sessions(
+    &self,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Vec<LogindSession>, ()> {
+    textual_error.change_context("Listing sessions using loginctl");
+
+    let output = self.run(&["list-sessions", "--no-legend"], textual_error)?;
+    let output = String::from_utf8_lossy(&output.stdout).into_owned();
+
+    let mut sessions = Vec::new();
+
+    // The session id comes first. The other columns vary between
+    // systemd versions, so the rest is asked for by name.
+    for session_id in output.lines().filter_map(|line| line.split_whitespace().next()) {
+      let output = self.run(
+        &[
+          "show-session", 
+          session_id, 
+          "--property=Name", 
+          "--property=Service", 
+          "--property=TTY", 
+          "--property=RemoteHost", 
+          "--property=Leader",
+        ],
+        textual_error,
+      )?;
+
+      let mut properties: HashMap<String, String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+
+      let Some(user_name) = properties
+        .remove("Name")
+        .and_then(|user_name| CString::new(user_name).ok())
+      else {
+        continue;
+      };
+
+      sessions.push(LogindSession {
+        id: session_id.to_owned(),
+        user_name: UserName::new(user_name),
+        service: properties.remove("Service"),
+        tty: properties.remove("TTY"),
+        remote_host: properties.remove("RemoteHost"),
+        leader: properties.remove("Leader").and_then(|leader| leader.parse().ok()),
+      });
+    }
+
+    Ok(sessions)
+  }
+
+  fn lock_session(
+    &self,
+    session_id: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    textual_error.change_context("Locking a session using loginctl");
+
+    self.run(&["lock-session", session_id], textual_error)?;
+    Ok(())
+  }
+
+  fn terminate_session(
+    &self,
+    session_id: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    textual_error.change_context("Terminating a session using loginctl");
+
+    self.run(&["terminate-session", session_id], textual_error)?;
+    Ok(())
+  }
+
+  fn kill_session_processes(
+    &self,
+    session_id: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    textual_error.change_context("Killing a session's processes using loginctl");
+
+    self.run(&["kill-session", "--signal=SIGKILL", session_id], textual_error)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockSessionAction {
+  LockSessions,
+  TerminateSessions,
+  KillProcesses,
+}
+
+// Records actions instead of applying them.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockSessionBackend {
+  actions: Mutex<Vec<(UserId, MockSessionAction)>>,
+  session_actions: Mutex<Vec<(String, MockSessionAction)>>,
+}
+
+#[cfg(test)]
+impl MockSessionBackend {
+  pub fn get_actions(&self) -> Vec<(UserId, MockSessionAction)> {
+    self
+      .actions
+      .lock()
+      .map(|actions| actions.clone())
+      .unwrap_or_default()
+  }
+
+  pub fn get_session_actions(&self) -> Vec<(String, MockSessionAction)> {
+    self
+      .session_actions
+      .lock()
+      .map(|actions| actions.clone())
+      .unwrap_or_default()
+  }
+
+  fn record_session_action(
+    &self,
+    session_id: &str,
+    action: MockSessionAction,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let Ok(mut actions) = self.session_actions.lock() else {
+      textual_error.change_context("Acting on a session using the mock backend");
+      textual_error.add_message("The mock backend's lock is poisoned");
+      return Err(());
+    };
+
+    actions.push((session_id.to_owned(), action));
+    Ok(())
+  }
+
+  fn record(
+    &self,
+    user_id: UserId,
+    action: MockSessionAction,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let Ok(mut actions) = self.actions.lock() else {
+      textual_error.change_context("Acting on a user's sessions using the mock backend");
+      textual_error.add_message("The mock backend's lock is poisoned");
+      return Err(());
+    };
+
+    actions.push((user_id, action));
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+impl IsSessionBackend for MockSessionBackend {
+  fn lock_sessions(
+    &self,
+    user_id: UserId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    self.record(user_id, MockSessionAction::LockSessions, textual_error)
+  }
+
+  fn terminate_sessions(
+    &self,
+    user_id: UserId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    self.record(user_id, MockSessionAction::TerminateSessions, textual_error)
+  }
+
+  fn kill_processes(
+    &self,
+    user_id: UserId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    self.record(user_id, MockSessionAction::KillProcesses, textual_error)
+  }
+
+  fn list_sessions(
+    &self,
+    _textual_error: &mut impl IsTextualError,
+  ) -> Result<Vec<LogindSession>, ()> {
+    Ok(Vec::new())
+  }
+
+  fn lock_session(
+    &self,
+    session_id: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    self.record_session_action(session_id, MockSessionAction::LockSessions, textual_error)
+  }
+
+  fn terminate_session(
+    &self,
+    session_id: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    self.record_session_action(session_id, MockSessionAction::TerminateSessions, textual_error)
+  }
+
+  fn kill_session_processes(
+    &self,
+    session_id: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    self.record_session_action(session_id, MockSessionAction::KillProcesses, textual_error)
+  }
+}
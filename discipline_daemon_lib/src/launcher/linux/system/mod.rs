@@ -4,4 +4,34 @@ pub use time::*;
 pub mod users;
 pub use users::*;
 
+pub mod passwords;
+pub use passwords::*;
+
+pub mod sd_notify;
+pub use sd_notify::*;
+
+pub mod logind;
+pub use logind::*;
+
+pub mod notifications;
+pub use notifications::*;
+
+pub mod nftables;
+pub use nftables::*;
+
+pub mod sockets;
+pub use sockets::*;
+
+pub mod files;
+pub use files::*;
+
+pub mod processes;
+pub use processes::*;
+
+pub mod accounts;
+pub use accounts::*;
+
+pub mod account_changes;
+pub use account_changes::*;
+
 pub mod pam;
\ No newline at end of file
@@ -0,0 +1,395 @@
+use std::fmt::Write as _;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+#[cfg(test)]
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use crate::x::IsTextualError;
+use super::UserId;
+
+// An address or a network in CIDR notation, like "192.168.1.0/24" or
+// "2001:db8::1".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NetworkAddress {
+  address: IpAddr,
+  prefix_length: Option<u8>,
+}
+
+impl NetworkAddress {
+  pub fn parse(string: &str) -> Result<Self, String> {
+    let (address, prefix_length) = match string.split_once('/') {
+      Some((address, prefix_length)) => {
+        (address, Some(prefix_length))
+      }
+      None => {
+        (string, None)
+      }
+    };
+
+    let address: IpAddr = address
+      .parse()
+      .map_err(|_| format!("\"{string}\" is not a valid IP address"))?;
+
+    let prefix_length = match prefix_length {
+      Some(prefix_length) => {
+        let maximum = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_length: u8 = prefix_length
+          .parse()
+          .ok()
+          .filter(|prefix_length| *prefix_length <= maximum)
+          .ok_or_else(|| format!("\"{string}\" has an invalid prefix length"))?;
+
+        Some(prefix_length)
+      }
+      None => {
+        None
+      }
+    };
+
+    Ok(Self {
+      address,
+      prefix_length,
+    })
+  }
+
+  pub fn is_ipv4(&self) -> bool {
+    self.address.is_ipv4()
+  }
+}
+
+impl std::fmt::Display for NetworkAddress {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.prefix_length {
+      Some(prefix_length) => {
+        write!(f, "{}/{prefix_length}", self.address)
+      }
+      None => {
+        write!(f, "{}", self.address)
+      }
+    }
+  }
+}
+
+impl TryFrom<String> for NetworkAddress {
+  type Error = String;
+
+  fn try_from(string: String) -> Result<Self, Self::Error> {
+    Self::parse(&string)
+  }
+}
+
+impl From<NetworkAddress> for String {
+  fn from(address: NetworkAddress) -> Self {
+    address.to_string()
+  }
+}
+
+// Generates the nftables ruleset that drops outbound traffic of
+// blocked users.
+//
+// The ruleset replaces our table atomically: declaring the table first
+// makes deleting it succeed even when it doesn't exist yet, and nft
+// applies the whole file as a single transaction. Applying the same
+// ruleset twice is therefore harmless.
+pub fn generate_ruleset(
+  table_name: &str,
+  allowlist: &[NetworkAddress],
+  blocked_user_ids: &[UserId],
+) -> String {
+  let mut blocked_user_ids: Vec<_> = blocked_user_ids
+    .iter()
+    .map(UserId::inner)
+    .collect();
+
+  blocked_user_ids.sort_unstable();
+  blocked_user_ids.dedup();
+
+  let mut ruleset = String::new();
+  let _ = writeln!(ruleset, "table inet {table_name}");
+  let _ = writeln!(ruleset, "delete table inet {table_name}");
+  let _ = writeln!(ruleset, "table inet {table_name} {{");
+  let _ = writeln!(ruleset, "  chain output {{");
+  let _ = writeln!(ruleset, "    type filter hook output priority filter; policy accept;");
+
+  if !blocked_user_ids.is_empty() {
+    let _ = writeln!(ruleset, "    oif \"lo\" accept");
+
+    for address in allowlist {
+      let family = if address.is_ipv4() { "ip" } else { "ip6" };
+      let _ = writeln!(ruleset, "    {family} daddr {address} accept");
+    }
+
+    for user_id in blocked_user_ids {
+      let _ = writeln!(ruleset, "    meta skuid {user_id} drop");
+    }
+  }
+
+  let _ = writeln!(ruleset, "  }}");
+  let _ = writeln!(ruleset, "}}");
+  ruleset
+}
+
+// Generates the nftables ruleset that redirects the DNS queries of the
+// given users to our resolver on `port`, replacing our table like
+// `generate_ruleset` does. TCP is redirected along with UDP, since
+// clients fall back to it for large responses and could otherwise
+// reach any server they like.
+pub fn generate_dns_redirect_ruleset(
+  table_name: &str,
+  port: u16,
+  user_ids: &[UserId],
+) -> String {
+  let mut user_ids: Vec<_> = user_ids
+    .iter()
+    .map(UserId::inner)
+    .collect();
+
+  user_ids.sort_unstable();
+  user_ids.dedup();
+
+  let mut ruleset = String::new();
+  let _ = writeln!(ruleset, "table inet {table_name}");
+  let _ = writeln!(ruleset, "delete table inet {table_name}");
+  let _ = writeln!(ruleset, "table inet {table_name} {{");
+  let _ = writeln!(ruleset, "  chain output {{");
+  let _ = writeln!(ruleset, "    type nat hook output priority -100; policy accept;");
+
+  for user_id in user_ids {
+    let _ = writeln!(ruleset, "    meta skuid {user_id} udp dport 53 redirect to :{port}");
+    let _ = writeln!(ruleset, "    meta skuid {user_id} tcp dport 53 redirect to :{port}");
+  }
+
+  let _ = writeln!(ruleset, "  }}");
+  let _ = writeln!(ruleset, "}}");
+  ruleset
+}
+
+pub trait IsFirewallBackend {
+  fn apply_ruleset(
+    &self,
+    ruleset: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()>;
+}
+
+// Applies rulesets by piping them to "nft -f -".
+pub struct NftBackend {
+  program: PathBuf,
+}
+
+impl Default for NftBackend {
+  fn default() -> Self {
+    Self {
+      program: PathBuf::from("/usr/sbin/nft"),
+    }
+  }
+}
+
+impl NftBackend {
+  pub fn new(program: PathBuf) -> Self {
+    Self {
+      program,
+    }
+  }
+}
+
+impl IsFirewallBackend for NftBackend {
+  fn apply_ruleset(
+    &self,
+    ruleset: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    textual_error.change_context("Applying an nftables ruleset using nft");
+    textual_error.add_attachement_display("Program", self.program.display());
+
+    let mut child = match Command::new(&self.program)
+      .args(["-f", "-"])
+      .stdin(Stdio::piped())
+      .stdout(Stdio::null())
+      .stderr(Stdio::piped())
+      .spawn()
+    {
+      Ok(child) => {
+        child
+      }
+      Err(error) => {
+        textual_error.add_message("Failed to spawn nft");
+        textual_error.add_attachement_display("Error", error);
+        return Err(());
+      }
+    };
+
+    let write_result = match child.stdin.take() {
+      Some(mut stdin) => {
+        stdin.write_all(ruleset.as_bytes())
+      }
+      None => {
+        textual_error.add_message("nft's standard input isn't available");
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(());
+      }
+    };
+
+    if let Err(error) = write_result {
+      textual_error.add_message("Failed to write to nft's standard input");
+      textual_error.add_attachement_display("Error", error);
+      let _ = child.kill();
+      let _ = child.wait();
+      return Err(());
+    }
+
+    let output = match child.wait_with_output() {
+      Ok(output) => {
+        output
+      }
+      Err(error) => {
+        textual_error.add_message("Failed to wait for nft to exit");
+        textual_error.add_attachement_display("Error", error);
+        return Err(());
+      }
+    };
+
+    if !output.status.success() {
+      textual_error.add_message("nft exited unsuccessfully");
+      textual_error.add_attachement_display("Exit status", output.status);
+      textual_error.add_attachement_display("Standard error", String::from_utf8_lossy(&output.stderr));
+      textual_error.add_attachement_display("Ruleset", ruleset);
+      return Err(());
+    }
+
+    Ok(())
+  }
+}
+
+// Records rulesets instead of applying them.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockFirewallBackend {
+  rulesets: Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl MockFirewallBackend {
+  pub fn get_rulesets(&self) -> Vec<String> {
+    self
+      .rulesets
+      .lock()
+      .map(|rulesets| rulesets.clone())
+      .unwrap_or_default()
+  }
+}
+
+#[cfg(test)]
+impl IsFirewallBackend for MockFirewallBackend {
+  fn apply_ruleset(
+    &self,
+    ruleset: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let Ok(mut rulesets) = self.rulesets.lock() else {
+      textual_error.change_context("Applying an nftables ruleset using the mock backend");
+      textual_error.add_message("The mock backend's lock is poisoned");
+      return Err(());
+    };
+
+    rulesets.push(ruleset.to_string());
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ruleset_without_blocked_users_accepts_everything() {
+    let ruleset = generate_ruleset("discipline", &[], &[]);
+
+    assert_eq!(
+      ruleset,
+      "table inet discipline\n\
+       delete table inet discipline\n\
+       table inet discipline {\n  \
+         chain output {\n    \
+           type filter hook output priority filter; policy accept;\n  \
+         }\n\
+       }\n",
+    );
+  }
+
+  #[test]
+  fn ruleset_drops_blocked_users_after_the_allowlist() {
+    let allowlist = [
+      NetworkAddress::parse("192.168.1.0/24").unwrap(),
+      NetworkAddress::parse("2001:db8::1").unwrap(),
+    ];
+    let blocked_user_ids = [
+      UserId::new(1001),
+      UserId::new(1000),
+      UserId::new(1001),
+    ];
+
+    let ruleset = generate_ruleset("discipline", &allowlist, &blocked_user_ids);
+
+    assert_eq!(
+      ruleset,
+      "table inet discipline\n\
+       delete table inet discipline\n\
+       table inet discipline {\n  \
+         chain output {\n    \
+           type filter hook output priority filter; policy accept;\n    \
+           oif \"lo\" accept\n    \
+           ip daddr 192.168.1.0/24 accept\n    \
+           ip6 daddr 2001:db8::1 accept\n    \
+           meta skuid 1000 drop\n    \
+           meta skuid 1001 drop\n  \
+         }\n\
+       }\n",
+    );
+  }
+
+  #[test]
+  fn ruleset_is_the_same_regardless_of_user_order() {
+    let first = generate_ruleset("discipline", &[], &[UserId::new(1), UserId::new(2)]);
+    let second = generate_ruleset("discipline", &[], &[UserId::new(2), UserId::new(1)]);
+
+    assert_eq!(first, second);
+  }
+
+  #[test]
+  fn dns_redirect_ruleset_redirects_each_user() {
+    let ruleset = generate_dns_redirect_ruleset(
+      "discipline_dns",
+      5300,
+      &[UserId::new(1001), UserId::new(1000)],
+    );
+
+    assert_eq!(
+      ruleset,
+      "table inet discipline_dns\n\
+       delete table inet discipline_dns\n\
+       table inet discipline_dns {\n  \
+         chain output {\n    \
+           type nat hook output priority -100; policy accept;\n    \
+           meta skuid 1000 udp dport 53 redirect to :5300\n    \
+           meta skuid 1000 tcp dport 53 redirect to :5300\n    \
+           meta skuid 1001 udp dport 53 redirect to :5300\n    \
+           meta skuid 1001 tcp dport 53 redirect to :5300\n  \
+         }\n\
+       }\n",
+    );
+  }
+
+  #[test]
+  fn network_addresses_are_validated() {
+    assert!(NetworkAddress::parse("10.0.0.0/8").is_ok());
+    assert!(NetworkAddress::parse("::1/128").is_ok());
+    assert!(NetworkAddress::parse("10.0.0.0/33").is_err());
+    assert!(NetworkAddress::parse("example.com").is_err());
+    assert!(NetworkAddress::parse("10.0.0.1; flush ruleset").is_err());
+  }
+}
@@ -0,0 +1,265 @@
+use std::ffi::CStr;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+#[cfg(test)]
+use std::sync::Mutex;
+use crate::x::IsTextualError;
+use super::{UserId, UserName};
+
+pub trait IsNotificationBackend {
+  fn notify(
+    &self,
+    user_id: UserId,
+    user_name: &UserName,
+    summary: &str,
+    body: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()>;
+}
+
+// Shows notifications in the user's graphical session through the
+// freedesktop Notifications D-Bus interface, reached with busctl on the
+// user's session bus. When that fails, because the user has no graphical
+// session for instance, the notice is written to the user's terminals.
+pub struct DesktopNotificationBackend {
+  program: PathBuf,
+  application_name: String,
+}
+
+impl Default for DesktopNotificationBackend {
+  fn default() -> Self {
+    Self {
+      program: PathBuf::from("/usr/bin/busctl"),
+      application_name: "Discipline".into(),
+    }
+  }
+}
+
+impl DesktopNotificationBackend {
+  // How long the notification stays on screen, in milliseconds.
+  const EXPIRE_TIMEOUT: &'static str = "15000";
+
+  pub fn new(program: PathBuf, application_name: String) -> Self {
+    Self {
+      program,
+      application_name,
+    }
+  }
+
+  fn notify_desktop(
+    &self,
+    user_name: &str,
+    summary: &str,
+    body: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    textual_error.change_context("Sending a desktop notification using busctl");
+    textual_error.add_attachement_display("Program", self.program.display());
+    textual_error.add_attachement_display("User name", user_name);
+
+    let output = Command::new(&self.program)
+      .arg(format!("--machine={user_name}@.host"))
+      .arg("--user")
+      .arg("call")
+      .arg("org.freedesktop.Notifications")
+      .arg("/org/freedesktop/Notifications")
+      .arg("org.freedesktop.Notifications")
+      .arg("Notify")
+      .arg("susssasa{sv}i")
+      .arg(&self.application_name)
+      // replaces_id, app_icon
+      .arg("0")
+      .arg("")
+      .arg(summary)
+      .arg(body)
+      // No actions and no hints.
+      .arg("0")
+      .arg("0")
+      .arg(Self::EXPIRE_TIMEOUT)
+      .output();
+
+    let output = match output {
+      Ok(output) => {
+        output
+      }
+      Err(error) => {
+        textual_error.add_message("Failed to run busctl");
+        textual_error.add_attachement_display("Error", error);
+        return Err(());
+      }
+    };
+
+    if !output.status.success() {
+      textual_error.add_message("busctl exited unsuccessfully");
+      textual_error.add_attachement_display("Exit status", output.status);
+      textual_error.add_attachement_display("Standard error", String::from_utf8_lossy(&output.stderr));
+      return Err(());
+    }
+
+    Ok(())
+  }
+
+  fn notify_terminals(
+    &self,
+    user_name: &UserName,
+    summary: &str,
+    body: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    textual_error.change_context("Writing a notice to a user's terminals");
+
+    let terminals = get_user_terminals(user_name);
+    if terminals.is_empty() {
+      textual_error.add_message("The user isn't logged in on any terminal");
+      return Err(());
+    }
+
+    let message = format!("\r\n\x07{}: {summary}\r\n{body}\r\n", self.application_name);
+    let mut written = false;
+
+    for terminal in terminals {
+      let result = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+        .open(&terminal)
+        .and_then(|mut file| file.write_all(message.as_bytes()));
+
+      match result {
+        Ok(()) => {
+          written = true;
+        }
+        Err(error) => {
+          textual_error.add_message("Failed to write to a terminal");
+          textual_error.add_attachement_display("Terminal", terminal.display());
+          textual_error.add_attachement_display("Io error", error);
+        }
+      }
+    }
+
+    if written {
+      Ok(())
+    } else {
+      Err(())
+    }
+  }
+}
+
+impl IsNotificationBackend for DesktopNotificationBackend {
+  fn notify(
+    &self,
+    _user_id: UserId,
+    user_name: &UserName,
+    summary: &str,
+    body: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    if let Ok(user_name) = user_name.inner().to_str()
+      && let Ok(()) = self.notify_desktop(user_name, summary, body, textual_error)
+    {
+      return Ok(());
+    }
+
+    self.notify_terminals(user_name, summary, body, textual_error)
+  }
+}
+
+// Lists the terminals the user is logged in on, according to utmp.
+fn get_user_terminals(user_name: &UserName) -> Vec<PathBuf> {
+  let mut terminals = Vec::new();
+
+  unsafe {
+    libc::setutxent();
+
+    loop {
+      let entry = libc::getutxent();
+      if entry.is_null() {
+        break;
+      }
+
+      let entry = &*entry;
+      if entry.ut_type != libc::USER_PROCESS {
+        continue;
+      }
+
+      // Neither field is necessarily nul-terminated.
+      let entry_user = read_fixed_c_string(&entry.ut_user);
+      if entry_user != user_name.inner().to_bytes() {
+        continue;
+      }
+
+      let line = read_fixed_c_string(&entry.ut_line);
+      let Ok(line) = std::str::from_utf8(line) else {
+        continue;
+      };
+      if line.is_empty() || line.split('/').any(|component| component == "..") {
+        continue;
+      }
+
+      let terminal = Path::new("/dev").join(line);
+      if !terminals.contains(&terminal) {
+        terminals.push(terminal);
+      }
+    }
+
+    libc::endutxent();
+  }
+
+  terminals
+}
+
+fn read_fixed_c_string(field: &[libc::c_char]) -> &[u8] {
+  let bytes = unsafe {
+    std::slice::from_raw_parts(field.as_ptr().cast::<u8>(), field.len())
+  };
+
+  match CStr::from_bytes_until_nul(bytes) {
+    Ok(string) => {
+      string.to_bytes()
+    }
+    Err(_) => {
+      bytes
+    }
+  }
+}
+
+// Records notifications instead of showing them.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockNotificationBackend {
+  notifications: Mutex<Vec<(UserName, String, String)>>,
+}
+
+#[cfg(test)]
+impl MockNotificationBackend {
+  pub fn get_notifications(&self) -> Vec<(UserName, String, String)> {
+    self
+      .notifications
+      .lock()
+      .map(|notifications| notifications.clone())
+      .unwrap_or_default()
+  }
+}
+
+#[cfg(test)]
+impl IsNotificationBackend for MockNotificationBackend {
+  fn notify(
+    &self,
+    _user_id: UserId,
+    user_name: &UserName,
+    summary: &str,
+    body: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let Ok(mut notifications) = self.notifications.lock() else {
+      textual_error.change_context("Sending a notification using the mock backend");
+      textual_error.add_message("The mock backend's lock is poisoned");
+      return Err(());
+    };
+
+    notifications.push((user_name.clone(), summary.to_string(), body.to_string()));
+    Ok(())
+  }
+}
@@ -0,0 +1,125 @@
+use std::fs::{OpenOptions, Permissions};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt, fchown};
+use std::path::Path;
+use std::time::SystemTime;
+use crate::x::{Duration, IsTextualError};
+
+// A file whose modification time the daemon refreshes every tick, so
+// the PAM module can tell how long the daemon has been unreachable
+// without having been able to reach it.
+//
+// Only root may write it: a regulated user who could touch it would
+// keep a `ClosedAfterGrace` failure policy open with the daemon gone.
+pub struct Heartbeat;
+
+impl Heartbeat {
+  const MODE: u32 = 0o644;
+
+  pub fn beat(path: &Path, textual_error: &mut impl IsTextualError) -> Result<(), ()> {
+    let file = match OpenOptions::new().create(true).truncate(false).write(true).mode(Self::MODE).open(path) {
+      Ok(file) => {
+        file
+      }
+      Err(error) => {
+        textual_error.change_context("Writing the heartbeat for the Linux-PAM module");
+        textual_error.add_message("Failed to open the heartbeat file");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Path", path.display());
+        return Err(());
+      }
+    };
+
+    // The mode only applies to new files, so one left behind by someone
+    // else is taken back.
+    let is_private = file
+      .metadata()
+      .is_ok_and(|metadata| metadata.uid() == 0 && metadata.mode() & 0o022 == 0);
+
+    if !is_private {
+      let result = fchown(&file, Some(0), Some(0))
+        .and_then(|()| file.set_permissions(Permissions::from_mode(Self::MODE)));
+
+      if let Err(error) = result {
+        textual_error.change_context("Writing the heartbeat for the Linux-PAM module");
+        textual_error.add_message("Failed to make the heartbeat file writable by root only");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Path", path.display());
+        return Err(());
+      }
+    }
+
+    if let Err(error) = file.set_modified(SystemTime::now()) {
+      textual_error.change_context("Writing the heartbeat for the Linux-PAM module");
+      textual_error.add_message("Failed to update the heartbeat file's modification time");
+      textual_error.add_attachement_display("Io error", error);
+      textual_error.add_attachement_display("Path", path.display());
+      return Err(());
+    }
+
+    Ok(())
+  }
+
+  // How long ago the daemon last beat, or None if it never did, the
+  // file can't be read, or someone other than root could have touched
+  // it.
+  pub fn get_age(path: &Path) -> Option<Duration> {
+    let metadata = std::fs::metadata(path).ok()?;
+
+    if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+      return None;
+    }
+
+    let modified_at = metadata.modified().ok()?;
+
+    // A heartbeat from the future means the clock went back, and the
+    // daemon was alive recently enough.
+    let age = SystemTime::now()
+      .duration_since(modified_at)
+      .unwrap_or_default();
+
+    Some(Duration::from_milliseconds(
+      u64::try_from(age.as_millis()).unwrap_or(u64::MAX)
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::x::{TextualError, UuidV4};
+  use super::*;
+
+  fn create_path() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("discipline-heartbeat-{}", UuidV4::generate().to_string()))
+  }
+
+  #[test]
+  fn heartbeats_are_writable_by_root_only() {
+    let path = create_path();
+    let mut textual_error = TextualError::new("Testing the heartbeat");
+
+    Heartbeat::beat(&path, &mut textual_error).unwrap();
+
+    let metadata = std::fs::metadata(&path).unwrap();
+    assert_eq!(metadata.uid(), 0);
+    assert_eq!(metadata.mode() & 0o777, Heartbeat::MODE);
+    assert!(Heartbeat::get_age(&path).is_some_and(|age| age.is_shorter_than(Duration::MINUTE)));
+
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn heartbeats_others_could_touch_are_taken_back_and_not_trusted_meanwhile() {
+    let path = create_path();
+    let mut textual_error = TextualError::new("Testing the heartbeat");
+
+    std::fs::write(&path, b"").unwrap();
+    std::fs::set_permissions(&path, Permissions::from_mode(0o666)).unwrap();
+    assert_eq!(Heartbeat::get_age(&path), None);
+
+    Heartbeat::beat(&path, &mut textual_error).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().mode() & 0o777, Heartbeat::MODE);
+    assert!(Heartbeat::get_age(&path).is_some());
+
+    std::fs::remove_file(&path).unwrap();
+  }
+}
@@ -0,0 +1,108 @@
+// HMAC-SHA-256, for signing what the daemon leaves for the PAM module
+// on disk.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub const TAG_LENGTH: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub fn sign(key: &[u8], message: &[u8]) -> [u8; TAG_LENGTH] {
+  // HMAC takes keys of any length.
+  let Ok(mut mac) = HmacSha256::new_from_slice(key) else {
+    unreachable!();
+  };
+
+  mac.update(message);
+  mac.finalize().into_bytes().into()
+}
+
+pub fn verify(key: &[u8], message: &[u8], tag: &[u8]) -> bool {
+  let Ok(mut mac) = HmacSha256::new_from_slice(key) else {
+    return false;
+  };
+
+  mac.update(message);
+  // Also in constant time, and refuses tags of the wrong length.
+  mac.verify_slice(tag).is_ok()
+}
+
+// Compares every byte whatever the first difference, so how much of a
+// guessed token matched can't be learned from how long the check took.
+// Only the length may leak.
+pub fn is_equal_in_constant_time(left: &[u8], right: &[u8]) -> bool {
+  if left.len() != right.len() {
+    return false;
+  }
+
+  left
+    .iter()
+    .zip(right)
+    .fold(0u8, |difference, (left, right)| difference | (left ^ right))
+    == 0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn decode_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+      .step_by(2)
+      .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).unwrap())
+      .collect()
+  }
+
+  // RFC 4231, test cases 1, 2, 4, 6 and 7. Test cases 6 and 7 use a key
+  // that's longer than a block.
+  const TEST_CASES: &[(&str, &str, &str)] = &[
+    (
+      "0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b",
+      "4869205468657265",
+      "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+    ),
+    (
+      "4a656665",
+      "7768617420646f2079612077616e7420666f72206e6f7468696e673f",
+      "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+    ),
+    (
+      "0102030405060708090a0b0c0d0e0f10111213141516171819",
+      "cdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd",
+      "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+    ),
+    (
+      "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+      "54657374205573696e67204c6172676572205468616e20426c6f636b2d53697a65204b6579202d2048617368204b6579204669727374",
+      "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+    ),
+    (
+      "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+      "5468697320697320612074657374207573696e672061206c6172676572207468616e20626c6f636b2d73697a65206b657920616e642061206c6172676572207468616e20626c6f636b2d73697a6520646174612e20546865206b6579206e6565647320746f20626520686173686564206265666f7265206265696e6720757365642062792074686520484d414320616c676f726974686d2e",
+      "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+    ),
+  ];
+
+  #[test]
+  fn tags_match_rfc_4231() {
+    for (key, message, tag) in TEST_CASES {
+      let (key, message, tag) = (decode_hex(key), decode_hex(message), decode_hex(tag));
+
+      assert_eq!(sign(&key, &message).as_slice(), tag.as_slice());
+      assert!(verify(&key, &message, &tag));
+    }
+  }
+
+  #[test]
+  fn altered_or_truncated_tags_are_refused() {
+    let tag = sign(b"key", b"message");
+
+    let mut altered_tag = tag;
+    altered_tag[TAG_LENGTH - 1] ^= 1;
+    assert!(!verify(b"key", b"message", &altered_tag));
+
+    assert!(!verify(b"key", b"message", &tag[..TAG_LENGTH - 1]));
+    assert!(!verify(b"other key", b"message", &tag));
+  }
+}
@@ -8,6 +8,15 @@ mod protocol;
 pub use protocol::*;
 
 mod module;
+pub use module::*;
+
+mod heartbeat;
+pub use heartbeat::Heartbeat;
+
+mod hmac;
+
+mod policy_cache;
+pub use policy_cache::*;
 
 // use super::*;
 
@@ -1,14 +1,27 @@
 use std::path::{PathBuf, Path};
 use serde::{Deserialize, Serialize};
 use crate::x::{Duration, IsTextualError};
-use super::{AuthenticationToken};
+use super::{AuthenticationToken, FailurePolicies, PolicyCacheReaderConfiguration};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModuleConfiguration {
   pub authentication_token: AuthenticationToken,
+  // How long connecting to the daemon, and each read and write after,
+  // may take before the failure policy decides instead.
   pub pam_call_timeout: Duration,
+  // Shown to users whose login is refused. See `render_refusal_message`
+  // for the placeholders it may use.
   pub pam_login_blocked_message: String,
   pub discipline_daemon_unix_domain_server_path: PathBuf,
+  #[serde(default)]
+  pub failure_policies: FailurePolicies,
+  // The daemon's `pam_heartbeat_path`. Without it, closed-after-grace
+  // policies refuse logins as soon as the daemon can't be reached.
+  #[serde(default)]
+  pub discipline_daemon_heartbeat_path: Option<PathBuf>,
+  // Consulted before the failure policies when set.
+  #[serde(default)]
+  pub policy_cache: Option<PolicyCacheReaderConfiguration>,
 }
 
 impl ModuleConfiguration {
@@ -17,6 +30,8 @@ impl ModuleConfiguration {
     textual_error: &mut impl IsTextualError,
   ) -> Result<ModuleConfiguration, ()> {
     let mut textual_error = textual_error.optional_context("Loading Discpline Linux-PAM Module Configuration from file");
+
+    AuthenticationToken::ensure_stored_privately(configuration_file_path.as_ref(), &mut textual_error)?;
   
     let configuration_file_content = match std::fs::read(&configuration_file_path) {
       Ok(value) => {
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use serde::{Deserialize, Serialize};
+use crate::x::Duration;
+use super::{
+  AllocationConfig,
+  GroupName,
+  UserName,
+  get_group_file_entry_with_group_name,
+  get_password_file_entry_with_user_name,
+};
+
+// What the module does with a login when it can't get an answer from
+// the daemon: the daemon isn't running, its socket is gone, or it took
+// longer than `pam_call_timeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FailurePolicy {
+  // Allows the login. Nobody gets locked out by a broken daemon, but
+  // stopping the daemon lifts every block.
+  #[default]
+  Open,
+  // Refuses the login.
+  Closed,
+  // Allows the login while the daemon was alive within the grace
+  // period, which rides out restarts and upgrades, then refuses it.
+  ClosedAfterGrace {
+    grace_period: Duration,
+  },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureDecision {
+  Allow,
+  Refuse,
+}
+
+impl FailurePolicy {
+  // `daemon_heartbeat_age` is how long ago the daemon was last known to
+  // be alive, or None if that isn't known at all.
+  pub fn decide(&self, daemon_heartbeat_age: Option<Duration>) -> FailureDecision {
+    match self {
+      FailurePolicy::Open => {
+        FailureDecision::Allow
+      }
+      FailurePolicy::Closed => {
+        FailureDecision::Refuse
+      }
+      FailurePolicy::ClosedAfterGrace { grace_period } => {
+        match daemon_heartbeat_age {
+          Some(age) if age.is_shorter_than(*grace_period) => {
+            FailureDecision::Allow
+          }
+          _ => {
+            FailureDecision::Refuse
+          }
+        }
+      }
+    }
+  }
+
+  fn is_stricter_than(&self, other: &FailurePolicy) -> bool {
+    match (self, other) {
+      (FailurePolicy::Closed, FailurePolicy::Closed) => {
+        false
+      }
+      (FailurePolicy::Closed, _) => {
+        true
+      }
+      (FailurePolicy::ClosedAfterGrace { grace_period }, FailurePolicy::ClosedAfterGrace { grace_period: other_grace_period }) => {
+        grace_period.is_shorter_than(*other_grace_period)
+      }
+      (FailurePolicy::ClosedAfterGrace { .. }, FailurePolicy::Open) => {
+        true
+      }
+      _ => {
+        false
+      }
+    }
+  }
+}
+
+// Failure policies are read by the module itself rather than asked of
+// the daemon, since they're needed exactly when the daemon can't answer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FailurePolicies {
+  // Applies to users with no policy of their own or of their groups.
+  #[serde(default)]
+  pub default: FailurePolicy,
+  #[serde(default)]
+  pub users: HashMap<String, FailurePolicy>,
+  #[serde(default)]
+  pub groups: HashMap<String, FailurePolicy>,
+}
+
+impl FailurePolicies {
+  // A user's own policy wins. Otherwise the strictest policy among
+  // their groups applies, and the default if none of them has one.
+  //
+  // Groups that can't be looked up are skipped, since failing to read
+  // the group database shouldn't lock out users that aren't in them.
+  pub fn get_policy(&self, user_name: &UserName) -> FailurePolicy {
+    if let Ok(user_name) = user_name.inner().to_str()
+      && let Some(policy) = self.users.get(user_name)
+    {
+      return *policy;
+    }
+
+    if self.groups.is_empty() {
+      return self.default;
+    }
+
+    let Ok(account) = get_password_file_entry_with_user_name(
+      user_name,
+      &AllocationConfig::default(),
+    ) else {
+      return self.default;
+    };
+
+    let mut group_policy: Option<FailurePolicy> = None;
+
+    for (group_name, policy) in &self.groups {
+      let Ok(group_name) = CString::new(group_name.as_str()) else {
+        continue;
+      };
+
+      let Ok(group) = get_group_file_entry_with_group_name(
+        &GroupName::new(group_name),
+        &AllocationConfig::default(),
+      ) else {
+        continue;
+      };
+
+      let is_member = group.group_id == account.primary_group_id
+        ||
+        group.member_names.contains(user_name);
+
+      if !is_member {
+        continue;
+      }
+
+      match &group_policy {
+        Some(group_policy) if !policy.is_stricter_than(group_policy) => {}
+        _ => {
+          group_policy = Some(*policy);
+        }
+      }
+    }
+
+    group_policy.unwrap_or(self.default)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const GRACE_PERIOD: Duration = Duration::MINUTE;
+
+  fn create_user_name(name: &str) -> UserName {
+    UserName::new(CString::new(name).unwrap())
+  }
+
+  #[test]
+  fn policies_decide_by_the_daemon_heartbeat() {
+    let closed_after_grace = FailurePolicy::ClosedAfterGrace { grace_period: GRACE_PERIOD };
+    let within_grace = Some(Duration::from_milliseconds(GRACE_PERIOD.as_total_milliseconds() - 1));
+
+    let cases = [
+      (FailurePolicy::Open, None, FailureDecision::Allow),
+      (FailurePolicy::Open, Some(Duration::day()), FailureDecision::Allow),
+      (FailurePolicy::Closed, None, FailureDecision::Refuse),
+      (FailurePolicy::Closed, Some(Duration::zero()), FailureDecision::Refuse),
+      (closed_after_grace, Some(Duration::zero()), FailureDecision::Allow),
+      (closed_after_grace, within_grace, FailureDecision::Allow),
+      // The grace period is over once it has passed entirely.
+      (closed_after_grace, Some(GRACE_PERIOD), FailureDecision::Refuse),
+      (closed_after_grace, Some(Duration::day()), FailureDecision::Refuse),
+      // A daemon that was never seen alive gets no grace.
+      (closed_after_grace, None, FailureDecision::Refuse),
+    ];
+
+    for (policy, daemon_heartbeat_age, decision) in cases {
+      assert_eq!(policy.decide(daemon_heartbeat_age), decision, "{policy:?} with a heartbeat from {daemon_heartbeat_age:?} ago");
+    }
+  }
+
+  #[test]
+  fn strictness_orders_closed_then_shorter_grace_then_open() {
+    let short_grace = FailurePolicy::ClosedAfterGrace { grace_period: GRACE_PERIOD };
+    let long_grace = FailurePolicy::ClosedAfterGrace { grace_period: Duration::HOUR };
+
+    let cases = [
+      (FailurePolicy::Closed, FailurePolicy::Open, true),
+      (FailurePolicy::Closed, long_grace, true),
+      (FailurePolicy::Closed, FailurePolicy::Closed, false),
+      (short_grace, long_grace, true),
+      (long_grace, short_grace, false),
+      (short_grace, short_grace, false),
+      (short_grace, FailurePolicy::Open, true),
+      (short_grace, FailurePolicy::Closed, false),
+      (FailurePolicy::Open, FailurePolicy::Open, false),
+      (FailurePolicy::Open, long_grace, false),
+      (FailurePolicy::Open, FailurePolicy::Closed, false),
+    ];
+
+    for (policy, other, is_stricter) in cases {
+      assert_eq!(policy.is_stricter_than(&other), is_stricter, "{policy:?} against {other:?}");
+    }
+  }
+
+  #[test]
+  fn a_users_own_policy_beats_their_groups() {
+    // root is in the root group on any system the tests run on.
+    let policies = FailurePolicies {
+      default: FailurePolicy::Open,
+      users: HashMap::from([("root".to_string(), FailurePolicy::Open)]),
+      groups: HashMap::from([("root".to_string(), FailurePolicy::Closed)]),
+    };
+
+    assert_eq!(policies.get_policy(&create_user_name("root")), FailurePolicy::Open);
+  }
+
+  #[test]
+  fn the_strictest_group_policy_applies_and_unknown_groups_are_skipped() {
+    let short_grace = FailurePolicy::ClosedAfterGrace { grace_period: GRACE_PERIOD };
+
+    let policies = FailurePolicies {
+      default: FailurePolicy::Open,
+      users: HashMap::new(),
+      groups: HashMap::from([
+        ("root".to_string(), short_grace),
+        ("discipline-no-such-group".to_string(), FailurePolicy::Closed),
+      ]),
+    };
+
+    assert_eq!(policies.get_policy(&create_user_name("root")), short_grace);
+  }
+
+  #[test]
+  fn users_without_a_policy_get_the_default() {
+    let policies = FailurePolicies {
+      default: FailurePolicy::Closed,
+      users: HashMap::from([("root".to_string(), FailurePolicy::Open)]),
+      groups: HashMap::from([("root".to_string(), FailurePolicy::Open)]),
+    };
+
+    // Not in the root group, or no such user at all.
+    assert_eq!(policies.get_policy(&create_user_name("discipline-no-such-user")), FailurePolicy::Closed);
+    assert_eq!(FailurePolicies::default().get_policy(&create_user_name("root")), FailurePolicy::Open);
+  }
+}
@@ -23,8 +23,14 @@ pub struct SystemLogger {
   reopen_count_within_window: u8,
 }
 
+impl Default for SystemLogger {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl SystemLogger {
-  fn new() -> Self {
+  pub fn new() -> Self {
     Self {
       log: None,
       reopen_interval: Duration::from_milliseconds(1_000),
@@ -34,7 +40,7 @@ impl SystemLogger {
     }
   }
 
-  fn write(&mut self, message: &str) -> Result<(), Error> {
+  pub fn write(&mut self, message: &str) -> Result<(), Error> {
     if let Some(log) = &mut self.log {
       if let Ok(()) = log.err(message) {
         return Ok(());
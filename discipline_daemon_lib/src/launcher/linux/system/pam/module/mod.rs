@@ -1,10 +1,19 @@
 use super::*;
 
 mod logger;
-use logger::SystemLogger;
+pub use logger::SystemLogger;
 
 // mod module_data;
 // use module_data::*;
 
 mod configuration;
-use configuration::*;
\ No newline at end of file
+pub use configuration::*;
+
+mod failure_policy;
+pub use failure_policy::*;
+
+mod refusal_message;
+pub use refusal_message::render_refusal_message;
+
+mod session_environment;
+pub use session_environment::*;
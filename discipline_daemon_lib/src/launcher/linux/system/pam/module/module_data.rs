@@ -1,6 +1,7 @@
 use std::sync::{Mutex, MutexGuard};
 use std::path::{Path, PathBuf};
 use crate::x::{IsTextualError, OptionalTextualErrorContext};
+use crate::x::launcher::{LoginContext, SessionDetails};
 // use super::{SystemLogger, ClientConnection, EstablishConnectionError, UserNameRef, ModuleConfiguration};
 use super::{SystemLogger, UserNameRef, ModuleConfiguration};
 
@@ -60,7 +61,11 @@ impl ModuleDataMutex {
     self.mutex.lock().map_err(|_| ())
   }
 
-  pub fn is_user_session_open_blocked(&self, user_name: UserNameRef<'_>) -> bool {
+  pub fn is_user_session_open_blocked(
+    &self, 
+    user_name: UserNameRef<'_>,
+    login_context: &LoginContext,
+  ) -> bool {
     let mut textual_error = OptionalTextualErrorContext::new("action");
     
     let Ok(mut data) = self.lock() else {
@@ -69,11 +74,11 @@ impl ModuleDataMutex {
 
     data
       .connection
-      .is_user_session_open_blocked(user_name, &mut textual_error)
-      .unwrap_or(false)
+      .get_login_refusal(user_name, login_context, &mut textual_error)
+      .is_ok_and(|refusal| refusal.is_some())
   }
 
-  pub fn on_session_opened(&self, user_name: UserNameRef<'_>) {
+  pub fn on_session_opened(&self, user_name: UserNameRef<'_>, details: &SessionDetails) {
     let mut textual_error = OptionalTextualErrorContext::new("");
 
     let mut data = match self.lock() {
@@ -85,7 +90,7 @@ impl ModuleDataMutex {
       }
     };
     
-    match data.connection.send_user_session_opened_notification(user_name, &mut textual_error) {
+    match data.connection.send_user_session_opened_notification(user_name, details, &mut textual_error) {
       Ok(value) => {
         value
       }
@@ -96,7 +101,7 @@ impl ModuleDataMutex {
     };
   }
 
-  pub fn on_session_closed(&self, user_name: UserNameRef<'_>) {
+  pub fn on_session_closed(&self, user_name: UserNameRef<'_>, details: &SessionDetails) {
     let mut textual_error = OptionalTextualErrorContext::new("");
 
     let mut data = match self.mutex.lock() {
@@ -109,7 +114,7 @@ impl ModuleDataMutex {
       }
     };
 
-    if let Err(error) = data.connection.send_user_session_closed_notification(user_name, &mut textual_error) {
+    if let Err(error) = data.connection.send_user_session_closed_notification(user_name, details, &mut textual_error) {
       // TODO: Log the error.
     }
   }
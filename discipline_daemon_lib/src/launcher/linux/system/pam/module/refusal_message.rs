@@ -0,0 +1,226 @@
+use crate::x::{DateTime, Duration};
+use crate::x::launcher::{BlockReason, LoginClass, LoginRefusal, LoginRefusalReason};
+
+// Fills in `pam_login_blocked_message`, which may refer to:
+//
+// - {rule}: what refused the login, like "a blocked time range".
+// - {until}: when logging in is allowed again, in local time.
+// - {remaining}: how long that is from now, like "1 hour 5 minutes".
+//
+// Refusals that don't end on their own say "further notice" and
+// "unknown" for the last two.
+pub fn render_refusal_message(template: &str, refusal: &LoginRefusal, now: DateTime) -> String {
+  let until = refusal
+    .blocked_until
+    .and_then(chrono::DateTime::from_timestamp_millis)
+    .map(|blocked_until| {
+      blocked_until
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+    })
+    .unwrap_or_else(|| "further notice".into());
+
+  let remaining = refusal
+    .blocked_until
+    .and_then(|blocked_until| DateTime::from_timestamp(blocked_until).ok())
+    .map(|blocked_until| describe_duration(now.till_or_zero(blocked_until)))
+    .unwrap_or_else(|| "unknown".into());
+
+  template
+    .replace("{rule}", describe_reason(refusal.reason))
+    .replace("{until}", &until)
+    .replace("{remaining}", &remaining)
+}
+
+fn describe_reason(reason: LoginRefusalReason) -> &'static str {
+  match reason {
+    LoginRefusalReason::Blocked(reason) => {
+      describe_block_reason(reason)
+    }
+    LoginRefusalReason::RequestingUserBlocked(reason) => {
+      match reason {
+        BlockReason::TimeRange => {
+          "switching accounts during your blocked time range"
+        }
+        BlockReason::AllowanceExhaustion => {
+          "switching accounts after your screen time allowance ran out"
+        }
+        BlockReason::Lock => {
+          "switching accounts while you're locked"
+        }
+      }
+    }
+    LoginRefusalReason::RefusedLoginClass(class) => {
+      match class {
+        LoginClass::Graphical => {
+          "graphical logins aren't allowed for this account"
+        }
+        LoginClass::Console => {
+          "console logins aren't allowed for this account"
+        }
+        LoginClass::Remote => {
+          "remote logins aren't allowed for this account"
+        }
+        LoginClass::SwitchUser => {
+          "switching to this account isn't allowed"
+        }
+        LoginClass::Elevation => {
+          "running commands as this account isn't allowed"
+        }
+        LoginClass::Other => {
+          "this kind of login isn't allowed for this account"
+        }
+      }
+    }
+    LoginRefusalReason::Unverifiable => {
+      "Discipline couldn't be reached to check your schedule"
+    }
+  }
+}
+
+fn describe_block_reason(reason: BlockReason) -> &'static str {
+  match reason {
+    BlockReason::TimeRange => {
+      "a blocked time range"
+    }
+    BlockReason::AllowanceExhaustion => {
+      "your screen time allowance ran out"
+    }
+    BlockReason::Lock => {
+      "a lock"
+    }
+  }
+}
+
+// Rounds up to the minute, so a login refused for a few more seconds
+// doesn't read "0 minutes".
+fn describe_duration(duration: Duration) -> String {
+  let minutes = duration
+    .as_total_milliseconds()
+    .div_ceil(Duration::MILLISECONDS_PER_MINUTE);
+
+  let (days, hours, minutes) = (minutes / (60 * 24), minutes / 60 % 24, minutes % 60);
+
+  let parts: Vec<String> = [(days, "day"), (hours, "hour"), (minutes, "minute")]
+    .into_iter()
+    .filter(|(count, _)| *count > 0)
+    .map(|(count, unit)| {
+      if count == 1 {
+        format!("1 {unit}")
+      } else {
+        format!("{count} {unit}s")
+      }
+    })
+    .collect();
+
+  if parts.is_empty() {
+    "less than a minute".into()
+  } else {
+    parts.join(" ")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const TEMPLATE: &str = "Refused because of {rule}; until {until}, {remaining} from now.";
+  // 2026-10-19 12:00:00 UTC.
+  const NOW: i64 = 1_792_411_200_000;
+
+  fn render(reason: LoginRefusalReason, blocked_until: Option<i64>) -> String {
+    let refusal = LoginRefusal { reason, blocked_until };
+    render_refusal_message(TEMPLATE, &refusal, DateTime::from_timestamp(NOW).unwrap())
+  }
+
+  fn format_local(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(timestamp)
+      .unwrap()
+      .with_timezone(&chrono::Local)
+      .format("%Y-%m-%d %H:%M")
+      .to_string()
+  }
+
+  #[test]
+  fn each_reason_is_described() {
+    let cases = [
+      (LoginRefusalReason::Blocked(BlockReason::TimeRange), "a blocked time range"),
+      (LoginRefusalReason::Blocked(BlockReason::AllowanceExhaustion), "your screen time allowance ran out"),
+      (LoginRefusalReason::Blocked(BlockReason::Lock), "a lock"),
+      (LoginRefusalReason::RequestingUserBlocked(BlockReason::TimeRange), "switching accounts during your blocked time range"),
+      (LoginRefusalReason::RequestingUserBlocked(BlockReason::AllowanceExhaustion), "switching accounts after your screen time allowance ran out"),
+      (LoginRefusalReason::RequestingUserBlocked(BlockReason::Lock), "switching accounts while you're locked"),
+      (LoginRefusalReason::RefusedLoginClass(LoginClass::Graphical), "graphical logins aren't allowed for this account"),
+      (LoginRefusalReason::RefusedLoginClass(LoginClass::Console), "console logins aren't allowed for this account"),
+      (LoginRefusalReason::RefusedLoginClass(LoginClass::Remote), "remote logins aren't allowed for this account"),
+      (LoginRefusalReason::RefusedLoginClass(LoginClass::SwitchUser), "switching to this account isn't allowed"),
+      (LoginRefusalReason::RefusedLoginClass(LoginClass::Elevation), "running commands as this account isn't allowed"),
+      (LoginRefusalReason::RefusedLoginClass(LoginClass::Other), "this kind of login isn't allowed for this account"),
+      (LoginRefusalReason::Unverifiable, "Discipline couldn't be reached to check your schedule"),
+    ];
+
+    for (reason, description) in cases {
+      assert_eq!(
+        render(reason, None),
+        format!("Refused because of {description}; until further notice, unknown from now."),
+        "{reason:?}",
+      );
+    }
+  }
+
+  #[test]
+  fn refusals_with_an_end_say_when() {
+    let blocked_until = NOW + 90 * 60 * 1000;
+
+    assert_eq!(
+      render(LoginRefusalReason::Blocked(BlockReason::Lock), Some(blocked_until)),
+      format!("Refused because of a lock; until {}, 1 hour 30 minutes from now.", format_local(blocked_until)),
+    );
+  }
+
+  #[test]
+  fn refusals_that_already_ended_have_nothing_remaining() {
+    let blocked_until = NOW - 60 * 1000;
+
+    assert_eq!(
+      render(LoginRefusalReason::Blocked(BlockReason::Lock), Some(blocked_until)),
+      format!("Refused because of a lock; until {}, less than a minute from now.", format_local(blocked_until)),
+    );
+  }
+
+  #[test]
+  fn templates_without_placeholders_are_kept() {
+    let refusal = LoginRefusal {
+      reason: LoginRefusalReason::Unverifiable,
+      blocked_until: Some(NOW),
+    };
+
+    assert_eq!(
+      render_refusal_message("Go outside.", &refusal, DateTime::from_timestamp(NOW).unwrap()),
+      "Go outside.",
+    );
+  }
+
+  #[test]
+  fn durations_are_described() {
+    let cases = [
+      (Duration::zero(), "less than a minute"),
+      (Duration::SECOND, "1 minute"),
+      (Duration::MINUTE, "1 minute"),
+      (Duration::from_milliseconds(Duration::MILLISECONDS_PER_MINUTE + 1), "2 minutes"),
+      (Duration::from_minutes_or_panic(59), "59 minutes"),
+      (Duration::HOUR, "1 hour"),
+      (Duration::from_minutes_or_panic(61), "1 hour 1 minute"),
+      (Duration::from_minutes_or_panic(120), "2 hours"),
+      (Duration::day(), "1 day"),
+      (Duration::day().saturating_add(Duration::MINUTE), "1 day 1 minute"),
+      (Duration::from_minutes_or_panic(2 * 24 * 60 + 3 * 60 + 4), "2 days 3 hours 4 minutes"),
+      (Duration::from_minutes_or_panic(10 * 24 * 60), "10 days"),
+    ];
+
+    for (duration, description) in cases {
+      assert_eq!(describe_duration(duration), description, "{duration:?}");
+    }
+  }
+}
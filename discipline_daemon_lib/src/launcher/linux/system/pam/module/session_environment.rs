@@ -0,0 +1,100 @@
+use chrono::SecondsFormat;
+use crate::x::launcher::SessionDeadline;
+
+// When the session will be cut off, in RFC 3339 and local time. Unset
+// when nothing is going to block the user.
+pub const SESSION_DEADLINE_VARIABLE: &str = "DISCIPLINE_SESSION_DEADLINE";
+// The name of the profile regulating the user.
+pub const PROFILE_VARIABLE: &str = "DISCIPLINE_PROFILE";
+
+// The "NAME=value" pairs to pam_putenv when a regulated user's session
+// opens, so prompts and status bars can show the time left without
+// asking the daemon. A bare "NAME" removes a variable that an earlier
+// module, or the caller's environment, set.
+//
+// The deadline is only as good as it was when the session opened.
+// Rules changed later, or time spent logged out, move it.
+pub fn render_session_environment(deadline: &SessionDeadline) -> Vec<String> {
+  let mut variables = vec![
+    format!("{PROFILE_VARIABLE}={}", deadline.profile_name),
+  ];
+
+  let ends_at = deadline
+    .ends_at
+    .and_then(chrono::DateTime::from_timestamp_millis)
+    .map(|ends_at| {
+      ends_at
+        .with_timezone(&chrono::Local)
+        .to_rfc3339_opts(SecondsFormat::Secs, false)
+    });
+
+  match ends_at {
+    Some(ends_at) => {
+      variables.push(format!("{SESSION_DEADLINE_VARIABLE}={ends_at}"));
+    }
+    None => {
+      variables.push(SESSION_DEADLINE_VARIABLE.to_owned());
+    }
+  }
+
+  variables
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn users_with_a_deadline_get_it_in_local_time() {
+    // 2026-10-19 12:00:00 UTC.
+    let ends_at = 1_792_411_200_000;
+    let deadline = SessionDeadline {
+      profile_name: "school nights".into(),
+      ends_at: Some(ends_at),
+    };
+
+    let local_ends_at = chrono::DateTime::from_timestamp_millis(ends_at)
+      .unwrap()
+      .with_timezone(&chrono::Local)
+      .to_rfc3339_opts(SecondsFormat::Secs, false);
+
+    assert_eq!(
+      render_session_environment(&deadline),
+      vec![
+        "DISCIPLINE_PROFILE=school nights".to_string(),
+        format!("DISCIPLINE_SESSION_DEADLINE={local_ends_at}"),
+      ],
+    );
+  }
+
+  #[test]
+  fn deadlines_are_rfc_3339_to_the_second() {
+    let deadline = SessionDeadline {
+      profile_name: "default".into(),
+      ends_at: Some(1_792_411_200_999),
+    };
+
+    let variables = render_session_environment(&deadline);
+    let ends_at = variables[1].strip_prefix("DISCIPLINE_SESSION_DEADLINE=").unwrap();
+
+    let parsed = chrono::DateTime::parse_from_rfc3339(ends_at).unwrap();
+    assert_eq!(parsed.timestamp_millis(), 1_792_411_200_000);
+    assert!(!ends_at.contains('.'), "{ends_at}");
+  }
+
+  #[test]
+  fn users_without_a_deadline_have_it_removed() {
+    let deadline = SessionDeadline {
+      profile_name: "default".into(),
+      ends_at: None,
+    };
+
+    assert_eq!(
+      render_session_environment(&deadline),
+      vec![
+        "DISCIPLINE_PROFILE=default".to_string(),
+        "DISCIPLINE_SESSION_DEADLINE".to_string(),
+      ],
+    );
+  }
+}
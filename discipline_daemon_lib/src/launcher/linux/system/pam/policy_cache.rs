@@ -0,0 +1,700 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::x::{CountdownState, DateTime, Duration, Instant, IsTextualError, RuleEnabler, TimeRange, WeekdaySet};
+use crate::x::launcher::{BlockReason, LoginClass, LoginContext, LoginPolicy, LoginRefusal, LoginRefusalReason, UserId, UserName, UserProfile};
+use super::{AuthenticationToken, hmac};
+
+// Where the daemon leaves a copy of everyone's block schedule, so the
+// PAM module can still enforce it when the daemon can't be reached.
+// Only root may read the file: it lists who is regulated and when.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyCacheConfiguration {
+  pub path: PathBuf,
+  // How often the cache is rewritten when nothing in it changed, which
+  // is what tells the module how old its information is.
+  pub refresh_interval: Duration,
+}
+
+// The module's side: where it finds the cache, and how old a cache it
+// still trusts. Rules may have changed since it was written, and a
+// cache nobody refreshes for long enough is more likely left behind on
+// purpose than by accident.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyCacheReaderConfiguration {
+  pub path: PathBuf,
+  pub maximum_age: Duration,
+}
+
+// When the rules of a user's screen regulation block them, in wall
+// clock terms the module can check without the daemon's monotonic
+// clock.
+//
+// Allowance rules are left out: whether they block depends on usage
+// only the daemon counts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledBlock {
+  pub weekdays: WeekdaySet,
+  // None blocks the whole day.
+  pub time_range: Option<TimeRange>,
+  // When the rule is enabled, as UNIX timestamps in milliseconds. None
+  // means since before the cache was written, or until further notice.
+  pub enabled_from: Option<i64>,
+  pub enabled_till: Option<i64>,
+}
+
+impl ScheduledBlock {
+  fn is_active(&self, now: DateTime) -> bool {
+    let timestamp = now.as_timestamp();
+
+    self.enabled_from.is_none_or(|enabled_from| enabled_from <= timestamp)
+    &&
+    self.enabled_till.is_none_or(|enabled_till| timestamp < enabled_till)
+    &&
+    self.weekdays.contains(now.weekday())
+    &&
+    self.time_range.is_none_or(|time_range| time_range.contains(now.time()))
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockSchedule {
+  pub user_id: UserId,
+  pub blocks: Vec<ScheduledBlock>,
+  pub login_policy: LoginPolicy,
+}
+
+impl BlockSchedule {
+  pub fn create(profile: &UserProfile, now: Instant, wall_now: DateTime) -> Self {
+    let regulation = &profile.screen_access_regulation;
+    let mut blocks = Vec::new();
+
+    for rule in regulation.always_rules.rules.values() {
+      if let Some((enabled_from, enabled_till)) = get_enabled_period(&rule.enabler, now, wall_now) {
+        blocks.push(ScheduledBlock {
+          weekdays: WeekdaySet::ALL,
+          time_range: None,
+          enabled_from,
+          enabled_till,
+        });
+      }
+    }
+
+    for rule in regulation.time_range_rules.get_rules() {
+      if let Some((enabled_from, enabled_till)) = get_enabled_period(&rule.enabler, now, wall_now) {
+        blocks.push(ScheduledBlock {
+          weekdays: WeekdaySet::ALL,
+          time_range: Some(rule.condition),
+          enabled_from,
+          enabled_till,
+        });
+      }
+    }
+
+    Self {
+      user_id: profile.user_id,
+      blocks,
+      login_policy: profile.login_policy.clone(),
+    }
+  }
+
+  // Why the schedule blocks the user right now. Like the daemon, a lock
+  // is reported before a time range.
+  fn get_block_reason(&self, now: DateTime) -> Option<BlockReason> {
+    let active_blocks = self.blocks.iter().filter(|block| block.is_active(now));
+    let mut reason = None;
+
+    for block in active_blocks {
+      if block.time_range.is_none() {
+        return Some(BlockReason::Lock);
+      }
+      reason = Some(BlockReason::TimeRange);
+    }
+
+    reason
+  }
+}
+
+// Converts the period a rule is enabled from the monotonic clock to the
+// wall clock, or None if it won't be enabled again.
+fn get_enabled_period(
+  enabler: &RuleEnabler,
+  now: Instant,
+  wall_now: DateTime,
+) -> Option<(Option<i64>, Option<i64>)> {
+  let to_timestamp = |instant: Instant| {
+    let offset = i64::try_from(instant.since_or_zero(now).as_total_milliseconds()).unwrap_or(i64::MAX);
+    wall_now.as_timestamp().saturating_add(offset)
+  };
+
+  match enabler {
+    RuleEnabler::Countdown(enabler) => {
+      let countdown = enabler.countdown.as_ref()?;
+      match countdown.get_state(now) {
+        CountdownState::Pending | CountdownState::Running => {
+          Some((Some(to_timestamp(countdown.get_from())), Some(to_timestamp(countdown.get_till()))))
+        }
+        CountdownState::Finished => {
+          None
+        }
+      }
+    }
+    // Enabled until a plea's countdown runs out.
+    RuleEnabler::CountdownAfterPlea(enabler) => {
+      let Some(countdown) = &enabler.countdown else {
+        return Some((None, None));
+      };
+
+      match countdown.get_state(now) {
+        CountdownState::Pending | CountdownState::Running => {
+          Some((None, Some(to_timestamp(countdown.get_till()))))
+        }
+        CountdownState::Finished => {
+          None
+        }
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyCache {
+  // UNIX timestamp in milliseconds.
+  pub written_at: i64,
+  // Keyed by user name. Users that aren't in it weren't regulated.
+  pub schedules: HashMap<String, BlockSchedule>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PolicyCacheDecision {
+  Allow,
+  // The cache doesn't say when blocks end, so the refusal never has a
+  // `blocked_until`.
+  Refuse(LoginRefusal),
+  // The cache is older than the module accepts.
+  Stale,
+}
+
+impl PolicyCache {
+  // The file starts with a line naming the format, its version and the
+  // payload's HMAC-SHA-256, keyed with the PAM authentication token,
+  // in hex. The JSON payload follows.
+  const MAGIC: &'static str = "discipline-pam-policy-cache";
+  const VERSION: u32 = 1;
+
+  pub fn encode(&self, authentication_token: &AuthenticationToken) -> Result<Vec<u8>, serde_json::Error> {
+    let payload = serde_json::to_vec(self)?;
+    let tag = hmac::sign(authentication_token.as_bytes(), &payload);
+
+    let mut encoded = format!("{} {} {}\n", Self::MAGIC, Self::VERSION, encode_hex(&tag)).into_bytes();
+    encoded.extend_from_slice(&payload);
+    Ok(encoded)
+  }
+
+  pub fn decode(
+    encoded: &[u8],
+    authentication_token: &AuthenticationToken,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Self, ()> {
+    textual_error.change_context("Decoding the PAM policy cache");
+
+    let Some(header_length) = encoded.iter().position(|byte| *byte == b'\n') else {
+      textual_error.add_message("The header is missing");
+      return Err(());
+    };
+
+    let (header, payload) = (&encoded[..header_length], &encoded[header_length + 1..]);
+    let header = String::from_utf8_lossy(header);
+    let mut header = header.split(' ');
+
+    if header.next() != Some(Self::MAGIC) {
+      textual_error.add_message("The file isn't a policy cache");
+      return Err(());
+    }
+
+    let version = header.next().unwrap_or_default();
+    if version != Self::VERSION.to_string() {
+      textual_error.add_message("The cache's format version isn't supported");
+      textual_error.add_attachement_display("Version", version);
+      textual_error.add_attachement_display("Supported version", Self::VERSION);
+      return Err(());
+    }
+
+    let tag = header.next().and_then(decode_hex).unwrap_or_default();
+    if !hmac::verify(authentication_token.as_bytes(), payload, &tag) {
+      textual_error.add_message("The cache's signature doesn't match its content");
+      return Err(());
+    }
+
+    match serde_json::from_slice(payload) {
+      Ok(cache) => {
+        Ok(cache)
+      }
+      Err(error) => {
+        textual_error.add_message("Failed to deserialize the payload");
+        textual_error.add_attachement_display("Deserialization error", error);
+        Err(())
+      }
+    }
+  }
+
+  pub fn load(
+    path: &Path,
+    authentication_token: &AuthenticationToken,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Self, ()> {
+    match std::fs::read(path) {
+      Ok(encoded) => {
+        Self::decode(&encoded, authentication_token, textual_error)
+      }
+      Err(error) => {
+        textual_error.change_context("Loading the PAM policy cache");
+        textual_error.add_message("An io error occured");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Path", path.display());
+        Err(())
+      }
+    }
+  }
+
+  pub fn get_age(&self, now: DateTime) -> Duration {
+    let age = now.as_timestamp().saturating_sub(self.written_at).max(0);
+    Duration::from_milliseconds(age as u64)
+  }
+
+  // Decides like the daemon would have when the cache was written,
+  // except that allowances aren't counted.
+  pub fn decide(
+    &self,
+    user_name: &UserName,
+    login_context: &LoginContext,
+    maximum_age: Duration,
+    now: DateTime,
+  ) -> PolicyCacheDecision {
+    if self.get_age(now).is_longer_than(maximum_age) {
+      return PolicyCacheDecision::Stale;
+    }
+
+    let class = login_context.get_class();
+
+    let refuse = |reason| PolicyCacheDecision::Refuse(LoginRefusal {
+      reason,
+      blocked_until: None,
+    });
+
+    if let LoginClass::SwitchUser | LoginClass::Elevation = class {
+      let requesting_user_block_reason = login_context
+        .requesting_user_id
+        .and_then(|user_id| self.schedules.values().find(|schedule| schedule.user_id == user_id))
+        .filter(|schedule| schedule.login_policy.refuse_switching_users_while_blocked)
+        .and_then(|schedule| schedule.get_block_reason(now));
+
+      if let Some(reason) = requesting_user_block_reason {
+        return refuse(LoginRefusalReason::RequestingUserBlocked(reason));
+      }
+    }
+
+    let Some(schedule) = user_name
+      .inner()
+      .to_str()
+      .ok()
+      .and_then(|user_name| self.schedules.get(user_name))
+    else {
+      return PolicyCacheDecision::Allow;
+    };
+
+    if schedule.login_policy.is_refused(class) {
+      return refuse(LoginRefusalReason::RefusedLoginClass(class));
+    }
+    if schedule.login_policy.is_exempt(class) {
+      return PolicyCacheDecision::Allow;
+    }
+
+    match schedule.get_block_reason(now) {
+      Some(reason) => {
+        refuse(LoginRefusalReason::Blocked(reason))
+      }
+      None => {
+        PolicyCacheDecision::Allow
+      }
+    }
+  }
+}
+
+// Tracks what was last written, so the file is only rewritten when a
+// schedule changes or the refresh interval passes.
+pub struct PolicyCacheWriter {
+  // None when the cache is disabled.
+  configuration: Option<PolicyCacheConfiguration>,
+  authentication_token: AuthenticationToken,
+  written_schedules: Option<HashMap<String, BlockSchedule>>,
+  written_at: Option<Instant>,
+}
+
+impl PolicyCacheWriter {
+  pub fn create(
+    configuration: Option<PolicyCacheConfiguration>,
+    authentication_token: AuthenticationToken,
+  ) -> Self {
+    Self {
+      configuration,
+      authentication_token,
+      written_schedules: None,
+      written_at: None,
+    }
+  }
+
+  pub fn get_configuration(&self) -> Option<&PolicyCacheConfiguration> {
+    self.configuration.as_ref()
+  }
+
+  pub fn get_authentication_token(&self) -> &AuthenticationToken {
+    &self.authentication_token
+  }
+
+  pub fn needs_writing(&self, schedules: &HashMap<String, BlockSchedule>, now: Instant) -> bool {
+    let Some(configuration) = &self.configuration else {
+      return false;
+    };
+
+    let is_due = self
+      .written_at
+      .is_none_or(|written_at| now.since_or_zero(written_at).is_longer_than_or_equal_to(configuration.refresh_interval));
+
+    is_due || self.written_schedules.as_ref() != Some(schedules)
+  }
+
+  pub fn on_written(&mut self, schedules: HashMap<String, BlockSchedule>, now: Instant) {
+    self.written_schedules = Some(schedules);
+    self.written_at = Some(now);
+  }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+  if !hex.len().is_multiple_of(2) {
+    return None;
+  }
+
+  (0..hex.len())
+    .step_by(2)
+    .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::CString;
+  use crate::x::{Countdown, CountdownAfterPleaConditional, CountdownConditional, TextualError, Time, Weekday};
+  use super::*;
+
+  // A Tuesday, in UTC.
+  const TUESDAY_MIDNIGHT: i64 = 1_699_920_000_000;
+  const MILLISECONDS_PER_HOUR: i64 = 60 * 60 * 1000;
+
+  fn create_token(value: &str) -> AuthenticationToken {
+    serde_json::from_value(serde_json::json!({ "value": value })).unwrap()
+  }
+
+  fn create_cache() -> PolicyCache {
+    let mut schedules = HashMap::new();
+    schedules.insert("alex".to_string(), BlockSchedule {
+      user_id: UserId::new(1000),
+      blocks: Vec::new(),
+      login_policy: LoginPolicy::default(),
+    });
+
+    PolicyCache {
+      written_at: 1_700_000_000_000,
+      schedules,
+    }
+  }
+
+  fn decode(encoded: &[u8], authentication_token: &AuthenticationToken) -> Result<PolicyCache, ()> {
+    let mut textual_error = TextualError::new("Testing decoding the PAM policy cache");
+    PolicyCache::decode(encoded, authentication_token, &mut textual_error)
+  }
+
+  #[test]
+  fn encoded_caches_decode_with_the_same_token_only() {
+    let token = create_token("token");
+    let encoded = create_cache().encode(&token).unwrap();
+
+    let decoded = decode(&encoded, &token).unwrap();
+    assert_eq!(decoded.written_at, 1_700_000_000_000);
+    assert_eq!(decoded.schedules, create_cache().schedules);
+
+    assert!(decode(&encoded, &create_token("other token")).is_err());
+  }
+
+  #[test]
+  fn tampered_payloads_are_refused() {
+    let token = create_token("token");
+    let encoded = String::from_utf8(create_cache().encode(&token).unwrap()).unwrap();
+    let tampered = encoded.replace("1700000000000", "1800000000000");
+
+    assert_ne!(encoded, tampered);
+    assert!(decode(tampered.as_bytes(), &token).is_err());
+  }
+
+  #[test]
+  fn truncated_tags_are_refused() {
+    let token = create_token("token");
+    let encoded = String::from_utf8(create_cache().encode(&token).unwrap()).unwrap();
+    let (header, payload) = encoded.split_once('\n').unwrap();
+
+    // Two hex digits, so what's left still decodes.
+    let truncated = format!("{}\n{payload}", &header[..header.len() - 2]);
+    assert!(decode(truncated.as_bytes(), &token).is_err());
+
+    let without_tag = format!("{} {}\n{payload}", PolicyCache::MAGIC, PolicyCache::VERSION);
+    assert!(decode(without_tag.as_bytes(), &token).is_err());
+  }
+
+  #[test]
+  fn caches_without_a_header_line_are_refused() {
+    let token = create_token("token");
+    let encoded = create_cache().encode(&token).unwrap();
+    let without_newline: Vec<u8> = encoded.into_iter().filter(|byte| *byte != b'\n').collect();
+
+    assert!(decode(&without_newline, &token).is_err());
+    assert!(decode(b"", &token).is_err());
+  }
+
+  // `hour` hours after Tuesday's midnight, so 25 is 1 AM on Wednesday.
+  fn at(hour: i64) -> DateTime {
+    DateTime::from_timestamp(TUESDAY_MIDNIGHT + hour * MILLISECONDS_PER_HOUR).unwrap()
+  }
+
+  fn create_time_range(from_hour: u32, till_hour: u32) -> TimeRange {
+    let from = Time::from_timestamp(from_hour * MILLISECONDS_PER_HOUR as u32).unwrap();
+    let till = Time::from_timestamp(till_hour * MILLISECONDS_PER_HOUR as u32).unwrap();
+    TimeRange::from_times(from, till)
+  }
+
+  fn create_block(time_range: Option<TimeRange>) -> ScheduledBlock {
+    ScheduledBlock {
+      weekdays: WeekdaySet::ALL,
+      time_range,
+      enabled_from: None,
+      enabled_till: None,
+    }
+  }
+
+  fn create_schedule_cache(blocks: Vec<ScheduledBlock>, login_policy: LoginPolicy) -> PolicyCache {
+    let mut schedules = HashMap::new();
+    schedules.insert("alex".to_string(), BlockSchedule {
+      user_id: UserId::new(1000),
+      blocks,
+      login_policy,
+    });
+
+    PolicyCache {
+      written_at: at(0).as_timestamp(),
+      schedules,
+    }
+  }
+
+  fn create_login_context(service: &str, requesting_user_id: Option<u32>) -> LoginContext {
+    LoginContext {
+      service: service.to_string(),
+      tty: None,
+      remote_host: None,
+      requesting_user_id: requesting_user_id.map(UserId::new),
+    }
+  }
+
+  fn create_user_name(name: &str) -> UserName {
+    UserName::new(CString::new(name).unwrap())
+  }
+
+  fn refuse(reason: LoginRefusalReason) -> PolicyCacheDecision {
+    PolicyCacheDecision::Refuse(LoginRefusal {
+      reason,
+      blocked_until: None,
+    })
+  }
+
+  #[test]
+  fn caches_older_than_the_maximum_age_are_stale() {
+    let cache = create_schedule_cache(vec![create_block(None)], LoginPolicy::default());
+    let alex = create_user_name("alex");
+    let login_context = create_login_context("gdm-password", None);
+    let maximum_age = Duration::HOUR;
+
+    let written_at = at(0).as_timestamp();
+    let exactly_maximum_age = DateTime::from_timestamp(written_at + MILLISECONDS_PER_HOUR).unwrap();
+    let past_maximum_age = DateTime::from_timestamp(written_at + MILLISECONDS_PER_HOUR + 1).unwrap();
+
+    assert_eq!(
+      cache.decide(&alex, &login_context, maximum_age, exactly_maximum_age),
+      refuse(LoginRefusalReason::Blocked(BlockReason::Lock)),
+    );
+    assert_eq!(cache.decide(&alex, &login_context, maximum_age, past_maximum_age), PolicyCacheDecision::Stale);
+    // Users that aren't regulated aren't let through a stale cache either.
+    assert_eq!(
+      cache.decide(&create_user_name("root"), &login_context, maximum_age, past_maximum_age),
+      PolicyCacheDecision::Stale,
+    );
+  }
+
+  #[test]
+  fn blocks_crossing_midnight_are_active_on_both_sides_of_it() {
+    let block = create_block(Some(create_time_range(22, 6)));
+
+    let cases = [
+      (21, false),
+      (22, true),
+      (23, true),
+      (24, true),
+      (25, true),
+      (30, true),
+      (31, false),
+      (12, false),
+    ];
+
+    for (hour, is_active) in cases {
+      assert_eq!(block.is_active(at(hour)), is_active, "{hour} hours after Tuesday's midnight");
+    }
+  }
+
+  #[test]
+  fn blocks_are_active_on_their_weekdays_while_enabled() {
+    let tuesdays_only = ScheduledBlock {
+      weekdays: WeekdaySet::from_weekday(Weekday::Tue),
+      ..create_block(None)
+    };
+    assert!(tuesdays_only.is_active(at(12)));
+    assert!(!tuesdays_only.is_active(at(36)));
+
+    let enabled_for_an_hour = ScheduledBlock {
+      enabled_from: Some(at(12).as_timestamp()),
+      enabled_till: Some(at(13).as_timestamp()),
+      ..create_block(None)
+    };
+    assert!(!enabled_for_an_hour.is_active(at(11)));
+    assert!(enabled_for_an_hour.is_active(at(12)));
+    assert!(!enabled_for_an_hour.is_active(at(13)));
+  }
+
+  #[test]
+  fn locks_are_reported_before_time_ranges() {
+    let cache = create_schedule_cache(
+      vec![create_block(Some(create_time_range(10, 14))), create_block(None)],
+      LoginPolicy::default(),
+    );
+    let alex = create_user_name("alex");
+    let login_context = create_login_context("gdm-password", None);
+
+    assert_eq!(
+      cache.decide(&alex, &login_context, Duration::day(), at(12)),
+      refuse(LoginRefusalReason::Blocked(BlockReason::Lock)),
+    );
+
+    let cache = create_schedule_cache(vec![create_block(Some(create_time_range(10, 14)))], LoginPolicy::default());
+    assert_eq!(
+      cache.decide(&alex, &login_context, Duration::day(), at(12)),
+      refuse(LoginRefusalReason::Blocked(BlockReason::TimeRange)),
+    );
+    assert_eq!(cache.decide(&alex, &login_context, Duration::day(), at(15)), PolicyCacheDecision::Allow);
+  }
+
+  #[test]
+  fn blocked_users_cant_switch_to_other_accounts() {
+    let root = create_user_name("root");
+    let su_from_alex = create_login_context("su", Some(1000));
+
+    let cache = create_schedule_cache(vec![create_block(None)], LoginPolicy {
+      refuse_switching_users_while_blocked: true,
+      ..LoginPolicy::default()
+    });
+    assert_eq!(
+      cache.decide(&root, &su_from_alex, Duration::day(), at(12)),
+      refuse(LoginRefusalReason::RequestingUserBlocked(BlockReason::Lock)),
+    );
+    assert_eq!(
+      cache.decide(&root, &create_login_context("sudo", Some(1000)), Duration::day(), at(12)),
+      refuse(LoginRefusalReason::RequestingUserBlocked(BlockReason::Lock)),
+    );
+    // Someone else switching, or alex logging in some other way.
+    assert_eq!(cache.decide(&root, &create_login_context("su", Some(1001)), Duration::day(), at(12)), PolicyCacheDecision::Allow);
+    assert_eq!(cache.decide(&root, &create_login_context("sshd", Some(1000)), Duration::day(), at(12)), PolicyCacheDecision::Allow);
+
+    let cache = create_schedule_cache(vec![create_block(None)], LoginPolicy::default());
+    assert_eq!(cache.decide(&root, &su_from_alex, Duration::day(), at(12)), PolicyCacheDecision::Allow);
+  }
+
+  #[test]
+  fn refused_classes_are_refused_unblocked_and_exempt_ones_allowed_blocked() {
+    let alex = create_user_name("alex");
+    let sshd = create_login_context("sshd", None);
+    let gdm = create_login_context("gdm-password", None);
+
+    let cache = create_schedule_cache(Vec::new(), LoginPolicy {
+      refused_classes: vec![LoginClass::Remote],
+      ..LoginPolicy::default()
+    });
+    assert_eq!(
+      cache.decide(&alex, &sshd, Duration::day(), at(12)),
+      refuse(LoginRefusalReason::RefusedLoginClass(LoginClass::Remote)),
+    );
+    assert_eq!(cache.decide(&alex, &gdm, Duration::day(), at(12)), PolicyCacheDecision::Allow);
+
+    let cache = create_schedule_cache(vec![create_block(None)], LoginPolicy {
+      exempt_classes: vec![LoginClass::Remote],
+      ..LoginPolicy::default()
+    });
+    assert_eq!(cache.decide(&alex, &sshd, Duration::day(), at(12)), PolicyCacheDecision::Allow);
+    assert_eq!(
+      cache.decide(&alex, &gdm, Duration::day(), at(12)),
+      refuse(LoginRefusalReason::Blocked(BlockReason::Lock)),
+    );
+  }
+
+  #[test]
+  fn enabled_periods_are_converted_to_the_wall_clock() {
+    let now = Instant::from_timestamp(10 * MILLISECONDS_PER_HOUR as u64);
+    let wall_now = at(12);
+    let wall_at = |hour| Some(at(hour).as_timestamp());
+
+    let cases = [
+      // Enabled until someone pleads.
+      (RuleEnabler::CountdownAfterPlea(CountdownAfterPleaConditional::construct(Duration::HOUR, None)), Some((None, None))),
+      (
+        RuleEnabler::CountdownAfterPlea(CountdownAfterPleaConditional::construct(
+          Duration::HOUR,
+          Some(Countdown::construct(now.saturating_sub(Duration::MINUTE), Duration::HOUR)),
+        )),
+        Some((None, at(12).as_timestamp().checked_add(MILLISECONDS_PER_HOUR - 60 * 1000))),
+      ),
+      (
+        RuleEnabler::CountdownAfterPlea(CountdownAfterPleaConditional::construct(
+          Duration::HOUR,
+          Some(Countdown::construct(now.saturating_sub(Duration::HOUR.saturating_add(Duration::HOUR)), Duration::HOUR)),
+        )),
+        None,
+      ),
+      (
+        RuleEnabler::Countdown(CountdownConditional {
+          duration: Duration::HOUR,
+          countdown: Some(Countdown::construct(now.saturating_add(Duration::HOUR), Duration::HOUR)),
+        }),
+        Some((wall_at(13), wall_at(14))),
+      ),
+      (
+        RuleEnabler::Countdown(CountdownConditional {
+          duration: Duration::HOUR,
+          countdown: None,
+        }),
+        None,
+      ),
+    ];
+
+    for (index, (enabler, period)) in cases.into_iter().enumerate() {
+      assert_eq!(get_enabled_period(&enabler, now, wall_now), period, "case {index}");
+    }
+  }
+}
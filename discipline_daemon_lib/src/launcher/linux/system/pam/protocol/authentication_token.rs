@@ -1,6 +1,88 @@
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
 use serde::{Deserialize, Serialize};
+use crate::x::IsTextualError;
+use super::hmac;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct AuthenticationToken {
   value: String,
-}
\ No newline at end of file
+}
+
+impl AuthenticationToken {
+  // What the packaged configuration ships with, until the install
+  // script replaces it with a random token.
+  pub const PLACEHOLDER: &str = "change-me";
+
+  pub fn as_bytes(&self) -> &[u8] {
+    self.value.as_bytes()
+  }
+
+  // Use this rather than `==` on tokens a client sent.
+  pub fn is_equal_in_constant_time(&self, other: &AuthenticationToken) -> bool {
+    hmac::is_equal_in_constant_time(self.as_bytes(), other.as_bytes())
+  }
+
+  // Fails while the token is the one everyone who has the package
+  // knows.
+  pub fn ensure_not_placeholder(&self, textual_error: &mut impl IsTextualError) -> Result<(), ()> {
+    if self.value.is_empty() || self.value == Self::PLACEHOLDER {
+      textual_error.change_context("Checking the PAM authentication token");
+      textual_error.add_message("The token is empty or still the packaged placeholder, which anyone could send. Set it to a long random string");
+      return Err(());
+    }
+
+    Ok(())
+  }
+
+  // Fails unless the file a token is read from belongs to root, and
+  // nobody else may read or change it. A token anyone can read proves
+  // nothing about who sent it.
+  pub fn ensure_stored_privately(
+    path: &Path,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let metadata = match std::fs::metadata(path) {
+      Ok(value) => {
+        value
+      }
+      Err(error) => {
+        textual_error.change_context("Checking who may read a file holding the PAM authentication token");
+        textual_error.add_message("An io error occured");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Path", path.display());
+        return Err(());
+      }
+    };
+
+    if metadata.uid() != 0 || metadata.mode() & 0o077 != 0 {
+      textual_error.change_context("Checking who may read a file holding the PAM authentication token");
+      textual_error.add_message("The file must belong to root and be accessible to root only, like with mode 0600");
+      textual_error.add_attachement_display("Path", path.display());
+      textual_error.add_attachement_display("Owner user id", metadata.uid());
+      textual_error.add_attachement_display("Mode", format!("{:o}", metadata.mode() & 0o7777));
+      return Err(());
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::x::TextualError;
+  use super::*;
+
+  fn create_token(value: &str) -> AuthenticationToken {
+    AuthenticationToken { value: value.to_string() }
+  }
+
+  #[test]
+  fn the_placeholder_and_empty_tokens_are_refused() {
+    let mut textual_error = TextualError::new("Testing authentication tokens");
+
+    assert!(create_token(AuthenticationToken::PLACEHOLDER).ensure_not_placeholder(&mut textual_error).is_err());
+    assert!(create_token("").ensure_not_placeholder(&mut textual_error).is_err());
+    assert!(create_token("5f0c9a1e77d2b43c").ensure_not_placeholder(&mut textual_error).is_ok());
+  }
+}
@@ -1,405 +1,329 @@
 use std::path::PathBuf;
-use crate::x::IsTextualError;
-use super::{
-  UserNameRef, 
-  BlockingStream, 
-  BufferLength, 
-  BincodeSerializationFormat, 
-  EstablishConnectionRef, 
-  EstablishConnectionReply, 
-  EstablishConnectionError, 
-  IsUserSessionOpenBlockedRef, 
-  ClientMessageRef,
-  IsUserSessionOpenBlockedReply, 
-  UserSessionClosedNotificationRef, 
-  UserSessionOpenedNotificationRef, 
-  AuthenticationToken,
-};
-
-
-// TODO
-pub const MAXIMUM_MESSAGE_LENGTH: BufferLength = BufferLength::create_or_panic(7987);
-
-struct ClientStream {
+use std::time::Instant;
+use crate::x::{Duration, IsTextualError};
+use crate::x::launcher::{LoginContext, LoginRefusal, SessionDeadline, SessionDetails};
+use super::*;
+
+// The PAM module's side of the protocol. It runs inside login, sshd,
+// display managers and the like, so it blocks the calling thread for
+// at most the timeout per message, and never spawns threads or leaves
+// work behind in the host process.
+//
+// It connects on first use, and again after any error. While the
+// daemon can't be reached, connection attempts are spaced out, so
+// that every login doesn't wait out the timeout.
+pub struct ClientConnection {
   path: PathBuf,
-  stream: BlockingStream,
-  closed: bool,
+  authentication_token: AuthenticationToken,
+  timeout: Duration,
+  // Kept after the connection breaks, for reconnecting.
+  stream: Option<BlockingStream>,
+  is_connected: bool,
+  // The process that connected. A child forked from it shares its
+  // socket.
+  process_id: u32,
+  protocol_version: ProtocolVersion,
+  capabilities: Capabilities,
+  reconnect_delay: std::time::Duration,
+  next_connection_attempt: Option<Instant>,
 }
 
-impl ClientStream {
-  pub fn construct(
+impl ClientConnection {
+  const MINIMUM_RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+  const MAXIMUM_RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+
+  // Doesn't connect until the first message.
+  pub fn create(
     path: PathBuf,
-    stream: BlockingStream,
+    authentication_token: AuthenticationToken,
+    timeout: Duration,
   ) -> Self {
     Self {
       path,
-      stream,
-      closed,
+      authentication_token,
+      timeout,
+      stream: None,
+      is_connected: false,
+      process_id: std::process::id(),
+      protocol_version: MINIMUM_PROTOCOL_VERSION,
+      capabilities: Capabilities::default(),
+      reconnect_delay: Self::MINIMUM_RECONNECT_DELAY,
+      next_connection_attempt: None,
     }
   }
 
-  fn ensure_connected(
-    &mut self,
-    path: &Path,
-    textual_error: &mut impl IsTextualError,
-  ) -> Result<(), ()> {
-    if self.closed {
-      self.stream.reconnect(
-        &self.path, 
-        &mut textual_error.change_context("Discipline Linux-PAM Client Stream reconnecting after closing due to an eariler error"),
-      )?;
-    }
-    
-    Ok(())
+  pub fn get_protocol_version(&self) -> ProtocolVersion {
+    self.protocol_version
   }
 
-  fn shutdown(
-    &mut self, 
-    textual_error: &mut impl IsTextualError,
-  ) -> Result<(), ()> {
-    self.stream.shutdown(textual_error)
+  pub fn get_capabilities(&self) -> Capabilities {
+    self.capabilities
   }
 
-  fn write_establish_connection(
-    &mut self, 
-    authentication_token: &AuthenticationToken, 
+  fn ensure_connected(
+    &mut self,
     textual_error: &mut impl IsTextualError,
   ) -> Result<(), ()> {
-    self 
-      .stream 
-      .ensure_connected(
-        &self.path, 
-        &mut textual_error,
-      )?;
-
-    self.stream.write(
-      &EstablishConnectionRef { authentication_token }, 
-      &BincodeSerializationFormat,
-      textual_error,
-    )
-  }
+    // Writing to a socket shared with the parent would mix our messages
+    // with its own, and shutting it down would cut the parent off too,
+    // so a forked child only lets go of its copy.
+    if self.process_id != std::process::id() {
+      self.stream = None;
+      self.is_connected = false;
+      self.process_id = std::process::id();
+      self.reconnect_delay = Self::MINIMUM_RECONNECT_DELAY;
+      self.next_connection_attempt = None;
+    }
 
-  fn read_establish_connection_repl(
-    &mut self,
-    textual_error: &mut impl IsTextualError
-  ) -> Result<EstablishConnectionReply, ()> {
-    if let Err(()) = self.ensure_connected(&self.path, &mut textual_error) {
-      self.shutdown(textual_error)?;
+    if self.is_connected {
+      return Ok(());
+    }
+
+    if let Some(next_connection_attempt) = self.next_connection_attempt
+      && Instant::now() < next_connection_attempt
+    {
+      textual_error.change_context("Discipline Linux-PAM Module Client connecting to the daemon");
+      textual_error.add_message("Connecting failed moments ago, so it wasn't tried again yet");
+      textual_error.add_attachement_display("Path", self.path.display());
       return Err(());
     }
-    
-    let format = BincodeSerializationFormat;
 
-    match self.stream.read(&format, textual_error) {
-      Ok(value) => {
-        Ok(value)
+    match self.connect(textual_error) {
+      Ok(()) => {
+        self.is_connected = true;
+        self.reconnect_delay = Self::MINIMUM_RECONNECT_DELAY;
+        self.next_connection_attempt = None;
+        Ok(())
       }
       Err(()) => {
-        self.shutdown(textual_error)?;
-        return Err(());
+        self.next_connection_attempt = Some(Instant::now() + self.reconnect_delay);
+        self.reconnect_delay = (self.reconnect_delay * 2).min(Self::MAXIMUM_RECONNECT_DELAY);
+        Err(())
       }
     }
   }
 
-  fn write_is_user_session_open_blocked(
+  // Connects and goes through the handshake.
+  fn connect(
     &mut self,
-    user_name: UserNameRef,
     textual_error: &mut impl IsTextualError,
   ) -> Result<(), ()> {
-    if let Err(()) = self .stream .ensure_connected(&self.path, &mut textual_error) {
-      self.shutdown(textual_error)?;
-      return Err(());
-    }
-
-    let format = BincodeSerializationFormat;
-    let message = ClientMessageRef::IsUserSessionOpenBlocked(
-      IsUserSessionOpenBlockedRef { 
-        user_name,
+    let mut stream = match self.stream.take() {
+      Some(mut stream) => {
+        stream.reconnect(&self.path, textual_error)?;
+        stream
       }
-    );
-
-    if let Err(()) = self.stream.write(&message, &format, textual_error) {
-      self.shutdown(textual_error)?;
-      return Err(());
-    }
+      None => {
+        BlockingStream::connect_with_timeout(
+          &self.path,
+          MAXIMUM_MESSAGE_LENGTH,
+          self.timeout,
+          textual_error,
+        )?
+      }
+    };
 
-    Ok(())
-  }
+    let mut textual_error = textual_error
+      .optional_context("Discipline Linux-PAM Module Client establishing a connection");
 
-  fn read_is_user_session_open_blocked_reply(
-    &mut self,
-    textual_error: &mut impl IsTextualError
-  ) -> Result<IsUserSessionOpenBlockedReply, ()> {
-    if let Err(()) = self .stream .ensure_connected(&self.path, &mut textual_error) {
-      self.shutdown(textual_error)?;
-      return Err(());
-    }
+    let message = EstablishConnectionRef {
+      minimum_protocol_version: MINIMUM_PROTOCOL_VERSION,
+      maximum_protocol_version: PROTOCOL_VERSION,
+      capabilities: Capabilities::SUPPORTED,
+      authentication_token: &self.authentication_token,
+    };
 
-    let format = BincodeSerializationFormat;
+    stream.write(&message, &BincodeSerializationFormat, &mut textual_error)?;
+    let reply = stream.read(&BincodeSerializationFormat, &mut textual_error)?;
 
-    match self.stream.read(&format, textual_error) {
-      Ok(value) => {
-        Ok(value)
+    match reply {
+      EstablishConnectionReply::ServerBusy => {
+        textual_error.add_message("The daemon is serving as many connections as it can");
+        Err(())
       }
-      Err(()) => {
-        self.shutdown(textual_error)?;
+      EstablishConnectionReply::UnrecognizedAuthenticationToken => {
+        textual_error.add_message("The daemon didn't recognize the authentication token");
+        Err(())
+      }
+      EstablishConnectionReply::UnsupportedVersion { minimum_protocol_version, maximum_protocol_version } => {
+        textual_error.add_message("The daemon speaks none of the protocol versions this module does");
+        textual_error.add_attachement_display("Daemon's minimum protocol version", minimum_protocol_version);
+        textual_error.add_attachement_display("Daemon's maximum protocol version", maximum_protocol_version);
+        textual_error.add_attachement_display("Module's minimum protocol version", MINIMUM_PROTOCOL_VERSION);
+        textual_error.add_attachement_display("Module's maximum protocol version", PROTOCOL_VERSION);
         Err(())
       }
+      EstablishConnectionReply::ConnectionEstablished { protocol_version, capabilities } => {
+        self.stream = Some(stream);
+        self.protocol_version = protocol_version;
+        self.capabilities = capabilities;
+        Ok(())
+      }
     }
   }
 
-  fn write_user_session_opened_notification(
-    &mut self, 
-    user_name: UserNameRef,
+  fn send(
+    &mut self,
+    message: &ClientMessageRef,
     textual_error: &mut impl IsTextualError,
   ) -> Result<(), ()> {
-    if let Err(()) = self .stream .ensure_connected(&self.path, &mut textual_error) {
-      self.shutdown(textual_error)?;
-      return Err(());
-    }
+    self.ensure_connected(textual_error)?;
 
-    let format = BincodeSerializationFormat;
+    let Some(stream) = self.stream.as_mut() else {
+      return Err(());
+    };
 
-    let message = ClientMessageRef::UserSessionOpenedNotification(
-      UserSessionOpenedNotificationRef {
-        user_name,
-      }
-    );
-    
-    if let Err(()) = self.stream.write(&message, &format, textual_error) {
-      self.shutdown(textual_error)?;
+    // A stream that failed may be half way through a message, so the
+    // next one starts over on a new connection.
+    if let Err(()) = stream.write(message, &BincodeSerializationFormat, textual_error) {
+      self.is_connected = false;
       return Err(());
     }
 
     Ok(())
   }
 
-  fn write_user_session_closed_notification(
-    &mut self, 
-    user_name: UserNameRef,
+  fn request<Reply: IsDeserializable>(
+    &mut self,
+    message: &ClientMessageRef,
     textual_error: &mut impl IsTextualError,
-  ) -> Result<(), ()> {
-    if let Err(()) = self .stream .ensure_connected(&self.path, &mut textual_error) {
-      self.shutdown(textual_error)?;
-      return Err(());
-    }
+  ) -> Result<Reply, ()> {
+    self.send(message, textual_error)?;
 
-    let format = BincodeSerializationFormat;
-    let message = ClientMessageRef::UserSessionClosedNotification(
-      UserSessionClosedNotificationRef {
-        user_name,
-      }
-    );
-
-    if let Err(()) = self.stream.write(&message, &format, textual_error) {
-      self.shutdown(textual_error)?;
+    let Some(stream) = self.stream.as_mut() else {
       return Err(());
-    }
-
-    Ok(())
-  }
-}
-
-// struct ClientStream {
-//   stream: BlockingStream,
-// }
-
-// impl ClientStream {
-//   pub fn construct(stream: BlockingStream) -> Self {
-//     Self { stream }
-//   }
-
-//   fn write_establish_connection(
-//     &mut self, 
-//     authentication_token: &AuthenticationToken, 
-//     textual_error: &mut impl IsTextualError,
-//   ) -> Result<(), ()> {
-//     self.stream.write(
-//       &EstablishConnectionRef { authentication_token }, 
-//       &BincodeSerializationFormat,
-//       textual_error,
-//     )
-//   }
-
-//   fn read_establish_connection_repl(
-//     &mut self,
-//     textual_error: &mut impl IsTextualError
-//   ) -> Result<EstablishConnectionReply, ()> {
-//     self.stream.read(
-//       &BincodeSerializationFormat,
-//       textual_error,
-//     )
-//   }
-
-//   fn write_is_user_session_open_blocked(
-//     &mut self,
-//     user_name: UserNameRef,
-//     textual_error: &mut impl IsTextualError,
-//   ) -> Result<(), ()> {
-//     self.stream.write(
-//       &ClientMessageRef::IsUserSessionOpenBlocked(
-//         IsUserSessionOpenBlockedRef { 
-//           user_name,
-//         }
-//       ),
-//       &BincodeSerializationFormat,
-//       textual_error,
-//     )    
-//   }
-
-//   fn read_is_user_session_open_blocked_reply(
-//     &mut self,
-//     textual_error: &mut impl IsTextualError
-//   ) -> Result<IsUserSessionOpenBlockedReply, ()> {
-//     self.stream.read(
-//       &BincodeSerializationFormat,
-//       textual_error,
-//     )
-//   }
-
-//   fn write_user_session_opened_notification(
-//     &mut self, 
-//     user_name: UserNameRef,
-//     textual_error: &mut impl IsTextualError,
-//   ) -> Result<(), ()> {
-//     self.stream.write(
-//       &ClientMessageRef::UserSessionOpenedNotification(
-//         UserSessionOpenedNotificationRef {
-//           user_name,
-//         }
-//       ),
-//       &BincodeSerializationFormat,
-//       textual_error,
-//     )
-//   }
-
-//   fn write_user_session_closed_notification(
-//     &mut self, 
-//     user_name: UserNameRef,
-//     textual_error: &mut impl IsTextualError,
-//   ) -> Result<(), ()> {
-//     self.stream.write(
-//       &ClientMessageRef::UserSessionClosedNotification(
-//         UserSessionClosedNotificationRef {
-//           user_name,
-//         }
-//       ),
-//       &BincodeSerializationFormat,
-//       textual_error,
-//     )
-//   }
-
-//   fn ensure_connected(
-//     &mut self,
-//     path: &Path,
-//     textual_error: &mut impl IsTextualError,
-//   ) -> Result<(), ()> {
-//     self.stream.reconnect(path, textual_error)
-//   }
-// }
-
-pub struct ClientConnection {
-  path: PathBuf,
-  stream: ClientStream,
-  closed: bool,
-}
-
-impl ClientConnection {
-  pub fn connect(
-    path: PathBuf,
-    authentication_token: &AuthenticationToken, 
-    textual_error: &mut impl IsTextualError,
-  ) -> Result<Self, EstablishConnectionError> {
-    let stream = match BlockingStream::connect(
-      path, 
-      maximum_buffer_length, 
-      textual_error,
-    ) {
-      Ok(value) => {
-        value
-      }
-      Err(()) => {
-        return Err(EstablishConnectionError::Other);
-      }
     };
 
-    let mut stream = ClientStream::construct(
-      path, 
-      stream,
-    );
-    
-    if let Err(()) = stream.write_establish_connection(authentication_token, textual_error) {
-      return Err(EstablishConnectionError::Other);
-    }
-
-    let reply = match stream.read_establish_connection_repl(textual_error) {
-      Ok(value) => {
-        value
+    match stream.read(&BincodeSerializationFormat, textual_error) {
+      Ok(reply) => {
+        Ok(reply)
       }
       Err(()) => {
-        return Err(EstablishConnectionError::Other);
-      }
-    };
-
-    match reply {
-      EstablishConnectionReply::ServerBusy => {
-        Err(EstablishConnectionError::ServerBusy)
-      }
-      EstablishConnectionReply::UnrecognizedAuthenticationToken => {
-        Err(EstablishConnectionError::UnrecognizedAuthenticationToken)
-      }
-      EstablishConnectionReply::ConnectionEstablished => {
-        Ok(ClientConnection { 
-          stream,
-          path,
-          closed: false,
-        })
+        self.is_connected = false;
+        Err(())
       }
     }
   }
 
-  pub fn is_user_session_open_blocked(
-    &mut self, 
+  pub fn get_login_refusal(
+    &mut self,
     user_name: UserNameRef,
+    login_context: &LoginContext,
     textual_error: &mut impl IsTextualError,
-  ) -> Result<bool, ()> {
+  ) -> Result<Option<LoginRefusal>, ()> {
     let mut textual_error = textual_error
-      .optional_context("Discipline Linux-PAM Module Client sending an IsUserSessionOpenPermitted message");
+      .optional_context("Discipline Linux-PAM Module Client sending an IsUserSessionOpenBlocked message");
 
-    self
-      .stream
-      .write_is_user_session_open_blocked(user_name, &mut textual_error)?;
-
-    let reply = self
-      .stream
-      .read_is_user_session_open_blocked_reply(&mut textual_error)?;
+    let message = ClientMessageRef::IsUserSessionOpenBlocked(
+      IsUserSessionOpenBlockedRef {
+        user_name,
+        login_context,
+      }
+    );
 
-    Ok(reply.is_user_session_open_blocked)
+    let reply: IsUserSessionOpenBlockedReply = self.request(&message, &mut textual_error)?;
+    Ok(reply.refusal)
   }
 
   pub fn send_user_session_opened_notification(
-    &mut self, 
+    &mut self,
     user_name: UserNameRef,
+    details: &SessionDetails,
     textual_error: &mut impl IsTextualError,
   ) -> Result<(), ()> {
     let mut textual_error = textual_error
-      .optional_context("Discipline Linux-PAM Module Client sending a UserSessionOpenedNotification");
+      .optional_context("Discipline Linux-PAM Module Client sending a session opened notification");
+
+    self.ensure_connected(&mut textual_error)?;
+
+    if !self.capabilities.contains(Capabilities::SESSION_NOTIFICATIONS) {
+      return Ok(());
+    }
+
+    // Daemons without the registry would fail to read the details.
+    let message = if self.capabilities.contains(Capabilities::SESSION_REGISTRY) {
+      ClientMessageRef::SessionOpenedNotification(
+        SessionOpenedNotificationRef {
+          user_name,
+          details,
+        }
+      )
+    } else {
+      ClientMessageRef::UserSessionOpenedNotification(
+        UserSessionOpenedNotificationRef {
+          user_name,
+        }
+      )
+    };
 
-    self
-      .stream
-      .write_user_session_opened_notification(user_name, &mut textual_error) 
+    self.send(&message, &mut textual_error)
   }
 
   pub fn send_user_session_closed_notification(
-    &mut self, 
+    &mut self,
     user_name: UserNameRef,
+    details: &SessionDetails,
     textual_error: &mut impl IsTextualError,
   ) -> Result<(), ()> {
     let mut textual_error = textual_error
-      .optional_context("Discipline Linux-PAM Module Client sending a UserSessionClosedNotification");
+      .optional_context("Discipline Linux-PAM Module Client sending a session closed notification");
+
+    self.ensure_connected(&mut textual_error)?;
+
+    if !self.capabilities.contains(Capabilities::SESSION_NOTIFICATIONS) {
+      return Ok(());
+    }
 
-    self
-      .stream
-      .write_user_session_opened_notification(user_name, &mut textual_error) 
+    let message = if self.capabilities.contains(Capabilities::SESSION_REGISTRY) {
+      ClientMessageRef::SessionClosedNotification(
+        SessionClosedNotificationRef {
+          user_name,
+          details,
+        }
+      )
+    } else {
+      ClientMessageRef::UserSessionClosedNotification(
+        UserSessionClosedNotificationRef {
+          user_name,
+        }
+      )
+    };
+
+    self.send(&message, &mut textual_error)
   }
-}
\ No newline at end of file
+
+  // Ok(None) when the user isn't regulated, the session's kind of login
+  // is exempt from blocks, or the daemon is too old to tell. Daemons
+  // that don't take `login_context` give the user's deadline.
+  pub fn get_session_deadline(
+    &mut self,
+    user_name: UserNameRef,
+    login_context: &LoginContext,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Option<SessionDeadline>, ()> {
+    let mut textual_error = textual_error
+      .optional_context("Discipline Linux-PAM Module Client sending a GetSessionDeadline message");
+
+    self.ensure_connected(&mut textual_error)?;
+
+    let message = if self.capabilities.contains(Capabilities::LOGIN_SESSION_DEADLINES) {
+      ClientMessageRef::GetLoginSessionDeadline(
+        GetLoginSessionDeadlineRef {
+          user_name,
+          login_context,
+        }
+      )
+    } else if self.capabilities.contains(Capabilities::SESSION_DEADLINES) {
+      ClientMessageRef::GetSessionDeadline(
+        GetSessionDeadlineRef {
+          user_name,
+        }
+      )
+    } else {
+      return Ok(None);
+    };
+
+    let reply: GetSessionDeadlineReply = self.request(&message, &mut textual_error)?;
+    Ok(reply.deadline)
+  }
+}
@@ -1,19 +1,124 @@
 use serde::{Serialize, Deserialize};
-use super::{UserName, UserNameRef, AuthenticationToken};
+use super::{UserName, UserNameRef, AuthenticationToken, BufferLength};
+use crate::x::launcher::{LoginContext, LoginRefusal, SessionDeadline, SessionDetails};
+
+// Room for the largest message either side sends: a user name and a
+// login context, or a refusal. The module and the daemon must agree.
+pub const MAXIMUM_MESSAGE_LENGTH: BufferLength = BufferLength::create_or_panic(8192);
+
+// Compatibility policy
+//
+// Adding versions to the handshake was a flag-day break: modules and
+// daemons from before it send and expect a bare authentication token,
+// and refuse messages with bytes left over, so neither side can read
+// the other's handshake. They must be upgraded together, and a module
+// already loaded in a long-running process won't connect until that
+// process restarts.
+//
+// From protocol version 1 on, the handshake below is the one part of
+// the protocol whose layout never changes, so any module and any daemon
+// can at least agree on whether they can talk. Each side names the
+// range of protocol versions it speaks, and the daemon picks the
+// highest one both do, or replies `UnsupportedVersion`. Messages after
+// the handshake are in the agreed version.
+//
+// A new version is only needed when an existing message changes shape.
+// Additions that a side may ignore go in as capabilities instead, and
+// new enum variants are only ever appended, since bincode numbers them
+// in order. A daemon keeps speaking the versions of the modules it may
+// find loaded in long-running processes like sshd and gdm until those
+// have had a release to be restarted.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion(1);
+pub const MINIMUM_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ProtocolVersion(pub u16);
+
+impl std::fmt::Display for ProtocolVersion {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(formatter, "{}", self.0)
+  }
+}
+
+impl ProtocolVersion {
+  // The highest version both ranges include.
+  pub fn negotiate(
+    our_minimum: ProtocolVersion,
+    our_maximum: ProtocolVersion,
+    their_minimum: ProtocolVersion,
+    their_maximum: ProtocolVersion,
+  ) -> Option<ProtocolVersion> {
+    let version = our_maximum.min(their_maximum);
+
+    if version >= our_minimum && version >= their_minimum {
+      Some(version)
+    } else {
+      None
+    }
+  }
+}
+
+// Optional features, as bits, so a side can name ones the other doesn't
+// know about yet. Unknown bits are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Capabilities(pub u64);
+
+impl Capabilities {
+  // The daemon records UserSessionOpenedNotification and
+  // UserSessionClosedNotification, so they're worth sending.
+  pub const SESSION_NOTIFICATIONS: Capabilities = Capabilities(1 << 0);
+
+  // The daemon keeps a registry of sessions and understands
+  // SessionOpenedNotification and SessionClosedNotification, which say
+  // which session it is. Older daemons only get the user's name.
+  pub const SESSION_REGISTRY: Capabilities = Capabilities(1 << 1);
+
+  // The daemon answers GetSessionDeadline.
+  pub const SESSION_DEADLINES: Capabilities = Capabilities(1 << 2);
+
+  // The daemon answers GetLoginSessionDeadline, which says what kind of
+  // login the session is, so sessions exempt from blocks get none.
+  pub const LOGIN_SESSION_DEADLINES: Capabilities = Capabilities(1 << 3);
+
+  pub const SUPPORTED: Capabilities = Capabilities(
+    Self::SESSION_NOTIFICATIONS.0 
+      | Self::SESSION_REGISTRY.0 
+      | Self::SESSION_DEADLINES.0 
+      | Self::LOGIN_SESSION_DEADLINES.0
+  );
+
+  pub fn intersection(self, other: Capabilities) -> Capabilities {
+    Capabilities(self.0 & other.0)
+  }
+
+  pub fn contains(self, other: Capabilities) -> bool {
+    self.0 & other.0 == other.0
+  }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EstablishConnection {
+  pub minimum_protocol_version: ProtocolVersion,
+  pub maximum_protocol_version: ProtocolVersion,
+  pub capabilities: Capabilities,
   pub authentication_token: AuthenticationToken,
 }
 
 #[derive(Debug, Serialize)]
 pub struct EstablishConnectionRef<'a> {
+  pub minimum_protocol_version: ProtocolVersion,
+  pub maximum_protocol_version: ProtocolVersion,
+  pub capabilities: Capabilities,
   pub authentication_token: &'a AuthenticationToken,
 }
 
 pub enum EstablishConnectionError {
   ServerBusy,
   UnrecognizedAuthenticationToken,
+  UnsupportedVersion {
+    minimum_protocol_version: ProtocolVersion,
+    maximum_protocol_version: ProtocolVersion,
+  },
   Other,
 }
 
@@ -21,7 +126,17 @@ pub enum EstablishConnectionError {
 pub enum EstablishConnectionReply {
   ServerBusy,
   UnrecognizedAuthenticationToken,
-  ConnectionEstablished,
+  ConnectionEstablished {
+    protocol_version: ProtocolVersion,
+    // The ones both sides support.
+    capabilities: Capabilities,
+  },
+  // The daemon speaks none of the client's versions. It names its own
+  // range, for the client to log.
+  UnsupportedVersion {
+    minimum_protocol_version: ProtocolVersion,
+    maximum_protocol_version: ProtocolVersion,
+  },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -44,19 +159,75 @@ pub struct UserSessionClosedNotificationRef<'a> {
   pub user_name: UserNameRef<'a>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionOpenedNotification {
+  pub user_name: UserName,
+  pub details: SessionDetails,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionOpenedNotificationRef<'a> {
+  pub user_name: UserNameRef<'a>,
+  pub details: &'a SessionDetails,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionClosedNotification {
+  pub user_name: UserName,
+  pub details: SessionDetails,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionClosedNotificationRef<'a> {
+  pub user_name: UserNameRef<'a>,
+  pub details: &'a SessionDetails,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct IsUserSessionOpenBlocked {
   pub user_name: UserName,
+  pub login_context: LoginContext,
 }
 
 #[derive(Debug, Serialize)]
 pub struct IsUserSessionOpenBlockedRef<'a> {
   pub user_name: UserNameRef<'a>,
+  pub login_context: &'a LoginContext,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct IsUserSessionOpenBlockedReply {
-  pub is_user_session_open_blocked: bool,
+  // None when the login is allowed.
+  pub refusal: Option<LoginRefusal>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetSessionDeadline {
+  pub user_name: UserName,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetSessionDeadlineRef<'a> {
+  pub user_name: UserNameRef<'a>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetLoginSessionDeadline {
+  pub user_name: UserName,
+  pub login_context: LoginContext,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetLoginSessionDeadlineRef<'a> {
+  pub user_name: UserNameRef<'a>,
+  pub login_context: &'a LoginContext,
+}
+
+// The reply to both GetSessionDeadline and GetLoginSessionDeadline.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetSessionDeadlineReply {
+  // None when the user isn't regulated.
+  pub deadline: Option<SessionDeadline>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -64,6 +235,10 @@ pub enum ClientMessage {
   UserSessionOpenedNotification(UserSessionOpenedNotification),
   UserSessionClosedNotification(UserSessionClosedNotification),
   IsUserSessionOpenBlocked(IsUserSessionOpenBlocked),
+  SessionOpenedNotification(SessionOpenedNotification),
+  SessionClosedNotification(SessionClosedNotification),
+  GetSessionDeadline(GetSessionDeadline),
+  GetLoginSessionDeadline(GetLoginSessionDeadline),
 }
 
 #[derive(Debug, Serialize)]
@@ -71,9 +246,14 @@ pub enum ClientMessageRef<'a> {
   UserSessionOpenedNotification(UserSessionOpenedNotificationRef<'a>),
   UserSessionClosedNotification(UserSessionClosedNotificationRef<'a>),
   IsUserSessionOpenBlocked(IsUserSessionOpenBlockedRef<'a>),
+  SessionOpenedNotification(SessionOpenedNotificationRef<'a>),
+  SessionClosedNotification(SessionClosedNotificationRef<'a>),
+  GetSessionDeadline(GetSessionDeadlineRef<'a>),
+  GetLoginSessionDeadline(GetLoginSessionDeadlineRef<'a>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ServerMessage {
   IsUserSessionOpenBlockedReply(IsUserSessionOpenBlockedReply),
+  GetSessionDeadlineReply(GetSessionDeadlineReply),
 }
@@ -3,15 +3,17 @@ use super::*;
 mod authentication_token;
 pub use authentication_token::AuthenticationToken;
 
-// mod messages;
-// pub use messages::*;
+mod messages;
+pub use messages::*;
 
-// mod client_connection;
-// pub use client_connection::ClientConnection;
+mod client_connection;
+pub use client_connection::ClientConnection;
 
 mod server;
 pub use server::Server;
-// mod server_connection;
 
-// mod serialization;
-// use serialization::BincodeSerializationFormat;
\ No newline at end of file
+mod server_connection;
+use server_connection::*;
+
+mod serialization;
+use serialization::BincodeSerializationFormat;
\ No newline at end of file
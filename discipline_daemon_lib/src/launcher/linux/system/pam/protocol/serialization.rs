@@ -1,6 +1,16 @@
-use crate::launcher::linux::pam::stream::{IsSerializationFormat, IsDeserializable, IsSerializable};
+use std::any::type_name;
+use bincode::config::{BigEndian, Configuration, Fixint};
+use crate::x::IsTextualError;
+use super::{IsSerializationFormat, IsDeserializable, IsSerializable};
 
-pub struct BincodeSerializationFormat {}
+// Big endian, fixed size integers, so the module and the daemon agree
+// on the layout whatever bincode's defaults become. Message sizes are
+// limited by the streams' buffers.
+const BINCODE_CONFIGURATION: Configuration<BigEndian, Fixint> = bincode::config::standard()
+  .with_big_endian()
+  .with_fixed_int_encoding();
+
+pub struct BincodeSerializationFormat;
 
 impl IsSerializationFormat for BincodeSerializationFormat {
   fn deserialize<T>(
@@ -11,7 +21,26 @@ impl IsSerializationFormat for BincodeSerializationFormat {
   where
     T: IsDeserializable 
   {
-    todo!()
+    match bincode::serde::decode_from_slice(buffer, BINCODE_CONFIGURATION) {
+      Ok((value, read_length)) if read_length == buffer.len() => {
+        Ok(value)
+      }
+      Ok((_, read_length)) => {
+        textual_error.change_context("Deserializing a value using bincode");
+        textual_error.add_message("The buffer has bytes left over after the value");
+        textual_error.add_attachement_display("Value type name", type_name::<T>());
+        textual_error.add_attachement_display("Buffer length", buffer.len());
+        textual_error.add_attachement_display("Read length", read_length);
+        Err(())
+      }
+      Err(error) => {
+        textual_error.change_context("Deserializing a value using bincode");
+        textual_error.add_message("Bincode failed to deserialize the value");
+        textual_error.add_attachement_display("Value type name", type_name::<T>());
+        textual_error.add_attachement_display("Bincode error", error);
+        Err(())
+      }
+    }
   }
 
   fn serialize(
@@ -21,6 +50,16 @@ impl IsSerializationFormat for BincodeSerializationFormat {
     textual_error: &mut impl IsTextualError,
   ) -> Result<usize, ()> 
   {
-    todo!()
+    match bincode::serde::encode_into_slice(value, buffer, BINCODE_CONFIGURATION) {
+      Ok(length) => {
+        Ok(length)
+      }
+      Err(error) => {
+        textual_error.change_context("Serializing a value using bincode");
+        textual_error.add_message("Bincode failed to serialize the value");
+        textual_error.add_attachement_display("Bincode error", error);
+        Err(())
+      }
+    }
   }
 }
@@ -1,104 +1,478 @@
-use std::path::Path;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::Semaphore;
 use tokio::net::UnixListener;
-use tokio::spawn;
-use crate::x::IsTextualError;
-// use crate::{IsTextualError, OptionalTextualErrorContext, launcher::linux::pam::protocol::client_connection::MAXIMUM_MESSAGE_LENGTH};
+use tokio::sync::{Mutex, Semaphore, watch};
+use tokio::task::JoinSet;
+use crate::x::{IsTextualError, TextualError};
 use super::*;
 
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 pub struct Server {
-  // listener: UnixListener,
-  // semaphore: Arc<Semaphore>,
-  // authentication_token: AuthenticationToken,
-  // maximum_message_length: BufferLength,
-  // maximum_concurrent_connections: usize,
+  listener: UnixListener,
+  path: PathBuf,
+  semaphore: Arc<Semaphore>,
+  authentication_token: AuthenticationToken,
+  connections: JoinSet<()>,
 }
 
 impl Server {
+  const MAXIMUM_CONCURRENT_CONNECTIONS: usize = 16;
+
   pub async fn new(
     path: impl AsRef<Path>,
     authentication_token: AuthenticationToken,
     textual_error: &mut impl IsTextualError,
   ) -> Result<Self, ()> {
-    todo!()
-    // let mut textual_error = textual_error.optional_context("Creating Discipline Linux-PAM Module Server");
-
-    // let listener = match UnixListener::bind(path) {
-    //   Ok(value) => {
-    //     value
-    //   }
-    //   Err(error) => {
-    //     textual_error.add_message("An io error occured while binding the UnixListener");
-    //     textual_error.add_attachement_display("Io error", error);
-    //     return Err(());
-    //   }
-    // };
-      
-    // Ok(Self { 
-    //   listener,
-    //   semaphore: Arc::new(Semaphore::const_new(maximum_concurrent_connections)),
-    //   authentication_token,
-    //   maximum_message_length: MAXIMUM_MESSAGE_LENGTH,
-    //   maximum_concurrent_connections: 3,
-    // })
-  }
-
-  pub async fn start_auto_processing(&mut self, daemon: Arc<Daemon>) {
-    // loop {
-    //   let mut textual_error = OptionalTextualErrorContext::new("Discipline linux-PAM Module Server processing incoming connections");
-
-    //   let semaphore = Arc::clone(&self.semaphore);
-
-    //   let permit = match semaphore.acquire_owned().await {
-    //     Ok(value) => {
-    //       value
-    //     }
-    //     Err(error) => {
-    //       let mut textual_error = textual_error.optional_context("Aquiring a permit from the server's semaphore");
-    //       textual_error.add_attachement_display("Error", error);
-    //       // TODOL Use a proper loggin mechanism.
-    //       eprintln!("{textual_error}");
-          
-    //       // Aquiring a permit can only fail if the semaphore is closed, 
-    //       // which shouldn't do before we close the server. So, this is
-    //       // a fatal error.
-    //       return;
-    //     }
-    //   };
-
-    //   let connection = match self.listener.accept().await {
-    //     Ok(value) => {
-    //       value.0
-    //     }
-    //     Err(error) => {
-    //       let mut textaul_error = textual_error.optional_context("Accepting a new connection");
-    //       textaul_error.add_message("An io error occured");
-    //       textaul_error.add_attachement_display("Io error", error);
-
-    //       // TODO: Use a proper logging mechanism.
-    //       eprintln!("{textaul_error}");
-
-    //       // Since this isn't a fatal error, continue processing incoming connections.
-    //       continue;
-    //     }
-    //   };
-
-    //   let stream = ServerStream::construct(AsyncStream::construct(
-    //     connection, 
-    //     self.maximum_message_length,
-    //   ));
-
-    //   let Ok(mut connection) = ServerConnection::establish(stream, &self.authentication_token, &mut textual_error).await else {
-    //     continue;
-    //   };
-
-    //   let daemon = Arc::clone(&daemon);
-    //   // TODO: This will panic because the async runtime isn't configured globally. FIX.
-    //   spawn(async move {
-    //     connection.start_auto_processing(daemon).await;
-    //     drop(permit);
-    //   });
-    // }
+    let path = path.as_ref();
+
+    // A socket file left behind by a previous run makes binding fail.
+    match fs::remove_file(path) {
+      Ok(()) => {}
+      Err(error) if error.kind() == ErrorKind::NotFound => {}
+      Err(error) => {
+        textual_error.change_context("Creating Discipline Linux-PAM Module Server");
+        textual_error.add_message("Failed to remove a stale socket file");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Path", path.display());
+        return Err(());
+      }
+    }
+
+    let listener = bind_privately(path, textual_error)?;
+
+    Ok(Self {
+      listener,
+      path: path.to_path_buf(),
+      semaphore: Arc::new(Semaphore::new(Self::MAXIMUM_CONCURRENT_CONNECTIONS)),
+      authentication_token,
+      connections: JoinSet::new(),
+    })
+  }
+
+  // Accepts connections until `shutdown` changes, then waits for the
+  // connections that are still open to finish.
+  pub async fn serve(
+    mut self, 
+    daemon: Arc<Mutex<Daemon>>,
+    mut shutdown: watch::Receiver<bool>,
+  ) {
+    loop {
+      let connection = tokio::select! {
+        _ = shutdown.changed() => {
+          break;
+        }
+        connection = self.listener.accept() => {
+          match connection {
+            Ok((connection, _)) => {
+              connection
+            }
+            Err(error) => {
+              // TODO: Use a proper logging mechanism.
+              eprintln!("Discipline Linux-PAM Module Server: Failed to accept a connection: {error}");
+              continue;
+            }
+          }
+        }
+      };
+
+      // Clients are told right away rather than left waiting for a
+      // slot, so that their failure policy decides without delay.
+      let Ok(permit) = Arc::clone(&self.semaphore).try_acquire_owned() else {
+        self.connections.spawn(reply_busy(connection));
+        continue;
+      };
+
+      let daemon = Arc::clone(&daemon);
+      let authentication_token = self.authentication_token.clone();
+      let shutdown = shutdown.clone();
+
+      self.connections.spawn(async move {
+        handle_connection(connection, daemon, authentication_token, shutdown).await;
+        drop(permit);
+      });
+
+      // Reap connections that already finished.
+      while self.connections.try_join_next().is_some() {}
+    }
+
+    while self.connections.join_next().await.is_some() {}
+    let _ = fs::remove_file(&self.path);
+  }
+}
+
+// Binds the socket inside a directory only root may enter, and moves it
+// into place once it's root's and 0600, so that there's never a moment
+// where others could connect.
+fn bind_privately(
+  path: &Path,
+  textual_error: &mut impl IsTextualError,
+) -> Result<UnixListener, ()> {
+  let Some(file_name) = path.file_name() else {
+    textual_error.change_context("Creating Discipline Linux-PAM Module Server");
+    textual_error.add_message("The socket path doesn't name a file");
+    textual_error.add_attachement_display("Path", path.display());
+    return Err(());
+  };
+
+  let mut staging_directory_name = std::ffi::OsString::from(".");
+  staging_directory_name.push(file_name);
+  staging_directory_name.push(".staging");
+  let staging_directory = path.with_file_name(staging_directory_name);
+  let staging_path = staging_directory.join(file_name);
+
+  // Left behind if a previous run was killed while binding.
+  match fs::remove_dir_all(&staging_directory) {
+    Ok(()) => {}
+    Err(error) if error.kind() == ErrorKind::NotFound => {}
+    Err(error) => {
+      textual_error.change_context("Creating Discipline Linux-PAM Module Server");
+      textual_error.add_message("Failed to remove a stale staging directory");
+      textual_error.add_attachement_display("Io error", error);
+      textual_error.add_attachement_display("Path", staging_directory.display());
+      return Err(());
+    }
+  }
+
+  if let Err(error) = fs::DirBuilder::new().mode(0o700).create(&staging_directory) {
+    textual_error.change_context("Creating Discipline Linux-PAM Module Server");
+    textual_error.add_message("Failed to create the staging directory");
+    textual_error.add_attachement_display("Io error", error);
+    textual_error.add_attachement_display("Path", staging_directory.display());
+    return Err(());
+  }
+
+  let listener = bind_in_staging_directory(&staging_path, path, textual_error);
+  let _ = fs::remove_dir_all(&staging_directory);
+  listener
+}
+
+fn bind_in_staging_directory(
+  staging_path: &Path,
+  path: &Path,
+  textual_error: &mut impl IsTextualError,
+) -> Result<UnixListener, ()> {
+  let listener = match UnixListener::bind(staging_path) {
+    Ok(value) => {
+      value
+    }
+    Err(error) => {
+      textual_error.change_context("Creating Discipline Linux-PAM Module Server");
+      textual_error.add_message("An io error occured while binding the UnixListener");
+      textual_error.add_attachement_display("Io error", error);
+      textual_error.add_attachement_display("Path", staging_path.display());
+      return Err(());
+    }
+  };
+
+  // Only root, which the PAM module runs as, may connect. The daemon
+  // runs as root too, so this only matters when the socket's directory
+  // gives new files another group.
+  if let Err(error) = std::os::unix::fs::chown(staging_path, Some(0), Some(0)) {
+    textual_error.change_context("Creating Discipline Linux-PAM Module Server");
+    textual_error.add_message("Failed to give the socket file to root");
+    textual_error.add_attachement_display("Io error", error);
+    textual_error.add_attachement_display("Path", staging_path.display());
+    return Err(());
+  }
+
+  if let Err(error) = fs::set_permissions(staging_path, fs::Permissions::from_mode(0o600)) {
+    textual_error.change_context("Creating Discipline Linux-PAM Module Server");
+    textual_error.add_message("Failed to restrict the socket file's permissions");
+    textual_error.add_attachement_display("Io error", error);
+    textual_error.add_attachement_display("Path", staging_path.display());
+    return Err(());
+  }
+
+  if let Err(error) = fs::rename(staging_path, path) {
+    textual_error.change_context("Creating Discipline Linux-PAM Module Server");
+    textual_error.add_message("Failed to move the socket file into place");
+    textual_error.add_attachement_display("Io error", error);
+    textual_error.add_attachement_display("Path", path.display());
+    return Err(());
+  }
+
+  Ok(listener)
+}
+
+async fn reply_busy(connection: tokio::net::UnixStream) {
+  let mut textual_error = TextualError::new("Discipline Linux-PAM Module Server turning a connection away");
+  let mut stream = ServerStream::construct(AsyncStream::construct(connection, MAXIMUM_MESSAGE_LENGTH));
+
+  let reply = stream.write_establish_connection_reply(EstablishConnectionReply::ServerBusy, &mut textual_error);
+  if let Ok(Err(())) = tokio::time::timeout(HANDSHAKE_TIMEOUT, reply).await {
+    // TODO: Use a proper logging mechanism.
+    eprintln!("{textual_error}");
+  }
+}
+
+async fn handle_connection(
+  connection: tokio::net::UnixStream,
+  daemon: Arc<Mutex<Daemon>>,
+  authentication_token: AuthenticationToken,
+  shutdown: watch::Receiver<bool>,
+) {
+  let mut textual_error = TextualError::new("Discipline Linux-PAM Module Server serving a connection");
+
+  // The kernel vouches for who connected, which the token alone can't:
+  // the module only ever runs as root, inside login, sshd, su and the
+  // like. Screen lockers that run as the user they unlock are turned
+  // away, and the module's failure policy decides for them.
+  let peer = match PeerIdentity::get(&connection) {
+    Ok(peer) => {
+      peer
+    }
+    Err(error) => {
+      // TODO: Use a proper logging mechanism.
+      eprintln!("Discipline Linux-PAM Module Server: Failed to read a connection's credentials: {error}");
+      return;
+    }
+  };
+
+  if peer.user_id != 0 {
+    // TODO: Use a proper logging mechanism.
+    eprintln!("Discipline Linux-PAM Module Server: Refused a connection from a process not running as root: {peer}");
+    return;
+  }
+
+  textual_error.add_attachement_display("Peer", &peer);
+
+  let stream = ServerStream::construct(AsyncStream::construct(connection, MAXIMUM_MESSAGE_LENGTH));
+
+  // A client that connects and never introduces itself would otherwise
+  // hold one of the connection slots forever.
+  let connection = tokio::time::timeout(
+    HANDSHAKE_TIMEOUT,
+    ServerConnection::establish(stream, &authentication_token, &mut textual_error),
+  ).await;
+
+  let mut connection = match connection {
+    Ok(Ok(connection)) => {
+      connection
+    }
+    Ok(Err(())) => {
+      // TODO: Use a proper logging mechanism.
+      eprintln!("{textual_error}");
+      return;
+    }
+    Err(_) => {
+      // TODO: Use a proper logging mechanism.
+      eprintln!("Discipline Linux-PAM Module Server: A client didn't complete the handshake in time");
+      return;
+    }
+  };
+
+  connection.start_auto_processing(daemon, shutdown).await;
+}
+
+// Who is on the other end of a connection, as the kernel saw it when
+// they connected. Logged when they're refused, and attached to errors
+// from serving them, so those can be traced back to the program.
+struct PeerIdentity {
+  user_id: u32,
+  process_id: Option<i32>,
+  // Read from /proc, so it may be gone if the process already exited.
+  executable: Option<PathBuf>,
+}
+
+impl PeerIdentity {
+  fn get(connection: &tokio::net::UnixStream) -> Result<Self, std::io::Error> {
+    let credentials = connection.peer_cred()?;
+    let process_id = credentials.pid();
+
+    Ok(Self {
+      user_id: credentials.uid(),
+      process_id,
+      executable: process_id.and_then(|process_id| fs::read_link(format!("/proc/{process_id}/exe")).ok()),
+    })
+  }
+}
+
+impl std::fmt::Display for PeerIdentity {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(formatter, "user id {}", self.user_id)?;
+
+    if let Some(process_id) = self.process_id {
+      write!(formatter, ", process id {process_id}")?;
+    }
+    if let Some(executable) = &self.executable {
+      write!(formatter, ", executable {}", executable.display())?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::os::unix::fs::FileTypeExt;
+  use std::time::Instant;
+  use tokio::io::AsyncReadExt;
+  use tokio::net::UnixStream;
+  use crate::x::{TextualError, UuidV4};
+  use super::*;
+
+  // The server gives its socket to root and only serves root.
+  fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+  }
+
+  fn create_token(value: &str) -> AuthenticationToken {
+    serde_json::from_value(serde_json::json!({ "value": value })).unwrap()
+  }
+
+  // Removed with everything in it when dropped.
+  struct SocketDirectory {
+    path: PathBuf,
+  }
+
+  impl SocketDirectory {
+    fn create() -> Self {
+      let path = std::env::temp_dir().join(format!("discipline-pam-server-{}", UuidV4::generate().to_string()));
+      fs::create_dir(&path).unwrap();
+      Self { path }
+    }
+
+    fn get_socket_path(&self) -> PathBuf {
+      self.path.join("pam.sock")
+    }
+  }
+
+  impl Drop for SocketDirectory {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.path);
+    }
+  }
+
+  async fn start_server(path: &Path) -> watch::Sender<bool> {
+    let mut textual_error = TextualError::new("Testing the PAM server");
+    let server = Server::new(path, create_token("token"), &mut textual_error).await.unwrap();
+    let daemon = Daemon::open_in_memory(&mut textual_error).unwrap();
+
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    tokio::spawn(server.serve(Arc::new(Mutex::new(daemon)), shutdown_receiver));
+    shutdown_sender
+  }
+
+  async fn establish_connection(path: &Path, authentication_token: &AuthenticationToken) -> EstablishConnectionReply {
+    let mut textual_error = TextualError::new("Testing the PAM server");
+    let mut stream = AsyncStream::connect(path, MAXIMUM_MESSAGE_LENGTH, &mut textual_error).await.unwrap();
+
+    let establish_connection = EstablishConnectionRef {
+      minimum_protocol_version: MINIMUM_PROTOCOL_VERSION,
+      maximum_protocol_version: PROTOCOL_VERSION,
+      capabilities: Capabilities::SUPPORTED,
+      authentication_token,
+    };
+    stream.write(&establish_connection, &BincodeSerializationFormat, &mut textual_error).await.unwrap();
+    stream.read(&BincodeSerializationFormat, &mut textual_error).await.unwrap()
+  }
+
+  async fn read_reply(stream: UnixStream) -> EstablishConnectionReply {
+    let mut textual_error = TextualError::new("Testing the PAM server");
+    let mut stream = AsyncStream::construct(stream, MAXIMUM_MESSAGE_LENGTH);
+    stream.read(&BincodeSerializationFormat, &mut textual_error).await.unwrap()
+  }
+
+  #[tokio::test]
+  async fn stale_socket_files_are_replaced() {
+    if !is_root() {
+      return;
+    }
+
+    let directory = SocketDirectory::create();
+    let path = directory.get_socket_path();
+    fs::write(&path, "left behind").unwrap();
+
+    let _shutdown = start_server(&path).await;
+
+    assert!(fs::metadata(&path).unwrap().file_type().is_socket());
+    assert!(matches!(
+      establish_connection(&path, &create_token("token")).await,
+      EstablishConnectionReply::ConnectionEstablished { protocol_version: PROTOCOL_VERSION, .. },
+    ));
+  }
+
+  #[tokio::test]
+  async fn only_root_may_use_the_socket_file() {
+    if !is_root() {
+      return;
+    }
+
+    let directory = SocketDirectory::create();
+    let path = directory.get_socket_path();
+    let _shutdown = start_server(&path).await;
+
+    let metadata = fs::metadata(&path).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    assert_eq!(std::os::unix::fs::MetadataExt::uid(&metadata), 0);
+
+    // Nothing is left next to it.
+    let entries = fs::read_dir(path.parent().unwrap()).unwrap().count();
+    assert_eq!(entries, 1);
+  }
+
+  #[tokio::test]
+  async fn clients_with_another_token_are_refused() {
+    if !is_root() {
+      return;
+    }
+
+    let directory = SocketDirectory::create();
+    let path = directory.get_socket_path();
+    let _shutdown = start_server(&path).await;
+
+    assert!(matches!(
+      establish_connection(&path, &create_token("other token")).await,
+      EstablishConnectionReply::UnrecognizedAuthenticationToken,
+    ));
+  }
+
+  #[tokio::test]
+  async fn clients_past_the_connection_limit_are_told_the_server_is_busy() {
+    if !is_root() {
+      return;
+    }
+
+    let directory = SocketDirectory::create();
+    let path = directory.get_socket_path();
+    let _shutdown = start_server(&path).await;
+
+    // Silent clients hold a slot until the handshake times out.
+    let mut silent_clients = Vec::new();
+    for _ in 0..Server::MAXIMUM_CONCURRENT_CONNECTIONS {
+      silent_clients.push(UnixStream::connect(&path).await.unwrap());
+    }
+
+    let client = UnixStream::connect(&path).await.unwrap();
+    assert!(matches!(read_reply(client).await, EstablishConnectionReply::ServerBusy));
+
+    drop(silent_clients);
+  }
+
+  #[tokio::test]
+  async fn clients_that_never_introduce_themselves_are_disconnected() {
+    if !is_root() {
+      return;
+    }
+
+    let directory = SocketDirectory::create();
+    let path = directory.get_socket_path();
+    let _shutdown = start_server(&path).await;
+
+    let started_at = Instant::now();
+    let mut client = UnixStream::connect(&path).await.unwrap();
+    let mut buffer = [0; 16];
+
+    let length = tokio::time::timeout(
+      HANDSHAKE_TIMEOUT * 2,
+      client.read(&mut buffer),
+    ).await.unwrap();
+
+    assert!(matches!(length, Ok(0)));
+    assert!(started_at.elapsed() >= HANDSHAKE_TIMEOUT);
   }
 }
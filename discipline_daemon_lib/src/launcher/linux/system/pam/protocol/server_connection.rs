@@ -1,7 +1,39 @@
 use std::sync::Arc;
-use crate::{IsTextualError, OptionalTextualErrorContext};
+use tokio::sync::{Mutex, watch};
+use crate::x::{IsTextualError, TextualError};
 use super::*;
 
+// Bincode, noting the likely cause when a client's handshake can't be
+// decoded. Io errors say nothing about the client's version, so they
+// get no such note.
+struct HandshakeSerializationFormat;
+
+impl IsSerializationFormat for HandshakeSerializationFormat {
+  fn serialize(
+    &self,
+    value: &impl IsSerializable,
+    buffer: &mut [u8],
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<usize, ()> {
+    BincodeSerializationFormat.serialize(value, buffer, textual_error)
+  }
+
+  fn deserialize<T>(
+    &self,
+    buffer: &[u8],
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<T, ()>
+  where
+    T: IsDeserializable
+  {
+    BincodeSerializationFormat
+      .deserialize(buffer, textual_error)
+      .map_err(|()| {
+        textual_error.add_message("A module from before protocol versioning sends a handshake this daemon can't read; it must be upgraded along with the daemon");
+      })
+  }
+}
+
 pub struct ServerStream {
   stream: AsyncStream,
 }
@@ -16,7 +48,7 @@ impl ServerStream {
     textual_error: &mut impl IsTextualError,
   ) -> Result<EstablishConnection, ()> {
     self.stream.read(
-      &BincodeSerializationFormat,
+      &HandshakeSerializationFormat,
       textual_error,
     ).await
   }
@@ -62,10 +94,25 @@ impl ServerStream {
       textual_error,
     ).await
   }
+
+  pub async fn write_get_session_deadline_reply(
+    &mut self,
+    reply: &GetSessionDeadlineReply,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    self.stream.write(
+      reply, 
+      &BincodeSerializationFormat,
+      textual_error,
+    ).await
+  }
 }
 
 pub struct ServerConnection {
   stream: ServerStream,
+  // There's only one version so far. Once there are more, messages are
+  // read and written according to this.
+  protocol_version: ProtocolVersion,
 }
 
 impl ServerConnection {
@@ -82,24 +129,53 @@ impl ServerConnection {
         value
       }
       Err(()) => {
-        textual_error.add_message("An io error occured while reading the EstablishConnection message");
+        textual_error.add_message("Failed to read the EstablishConnection message");
         return Err(());
       }
     };
 
-    if establish_connection.authentication_token != *authentication_token {
-      textual_error.add_message("The client's password was incorrect");
+    if !establish_connection.authentication_token.is_equal_in_constant_time(authentication_token) {
+      textual_error.add_message("The client's authentication token was incorrect");
 
       let reply = EstablishConnectionReply::UnrecognizedAuthenticationToken;
-      let mut textual_error = textual_error.optional_context("Sending EstablishConnectionReply::IncorrectPassword message to client");
+      let mut textual_error = textual_error.optional_context("Sending EstablishConnectionReply::UnrecognizedAuthenticationToken message to client");
       if let Err(()) = stream.write_establish_connection_reply(reply, &mut textual_error).await {
-        textual_error.add_message("Failed to send an ")
+        textual_error.add_message("Failed to send the reply");
       }
 
       return Err(());
     }
 
-    let reply = EstablishConnectionReply::ConnectionEstablished;
+    let Some(protocol_version) = ProtocolVersion::negotiate(
+      MINIMUM_PROTOCOL_VERSION,
+      PROTOCOL_VERSION,
+      establish_connection.minimum_protocol_version,
+      establish_connection.maximum_protocol_version,
+    ) else {
+      textual_error.add_message("The client speaks none of the protocol versions the daemon does");
+      textual_error.add_attachement_display("Client's minimum protocol version", establish_connection.minimum_protocol_version);
+      textual_error.add_attachement_display("Client's maximum protocol version", establish_connection.maximum_protocol_version);
+      textual_error.add_attachement_display("Daemon's minimum protocol version", MINIMUM_PROTOCOL_VERSION);
+      textual_error.add_attachement_display("Daemon's maximum protocol version", PROTOCOL_VERSION);
+
+      let reply = EstablishConnectionReply::UnsupportedVersion {
+        minimum_protocol_version: MINIMUM_PROTOCOL_VERSION,
+        maximum_protocol_version: PROTOCOL_VERSION,
+      };
+      let mut textual_error = textual_error.optional_context("Sending EstablishConnectionReply::UnsupportedVersion message to client");
+      if let Err(()) = stream.write_establish_connection_reply(reply, &mut textual_error).await {
+        textual_error.add_message("Failed to send the reply");
+      }
+
+      return Err(());
+    };
+
+    let capabilities = establish_connection.capabilities.intersection(Capabilities::SUPPORTED);
+
+    let reply = EstablishConnectionReply::ConnectionEstablished {
+      protocol_version,
+      capabilities,
+    };
     let mut textual_error = textual_error.optional_context("Sending EstablishConnectionReply::ConnectionEstablished");
     if let Err(()) = stream.write_establish_connection_reply(reply, &mut textual_error).await {
       return Err(());
@@ -107,6 +183,7 @@ impl ServerConnection {
 
     Ok(Self { 
       stream,
+      protocol_version,
     })
     // TextualError::new("Discipline Linux-PAM Module Server establishing a connection with client")
 
@@ -140,15 +217,32 @@ impl ServerConnection {
     //   })?;
   }
 
-  pub async fn start_auto_processing(&mut self, daemon: Arc<Daemon>) {
+  // Answers the client's messages until it disconnects, or until
+  // `shutdown` changes.
+  pub async fn start_auto_processing(
+    &mut self, 
+    daemon: Arc<Mutex<Daemon>>,
+    mut shutdown: watch::Receiver<bool>,
+  ) {
     loop {
-      let mut textual_error = OptionalTextualErrorContext::new("Discipline Daemon Linux-PAM Server Connection processing incoming messages");
+      let mut textual_error = TextualError::new("Discipline Daemon Linux-PAM Server Connection processing incoming messages");
+      textual_error.add_attachement_display("Protocol version", self.protocol_version);
 
-      let client_message: ClientMessage = match self.stream.read_client_message(&mut textual_error).await {
+      let client_message = tokio::select! {
+        _ = shutdown.changed() => {
+          return;
+        }
+        client_message = self.stream.read_client_message(&mut textual_error) => {
+          client_message
+        }
+      };
+
+      let client_message = match client_message {
         Ok(value) => {
           value
         }
         Err(()) => {
+          // Also how a client that hung up ends up here.
           // TODO: log via a proper logging mechanism
           eprintln!("{}", textual_error);
           return;
@@ -157,10 +251,13 @@ impl ServerConnection {
 
       match client_message {
         ClientMessage::IsUserSessionOpenBlocked(message) => {
-          let is_user_session_open_blocked = daemon.is_user_session_open_blocked(message.user_name.as_ref());
+          let refusal = daemon.lock().await.get_login_refusal(
+            &message.user_name, 
+            &message.login_context,
+          );
 
           let message = IsUserSessionOpenBlockedReply { 
-            is_user_session_open_blocked,
+            refusal,
           };
           
           if let Err(()) = self
@@ -173,13 +270,100 @@ impl ServerConnection {
             return;
           }
         }
+        // Sent by modules that don't know about the session registry.
         ClientMessage::UserSessionOpenedNotification(notification) => {
-          daemon.on_user_session_opened(notification.user_name.as_ref());
+          daemon.lock().await.on_user_session_opened(&notification.user_name, SessionDetails::default());
         }
         ClientMessage::UserSessionClosedNotification(notification) => {
-          daemon.on_user_session_closed(notification.user_name.as_ref());
+          daemon.lock().await.on_user_session_closed(&notification.user_name, &SessionDetails::default());
+        }
+        ClientMessage::SessionOpenedNotification(notification) => {
+          daemon.lock().await.on_user_session_opened(&notification.user_name, notification.details);
+        }
+        ClientMessage::SessionClosedNotification(notification) => {
+          daemon.lock().await.on_user_session_closed(&notification.user_name, &notification.details);
+        }
+        ClientMessage::GetSessionDeadline(message) => {
+          // Sent by modules that don't say what kind of login it is.
+          let deadline = daemon.lock().await.get_session_deadline(&message.user_name, None);
+
+          let reply = GetSessionDeadlineReply {
+            deadline,
+          };
+
+          if let Err(()) = self
+            .stream
+            .write_get_session_deadline_reply(&reply, &mut textual_error)
+            .await
+          {
+            eprintln!("{textual_error}");
+            return;
+          }
+        }
+        ClientMessage::GetLoginSessionDeadline(message) => {
+          let deadline = daemon
+            .lock()
+            .await
+            .get_session_deadline(&message.user_name, Some(message.login_context.get_class()));
+
+          let reply = GetSessionDeadlineReply {
+            deadline,
+          };
+
+          if let Err(()) = self
+            .stream
+            .write_get_session_deadline_reply(&reply, &mut textual_error)
+            .await
+          {
+            eprintln!("{textual_error}");
+            return;
+          }
         }
       }
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use tokio::io::AsyncWriteExt;
+  use tokio::net::UnixStream;
+  use super::*;
+
+  const HINT: &str = "A module from before protocol versioning";
+
+  fn create_token(value: &str) -> AuthenticationToken {
+    serde_json::from_value(serde_json::json!({ "value": value })).unwrap()
+  }
+
+  // Sends `bytes` as the client's handshake, then hangs up, and returns
+  // why the daemon refused the connection.
+  async fn establish(bytes: &[u8]) -> String {
+    let (server, mut client) = UnixStream::pair().unwrap();
+    client.write_all(bytes).await.unwrap();
+    drop(client);
+
+    let stream = ServerStream::construct(AsyncStream::construct(server, MAXIMUM_MESSAGE_LENGTH));
+    let mut textual_error = TextualError::new("Testing establishing connections");
+    let result = ServerConnection::establish(stream, &create_token("token"), &mut textual_error).await;
+
+    assert!(result.is_err());
+    textual_error.to_string()
+  }
+
+  #[tokio::test]
+  async fn undecodable_handshakes_hint_at_old_modules() {
+    let mut bytes = 3u32.to_be_bytes().to_vec();
+    bytes.extend([0xff, 0xff, 0xff]);
+
+    assert!(establish(&bytes).await.contains(HINT));
+  }
+
+  #[tokio::test]
+  async fn io_errors_dont_hint_at_old_modules() {
+    // The client hangs up without a word, or halfway through.
+    assert!(!establish(&[]).await.contains(HINT));
+    assert!(!establish(&[0, 0]).await.contains(HINT));
+    assert!(!establish(&[0, 0, 0, 8, 1]).await.contains(HINT));
+  }
+}
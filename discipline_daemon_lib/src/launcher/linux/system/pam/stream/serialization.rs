@@ -1,3 +1,5 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use crate::x::IsTextualError;
 
 pub trait IsSerializationFormat {
@@ -17,9 +19,13 @@ pub trait IsSerializationFormat {
     T: IsDeserializable;
 }
 
-pub trait IsSerializable {}
+pub trait IsSerializable: Serialize {}
 
-pub trait IsDeserializable {}
+impl<T: Serialize> IsSerializable for T {}
+
+pub trait IsDeserializable: DeserializeOwned {}
+
+impl<T: DeserializeOwned> IsDeserializable for T {}
 
 
 // use std::any::type_name;
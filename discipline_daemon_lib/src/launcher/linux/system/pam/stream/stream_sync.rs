@@ -1,14 +1,21 @@
 use std::any::type_name;
-use std::io::{Read, Write};
+use std::io::{self, ErrorKind, Read, Write};
+use std::mem;
 use std::net::Shutdown;
 use std::path::Path;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::net::UnixStream;
-use crate::x::IsTextualError;
+use std::time::Instant;
+use crate::x::{Duration, IsTextualError};
 use super::{BufferLength, MessageLength, IsSerializable, IsSerializationFormat, IsDeserializable};
 
 pub struct BlockingStream {
   stream: UnixStream,
   buffer: Vec<u8>,
+  // Applies to connecting, and to reading or writing each message as a
+  // whole. None waits forever.
+  timeout: Option<Duration>,
 }
 
 impl BlockingStream {
@@ -19,8 +26,26 @@ impl BlockingStream {
     Self {
       stream,
       buffer: vec![0; maximum_buffer_length.get()],
+      timeout: None,
     }
   }
+
+  // Like `connect`, but gives up once `timeout` passes, and so does
+  // every read and write on the stream after.
+  pub fn connect_with_timeout(
+    path: impl AsRef<Path>, 
+    maximum_buffer_length: BufferLength,
+    timeout: Duration,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<BlockingStream, ()> {
+    let stream = connect_with_timeout(path.as_ref(), timeout, textual_error)?;
+
+    Ok(BlockingStream {
+      stream,
+      buffer: vec![0; maximum_buffer_length.get()],
+      timeout: Some(timeout),
+    })
+  }
   
   pub fn connect(
     path: impl AsRef<Path>, 
@@ -48,6 +73,10 @@ impl BlockingStream {
     self.buffer.len() - MessageLength::BINARY_SIZE
   }
 
+  fn get_deadline(&self) -> Option<Instant> {
+    self.timeout.map(|timeout| Instant::now() + timeout.to_std_duration())
+  }
+
   pub fn read<Message, SerializationFormat>(
     &mut self, 
     format: &SerializationFormat,
@@ -60,11 +89,10 @@ impl BlockingStream {
     let mut textual_error = textual_error
       .optional_context("Receiving a message over a UnixStream");
 
+    let deadline = self.get_deadline();
     let mut message_length = [0; MessageLength::BINARY_SIZE];
 
-    self
-      .stream
-      .read_exact(&mut message_length)
+    read_exact(&self.stream, &mut message_length, deadline)
       .map_err(|error| {
         textual_error.add_message("An io error occured while reading the message length");
         textual_error.add_attachement_display("Message data type", type_name::<Message>());
@@ -82,9 +110,7 @@ impl BlockingStream {
 
     let message = &mut self.buffer[..message_length];
 
-    self
-      .stream
-      .read_exact(message)
+    read_exact(&self.stream, message, deadline)
       .map_err(|error| {
         textual_error.add_message("An io error occured while reading the message");
         textual_error.add_attachement_display("Message data type", type_name::<Message>());
@@ -129,8 +155,7 @@ impl BlockingStream {
         textual_error.add_attachement_display("Message buffer length", self.buffer.len());
       })?;
     
-    // if message_length > self.buffer.maximum_message_length() {
-    if message_length > 89 {
+    if message_length > self.maximum_message_size() {
       textual_error.add_message("Message length is larger than the maximum allowed length");
       textual_error.add_attachement_display("Message data type name", type_name::<Message>());
       textual_error.add_attachement_display("Message length", message_length);
@@ -155,9 +180,7 @@ impl BlockingStream {
       MessageLength::BINARY_SIZE + message_length
     ];
 
-    self
-      .stream
-      .write_all(&length_and_message) 
+    write_all(&self.stream, length_and_message, self.get_deadline())
       .map_err(|error| {
         textual_error.add_message("An io error occured");
         textual_error.add_attachement_display("Message data type name", type_name::<Message>());
@@ -184,6 +207,11 @@ impl BlockingStream {
     path: impl AsRef<Path>, 
     textual_error: &mut impl IsTextualError,
   ) -> Result<(), ()> {
+    if let Some(timeout) = self.timeout {
+      self.stream = connect_with_timeout(path.as_ref(), timeout, textual_error)?;
+      return Ok(());
+    }
+
     match UnixStream::connect(&path) {
       Ok(stream) => {
         self.stream = stream;
@@ -200,6 +228,206 @@ impl BlockingStream {
   }
 }
 
+// std has no connect timeout for Unix streams. Connecting to a Unix
+// socket only blocks while the listener's backlog is full, and a
+// nonblocking connect fails with EAGAIN rather than finishing in the
+// background, so it's retried until the deadline.
+fn connect_with_timeout(
+  path: &Path,
+  timeout: Duration,
+  textual_error: &mut impl IsTextualError,
+) -> Result<UnixStream, ()> {
+  match try_connect_with_timeout(path, timeout) {
+    Ok(stream) => {
+      Ok(stream)
+    }
+    Err(error) => {
+      textual_error.change_context("Connecting to a Unix Stream");
+      textual_error.add_message("An io error occured");
+      textual_error.add_attachement_display("Io error", error);
+      textual_error.add_attachement_display("Unix Stream path", path.display());
+      textual_error.add_attachement_display("Timeout in milliseconds", timeout.as_total_milliseconds());
+      Err(())
+    }
+  }
+}
+
+fn try_connect_with_timeout(path: &Path, timeout: Duration) -> io::Result<UnixStream> {
+  const RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+
+  // A zero timeout isn't accepted by set_read_timeout, and would mean
+  // giving up before trying anyway.
+  let timeout = timeout.to_std_duration().max(std::time::Duration::from_millis(1));
+  let deadline = Instant::now() + timeout;
+
+  let mut address = unsafe { mem::zeroed::<libc::sockaddr_un>() };
+  let path_bytes = path.as_os_str().as_bytes();
+  if path_bytes.len() >= address.sun_path.len() {
+    return Err(io::Error::new(ErrorKind::InvalidInput, "The socket path is too long"));
+  }
+
+  address.sun_family = libc::AF_UNIX as libc::sa_family_t;
+  for (destination, source) in address.sun_path.iter_mut().zip(path_bytes) {
+    *destination = *source as libc::c_char;
+  }
+
+  let socket = unsafe {
+    libc::socket(
+      libc::AF_UNIX, 
+      libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, 
+      0,
+    )
+  };
+
+  if socket < 0 {
+    return Err(io::Error::last_os_error());
+  }
+
+  let socket = unsafe { OwnedFd::from_raw_fd(socket) };
+
+  loop {
+    let status = unsafe {
+      libc::connect(
+        socket.as_raw_fd(),
+        &address as *const libc::sockaddr_un as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr_un>() as libc::socklen_t,
+      )
+    };
+
+    if status == 0 {
+      break;
+    }
+
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+      Some(libc::EINTR) => {
+        continue;
+      }
+      Some(libc::EAGAIN) => {
+        if Instant::now() >= deadline {
+          return Err(io::Error::new(ErrorKind::TimedOut, "The server's backlog stayed full"));
+        }
+
+        std::thread::sleep(RETRY_INTERVAL);
+      }
+      _ => {
+        return Err(error);
+      }
+    }
+  }
+
+  // Left nonblocking: reads and writes wait in `poll` instead, against
+  // a deadline for the whole message rather than for each syscall.
+  Ok(UnixStream::from(socket))
+}
+
+// Like `Read::read_exact`, but a nonblocking stream waits in `poll`
+// until `deadline` for more to arrive. A blocking stream never needs
+// to, and so ignores the deadline.
+fn read_exact(
+  mut stream: &UnixStream, 
+  buffer: &mut [u8], 
+  deadline: Option<Instant>,
+) -> io::Result<()> {
+  let mut read = 0;
+
+  while read < buffer.len() {
+    match stream.read(&mut buffer[read..]) {
+      Ok(0) => {
+        return Err(io::Error::from(ErrorKind::UnexpectedEof));
+      }
+      Ok(length) => {
+        read += length;
+      }
+      Err(error) if error.kind() == ErrorKind::Interrupted => {}
+      Err(error) if error.kind() == ErrorKind::WouldBlock => {
+        wait_until_ready(stream, libc::POLLIN, deadline)?;
+      }
+      Err(error) => {
+        return Err(error);
+      }
+    }
+  }
+
+  Ok(())
+}
+
+// The writing counterpart of `read_exact`.
+fn write_all(
+  mut stream: &UnixStream, 
+  buffer: &[u8], 
+  deadline: Option<Instant>,
+) -> io::Result<()> {
+  let mut written = 0;
+
+  while written < buffer.len() {
+    match stream.write(&buffer[written..]) {
+      Ok(0) => {
+        return Err(io::Error::from(ErrorKind::WriteZero));
+      }
+      Ok(length) => {
+        written += length;
+      }
+      Err(error) if error.kind() == ErrorKind::Interrupted => {}
+      Err(error) if error.kind() == ErrorKind::WouldBlock => {
+        wait_until_ready(stream, libc::POLLOUT, deadline)?;
+      }
+      Err(error) => {
+        return Err(error);
+      }
+    }
+  }
+
+  Ok(())
+}
+
+// Waits for `events` on the stream, or fails once `deadline` passes.
+// Errors and hang ups count as ready too, for the next read or write
+// to report.
+fn wait_until_ready(
+  stream: &UnixStream, 
+  events: libc::c_short, 
+  deadline: Option<Instant>,
+) -> io::Result<()> {
+  loop {
+    let timeout = match deadline {
+      Some(deadline) => {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+          return Err(io::Error::new(ErrorKind::TimedOut, "The peer didn't answer in time"));
+        }
+
+        // Rounded up, so the last fraction of a millisecond isn't
+        // polled with a timeout of zero over and over.
+        libc::c_int::try_from(remaining.as_millis() + 1).unwrap_or(libc::c_int::MAX)
+      }
+      None => {
+        -1
+      }
+    };
+
+    let mut poll_fd = libc::pollfd {
+      fd: stream.as_raw_fd(),
+      events,
+      revents: 0,
+    };
+
+    let status = unsafe { libc::poll(&mut poll_fd, 1, timeout) };
+    if status > 0 {
+      return Ok(());
+    }
+    if status == 0 {
+      // Checked against the deadline above.
+      continue;
+    }
+
+    let error = io::Error::last_os_error();
+    if error.kind() != ErrorKind::Interrupted {
+      return Err(error);
+    }
+  }
+}
+
 pub struct BasicStream {}
 pub struct StreamWithBuffer {}
 
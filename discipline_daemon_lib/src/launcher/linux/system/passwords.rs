@@ -0,0 +1,239 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+#[cfg(test)]
+use std::sync::Mutex;
+use crate::x::IsTextualError;
+use super::UserName;
+
+pub trait IsPasswordBackend {
+  fn set_password(
+    &self,
+    user_name: &UserName,
+    password: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()>;
+}
+
+// Changes passwords by writing "user_name:password" lines to the
+// standard input of chpasswd, so passwords never show up in the
+// process list.
+pub struct ChpasswdBackend {
+  program: PathBuf,
+}
+
+impl Default for ChpasswdBackend {
+  fn default() -> Self {
+    Self {
+      program: PathBuf::from("/usr/sbin/chpasswd"),
+    }
+  }
+}
+
+impl ChpasswdBackend {
+  pub fn new(program: PathBuf) -> Self {
+    Self {
+      program,
+    }
+  }
+}
+
+impl IsPasswordBackend for ChpasswdBackend {
+  fn set_password(
+    &self,
+    user_name: &UserName,
+    password: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    textual_error.change_context("Setting a user's password using chpasswd");
+    textual_error.add_attachement_display("Program", self.program.display());
+
+    let Ok(user_name) = user_name.inner().to_str() else {
+      textual_error.add_message("User name is not valid UTF-8");
+      return Err(());
+    };
+
+    textual_error.add_attachement_display("User name", user_name);
+
+    if user_name.contains(':') || user_name.contains('\n') {
+      textual_error.add_message("User name contains a character chpasswd can't handle");
+      return Err(());
+    }
+    if password.contains('\n') {
+      textual_error.add_message("Password contains a new line");
+      return Err(());
+    }
+
+    let mut child = match Command::new(&self.program)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::null())
+      .stderr(Stdio::piped())
+      .spawn()
+    {
+      Ok(child) => {
+        child
+      }
+      Err(error) => {
+        textual_error.add_message("Failed to spawn chpasswd");
+        textual_error.add_attachement_display("Error", error);
+        return Err(());
+      }
+    };
+
+    let write_result = match child.stdin.take() {
+      Some(mut stdin) => {
+        stdin.write_all(format!("{user_name}:{password}\n").as_bytes())
+      }
+      None => {
+        textual_error.add_message("chpasswd's standard input isn't available");
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(());
+      }
+    };
+
+    if let Err(error) = write_result {
+      textual_error.add_message("Failed to write to chpasswd's standard input");
+      textual_error.add_attachement_display("Error", error);
+      let _ = child.kill();
+      let _ = child.wait();
+      return Err(());
+    }
+
+    let output = match child.wait_with_output() {
+      Ok(output) => {
+        output
+      }
+      Err(error) => {
+        textual_error.add_message("Failed to wait for chpasswd to exit");
+        textual_error.add_attachement_display("Error", error);
+        return Err(());
+      }
+    };
+
+    if !output.status.success() {
+      textual_error.add_message("chpasswd exited unsuccessfully");
+      textual_error.add_attachement_display("Exit status", output.status);
+      textual_error.add_attachement_display("Standard error", String::from_utf8_lossy(&output.stderr));
+      return Err(());
+    }
+
+    Ok(())
+  }
+}
+
+// Records password changes instead of applying them.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockPasswordBackend {
+  changes: Mutex<Vec<(UserName, String)>>,
+  fails: bool,
+}
+
+#[cfg(test)]
+impl MockPasswordBackend {
+  pub fn failing() -> Self {
+    Self {
+      changes: Mutex::new(Vec::new()),
+      fails: true,
+    }
+  }
+
+  pub fn get_changes(&self) -> Vec<(UserName, String)> {
+    self
+      .changes
+      .lock()
+      .map(|changes| changes.clone())
+      .unwrap_or_default()
+  }
+}
+
+#[cfg(test)]
+impl IsPasswordBackend for MockPasswordBackend {
+  fn set_password(
+    &self,
+    user_name: &UserName,
+    password: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    if self.fails {
+      textual_error.change_context("Setting a user's password using the mock backend");
+      textual_error.add_message("The mock backend is configured to fail");
+      return Err(());
+    }
+
+    let Ok(mut changes) = self.changes.lock() else {
+      textual_error.change_context("Setting a user's password using the mock backend");
+      textual_error.add_message("The mock backend's lock is poisoned");
+      return Err(());
+    };
+
+    changes.push((user_name.clone(), password.to_string()));
+    Ok(())
+  }
+}
+
+// Unambiguous characters only, since people may have to type these
+// passwords by hand.
+const PASSWORD_ALPHABET: &[u8] = b"abcdefghijkmnopqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789-_.+=@#%";
+
+pub const DEFAULT_PASSWORD_LENGTH: usize = 32;
+
+pub fn generate_password(
+  length: usize,
+  textual_error: &mut impl IsTextualError,
+) -> Result<String, ()> {
+  // Largest multiple of the alphabet's length that fits in a byte.
+  // Bytes at or above it are rejected to avoid modulo bias.
+  let limit = 256 - (256 % PASSWORD_ALPHABET.len());
+  let mut password = String::with_capacity(length);
+  let mut buffer = [0u8; 64];
+
+  while password.len() < length {
+    fill_random_bytes(&mut buffer, textual_error)?;
+
+    for byte in buffer {
+      if password.len() >= length {
+        break;
+      }
+      if (byte as usize) < limit {
+        password.push(PASSWORD_ALPHABET[byte as usize % PASSWORD_ALPHABET.len()] as char);
+      }
+    }
+  }
+
+  Ok(password)
+}
+
+fn fill_random_bytes(
+  buffer: &mut [u8],
+  textual_error: &mut impl IsTextualError,
+) -> Result<(), ()> {
+  let mut filled = 0;
+
+  while filled < buffer.len() {
+    let result = unsafe {
+      libc::getrandom(
+        buffer[filled..].as_mut_ptr().cast(),
+        buffer.len() - filled,
+        0,
+      )
+    };
+
+    if result < 0 {
+      let error = std::io::Error::last_os_error();
+      if error.kind() == std::io::ErrorKind::Interrupted {
+        continue;
+      }
+
+      textual_error.change_context("Generating random bytes");
+      textual_error.add_message("getrandom failed");
+      textual_error.add_attachement_display("Error", error);
+      return Err(());
+    }
+
+    filled += result as usize;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,400 @@
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::PathBuf;
+#[cfg(test)]
+use std::sync::Mutex;
+use tokio::io::unix::AsyncFd;
+use crate::x::IsTextualError;
+use super::UserId;
+
+pub type ProcessId = libc::pid_t;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+  pub process_id: ProcessId,
+  // The real user id, which setuid programs don't change.
+  pub user_id: UserId,
+  // None for kernel threads, and for processes that exited while we
+  // were looking at them.
+  pub executable_path: Option<PathBuf>,
+  // The kernel's name for the process, at most 15 bytes long.
+  pub name: String,
+}
+
+impl ProcessInfo {
+  // The executable's file name, or the kernel's name for the process
+  // when the executable is unknown.
+  pub fn get_executable_name(&self) -> &str {
+    self
+      .executable_path
+      .as_ref()
+      .and_then(|path| path.file_name())
+      .and_then(|name| name.to_str())
+      .unwrap_or(&self.name)
+  }
+}
+
+pub trait IsProcessBackend {
+  fn list_processes(
+    &self,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Vec<ProcessInfo>, ()>;
+
+  fn get_process(&self, process_id: ProcessId) -> Option<ProcessInfo>;
+
+  fn kill_process(
+    &self,
+    process_id: ProcessId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()>;
+}
+
+// Reads processes from /proc and kills them with SIGKILL.
+pub struct ProcfsBackend {
+  directory: PathBuf,
+}
+
+impl Default for ProcfsBackend {
+  fn default() -> Self {
+    Self {
+      directory: PathBuf::from("/proc"),
+    }
+  }
+}
+
+impl ProcfsBackend {
+  pub fn new(directory: PathBuf) -> Self {
+    Self {
+      directory,
+    }
+  }
+}
+
+impl IsProcessBackend for ProcfsBackend {
+  fn list_processes(
+    &self,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<Vec<ProcessInfo>, ()> {
+    let entries = match fs::read_dir(&self.directory) {
+      Ok(entries) => {
+        entries
+      }
+      Err(error) => {
+        textual_error.change_context("Listing processes");
+        textual_error.add_message("Failed to read the proc directory");
+        textual_error.add_attachement_display("Io error", error);
+        textual_error.add_attachement_display("Directory", self.directory.display());
+        return Err(());
+      }
+    };
+
+    Ok(
+      entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .filter_map(|process_id| self.get_process(process_id))
+        .collect()
+    )
+  }
+
+  fn get_process(&self, process_id: ProcessId) -> Option<ProcessInfo> {
+    let directory = self.directory.join(process_id.to_string());
+    let status = fs::read_to_string(directory.join("status")).ok()?;
+
+    let mut name = None;
+    let mut user_id = None;
+
+    for line in status.lines() {
+      if let Some(value) = line.strip_prefix("Name:") {
+        name = Some(value.trim().to_string());
+      } else if let Some(value) = line.strip_prefix("Uid:") {
+        user_id = value.split_whitespace().next()?.parse().ok().map(UserId::new);
+      }
+    }
+
+    Some(ProcessInfo {
+      process_id,
+      user_id: user_id?,
+      executable_path: fs::read_link(directory.join("exe")).ok(),
+      name: name?,
+    })
+  }
+
+  fn kill_process(
+    &self,
+    process_id: ProcessId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    if unsafe { libc::kill(process_id, libc::SIGKILL) } == 0 {
+      return Ok(());
+    }
+
+    let error = io::Error::last_os_error();
+    // It exited on its own.
+    if error.raw_os_error() == Some(libc::ESRCH) {
+      return Ok(());
+    }
+
+    textual_error.change_context("Killing a process");
+    textual_error.add_attachement_display("Io error", error);
+    textual_error.add_attachement_display("Process id", process_id);
+    Err(())
+  }
+}
+
+// Serves a fixed list of processes and records kills instead of
+// killing.
+#[cfg(test)]
+pub struct MockProcessBackend {
+  processes: Vec<ProcessInfo>,
+  killed_process_ids: Mutex<Vec<ProcessId>>,
+}
+
+#[cfg(test)]
+impl MockProcessBackend {
+  pub fn new(processes: Vec<ProcessInfo>) -> Self {
+    Self {
+      processes,
+      killed_process_ids: Mutex::new(Vec::new()),
+    }
+  }
+
+  pub fn get_killed_process_ids(&self) -> Vec<ProcessId> {
+    self
+      .killed_process_ids
+      .lock()
+      .map(|process_ids| process_ids.clone())
+      .unwrap_or_default()
+  }
+}
+
+#[cfg(test)]
+impl IsProcessBackend for MockProcessBackend {
+  fn list_processes(
+    &self,
+    _textual_error: &mut impl IsTextualError,
+  ) -> Result<Vec<ProcessInfo>, ()> {
+    Ok(self.processes.clone())
+  }
+
+  fn get_process(&self, process_id: ProcessId) -> Option<ProcessInfo> {
+    self
+      .processes
+      .iter()
+      .find(|process| process.process_id == process_id)
+      .cloned()
+  }
+
+  fn kill_process(
+    &self,
+    process_id: ProcessId,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    let Ok(mut killed_process_ids) = self.killed_process_ids.lock() else {
+      textual_error.change_context("Killing a process using the mock backend");
+      textual_error.add_message("The mock backend's lock is poisoned");
+      return Err(());
+    };
+
+    killed_process_ids.push(process_id);
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessEvent {
+  // The process replaced its program, usually right after forking.
+  Execution(ProcessId),
+  Exit(ProcessId),
+}
+
+// Hears about every exec and exit on the system from the kernel's
+// process events connector (see linux/cn_proc.h), which needs
+// CAP_NET_ADMIN.
+pub struct ProcessEvents {
+  socket: AsyncFd<OwnedFd>,
+}
+
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+const PROC_EVENT_EXEC: u32 = 0x00000002;
+const PROC_EVENT_EXIT: u32 = 0x80000000;
+
+const NETLINK_HEADER_LENGTH: usize = mem::size_of::<libc::nlmsghdr>();
+// struct cn_msg: id.idx, id.val, seq, ack, len and flags.
+const CONNECTOR_HEADER_LENGTH: usize = 20;
+// struct proc_event: what, cpu and timestamp_ns, then the event data.
+const PROCESS_EVENT_HEADER_LENGTH: usize = 16;
+
+impl ProcessEvents {
+  pub fn open(textual_error: &mut impl IsTextualError) -> Result<Self, ()> {
+    textual_error.change_context("Subscribing to the kernel's process events");
+
+    let socket = unsafe {
+      libc::socket(
+        libc::AF_NETLINK,
+        libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+        libc::NETLINK_CONNECTOR,
+      )
+    };
+
+    if socket < 0 {
+      textual_error.add_message("Failed to create a netlink socket");
+      textual_error.add_attachement_display("Io error", io::Error::last_os_error());
+      return Err(());
+    }
+
+    let socket = unsafe { OwnedFd::from_raw_fd(socket) };
+
+    let mut address: libc::sockaddr_nl = unsafe { mem::zeroed() };
+    address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    address.nl_groups = CN_IDX_PROC;
+
+    let result = unsafe {
+      libc::bind(
+        socket.as_raw_fd(),
+        &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+      )
+    };
+
+    if result != 0 {
+      textual_error.add_message("Failed to bind the netlink socket");
+      textual_error.add_attachement_display("Io error", io::Error::last_os_error());
+      return Err(());
+    }
+
+    let message = create_listen_message();
+    let mut kernel_address: libc::sockaddr_nl = unsafe { mem::zeroed() };
+    kernel_address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+
+    let result = unsafe {
+      libc::sendto(
+        socket.as_raw_fd(),
+        message.as_ptr() as *const libc::c_void,
+        message.len(),
+        0,
+        &kernel_address as *const libc::sockaddr_nl as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+      )
+    };
+
+    if result < 0 {
+      textual_error.add_message("Failed to ask the kernel for process events");
+      textual_error.add_attachement_display("Io error", io::Error::last_os_error());
+      return Err(());
+    }
+
+    match AsyncFd::new(socket) {
+      Ok(socket) => {
+        Ok(Self { socket })
+      }
+      Err(error) => {
+        textual_error.add_message("Failed to register the netlink socket with the runtime");
+        textual_error.add_attachement_display("Io error", error);
+        Err(())
+      }
+    }
+  }
+
+  // Waits for the next process to call exec or exit. Events can be lost
+  // when we fall behind, which the periodic scan makes up for.
+  pub async fn next(&mut self) -> io::Result<ProcessEvent> {
+    let mut buffer = [0u8; 1024];
+
+    loop {
+      let mut guard = self.socket.readable().await?;
+
+      let result = guard.try_io(|socket| {
+        let length = unsafe {
+          libc::recv(
+            socket.as_raw_fd(),
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            buffer.len(),
+            0,
+          )
+        };
+
+        if length < 0 {
+          Err(io::Error::last_os_error())
+        } else {
+          Ok(length as usize)
+        }
+      });
+
+      let length = match result {
+        Ok(Ok(length)) => {
+          length
+        }
+        // ENOBUFS means events were dropped.
+        Ok(Err(error)) if error.raw_os_error() == Some(libc::ENOBUFS) => {
+          continue;
+        }
+        Ok(Err(error)) => {
+          return Err(error);
+        }
+        Err(_would_block) => {
+          continue;
+        }
+      };
+
+      if let Some(event) = parse_process_event(&buffer[..length]) {
+        return Ok(event);
+      }
+    }
+  }
+}
+
+fn create_listen_message() -> Vec<u8> {
+  let length = NETLINK_HEADER_LENGTH + CONNECTOR_HEADER_LENGTH + 4;
+  let mut message = Vec::with_capacity(length);
+
+  // struct nlmsghdr
+  message.extend_from_slice(&(length as u32).to_ne_bytes());
+  message.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+  message.extend_from_slice(&0u16.to_ne_bytes());
+  message.extend_from_slice(&0u32.to_ne_bytes());
+  message.extend_from_slice(&std::process::id().to_ne_bytes());
+  // struct cn_msg
+  message.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+  message.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+  message.extend_from_slice(&0u32.to_ne_bytes());
+  message.extend_from_slice(&0u32.to_ne_bytes());
+  message.extend_from_slice(&4u16.to_ne_bytes());
+  message.extend_from_slice(&0u16.to_ne_bytes());
+  // enum proc_cn_mcast_op
+  message.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+
+  message
+}
+
+// Events name threads by their thread group id, which is the process
+// id, and by their own id.
+fn parse_process_event(message: &[u8]) -> Option<ProcessEvent> {
+  let read_u32 = |offset: usize| -> Option<u32> {
+    let bytes = message.get(offset..offset + 4)?;
+    Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+  };
+
+  let event = NETLINK_HEADER_LENGTH + CONNECTOR_HEADER_LENGTH;
+  // struct exec_proc_event and struct exit_proc_event both start with
+  // process_pid, then process_tgid.
+  let thread_id = read_u32(event + PROCESS_EVENT_HEADER_LENGTH)? as ProcessId;
+  let process_id = read_u32(event + PROCESS_EVENT_HEADER_LENGTH + 4)? as ProcessId;
+
+  match read_u32(event)? {
+    PROC_EVENT_EXEC => {
+      Some(ProcessEvent::Execution(process_id))
+    }
+    // Only the exit of the whole process, not of one of its threads.
+    PROC_EVENT_EXIT if thread_id == process_id => {
+      Some(ProcessEvent::Exit(process_id))
+    }
+    _ => {
+      None
+    }
+  }
+}
@@ -0,0 +1,92 @@
+use std::env;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use crate::x::IsTextualError;
+
+// A minimal implementation of systemd's sd_notify protocol: state
+// changes are sent as newline-separated "KEY=VALUE" pairs in a single
+// datagram to the socket named by the NOTIFY_SOCKET variable.
+//
+// When NOTIFY_SOCKET isn't set, we weren't started by systemd with
+// Type=notify, and notifying does nothing.
+pub struct SystemdNotifier {
+  socket_address: Option<String>,
+}
+
+impl SystemdNotifier {
+  pub fn from_environment() -> Self {
+    Self {
+      socket_address: env::var("NOTIFY_SOCKET")
+        .ok()
+        .filter(|address| !address.is_empty()),
+    }
+  }
+
+  pub fn notify_ready(&self, textual_error: &mut impl IsTextualError) -> Result<(), ()> {
+    self.notify("READY=1", textual_error)
+  }
+
+  pub fn notify_stopping(&self, textual_error: &mut impl IsTextualError) -> Result<(), ()> {
+    self.notify("STOPPING=1", textual_error)
+  }
+
+  pub fn notify_status(
+    &self,
+    status: &str,
+    textual_error: &mut impl IsTextualError,
+  ) -> Result<(), ()> {
+    // A status can't span multiple lines.
+    let status = status.replace('\n', " ");
+    self.notify(&format!("STATUS={status}"), textual_error)
+  }
+
+  pub fn notify(&self, state: &str, textual_error: &mut impl IsTextualError) -> Result<(), ()> {
+    let Some(socket_address) = &self.socket_address else {
+      return Ok(());
+    };
+
+    let mut textual_error = textual_error.optional_context("Notifying systemd of a state change");
+    textual_error.add_attachement_display("State", state);
+    textual_error.add_attachement_display("Socket address", socket_address);
+
+    // Addresses that start with "@" are in the abstract namespace.
+    let address = match socket_address.strip_prefix('@') {
+      Some(name) => {
+        SocketAddr::from_abstract_name(name.as_bytes())
+      }
+      None => {
+        SocketAddr::from_pathname(socket_address)
+      }
+    };
+
+    let address = match address {
+      Ok(address) => {
+        address
+      }
+      Err(error) => {
+        textual_error.add_message("The socket address is invalid");
+        textual_error.add_attachement_display("Io error", error);
+        return Err(());
+      }
+    };
+
+    let socket = match UnixDatagram::unbound() {
+      Ok(socket) => {
+        socket
+      }
+      Err(error) => {
+        textual_error.add_message("Failed to create a unix datagram socket");
+        textual_error.add_attachement_display("Io error", error);
+        return Err(());
+      }
+    };
+
+    if let Err(error) = socket.send_to_addr(state.as_bytes(), &address) {
+      textual_error.add_message("Failed to send the notification");
+      textual_error.add_attachement_display("Io error", error);
+      return Err(());
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,105 @@
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use super::UserId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketProtocol {
+  Tcp,
+  Udp,
+}
+
+impl SocketProtocol {
+  fn get_tables(self) -> [&'static str; 2] {
+    match self {
+      Self::Tcp => {
+        ["/proc/net/tcp", "/proc/net/tcp6"]
+      }
+      Self::Udp => {
+        ["/proc/net/udp", "/proc/net/udp6"]
+      }
+    }
+  }
+}
+
+// Finds the user owning the local socket bound to `address` in the
+// kernel's socket tables. This is how the servers that only the
+// firewall sends traffic to tell which user a peer belongs to.
+pub fn find_socket_owner(protocol: SocketProtocol, address: SocketAddr) -> Option<UserId> {
+  protocol
+    .get_tables()
+    .into_iter()
+    .filter_map(|path| fs::read_to_string(path).ok())
+    .find_map(|table| find_socket_owner_in_table(&table, address))
+}
+
+fn find_socket_owner_in_table(table: &str, address: SocketAddr) -> Option<UserId> {
+  for line in table.lines().skip(1) {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (Some(local_address), Some(user_id)) = (fields.get(1), fields.get(7)) else {
+      continue;
+    };
+    let Some((local_ip, local_port)) = local_address.split_once(':') else {
+      continue;
+    };
+
+    if u16::from_str_radix(local_port, 16).ok() != Some(address.port()) {
+      continue;
+    }
+
+    let Some(local_ip) = parse_proc_net_address(local_ip) else {
+      continue;
+    };
+
+    let matches = local_ip.is_unspecified()
+      || local_ip == address.ip()
+      || matches!(
+        (local_ip, address.ip()),
+        (IpAddr::V6(local_ip), IpAddr::V4(ip)) if local_ip.to_ipv4_mapped() == Some(ip),
+      );
+
+    if matches {
+      return user_id.parse().ok().map(UserId::new);
+    }
+  }
+
+  None
+}
+
+// Addresses are printed as 32-bit words in host byte order.
+fn parse_proc_net_address(hex: &str) -> Option<IpAddr> {
+  let mut bytes = Vec::with_capacity(16);
+  for index in (0..hex.len()).step_by(8) {
+    let word = u32::from_str_radix(hex.get(index..index + 8)?, 16).ok()?;
+    bytes.extend_from_slice(&word.to_ne_bytes());
+  }
+
+  match bytes.len() {
+    4 => {
+      Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?))
+    }
+    16 => {
+      Some(IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?))
+    }
+    _ => {
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::Ipv4Addr;
+  use super::*;
+
+  #[test]
+  fn socket_owners_are_found_in_proc_net_tables() {
+    let table = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+                 \x20 1: 0100007F:D431 00000000:0000 07 00000000:00000000 00:00000000 00000000  1000        0 1 2 0\n";
+
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, 0xD431));
+    assert_eq!(find_socket_owner_in_table(table, address), Some(UserId::new(1000)));
+
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, 0xD432));
+    assert_eq!(find_socket_owner_in_table(table, address), None);
+  }
+}
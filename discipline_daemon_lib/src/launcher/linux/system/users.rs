@@ -41,7 +41,7 @@ impl UserName {
   }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 pub struct UserNameRef<'a> {
   inner: &'a CStr
 }
@@ -58,7 +58,7 @@ impl<'a> UserNameRef<'a> {
   }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct GroupId {
   inner: gid_t,
 }
@@ -75,7 +75,7 @@ impl GroupId {
   }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct GroupName {
   inner: CString,
 }
@@ -101,8 +101,11 @@ pub struct PasswordFileEntry {
 
 #[derive(Debug, Clone)]
 pub struct GroupFileEntry {
-  group_id: GroupId,
-  group_name: GroupName,
+  pub group_id: GroupId,
+  pub group_name: GroupName,
+  // The users listed in the group database. Users whose primary group
+  // this is are members too, but usually aren't listed.
+  pub member_names: Vec<UserName>,
 }
 
 unsafe fn sanitize_password_file_entry(entry: passwd) -> PasswordFileEntry {
@@ -123,9 +126,20 @@ unsafe fn sanitize_group_file_entry(entry: group) -> GroupFileEntry {
   let group_name = unsafe { CStr::from_ptr(entry.gr_name) };
   let group_name = GroupName::new(group_name.to_owned());
 
+  let mut member_names = Vec::new();
+  if !entry.gr_mem.is_null() {
+    let mut member = entry.gr_mem;
+    while !unsafe { *member }.is_null() {
+      let member_name = unsafe { CStr::from_ptr(*member) };
+      member_names.push(UserName::new(member_name.to_owned()));
+      member = unsafe { member.add(1) };
+    }
+  }
+
   GroupFileEntry {
     group_id,
     group_name,
+    member_names,
   }
 }
 
@@ -137,6 +151,15 @@ pub struct AllocationConfig {
   pub memory_allocation_increment_factor: usize,
 }
 
+impl Default for AllocationConfig {
+  fn default() -> Self {
+    Self {
+      maximum_memory_allocation_retries: 8,
+      memory_allocation_increment_factor: 4096,
+    }
+  }
+}
+
 impl AllocationConfig {
   pub fn create_allocation_control(&self) -> AllocationControl {
     AllocationControl { 
@@ -195,7 +218,7 @@ pub fn get_password_file_entry_with_user_id(
   let mut buffer = vec![0; allocation_control.initial_allocation_size()];
   let mut result = ptr::null_mut::<passwd>();
 
-  loop {
+  let status = loop {
     let status = unsafe { 
       libc::getpwuid_r(
         user_id.inner(), 
@@ -207,7 +230,7 @@ pub fn get_password_file_entry_with_user_id(
     };
 
     if status != libc::ERANGE {
-      break;
+      break status;
     }
 
     let Some(increased_allocation_size) = allocation_control.next_allocation_size() else {
@@ -215,11 +238,15 @@ pub fn get_password_file_entry_with_user_id(
     };
 
     buffer.resize(increased_allocation_size, 0);
+  };
+
+  if result.is_null() && status == 0 {
+    // There is no such user.
+    return Err(GetPasswordFileEntryError::NoSuchUser);
   }
 
   if result.is_null() {
-    // There is no such user, or an error has occurred.
-    // errno gets set if there’s an error.
+    // An error has occurred.
     return Err(GetPasswordFileEntryError::SystemCallFailed);
   }
 
@@ -244,7 +271,7 @@ pub fn get_password_file_entry_with_user_name(
   let mut buffer = vec![0; allocation_control.initial_allocation_size()];
   let mut result = ptr::null_mut::<passwd>();
 
-  loop {
+  let status = loop {
     let status = unsafe {
       libc::getpwnam_r(
         user_name.inner().as_ptr(),
@@ -256,7 +283,7 @@ pub fn get_password_file_entry_with_user_name(
     };
 
     if status != libc::ERANGE {
-      break;
+      break status;
     }
 
     let Some(increased_allocation_size) = allocation_control.next_allocation_size() else {
@@ -264,11 +291,15 @@ pub fn get_password_file_entry_with_user_name(
     };
 
     buffer.resize(increased_allocation_size, 0);
+  };
+
+  if result.is_null() && status == 0 {
+    // There is no such user.
+    return Err(GetPasswordFileEntryError::NoSuchUser);
   }
 
   if result.is_null() {
-    // There is no such user, or an error has occurred.
-    // errno gets set if there’s an error.
+    // An error has occurred.
     return Err(GetPasswordFileEntryError::SystemCallFailed);
   }
 
@@ -299,7 +330,7 @@ pub fn get_group_file_entry_with_group_id(
   let mut buffer = vec![0; allocation_control.initial_allocation_size()];
   let mut result = ptr::null_mut::<group>();
 
-  loop {
+  let status = loop {
     let status = unsafe { 
       libc::getgrgid_r(
         group_id.inner(), 
@@ -311,7 +342,7 @@ pub fn get_group_file_entry_with_group_id(
     };
 
     if status != libc::ERANGE {
-      break;
+      break status;
     }
 
     let Some(increased_allocation_size) = allocation_control.next_allocation_size() else {
@@ -319,11 +350,15 @@ pub fn get_group_file_entry_with_group_id(
     };
 
     buffer.resize(increased_allocation_size, 0);
+  };
+
+  if result.is_null() && status == 0 {
+    // There is no such group.
+    return Err(GetGroupFileEntryError::NoSuchGroup);
   }
 
   if result.is_null() {
-    // There is no such group, or an error has occurred.
-    // errno gets set if there’s an error.
+    // An error has occurred.
     return Err(GetGroupFileEntryError::SystemCallFailed);
   }
 
@@ -348,7 +383,7 @@ pub fn get_group_file_entry_with_group_name(
   let mut buffer = vec![0; allocation_control.initial_allocation_size()];
   let mut result = ptr::null_mut::<group>();
 
-  loop {
+  let status = loop {
     let status = unsafe {
       libc::getgrnam_r(
         group_name.inner().as_ptr(),
@@ -360,7 +395,7 @@ pub fn get_group_file_entry_with_group_name(
     };
 
     if status != libc::ERANGE {
-      break;
+      break status;
     }
 
     let Some(increased_allocation_size) = allocation_control.next_allocation_size() else {
@@ -368,11 +403,15 @@ pub fn get_group_file_entry_with_group_name(
     };
 
     buffer.resize(increased_allocation_size, 0);
+  };
+
+  if result.is_null() && status == 0 {
+    // There is no such group.
+    return Err(GetGroupFileEntryError::NoSuchGroup);
   }
 
   if result.is_null() {
-    // There is no such group, or an error has occurred.
-    // errno gets set if there’s an error.
+    // An error has occurred.
     return Err(GetGroupFileEntryError::SystemCallFailed);
   }
 
@@ -436,11 +475,11 @@ struct PasswordFileIterator;
 /// # Examples
 ///
 /// ```
-/// use users::all_users;
+/// use discipline_daemon::launcher::all_users;
 ///
 /// let iter = unsafe { all_users() };
 /// for user in iter {
-///     println!("User #{} ({:?})", user.uid(), user.name());
+///     println!("User #{:?} ({:?})", user.user_id, user.user_name);
 /// }
 /// ```
 pub unsafe fn all_users() -> impl Iterator<Item = PasswordFileEntry> {
@@ -1,13 +1,17 @@
+// Fallible functions describe what went wrong through the
+// `textual_error` they're given and return `Result<_, ()>`.
+#![allow(clippy::result_unit_err)]
+
 // mod ui_text;
 
 mod rules;
 
-mod launcher;
+pub mod launcher;
 
 mod serializaton;
 
 mod vaults;
-mod chronic;
+pub mod chronic;
 mod other;
 mod conditionals;
 // pub mod rules;
@@ -15,25 +19,13 @@ mod conditionals;
 // pub mod operating_system;
 // pub mod users;
 // pub mod daemon;
-mod database;
+pub mod database;
 pub mod x;
-mod protocol;
+pub mod protocol;
 // pub mod procedures;
 // pub mod state;
 // pub mod vs;
 
-// #[tokio::main]
-// async fn main() {
-//   use crate::x::{Daemon, DaemonLaunchConfiguration};
-
-//   println!("Hi from main");
-//   let daemon = Daemon::open(DaemonLaunchConfiguration {
-//     api_server_port: 9090,
-//     database_directory: PathBuf::from("/workspaces/discipline/discipline_daemon_lib/data"),
-//   }).await.unwrap();
-
-//   daemon.start().await;
-// }
-
+pub mod procedures;
 
-mod procedures;
\ No newline at end of file
+pub use x::{IsTextualError, OptionalTextualErrorContext, TextualError, TextualErrorContext, ToTextualError};
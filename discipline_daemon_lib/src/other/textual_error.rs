@@ -169,7 +169,16 @@ impl TextualError {
   }
 
   pub fn optional_context(&mut self, new_context_action: impl Into<String>) -> OptionalTextualErrorContext<'_> {
-    todo!()
+    OptionalTextualErrorContext {
+      error: self,
+      action: new_context_action.into(),
+      messages: Vec::new(),
+      attachements: Vec::new(),
+    }
+  }
+
+  fn push_context(&mut self, context: TextualErrorContext) {
+    self.eariler_contexts.push(replace(&mut self.context, context));
   }
 
   pub fn change_context_optional(&mut self, new_context_action: impl Into<String>) {
@@ -285,24 +294,47 @@ pub trait IsTextualError {
   fn with_context(self, action: impl Into<String>) -> TextualError;
 }
 
+impl<'a> OptionalTextualErrorContext<'a> {
+  fn flush(&mut self) {
+    if self.messages.is_empty() && self.attachements.is_empty() {
+      return;
+    }
+
+    self.error.push_context(TextualErrorContext {
+      action: self.action.clone(),
+      messages: std::mem::take(&mut self.messages),
+      attachements: std::mem::take(&mut self.attachements),
+    });
+  }
+}
+
+// The context is only added to the error if something was written to it.
+impl<'a> Drop for OptionalTextualErrorContext<'a> {
+  fn drop(&mut self) {
+    self.flush();
+  }
+}
+
 impl<'a> IsTextualError for OptionalTextualErrorContext<'a> {
   fn add_attachement_debug(&mut self, name: impl Into<String>, value: impl Debug) {
-    todo!()
+    OptionalTextualErrorContext::add_attachement_debug(self, name, value);
   }
   fn add_attachement_display(&mut self, name: impl Into<String>, value: impl Display) {
-    todo!()
+    OptionalTextualErrorContext::add_attachement_display(self, name, value);
   }
   fn add_message(&mut self, new_error_message: impl Into<String>) {
-    todo!()
+    OptionalTextualErrorContext::add_message(self, new_error_message);
   }
   fn change_context(&mut self, new_context_action: impl Into<String>) {
-    todo!()
+    self.flush();
+    self.action = new_context_action.into();
   }
   fn new(action: impl Into<String>) -> Self {
-    todo!()
+    OptionalTextualErrorContext::new(action)
   }
   fn optional_context(&mut self, new_context_action: impl Into<String>) -> OptionalTextualErrorContext<'_> {
-    todo!()
+    self.flush();
+    self.error.optional_context(new_context_action)
   }
   fn with_attachement_debug(self, name: impl Into<String>, value: impl Debug) -> TextualError {
     todo!()
@@ -314,7 +346,40 @@ impl<'a> IsTextualError for OptionalTextualErrorContext<'a> {
     todo!()
   }
   fn with_message(self, message: impl Into<String>) -> Self {
-    todo!()
+    OptionalTextualErrorContext::with_message(self, message)
+  }
+}
+
+impl IsTextualError for TextualError {
+  fn new(action: impl Into<String>) -> Self {
+    TextualError::new(action)
+  }
+  fn add_message(&mut self, new_error_message: impl Into<String>) {
+    TextualError::add_message(self, new_error_message);
+  }
+  fn add_attachement_debug(&mut self, name: impl Into<String>, value: impl Debug) {
+    TextualError::add_attachement_debug(self, name, value);
+  }
+  fn add_attachement_display(&mut self, name: impl Into<String>, value: impl Display) {
+    TextualError::add_attachement_display(self, name, value);
+  }
+  fn change_context(&mut self, new_context_action: impl Into<String>) {
+    TextualError::change_context(self, new_context_action);
+  }
+  fn optional_context(&mut self, new_context_action: impl Into<String>) -> OptionalTextualErrorContext<'_> {
+    TextualError::optional_context(self, new_context_action)
+  }
+  fn with_message(self, message: impl Into<String>) -> Self {
+    TextualError::with_message(self, message)
+  }
+  fn with_attachement_debug(self, name: impl Into<String>, value: impl Debug) -> TextualError {
+    TextualError::with_attachement_debug(self, name, value)
+  }
+  fn with_attachement_display(self, name: impl Into<String>, value: impl Display) -> TextualError {
+    TextualError::with_attachement_display(self, name, value)
+  }
+  fn with_context(self, action: impl Into<String>) -> TextualError {
+    TextualError::with_context(self, action)
   }
 }
 
@@ -347,16 +412,3 @@ fn do_something_2(textual_error: &mut impl IsTextualError) -> Result<(), Error>
     Ok(())
   }
 }
-
-fn do_something_3(textual_error: &mut impl IsTextualError) -> Result<(), ()> {
-  let an_error_occured = true;
-  if an_error_occured {
-    return Err(
-      TextualError::new("Doing something")
-        .add_message("We were doing something, but something went wrong")
-        .add_attachement_display("Some attachement", "A tiny, 10-cm smol, 8yo, endearing automata boy with a back fan zoomed by just now")
-    )
-  } else {
-    Ok(())
-  }
-}
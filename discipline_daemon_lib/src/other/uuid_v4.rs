@@ -60,6 +60,12 @@ mod serialization {
     where
       S: Serializer,
     {
+      // As a string in formats like JSON, which only allow strings as
+      // map keys, and rules are kept in maps keyed by their ids.
+      if serializer.is_human_readable() {
+        return self.inner.hyphenated().to_string().serialize(serializer);
+      }
+
       self.inner.as_bytes().serialize(serializer)
     }
   }
@@ -69,6 +75,18 @@ mod serialization {
     where
       D: Deserializer<'a>,
     {
+      if deserializer.is_human_readable() {
+        let string = String::deserialize(deserializer)?;
+
+        return UuidV4::from_string(&string).map_err(|_| {
+          Error::custom(
+            TextualError::new("Creating UuidV4 from String")
+              .with_attachement_display("String", string)
+              .with_context("Deserializing UuidV4, which is serialized as String")
+          )
+        });
+      }
+
       let bytes = Vec::<u8>::deserialize(deserializer).map_err(|error| {
         Error::custom(
           TextualError::new("Deserializing Vec<u8>")
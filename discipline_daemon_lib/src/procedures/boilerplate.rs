@@ -8,8 +8,8 @@ pub enum CountdownConditionalLocation<'a> {
 
 }
 pub enum CountdownAfterPleaConditionalLocation<'a> {
-  A(&'a str)
-
+  A(&'a str),
+  VaultProtector { vault_id: &'a UuidV4 },
 }
 
 pub enum AlwaysRuleLocation<'a> {
@@ -1,6 +1,7 @@
 mod countdown_conditional;
 mod countdown_after_plea_conditional;
 pub mod always_rule;
+pub mod vault;
 // pub mod time_range_rule;
 
 mod boilerplate;
@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use crate::x::{DateTime, Database, IsTextualError, MonotonicClock, UuidV4, Vault, VaultDatum, VaultQuotaViolation, VaultReveal, VaultState, Vaults, VaultsStats};
+use crate::x::database::{CountdownAfterPleaConditionalDbAdapter, CountdownAfterPleaConditionalDbAdapterError};
+use crate::x::database::{vault_data_table, vault_reveal_table, vault_table};
+use crate::x::procedures::CountdownAfterPleaConditionalLocation;
+
+pub enum OpenReturn {
+  NoSuchVault,
+  Locked,
+  Unlocking,
+  AlreadyRevealed,
+  InternalError,
+  Success,
+}
+
+// Starts a reveal window for a vault whose protector finished
+// counting down. The reveal is recorded before the window starts,
+// so a reveal that couldn't be recorded never happens.
+pub fn open(
+  database: &Database,
+  vaults: &mut Vaults,
+  vault_id: &UuidV4,
+  clock: &MonotonicClock,
+  textual_error: &mut impl IsTextualError,
+) -> OpenReturn {
+  let Some(vault) = vaults.get_vault_given_id_mut(vault_id) else {
+    return OpenReturn::NoSuchVault;
+  };
+
+  let now = clock.now();
+  match vault.get_state(now) {
+    VaultState::Locked => {
+      return OpenReturn::Locked;
+    }
+    VaultState::Unlocking => {
+      return OpenReturn::Unlocking;
+    }
+    VaultState::Revealed => {
+      return OpenReturn::AlreadyRevealed;
+    }
+    VaultState::Unlocked => {}
+  }
+
+  let reveal_state = vault.create_reveal_state(now);
+  let reveal_id = UuidV4::generate();
+  let reveal = VaultReveal {
+    vault_id: vault_id.clone(),
+    revealed_at: DateTime::now(),
+    window: reveal_state.window.clone(),
+  };
+
+  if let Err(error) = vault_reveal_table::insert_reveal(
+    database,
+    &reveal_id,
+    &reveal,
+    textual_error,
+  ) {
+    return match error {
+      vault_reveal_table::InsertError::DuplicateRevealId => {
+        OpenReturn::InternalError
+      }
+      vault_reveal_table::InsertError::Other => {
+        OpenReturn::InternalError
+      }
+    };
+  }
+
+  // The window is saved too, or a restart would end it early.
+  if vault_table::update_reveal_window(
+    database,
+    vault_id,
+    Some(&reveal_state.window),
+    textual_error,
+  ).is_err() {
+    return OpenReturn::InternalError;
+  }
+
+  vault.reveal_from_reveal_state(reveal_state);
+  OpenReturn::Success
+}
+
+pub enum ReadDataReturn<'a> {
+  NoSuchVault,
+  NotRevealed,
+  Success(&'a HashMap<UuidV4, VaultDatum>),
+}
+
+pub fn read_data<'a>(
+  vaults: &'a Vaults,
+  vault_id: &UuidV4,
+  clock: &MonotonicClock,
+) -> ReadDataReturn<'a> {
+  let Some(vault) = vaults.get_vault_given_id(vault_id) else {
+    return ReadDataReturn::NoSuchVault;
+  };
+
+  match vault.get_data(clock.now()) {
+    Some(data) => {
+      ReadDataReturn::Success(data)
+    }
+    None => {
+      ReadDataReturn::NotRevealed
+    }
+  }
+}
+
+pub enum RelockReturn {
+  RevealWindowStillRunning,
+  Database(CountdownAfterPleaConditionalDbAdapterError),
+  Success,
+}
+
+pub fn relock(
+  database: &Database,
+  adapter: &CountdownAfterPleaConditionalDbAdapter,
+  vault_id: &UuidV4,
+  vault: &mut Vault,
+  clock: &MonotonicClock,
+  textual_error: &mut impl IsTextualError,
+) -> RelockReturn {
+  if vault.is_revealed(clock.now()) {
+    return RelockReturn::RevealWindowStillRunning;
+  }
+
+  if let Err(error) = adapter.activate(
+    database,
+    &CountdownAfterPleaConditionalLocation::VaultProtector { vault_id },
+    textual_error,
+  ) {
+    return RelockReturn::Database(error);
+  }
+
+  // If this fails, the protector is re-armed but the finished window
+  // is still saved, so the vault is relocked again next time.
+  if vault_table::update_reveal_window(
+    database,
+    vault_id,
+    None,
+    textual_error,
+  ).is_err() {
+    return RelockReturn::Database(CountdownAfterPleaConditionalDbAdapterError::Other);
+  }
+
+  vault.relock();
+  RelockReturn::Success
+}
+
+// Re-arms the protector of every vault whose reveal window is over,
+// and returns the vaults that were relocked. This is meant to run
+// periodically and once when the daemon starts, since reveal windows
+// may have ended while the daemon was down.
+pub fn relock_finished_reveals(
+  database: &Database,
+  adapter: &CountdownAfterPleaConditionalDbAdapter,
+  vaults: &mut Vaults,
+  clock: &MonotonicClock,
+  textual_error: &mut impl IsTextualError,
+) -> Vec<UuidV4> {
+  let now = clock.now();
+  let mut relocked_vaults = Vec::new();
+
+  for vault_id in vaults.get_vaults_that_need_relock(now) {
+    let Some(vault) = vaults.get_vault_given_id_mut(&vault_id) else {
+      continue;
+    };
+
+    // On failure the vault stays in the "Locked" state anyway, since
+    // its reveal window is over, and we try again on the next run.
+    if let RelockReturn::Success = relock(
+      database,
+      adapter,
+      &vault_id,
+      vault,
+      clock,
+      textual_error,
+    ) {
+      relocked_vaults.push(vault_id);
+    }
+  }
+
+  relocked_vaults
+}
+
+pub enum AddDatumReturn {
+  NoSuchVault,
+  QuotaViolation(VaultQuotaViolation),
+  DuplicateDatumId,
+  InternalError,
+  Success,
+}
+
+// Data may be added to a vault at any time, even while it's locked,
+// since adding data doesn't reveal anything.
+pub fn add_datum(
+  database: &Database,
+  vaults: &mut Vaults,
+  vaults_stats: &mut VaultsStats,
+  vault_id: &UuidV4,
+  datum_id: Option<UuidV4>,
+  datum: VaultDatum,
+  textual_error: &mut impl IsTextualError,
+) -> AddDatumReturn {
+  let Some(vault) = vaults.get_vault_given_id_mut(vault_id) else {
+    return AddDatumReturn::NoSuchVault;
+  };
+
+  if let Err(violation) = vaults_stats.check_datum_quotas(vault, &datum, None) {
+    return AddDatumReturn::QuotaViolation(violation);
+  }
+
+  let client_created_datum_id = datum_id.is_some();
+  let datum_id = datum_id.unwrap_or_else(UuidV4::generate);
+
+  if let Err(error) = vault_data_table::insert_datum(
+    database,
+    vault_id,
+    &datum_id,
+    &datum,
+    textual_error,
+  ) {
+    return match error {
+      vault_data_table::InsertError::DuplicateDatumId if client_created_datum_id => {
+        AddDatumReturn::DuplicateDatumId
+      }
+      vault_data_table::InsertError::DuplicateDatumId => {
+        AddDatumReturn::InternalError
+      }
+      vault_data_table::InsertError::Other => {
+        AddDatumReturn::InternalError
+      }
+    };
+  }
+
+  vaults_stats.update_after_datum_added(datum.get_size());
+  vault.set_datum(datum_id, datum);
+  AddDatumReturn::Success
+}
+
+pub enum DeleteDatumReturn {
+  NoSuchVault,
+  NoSuchDatum,
+  NotRevealed,
+  InternalError,
+  Success,
+}
+
+// Deleting data is only allowed while the vault is revealed. Otherwise,
+// deleting and re-adding a datum would be a way around its protector.
+pub fn delete_datum(
+  database: &Database,
+  vaults: &mut Vaults,
+  vaults_stats: &mut VaultsStats,
+  vault_id: &UuidV4,
+  datum_id: &UuidV4,
+  clock: &MonotonicClock,
+  textual_error: &mut impl IsTextualError,
+) -> DeleteDatumReturn {
+  let Some(vault) = vaults.get_vault_given_id_mut(vault_id) else {
+    return DeleteDatumReturn::NoSuchVault;
+  };
+
+  if !vault.is_revealed(clock.now()) {
+    return DeleteDatumReturn::NotRevealed;
+  }
+
+  if !vault.data.contains_key(datum_id) {
+    return DeleteDatumReturn::NoSuchDatum;
+  }
+
+  if vault_data_table::delete_datum(database, datum_id, textual_error).is_err() {
+    return DeleteDatumReturn::InternalError;
+  }
+
+  if let Some(datum) = vault.delete_datum(datum_id) {
+    vaults_stats.update_after_datum_deleted(datum.get_size());
+  }
+
+  DeleteDatumReturn::Success
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use crate::x::{Countdown, CountdownAfterPleaConditional, Duration, Instant, TextualError, VaultName, VaultProtector};
+  use super::*;
+
+  fn create_clock(elapsed_time: Duration) -> MonotonicClock {
+    let mut clock = MonotonicClock::create(
+      Instant::from_timestamp(0), 
+      Instant::from_timestamp(0), 
+      Duration::MINUTE,
+    );
+
+    clock.total_elapsed_duration = elapsed_time;
+    clock
+  }
+
+  // A vault whose protector was pleaded for at the start of the clock
+  // and takes an hour to count down.
+  fn create_vault(protector_countdown: Option<Countdown>) -> Vault {
+    let mut vault = Vault::create(
+      VaultName::new("Recovery codes".to_string()).unwrap(),
+      VaultProtector::CountdownAfterPlea(CountdownAfterPleaConditional::construct(
+        Duration::HOUR,
+        protector_countdown,
+      )),
+    );
+
+    vault.set_datum(UuidV4::generate(), VaultDatum::new("123-456".to_string()).unwrap());
+    vault
+  }
+
+  fn create_pleaded_vault() -> Vault {
+    create_vault(Some(Countdown::construct(Instant::from_timestamp(0), Duration::HOUR)))
+  }
+
+  fn add_vault(database: &Database, vaults: &mut Vaults, vault: Vault) -> UuidV4 {
+    let vault_id = UuidV4::generate();
+    let mut textual_error = TextualError::new("Adding a vault for a test");
+    assert!(vault_table::insert_vault(database, &vault_id, &vault, &mut textual_error).is_ok());
+    vaults.add_vault(vault_id.clone(), vault);
+    vault_id
+  }
+
+  fn load_saved_vault(database: &Database, vault_id: &UuidV4) -> Vault {
+    let mut textual_error = TextualError::new("Loading vaults for a test");
+    let Ok(mut vaults) = vault_table::load_vaults(database, &mut textual_error) else {
+      panic!("{textual_error}");
+    };
+    vaults.remove(vault_id).unwrap()
+  }
+
+  #[test]
+  fn opening_an_unlocked_vault_reveals_its_data_and_saves_the_window() {
+    let mut textual_error = TextualError::new("Testing");
+    let database = Database::open_in_memory(&mut textual_error).unwrap();
+    let mut vaults = Vaults::new();
+    let vault_id = add_vault(&database, &mut vaults, create_pleaded_vault());
+    let clock = create_clock(Duration::HOUR.saturating_add(Duration::SECOND));
+
+    assert!(matches!(read_data(&vaults, &vault_id, &clock), ReadDataReturn::NotRevealed));
+    assert!(matches!(
+      open(&database, &mut vaults, &vault_id, &clock, &mut textual_error),
+      OpenReturn::Success
+    ));
+    assert!(matches!(read_data(&vaults, &vault_id, &clock), ReadDataReturn::Success(data) if data.len() == 1));
+
+    let saved_window = load_saved_vault(&database, &vault_id).reveal_window.unwrap();
+    assert_eq!(saved_window.get_from(), clock.now());
+    assert_eq!(saved_window.get_total_duration(), Vault::DEFAULT_REVEAL_DURATION);
+  }
+
+  #[test]
+  fn only_unlocked_vaults_may_be_opened() {
+    let mut textual_error = TextualError::new("Testing");
+    let database = Database::open_in_memory(&mut textual_error).unwrap();
+    let mut vaults = Vaults::new();
+    let locked_vault_id = add_vault(&database, &mut vaults, create_vault(None));
+    let unlocking_vault_id = add_vault(&database, &mut vaults, create_pleaded_vault());
+    let clock = create_clock(Duration::MINUTE);
+
+    assert!(matches!(
+      open(&database, &mut vaults, &locked_vault_id, &clock, &mut textual_error),
+      OpenReturn::Locked
+    ));
+    assert!(matches!(
+      open(&database, &mut vaults, &unlocking_vault_id, &clock, &mut textual_error),
+      OpenReturn::Unlocking
+    ));
+    assert!(matches!(
+      open(&database, &mut vaults, &UuidV4::generate(), &clock, &mut textual_error),
+      OpenReturn::NoSuchVault
+    ));
+  }
+
+  #[test]
+  fn a_running_reveal_window_is_neither_reopened_nor_relocked() {
+    let mut textual_error = TextualError::new("Testing");
+    let database = Database::open_in_memory(&mut textual_error).unwrap();
+    let mut vaults = Vaults::new();
+    let vault_id = add_vault(&database, &mut vaults, create_pleaded_vault());
+    let clock = create_clock(Duration::HOUR.saturating_add(Duration::SECOND));
+    let adapter = CountdownAfterPleaConditionalDbAdapter {};
+
+    open(&database, &mut vaults, &vault_id, &clock, &mut textual_error);
+
+    assert!(matches!(
+      open(&database, &mut vaults, &vault_id, &clock, &mut textual_error),
+      OpenReturn::AlreadyRevealed
+    ));
+    assert!(matches!(
+      relock(&database, &adapter, &vault_id, vaults.get_vault_given_id_mut(&vault_id).unwrap(), &clock, &mut textual_error),
+      RelockReturn::RevealWindowStillRunning
+    ));
+    assert!(relock_finished_reveals(&database, &adapter, &mut vaults, &clock, &mut textual_error).is_empty());
+  }
+
+  #[test]
+  fn vaults_relock_once_their_reveal_window_is_over() {
+    let mut textual_error = TextualError::new("Testing");
+    let database = Database::open_in_memory(&mut textual_error).unwrap();
+    let mut vaults = Vaults::new();
+    let vault_id = add_vault(&database, &mut vaults, create_pleaded_vault());
+    let mut clock = create_clock(Duration::HOUR.saturating_add(Duration::SECOND));
+    let adapter = CountdownAfterPleaConditionalDbAdapter {};
+
+    open(&database, &mut vaults, &vault_id, &clock, &mut textual_error);
+    clock.total_elapsed_duration = clock
+      .total_elapsed_duration
+      .saturating_add(Vault::DEFAULT_REVEAL_DURATION);
+
+    // Closed as soon as the window ends, even before the protector is
+    // re-armed.
+    let vault = vaults.get_vault_given_id(&vault_id).unwrap();
+    assert_eq!(vault.get_state(clock.now()), VaultState::Locked);
+    assert!(matches!(read_data(&vaults, &vault_id, &clock), ReadDataReturn::NotRevealed));
+
+    let relocked_vaults = relock_finished_reveals(&database, &adapter, &mut vaults, &clock, &mut textual_error);
+    assert_eq!(relocked_vaults, vec![vault_id.clone()]);
+
+    let vault = vaults.get_vault_given_id(&vault_id).unwrap();
+    assert!(vault.reveal_window.is_none());
+    assert_eq!(vault.get_state(clock.now()), VaultState::Locked);
+
+    // A restart finds the vault locked, and a new plea is needed.
+    let saved_vault = load_saved_vault(&database, &vault_id);
+    assert!(saved_vault.reveal_window.is_none());
+    assert_eq!(saved_vault.get_state(clock.now()), VaultState::Locked);
+    assert!(relock_finished_reveals(&database, &adapter, &mut vaults, &clock, &mut textual_error).is_empty());
+  }
+
+  #[test]
+  fn reveal_windows_that_ended_while_the_daemon_was_down_are_relocked() {
+    let mut textual_error = TextualError::new("Testing");
+    let database = Database::open_in_memory(&mut textual_error).unwrap();
+    let mut vaults = Vaults::new();
+    let vault_id = add_vault(&database, &mut vaults, create_pleaded_vault());
+    let clock = create_clock(Duration::HOUR.saturating_add(Duration::SECOND));
+    let adapter = CountdownAfterPleaConditionalDbAdapter {};
+
+    open(&database, &mut vaults, &vault_id, &clock, &mut textual_error);
+
+    let mut vaults = Vaults::construct(HashMap::from([
+      (vault_id.clone(), load_saved_vault(&database, &vault_id)),
+    ]));
+
+    let later = create_clock(Duration::DAY);
+    assert_eq!(
+      relock_finished_reveals(&database, &adapter, &mut vaults, &later, &mut textual_error),
+      vec![vault_id.clone()]
+    );
+    assert!(load_saved_vault(&database, &vault_id).reveal_window.is_none());
+  }
+}
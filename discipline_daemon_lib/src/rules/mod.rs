@@ -36,6 +36,42 @@ impl RuleEnabler {
     }
   }
 
+  // How long until the rule becomes enabled without anyone acting on
+  // it, zero if it already is, and None if it won't.
+  pub fn get_duration_till_enabled(&self, now: Instant) -> Option<Duration> {
+    match self {
+      Self::Countdown(enabler) => {
+        enabler.get_duration_till_active(now)
+      }
+      Self::CountdownAfterPlea(enabler) => {
+        if enabler.is_activate_or_deactivating(now) {
+          Some(Duration::zero())
+        } else {
+          None
+        }
+      }
+    }
+  }
+
+  // How long until the rule stops being enabled without anyone acting
+  // on it, or None if it stays enabled until someone does.
+  pub fn get_duration_till_disabled(&self, now: Instant) -> Option<Duration> {
+    match self {
+      Self::Countdown(enabler) => {
+        enabler
+          .countdown
+          .as_ref()
+          .map(|countdown| countdown.get_time_till_finish_or_zero(now))
+      }
+      Self::CountdownAfterPlea(enabler) => {
+        enabler
+          .countdown
+          .as_ref()
+          .map(|countdown| countdown.get_time_till_finish_or_zero(now))
+      }
+    }
+  }
+
   pub fn enable(&mut self, now: Instant) {
     match self {
       Self::Countdown(enabler) => {
@@ -79,6 +115,57 @@ impl TimeRangeRule {
     &&
     self.condition.contains(time)
   }
+
+  // How long until the rule stops being active, which is when the
+  // current time of day leaves its range unless it's disabled first.
+  pub fn get_duration_till_deactivation(
+    &self,
+    time: Time,
+    instant: Instant,
+  ) -> Duration {
+    let now = time.as_elapsed_time();
+    let till = self.condition.till().as_elapsed_time();
+    let till_range_end = if till.is_longer_than(now) {
+      till.saturating_sub(now)
+    } else {
+      Duration::day().saturating_sub(now).saturating_add(till)
+    };
+
+    match self.enabler.get_duration_till_disabled(instant) {
+      Some(till_disabled) => {
+        till_disabled.min(till_range_end)
+      }
+      None => {
+        till_range_end
+      }
+    }
+  }
+
+  // How long until the current time of day enters the rule's range,
+  // provided the rule is still enabled by then.
+  pub fn get_duration_till_activation(
+    &self,
+    time: Time,
+    instant: Instant,
+  ) -> Option<Duration> {
+    if self.is_activated(time, instant) {
+      return Some(Duration::zero());
+    }
+
+    let now = time.as_elapsed_time();
+    let from = self.condition.from().as_elapsed_time();
+    let duration = if from.is_longer_than(now) {
+      from.saturating_sub(now)
+    } else {
+      Duration::day().saturating_sub(now).saturating_add(from)
+    };
+
+    if self.enabler.is_rule_enabled(instant.saturating_add(duration)) {
+      Some(duration)
+    } else {
+      None
+    }
+  }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -93,6 +180,18 @@ impl TimeRangeRules {
     }
   }
 
+  // Adds the other collection's rules, which then count like this
+  // collection's own.
+  pub fn merge(&mut self, other: &Self) {
+    for (rule_id, rule) in &other.rules {
+      self.rules.insert(rule_id.clone(), rule.clone());
+    }
+  }
+
+  pub fn get_rules(&self) -> impl Iterator<Item = &TimeRangeRule> {
+    self.rules.values()
+  }
+
   pub fn are_some_active(
     &self,
     time: Time,
@@ -102,6 +201,33 @@ impl TimeRangeRules {
       rule.is_activated(time, instant)
     })
   }
+
+  pub fn get_duration_till_some_activate(
+    &self,
+    time: Time,
+    instant: Instant,
+  ) -> Option<Duration> {
+    self
+      .rules
+      .values()
+      .filter_map(|rule| rule.get_duration_till_activation(time, instant))
+      .min()
+  }
+
+  // How long until none of the active rules are. Ranges that follow
+  // each other without a gap aren't joined.
+  pub fn get_duration_till_all_deactivate(
+    &self,
+    time: Time,
+    instant: Instant,
+  ) -> Option<Duration> {
+    self
+      .rules
+      .values()
+      .filter(|rule| rule.is_activated(time, instant))
+      .map(|rule| rule.get_duration_till_deactivation(time, instant))
+      .max()
+  }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -121,6 +247,10 @@ impl AlwaysRule {
   pub fn is_active(&self, now: Instant) -> bool {
     self.enabler.is_rule_enabled(now)
   }
+
+  pub fn get_duration_till_activation(&self, now: Instant) -> Option<Duration> {
+    self.enabler.get_duration_till_enabled(now)
+  }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -135,13 +265,40 @@ impl AlwaysRules {
     }
   }
 
+  pub fn merge(&mut self, other: &Self) {
+    for (rule_id, rule) in &other.rules {
+      self.rules.insert(rule_id.clone(), rule.clone());
+    }
+  }
+
   pub fn are_some_active(&self, now: Instant) -> bool {
     self.rules.values().any(|rule| {
       rule.is_active(now)
     })
   }
+
+  pub fn get_duration_till_some_activate(&self, now: Instant) -> Option<Duration> {
+    self
+      .rules
+      .values()
+      .filter_map(|rule| rule.get_duration_till_activation(now))
+      .min()
+  }
+
+  // How long until none of the active rules are, or None if one of
+  // them stays active until someone acts.
+  pub fn get_duration_till_all_deactivate(&self, now: Instant) -> Option<Duration> {
+    let mut longest = Duration::zero();
+
+    for rule in self.rules.values().filter(|rule| rule.is_active(now)) {
+      longest = longest.max(rule.enabler.get_duration_till_disabled(now)?);
+    }
+
+    Some(longest)
+  }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimeAllowanceRule {
   pub enabler: RuleEnabler,
   pub allowance: Duration,
@@ -167,11 +324,57 @@ impl TimeAllowanceRule {
     &&
     used_allowance.is_longer_than_or_equal_to(self.allowance)
   }
+
+  // How long until the allowance runs out if it keeps being used,
+  // provided the rule is still enabled by then.
+  pub fn get_duration_till_exhaustion(&self, now: Instant, used_allowance: Duration) -> Option<Duration> {
+    let remaining = self.allowance.saturating_sub(used_allowance);
+
+    if self.is_enabled(now.saturating_add(remaining)) {
+      Some(remaining)
+    } else {
+      None
+    }
+  }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TimeAllowanceRules {
-  rules: HashMap<UuidV4, TimeAllowanceRules>,
+  rules: HashMap<UuidV4, TimeAllowanceRule>,
+}
+
+impl TimeAllowanceRules {
+  pub fn new() -> Self {
+    Self {
+      rules: HashMap::new(),
+    }
+  }
+
+  pub fn construct(rules: HashMap<UuidV4, TimeAllowanceRule>) -> Self {
+    Self {
+      rules,
+    }
+  }
+
+  pub fn merge(&mut self, other: &Self) {
+    for (rule_id, rule) in &other.rules {
+      self.rules.insert(rule_id.clone(), rule.clone());
+    }
+  }
+
+  pub fn are_some_active(&self, now: Instant, used_allowance: Duration) -> bool {
+    self.rules.values().any(|rule| {
+      rule.is_active(now, used_allowance)
+    })
+  }
+
+  pub fn get_duration_till_some_exhaust(&self, now: Instant, used_allowance: Duration) -> Option<Duration> {
+    self
+      .rules
+      .values()
+      .filter_map(|rule| rule.get_duration_till_exhaustion(now, used_allowance))
+      .min()
+  }
 }
 
 
@@ -182,6 +385,8 @@ pub struct RulesStats {
 }
 
 impl RulesStats {
+  pub const DEFAULT_MAXIMUM_RULES_NUMBER: usize = 500;
+
   pub fn new(maximum_rules_number: usize) -> Self {
     Self {
       rules_number: 0,
@@ -1,5 +1,6 @@
+use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
-use crate::x::{CountdownAfterPleaConditional, IsTextualError};
+use crate::x::{Countdown, CountdownAfterPleaConditional, CountdownAfterPleaConditionalState, DateTime, Duration, Instant, IsTextualError, TextualErrorContext, ToTextualError, UuidV4};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VaultName {
@@ -11,16 +12,33 @@ pub enum CreateVaultNameFromStringError {
   LengthViolation { string: String }
 }
 
+impl ToTextualError for CreateVaultNameFromStringError {
+  fn to_textual_error_context(&self) -> TextualErrorContext {
+    let mut context = TextualErrorContext::new("Creating VaultName from String");
+
+    match self {
+      Self::LengthViolation { string } => {
+        context.add_message("String length is invalid");
+        context.add_attachement_display("Minimum valid length", VaultName::MINIMUM_LENGTH);
+        context.add_attachement_display("Maximum valid length", VaultName::MAXIMUM_LENGTH);
+        context.add_attachement_display("Found string length", string.len());
+      }
+    }
+
+    context
+  }
+}
+
 impl VaultName {
   pub const MINIMUM_LENGTH: usize = 1;
   pub const MAXIMUM_LENGTH: usize = 300;
 
   pub fn new(string: String) -> Result<Self, CreateVaultNameFromStringError> {
     if string.len() < Self::MINIMUM_LENGTH {
-      return Ok(Self { string });
+      return Err(CreateVaultNameFromStringError::LengthViolation { string });
     }
     if string.len() > Self::MAXIMUM_LENGTH {
-      return Ok(Self { string });
+      return Err(CreateVaultNameFromStringError::LengthViolation { string });
     }
     Ok(Self { string })
   }
@@ -32,9 +50,49 @@ impl AsRef<str> for VaultName {
   }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultKeyValueEntry {
+  pub label: String,
+  pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VaultDatumContent {
+  Text(String),
+  // A file such as a key file or a PDF of 2FA recovery codes.
+  Blob { file_name: String, bytes: Vec<u8> },
+  KeyValue(Vec<VaultKeyValueEntry>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VaultDatumVariant {
+  Text,
+  Blob,
+  KeyValue,
+}
+
+impl VaultDatumVariant {
+  pub fn from_number(number: u8) -> Option<Self> {
+    match number {
+      0 => Some(Self::Text),
+      1 => Some(Self::Blob),
+      2 => Some(Self::KeyValue),
+      _ => None,
+    }
+  }
+
+  pub fn to_number(self) -> u8 {
+    match self {
+      Self::Text => 0,
+      Self::Blob => 1,
+      Self::KeyValue => 2,
+    }
+  }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VaultDatum {
-  pub(super) string: String,
+  pub(super) content: VaultDatumContent,
 }
 
 #[derive(Debug, Clone)]
@@ -42,24 +100,199 @@ pub enum CreateVaultDatumFromStringError {
   LengthViolation { string: String }
 }
 
+impl ToTextualError for CreateVaultDatumFromStringError {
+  fn to_textual_error_context(&self) -> TextualErrorContext {
+    let mut context = TextualErrorContext::new("Creating a text VaultDatum from String");
+
+    match self {
+      Self::LengthViolation { string } => {
+        context.add_message("String length is invalid");
+        context.add_attachement_display("Minimum valid length", VaultDatum::MINIMUM_TEXT_LENGTH);
+        context.add_attachement_display("Maximum valid length", VaultDatum::MAXIMUM_TEXT_LENGTH);
+        context.add_attachement_display("Found string length", string.len());
+      }
+    }
+
+    context
+  }
+}
+
+#[derive(Debug, Clone)]
+pub enum CreateVaultDatumFromBlobError {
+  FileNameLengthViolation { file_name: String },
+  SizeViolation { size: usize },
+}
+
+impl ToTextualError for CreateVaultDatumFromBlobError {
+  fn to_textual_error_context(&self) -> TextualErrorContext {
+    let mut context = TextualErrorContext::new("Creating a blob VaultDatum from a file");
+
+    match self {
+      Self::FileNameLengthViolation { file_name } => {
+        context.add_message("File name length is invalid");
+        context.add_attachement_display("Minimum valid length", VaultDatum::MINIMUM_FILE_NAME_LENGTH);
+        context.add_attachement_display("Maximum valid length", VaultDatum::MAXIMUM_FILE_NAME_LENGTH);
+        context.add_attachement_display("Found file name length", file_name.len());
+      }
+      Self::SizeViolation { size } => {
+        context.add_message("File size is invalid");
+        context.add_attachement_display("Minimum valid size", VaultDatum::MINIMUM_BLOB_SIZE);
+        context.add_attachement_display("Maximum valid size", VaultDatum::MAXIMUM_BLOB_SIZE);
+        context.add_attachement_display("Found size", size);
+      }
+    }
+
+    context
+  }
+}
+
+#[derive(Debug, Clone)]
+pub enum CreateVaultDatumFromEntriesError {
+  EntryNumberViolation { number: usize },
+  LabelLengthViolation { label: String },
+  ValueLengthViolation { label: String, length: usize },
+  DuplicateLabel { label: String },
+}
+
+impl ToTextualError for CreateVaultDatumFromEntriesError {
+  fn to_textual_error_context(&self) -> TextualErrorContext {
+    let mut context = TextualErrorContext::new("Creating a key-value VaultDatum from entries");
+
+    match self {
+      Self::EntryNumberViolation { number } => {
+        context.add_message("Entry number is invalid");
+        context.add_attachement_display("Minimum valid number", VaultDatum::MINIMUM_ENTRY_NUMBER);
+        context.add_attachement_display("Maximum valid number", VaultDatum::MAXIMUM_ENTRY_NUMBER);
+        context.add_attachement_display("Found number", number);
+      }
+      Self::LabelLengthViolation { label } => {
+        context.add_message("Entry label length is invalid");
+        context.add_attachement_display("Minimum valid length", VaultDatum::MINIMUM_LABEL_LENGTH);
+        context.add_attachement_display("Maximum valid length", VaultDatum::MAXIMUM_LABEL_LENGTH);
+        context.add_attachement_display("Found label length", label.len());
+      }
+      Self::ValueLengthViolation { label, length } => {
+        context.add_message("Entry value length is invalid");
+        context.add_attachement_display("Label", label);
+        context.add_attachement_display("Minimum valid length", VaultDatum::MINIMUM_TEXT_LENGTH);
+        context.add_attachement_display("Maximum valid length", VaultDatum::MAXIMUM_TEXT_LENGTH);
+        context.add_attachement_display("Found value length", length);
+      }
+      Self::DuplicateLabel { label } => {
+        context.add_message("Two entries have the same label");
+        context.add_attachement_display("Label", label);
+      }
+    }
+
+    context
+  }
+}
+
 impl VaultDatum {
-  pub const MINIMUM_LENGTH: usize = 1;
-  pub const MAXIMUM_LENGTH: usize = 10000;
+  pub const MINIMUM_TEXT_LENGTH: usize = 1;
+  pub const MAXIMUM_TEXT_LENGTH: usize = 10000;
+  pub const MINIMUM_FILE_NAME_LENGTH: usize = 1;
+  pub const MAXIMUM_FILE_NAME_LENGTH: usize = 255;
+  pub const MINIMUM_BLOB_SIZE: usize = 1;
+  pub const MAXIMUM_BLOB_SIZE: usize = 1024 * 1024;
+  pub const MINIMUM_ENTRY_NUMBER: usize = 1;
+  pub const MAXIMUM_ENTRY_NUMBER: usize = 100;
+  pub const MINIMUM_LABEL_LENGTH: usize = 1;
+  pub const MAXIMUM_LABEL_LENGTH: usize = 300;
 
   pub fn new(string: String) -> Result<Self, CreateVaultDatumFromStringError> {
-    if string.len() < Self::MINIMUM_LENGTH {
-      return Ok(Self { string });
+    if string.len() < Self::MINIMUM_TEXT_LENGTH {
+      return Err(CreateVaultDatumFromStringError::LengthViolation { string });
     }
-    if string.len() > Self::MAXIMUM_LENGTH {
-      return Ok(Self { string });
+    if string.len() > Self::MAXIMUM_TEXT_LENGTH {
+      return Err(CreateVaultDatumFromStringError::LengthViolation { string });
     }
-    Ok(Self { string })
+    Ok(Self { content: VaultDatumContent::Text(string) })
   }
-}
 
-impl AsRef<str> for VaultDatum {
-  fn as_ref(&self) -> &str {
-    &self.string
+  pub fn new_blob(file_name: String, bytes: Vec<u8>) -> Result<Self, CreateVaultDatumFromBlobError> {
+    if file_name.len() < Self::MINIMUM_FILE_NAME_LENGTH 
+    || file_name.len() > Self::MAXIMUM_FILE_NAME_LENGTH 
+    {
+      return Err(CreateVaultDatumFromBlobError::FileNameLengthViolation { file_name });
+    }
+    if bytes.len() < Self::MINIMUM_BLOB_SIZE 
+    || bytes.len() > Self::MAXIMUM_BLOB_SIZE 
+    {
+      return Err(CreateVaultDatumFromBlobError::SizeViolation { size: bytes.len() });
+    }
+    Ok(Self { content: VaultDatumContent::Blob { file_name, bytes } })
+  }
+
+  pub fn new_key_value(entries: Vec<VaultKeyValueEntry>) -> Result<Self, CreateVaultDatumFromEntriesError> {
+    if entries.len() < Self::MINIMUM_ENTRY_NUMBER 
+    || entries.len() > Self::MAXIMUM_ENTRY_NUMBER 
+    {
+      return Err(CreateVaultDatumFromEntriesError::EntryNumberViolation { number: entries.len() });
+    }
+
+    for (index, entry) in entries.iter().enumerate() {
+      if entry.label.len() < Self::MINIMUM_LABEL_LENGTH 
+      || entry.label.len() > Self::MAXIMUM_LABEL_LENGTH 
+      {
+        return Err(CreateVaultDatumFromEntriesError::LabelLengthViolation { label: entry.label.clone() });
+      }
+      if entry.value.len() < Self::MINIMUM_TEXT_LENGTH 
+      || entry.value.len() > Self::MAXIMUM_TEXT_LENGTH 
+      {
+        return Err(CreateVaultDatumFromEntriesError::ValueLengthViolation { 
+          label: entry.label.clone(), 
+          length: entry.value.len(),
+        });
+      }
+      if entries[..index].iter().any(|other| other.label == entry.label) {
+        return Err(CreateVaultDatumFromEntriesError::DuplicateLabel { label: entry.label.clone() });
+      }
+    }
+
+    Ok(Self { content: VaultDatumContent::KeyValue(entries) })
+  }
+
+  // For content that was validated already, like saved content.
+  pub fn construct(content: VaultDatumContent) -> Self {
+    Self { content }
+  }
+
+  pub fn get_content(&self) -> &VaultDatumContent {
+    &self.content
+  }
+
+  pub fn get_variant(&self) -> VaultDatumVariant {
+    match &self.content {
+      VaultDatumContent::Text(_) => VaultDatumVariant::Text,
+      VaultDatumContent::Blob { .. } => VaultDatumVariant::Blob,
+      VaultDatumContent::KeyValue(_) => VaultDatumVariant::KeyValue,
+    }
+  }
+
+  pub fn as_text(&self) -> Option<&str> {
+    match &self.content {
+      VaultDatumContent::Text(string) => Some(string),
+      _ => None,
+    }
+  }
+
+  // The number of bytes this datum counts for against quotas.
+  pub fn get_size(&self) -> usize {
+    match &self.content {
+      VaultDatumContent::Text(string) => {
+        string.len()
+      }
+      VaultDatumContent::Blob { file_name, bytes } => {
+        file_name.len() + bytes.len()
+      }
+      VaultDatumContent::KeyValue(entries) => {
+        entries
+          .iter()
+          .map(|entry| entry.label.len() + entry.value.len())
+          .sum()
+      }
+    }
   }
 }
 
@@ -68,6 +301,32 @@ pub enum VaultProtector {
   CountdownAfterPlea(CountdownAfterPleaConditional)
 }
 
+impl VaultProtector {
+  pub fn get_state(&self, now: Instant) -> CountdownAfterPleaConditionalState {
+    match self {
+      Self::CountdownAfterPlea(conditional) => {
+        conditional.get_state(now)
+      }
+    }
+  }
+
+  pub fn rearm(&mut self) {
+    match self {
+      Self::CountdownAfterPlea(conditional) => {
+        conditional.activate();
+      }
+    }
+  }
+
+  pub fn get_variant(&self) -> VaultProtectorVariant {
+    match self {
+      Self::CountdownAfterPlea(_) => {
+        VaultProtectorVariant::CountdownAfterPlea
+      }
+    }
+  }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VaultProtectorVariant {
   CountdownAfterPlea,
@@ -75,11 +334,55 @@ pub enum VaultProtectorVariant {
 
 impl VaultProtectorVariant {
   pub fn from_number(number: u8, textual_error: &mut impl IsTextualError) -> Result<Self, ()> {
-    todo!()
+    match number {
+      0 => {
+        Ok(Self::CountdownAfterPlea)
+      }
+      _ => {
+        textual_error.change_context("Creating a VaultProtectorVariant from a number");
+        textual_error.add_message("The number doesn't stand for any variant");
+        textual_error.add_attachement_display("Number", number);
+        Err(())
+      }
+    }
   }
 
   pub fn to_number(self) -> u8 {
-    todo!()
+    match self {
+      Self::CountdownAfterPlea => {
+        0
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VaultState {
+  // The protector is active, so the vault's data is hidden.
+  Locked,
+  // Someone pleaded for the vault and the protector is counting down.
+  Unlocking,
+  // The protector finished counting down. The vault may be opened.
+  Unlocked,
+  // A reveal window is running. The vault's data may be read.
+  Revealed,
+}
+
+impl VaultState {
+  pub fn is_locked(self) -> bool {
+    matches!(self, Self::Locked)
+  }
+
+  pub fn is_unlocking(self) -> bool {
+    matches!(self, Self::Unlocking)
+  }
+
+  pub fn is_unlocked(self) -> bool {
+    matches!(self, Self::Unlocked)
+  }
+
+  pub fn is_revealed(self) -> bool {
+    matches!(self, Self::Revealed)
   }
 }
 
@@ -87,6 +390,168 @@ impl VaultProtectorVariant {
 pub struct Vault {
   pub name: VaultName,
   pub protector: VaultProtector,
+  pub reveal_duration: Duration,
+  pub reveal_window: Option<Countdown>,
+  pub data: HashMap<UuidV4, VaultDatum>,
+}
+
+impl Vault {
+  pub const DEFAULT_REVEAL_DURATION: Duration = Duration::from_minutes_or_panic(5);
+
+  pub fn create(name: VaultName, protector: VaultProtector) -> Self {
+    Self {
+      name,
+      protector,
+      reveal_duration: Self::DEFAULT_REVEAL_DURATION,
+      reveal_window: None,
+      data: HashMap::new(),
+    }
+  }
+
+  pub fn construct(
+    name: VaultName,
+    protector: VaultProtector,
+    reveal_duration: Duration,
+    reveal_window: Option<Countdown>,
+    data: HashMap<UuidV4, VaultDatum>,
+  ) -> Self {
+    Self {
+      name,
+      protector,
+      reveal_duration,
+      reveal_window,
+      data,
+    }
+  }
+
+  pub fn get_state(&self, now: Instant) -> VaultState {
+    if let Some(reveal_window) = &self.reveal_window {
+      if reveal_window.is_running(now) {
+        return VaultState::Revealed;
+      }
+
+      // The reveal window is over but the protector wasn't re-armed 
+      // yet. Don't fall back to the protector's state, which is still 
+      // deactivated, or the vault would stay open until "relock" runs.
+      return VaultState::Locked;
+    }
+
+    match self.protector.get_state(now) {
+      CountdownAfterPleaConditionalState::Active => {
+        VaultState::Locked
+      }
+      CountdownAfterPleaConditionalState::Deactivating => {
+        VaultState::Unlocking
+      }
+      CountdownAfterPleaConditionalState::Deactivated => {
+        VaultState::Unlocked
+      }
+    }
+  }
+
+  pub fn is_revealed(&self, now: Instant) -> bool {
+    self.get_state(now).is_revealed()
+  }
+
+  pub fn needs_relock(&self, now: Instant) -> bool {
+    matches!(&self.reveal_window, Some(reveal_window) if reveal_window.is_finished(now))
+  }
+
+  pub fn get_data(&self, now: Instant) -> Option<&HashMap<UuidV4, VaultDatum>> {
+    if self.is_revealed(now) {
+      Some(&self.data)
+    } else {
+      None
+    }
+  }
+
+  pub fn get_data_size(&self) -> usize {
+    self.data.values().map(VaultDatum::get_size).sum()
+  }
+
+  pub fn create_reveal_state(&self, now: Instant) -> VaultRevealState {
+    VaultRevealState {
+      window: Countdown::create(now, self.reveal_duration),
+    }
+  }
+
+  pub fn reveal_from_reveal_state(&mut self, reveal_state: VaultRevealState) {
+    self.reveal_window = Some(reveal_state.window);
+  }
+
+  pub fn set_datum(&mut self, datum_id: UuidV4, datum: VaultDatum) {
+    self.data.insert(datum_id, datum);
+  }
+
+  pub fn delete_datum(&mut self, datum_id: &UuidV4) -> Option<VaultDatum> {
+    self.data.remove(datum_id)
+  }
+
+  pub fn relock(&mut self) {
+    self.reveal_window = None;
+    self.protector.rearm();
+  }
+}
+
+pub struct VaultRevealState {
+  pub window: Countdown,
+}
+
+// A record of one time a vault was opened. These are kept for
+// as long as the vault exists so that users can review them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultReveal {
+  pub vault_id: UuidV4,
+  pub revealed_at: DateTime,
+  pub window: Countdown,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Vaults {
+  vaults: HashMap<UuidV4, Vault>,
+}
+
+impl Vaults {
+  pub fn new() -> Self {
+    Self {
+      vaults: HashMap::new(),
+    }
+  }
+
+  pub fn construct(vaults: HashMap<UuidV4, Vault>) -> Self {
+    Self { 
+      vaults,
+    }
+  }
+
+  pub fn get_all_vaults(&self) -> impl Iterator<Item = (&UuidV4, &Vault)> {
+    self.vaults.iter()
+  }
+
+  pub fn get_vault_given_id(&self, vault_id: &UuidV4) -> Option<&Vault> {
+    self.vaults.get(vault_id)
+  }
+
+  pub fn get_vault_given_id_mut(&mut self, vault_id: &UuidV4) -> Option<&mut Vault> {
+    self.vaults.get_mut(vault_id)
+  }
+
+  pub fn get_vaults_that_need_relock(&self, now: Instant) -> Vec<UuidV4> {
+    self
+      .vaults
+      .iter()
+      .filter(|(_, vault)| vault.needs_relock(now))
+      .map(|(vault_id, _)| vault_id.clone())
+      .collect()
+  }
+
+  pub fn add_vault(&mut self, vault_id: UuidV4, vault: Vault) {
+    self.vaults.insert(vault_id, vault);
+  }
+
+  pub fn delete_vault(&mut self, vault_id: &UuidV4) {
+    self.vaults.remove(vault_id);
+  }
 }
 
 // TODO: Rename to CommonInfo or Singleton
@@ -96,6 +561,10 @@ pub struct VaultsStats {
   maximum_vault_number: usize,
   data_number: usize,
   maximum_data_number: usize,
+  data_size: usize,
+  maximum_data_size: usize,
+  maximum_datum_size: usize,
+  maximum_vault_data_size: usize,
 }
 
 impl Default for VaultsStats {
@@ -105,34 +574,107 @@ impl Default for VaultsStats {
       maximum_data_number: 500,
       vault_number: 0,
       maximum_vault_number: 500,
+      data_size: 0,
+      maximum_data_size: 64 * 1024 * 1024,
+      maximum_datum_size: VaultDatum::MAXIMUM_BLOB_SIZE,
+      maximum_vault_data_size: 8 * 1024 * 1024,
     }
   }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum VaultQuotaViolation {
+  TooManyData,
+  DatumTooLarge { size: usize, maximum_size: usize },
+  VaultTooLarge { size: usize, maximum_size: usize },
+  TotalDataTooLarge { size: usize, maximum_size: usize },
+}
+
+impl ToTextualError for VaultQuotaViolation {
+  fn to_textual_error_context(&self) -> TextualErrorContext {
+    let mut context = TextualErrorContext::new("Checking vault quotas before adding a datum");
+
+    match self {
+      Self::TooManyData => {
+        context.add_message("The maximum number of vault data was reached");
+      }
+      Self::DatumTooLarge { size, maximum_size } => {
+        context.add_message("The datum is too large");
+        context.add_attachement_display("Datum size", size);
+        context.add_attachement_display("Maximum datum size", maximum_size);
+      }
+      Self::VaultTooLarge { size, maximum_size } => {
+        context.add_message("The vault would exceed its size quota");
+        context.add_attachement_display("Vault size with the datum", size);
+        context.add_attachement_display("Maximum vault size", maximum_size);
+      }
+      Self::TotalDataTooLarge { size, maximum_size } => {
+        context.add_message("All vaults together would exceed their size quota");
+        context.add_attachement_display("Total size with the datum", size);
+        context.add_attachement_display("Maximum total size", maximum_size);
+      }
+    }
+
+    context
+  }
+}
+
 impl VaultsStats {
   pub fn construct(
     vault_number: usize,
     maximum_vault_number: usize,
     data_number: usize,
     maximum_data_number: usize,
+    data_size: usize,
+    maximum_data_size: usize,
+    maximum_datum_size: usize,
+    maximum_vault_data_size: usize,
   ) -> Self {
     Self {
       vault_number,
       maximum_vault_number,
       data_number,
       maximum_data_number,
+      data_size,
+      maximum_data_size,
+      maximum_datum_size,
+      maximum_vault_data_size,
+    }
+  }
+
+  // Counts the vaults and their data again, since only the quotas are
+  // configured while the usage follows from what's stored.
+  pub fn recount_usage(&mut self, vaults: &Vaults) {
+    self.vault_number = 0;
+    self.data_number = 0;
+    self.data_size = 0;
+
+    for (_, vault) in vaults.get_all_vaults() {
+      self.vault_number = self.vault_number.saturating_add(1);
+      self.data_number = self.data_number.saturating_add(vault.data.len());
+      self.data_size = self.data_size.saturating_add(vault.get_data_size());
     }
   }
 
   pub fn try_decrement_vault_number(&mut self) {
-    if let None = self.vault_number.checked_sub(1) {
-      // TODO: Log this case
+    match self.vault_number.checked_sub(1) {
+      Some(vault_number) => {
+        self.vault_number = vault_number;
+      }
+      None => {
+        // TODO: Log this case
+      }
     }
   }
   
   pub fn try_increment_vault_number(&mut self) {
-    if let None = self.vault_number.checked_add(1) {
-      // TODO: Log this case
+    match self.vault_number.checked_add(1) {
+      Some(vault_number) => {
+        self.vault_number = vault_number;
+      }
+      None => {
+        // TODO: Log this case
+      }
     }
   }
   
@@ -141,21 +683,97 @@ impl VaultsStats {
   }
 
   pub fn try_decrement_data_number(&mut self) {
-    if let None = self.data_number.checked_sub(1) {
-      // TODO: Log this case
+    match self.data_number.checked_sub(1) {
+      Some(data_number) => {
+        self.data_number = data_number;
+      }
+      None => {
+        // TODO: Log this case
+      }
     }
   }
 
   pub fn try_increment_data_number(&mut self) {
-    if let None = self.data_number.checked_add(1) {
-      // TODO: Log this case
+    match self.data_number.checked_add(1) {
+      Some(data_number) => {
+        self.data_number = data_number;
+      }
+      None => {
+        // TODO: Log this case
+      }
     }
   }
 
-  pub fn may_add_another_datum(&mut self) -> bool {
+  pub fn may_add_another_datum(&self) -> bool {
     self.data_number < self.maximum_data_number
   }
 
+  // Checks every quota that adding `datum` to `vault` would affect.
+  // `replaced_datum_size` is the size of the datum being replaced, if any.
+  pub fn check_datum_quotas(
+    &self, 
+    vault: &Vault, 
+    datum: &VaultDatum,
+    replaced_datum_size: Option<usize>,
+  ) -> Result<(), VaultQuotaViolation> {
+    if replaced_datum_size.is_none() && !self.may_add_another_datum() {
+      return Err(VaultQuotaViolation::TooManyData);
+    }
+
+    let datum_size = datum.get_size();
+    if datum_size > self.maximum_datum_size {
+      return Err(VaultQuotaViolation::DatumTooLarge { 
+        size: datum_size, 
+        maximum_size: self.maximum_datum_size,
+      });
+    }
+
+    let replaced_datum_size = replaced_datum_size.unwrap_or(0);
+
+    let vault_size = vault
+      .get_data_size()
+      .saturating_sub(replaced_datum_size)
+      .saturating_add(datum_size);
+
+    if vault_size > self.maximum_vault_data_size {
+      return Err(VaultQuotaViolation::VaultTooLarge { 
+        size: vault_size, 
+        maximum_size: self.maximum_vault_data_size,
+      });
+    }
+
+    let data_size = self
+      .data_size
+      .saturating_sub(replaced_datum_size)
+      .saturating_add(datum_size);
+
+    if data_size > self.maximum_data_size {
+      return Err(VaultQuotaViolation::TotalDataTooLarge { 
+        size: data_size, 
+        maximum_size: self.maximum_data_size,
+      });
+    }
+
+    Ok(())
+  }
+
+  pub fn update_after_datum_added(&mut self, datum_size: usize) {
+    self.try_increment_data_number();
+    self.data_size = self.data_size.saturating_add(datum_size);
+  }
+
+  pub fn update_after_datum_replaced(&mut self, previous_datum_size: usize, datum_size: usize) {
+    self.data_size = self
+      .data_size
+      .saturating_sub(previous_datum_size)
+      .saturating_add(datum_size);
+  }
+
+  pub fn update_after_datum_deleted(&mut self, datum_size: usize) {
+    self.try_decrement_data_number();
+    self.data_size = self.data_size.saturating_sub(datum_size);
+  }
+
   pub fn get_vault_number(&self) -> usize {
     self.vault_number
   }
@@ -171,4 +789,209 @@ impl VaultsStats {
   pub fn get_maximum_data_number(&self) -> usize {
     self.maximum_data_number
   }
-}
\ No newline at end of file
+
+  pub fn get_data_size(&self) -> usize {
+    self.data_size
+  }
+
+  pub fn get_maximum_data_size(&self) -> usize {
+    self.maximum_data_size
+  }
+
+  pub fn get_maximum_datum_size(&self) -> usize {
+    self.maximum_datum_size
+  }
+
+  pub fn get_maximum_vault_data_size(&self) -> usize {
+    self.maximum_vault_data_size
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn create_vault() -> Vault {
+    Vault::create(
+      VaultName::new("Passwords".to_string()).unwrap(),
+      VaultProtector::CountdownAfterPlea(CountdownAfterPleaConditional::construct(Duration::HOUR, None)),
+    )
+  }
+
+  fn create_text(length: usize) -> VaultDatum {
+    VaultDatum::new("a".repeat(length)).unwrap()
+  }
+
+  fn create_entry(label: &str, value: &str) -> VaultKeyValueEntry {
+    VaultKeyValueEntry { label: label.to_string(), value: value.to_string() }
+  }
+
+  // Small quotas, so they're easy to reach.
+  fn create_stats(data_number: usize, data_size: usize) -> VaultsStats {
+    VaultsStats::construct(1, 10, data_number, 3, data_size, 100, 40, 60)
+  }
+
+  #[test]
+  fn vault_names_must_have_a_valid_length() {
+    assert!(VaultName::new(String::new()).is_err());
+    assert!(VaultName::new("a".repeat(VaultName::MAXIMUM_LENGTH)).is_ok());
+    assert!(VaultName::new("a".repeat(VaultName::MAXIMUM_LENGTH + 1)).is_err());
+  }
+
+  #[test]
+  fn texts_must_have_a_valid_length() {
+    assert!(matches!(
+      VaultDatum::new(String::new()),
+      Err(CreateVaultDatumFromStringError::LengthViolation { .. }),
+    ));
+    assert!(VaultDatum::new("a".repeat(VaultDatum::MAXIMUM_TEXT_LENGTH)).is_ok());
+    assert!(matches!(
+      VaultDatum::new("a".repeat(VaultDatum::MAXIMUM_TEXT_LENGTH + 1)),
+      Err(CreateVaultDatumFromStringError::LengthViolation { .. }),
+    ));
+  }
+
+  #[test]
+  fn blobs_must_have_a_valid_file_name_and_size() {
+    assert!(matches!(
+      VaultDatum::new_blob(String::new(), vec![0]),
+      Err(CreateVaultDatumFromBlobError::FileNameLengthViolation { .. }),
+    ));
+    assert!(matches!(
+      VaultDatum::new_blob("a".repeat(VaultDatum::MAXIMUM_FILE_NAME_LENGTH + 1), vec![0]),
+      Err(CreateVaultDatumFromBlobError::FileNameLengthViolation { .. }),
+    ));
+    assert!(matches!(
+      VaultDatum::new_blob("key.pem".to_string(), Vec::new()),
+      Err(CreateVaultDatumFromBlobError::SizeViolation { size: 0 }),
+    ));
+    assert!(matches!(
+      VaultDatum::new_blob("key.pem".to_string(), vec![0; VaultDatum::MAXIMUM_BLOB_SIZE + 1]),
+      Err(CreateVaultDatumFromBlobError::SizeViolation { .. }),
+    ));
+
+    let datum = VaultDatum::new_blob("key.pem".to_string(), vec![0; 10]).unwrap();
+    assert_eq!(datum.get_size(), 17);
+  }
+
+  #[test]
+  fn key_value_entries_are_validated() {
+    assert!(matches!(
+      VaultDatum::new_key_value(Vec::new()),
+      Err(CreateVaultDatumFromEntriesError::EntryNumberViolation { number: 0 }),
+    ));
+
+    let entries = (0..=VaultDatum::MAXIMUM_ENTRY_NUMBER)
+      .map(|index| create_entry(&index.to_string(), "value"))
+      .collect();
+    assert!(matches!(
+      VaultDatum::new_key_value(entries),
+      Err(CreateVaultDatumFromEntriesError::EntryNumberViolation { .. }),
+    ));
+
+    assert!(matches!(
+      VaultDatum::new_key_value(vec![create_entry("", "value")]),
+      Err(CreateVaultDatumFromEntriesError::LabelLengthViolation { .. }),
+    ));
+    assert!(matches!(
+      VaultDatum::new_key_value(vec![create_entry("user", "")]),
+      Err(CreateVaultDatumFromEntriesError::ValueLengthViolation { length: 0, .. }),
+    ));
+    assert!(matches!(
+      VaultDatum::new_key_value(vec![create_entry("user", "alex"), create_entry("user", "sam")]),
+      Err(CreateVaultDatumFromEntriesError::DuplicateLabel { .. }),
+    ));
+
+    let datum = VaultDatum::new_key_value(vec![create_entry("user", "alex"), create_entry("pin", "1234")]).unwrap();
+    assert_eq!(datum.get_size(), 15);
+  }
+
+  #[test]
+  fn the_number_of_data_is_limited() {
+    let vault = create_vault();
+
+    assert!(create_stats(2, 0).check_datum_quotas(&vault, &create_text(1), None).is_ok());
+    assert!(matches!(
+      create_stats(3, 0).check_datum_quotas(&vault, &create_text(1), None),
+      Err(VaultQuotaViolation::TooManyData),
+    ));
+    // Replacing a datum doesn't add one.
+    assert!(create_stats(3, 0).check_datum_quotas(&vault, &create_text(1), Some(1)).is_ok());
+  }
+
+  #[test]
+  fn the_size_of_a_datum_is_limited() {
+    let vault = create_vault();
+
+    assert!(create_stats(0, 0).check_datum_quotas(&vault, &create_text(40), None).is_ok());
+    assert!(matches!(
+      create_stats(0, 0).check_datum_quotas(&vault, &create_text(41), None),
+      Err(VaultQuotaViolation::DatumTooLarge { size: 41, maximum_size: 40 }),
+    ));
+  }
+
+  #[test]
+  fn the_size_of_a_vault_is_limited() {
+    let mut vault = create_vault();
+    vault.set_datum(UuidV4::generate(), create_text(30));
+    let stats = create_stats(1, 30);
+
+    assert!(stats.check_datum_quotas(&vault, &create_text(30), None).is_ok());
+    assert!(matches!(
+      stats.check_datum_quotas(&vault, &create_text(31), None),
+      Err(VaultQuotaViolation::VaultTooLarge { size: 61, maximum_size: 60 }),
+    ));
+    // The replaced datum's size is given back.
+    assert!(stats.check_datum_quotas(&vault, &create_text(40), Some(30)).is_ok());
+  }
+
+  #[test]
+  fn the_size_of_all_data_is_limited() {
+    let vault = create_vault();
+    let stats = create_stats(2, 80);
+
+    assert!(stats.check_datum_quotas(&vault, &create_text(20), None).is_ok());
+    assert!(matches!(
+      stats.check_datum_quotas(&vault, &create_text(21), None),
+      Err(VaultQuotaViolation::TotalDataTooLarge { size: 101, maximum_size: 100 }),
+    ));
+    assert!(stats.check_datum_quotas(&vault, &create_text(30), Some(10)).is_ok());
+  }
+
+  #[test]
+  fn usage_follows_added_replaced_and_deleted_data() {
+    let mut stats = create_stats(0, 0);
+
+    stats.update_after_datum_added(10);
+    stats.update_after_datum_added(20);
+    stats.update_after_datum_replaced(20, 5);
+    stats.update_after_datum_deleted(10);
+
+    assert_eq!(stats.get_data_number(), 1);
+    assert_eq!(stats.get_data_size(), 5);
+  }
+
+  #[test]
+  fn usage_is_recounted_from_the_vaults() {
+    let mut first_vault = create_vault();
+    first_vault.set_datum(UuidV4::generate(), create_text(10));
+    first_vault.set_datum(UuidV4::generate(), create_text(20));
+
+    let mut second_vault = create_vault();
+    second_vault.set_datum(UuidV4::generate(), create_text(5));
+
+    let mut vaults = Vaults::new();
+    vaults.add_vault(UuidV4::generate(), first_vault);
+    vaults.add_vault(UuidV4::generate(), second_vault);
+    vaults.add_vault(UuidV4::generate(), create_vault());
+
+    // Whatever was counted before doesn't matter.
+    let mut stats = create_stats(7, 70);
+    stats.recount_usage(&vaults);
+
+    assert_eq!(stats.get_vault_number(), 3);
+    assert_eq!(stats.get_data_number(), 3);
+    assert_eq!(stats.get_data_size(), 35);
+    assert_eq!(stats.get_maximum_data_size(), 100);
+  }
+}
@@ -1,7 +1,28 @@
-use std::{ffi::CStr, path::Path};
+use std::{ffi::CStr, path::{Path, PathBuf}};
+use discipline_daemon::launcher::pam::FailurePolicy;
 
 const DISCIPLINE_PAM_MODULE_DATA_NAME: &str = concat!(env!("DISCIPLINE_PAM_MODULE_DATA_NAME"), "\0");
 const DISCIPLINE_INSTALLATION_DIRECTORY: &str = env!("DISCIPLINE_INSTALLATION_DIRECTORY");
+const DISCIPLINE_PAM_MODULE_DEFAULT_FAILURE_POLICY: &str = env!("DISCIPLINE_PAM_MODULE_DEFAULT_FAILURE_POLICY");
+
+// The failure policy for when the module can't be created, since then
+// there are no configured ones to go by. A value other than "open" or
+// "closed" fails the build.
+pub(crate) const DEFAULT_FAILURE_POLICY: FailurePolicy = parse_failure_policy(DISCIPLINE_PAM_MODULE_DEFAULT_FAILURE_POLICY);
+
+const fn parse_failure_policy(value: &str) -> FailurePolicy {
+  match value.as_bytes() {
+    b"open" => {
+      FailurePolicy::Open
+    }
+    b"closed" => {
+      FailurePolicy::Closed
+    }
+    _ => {
+      panic!("DISCIPLINE_PAM_MODULE_DEFAULT_FAILURE_POLICY must be \"open\" or \"closed\"")
+    }
+  }
+}
 
 pub(crate) const fn discipline_pam_module_data_name() -> &'static CStr {
   unsafe {
@@ -11,4 +32,8 @@ pub(crate) const fn discipline_pam_module_data_name() -> &'static CStr {
 
 pub(crate) fn discipline_installation_directory() -> &'static Path {
   Path::new(DISCIPLINE_INSTALLATION_DIRECTORY)
-}
\ No newline at end of file
+}
+
+pub(crate) fn discipline_pam_module_log_file_path() -> PathBuf {
+  discipline_installation_directory().join("linux_pam_module.log")
+}
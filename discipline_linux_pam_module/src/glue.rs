@@ -1,15 +1,41 @@
 use std::{fmt::Debug, ptr};
 use std::ffi::{CStr, CString};
 use libc::{c_char, c_int, c_void};
-use discipline_daemon::operating_system::UserName;
+use discipline_daemon::launcher::{LoginContext, SessionDetails, UserId, UserName};
+use discipline_daemon::launcher::pam::FailureDecision;
 use crate::*;
 
 enum GetModuleDataError {
   PamErrorWhileGettingData(i32),
   PamErrorWhileSettingData(i32),
+  // Module::create logs the details itself.
   ErrorWhileCreatingInitialModuleData(CreateModuleError),
 }
 
+impl GetModuleDataError {
+  fn describe(&self) -> String {
+    match self {
+      Self::PamErrorWhileGettingData(status_code) => {
+        format!("pam_get_data failed with status code {status_code}")
+      }
+      Self::PamErrorWhileSettingData(status_code) => {
+        format!("pam_set_data failed with status code {status_code}")
+      }
+      Self::ErrorWhileCreatingInitialModuleData(CreateModuleError::AuthenticationTokenRejected) => {
+        "the authentication token is rejected, see the error logged before this".into()
+      }
+      Self::ErrorWhileCreatingInitialModuleData(CreateModuleError::ConfigurationUnusable) => {
+        "the configuration is unusable, see the error logged before this".into()
+      }
+    }
+  }
+}
+
+// For when there's no module, and so no logger of its own, to log with.
+fn log_without_module(message: &str) {
+  Logger::create(discipline_pam_module_log_file_path()).write_str(message);
+}
+
 unsafe extern "C" fn cleanup(
   _pam_handle: *mut pam_handle_t,
   data: *mut c_void,
@@ -38,9 +64,7 @@ unsafe fn get_module_data(pamh: *mut pam_handle_t) -> Result<*const Module, GetM
   }
 
   let data = Module::create()
-    .map_err(|error| {
-      GetModuleDataError::ErrorWhileCreatingInitialModuleData(error)
-    })?;
+    .map_err(GetModuleDataError::ErrorWhileCreatingInitialModuleData)?;
 
   let data = Box::new(data);
   let data = Box::into_raw(data) as *mut Module;
@@ -85,6 +109,69 @@ unsafe fn get_user_name(pamh: *mut pam_handle_t) -> Result<UserName, ()> {
   }))
 }
 
+// Reads a string item, like PAM_TTY. Items that aren't set, or aren't
+// valid UTF-8, are None.
+unsafe fn get_string_item(pamh: *mut pam_handle_t, item_type: c_int) -> Option<String> {
+  let mut item: *const c_void = ptr::null();
+
+  let status_code = unsafe {
+    pam::pam_get_item(
+      pamh,
+      item_type,
+      (&mut item) as *mut *const c_void,
+    )
+  };
+
+  if status_code != pam::PAM_SUCCESS || item.is_null() {
+    return None;
+  }
+
+  unsafe { CStr::from_ptr(item as *const c_char) }
+    .to_str()
+    .ok()
+    .map(ToOwned::to_owned)
+}
+
+unsafe fn get_login_context(pamh: *mut pam_handle_t) -> LoginContext {
+  LoginContext {
+    service: unsafe { get_string_item(pamh, pam::PAM_SERVICE) }.unwrap_or_default(),
+    tty: unsafe { get_string_item(pamh, pam::PAM_TTY) },
+    remote_host: unsafe { get_string_item(pamh, pam::PAM_RHOST) },
+    // su and sudo are setuid, so only the real user id still says who
+    // ran them.
+    requesting_user_id: Some(UserId::new(unsafe { libc::getuid() })),
+  }
+}
+
+// Reads a variable from the PAM environment, which modules earlier in
+// the stack may have set.
+unsafe fn get_environment_variable(pamh: *mut pam_handle_t, name: &CStr) -> Option<String> {
+  let value = unsafe { pam::pam_getenv(pamh, name.as_ptr()) };
+
+  if value.is_null() {
+    return None;
+  }
+
+  unsafe { CStr::from_ptr(value) }
+    .to_str()
+    .ok()
+    .map(ToOwned::to_owned)
+}
+
+unsafe fn get_session_details(pamh: *mut pam_handle_t) -> SessionDetails {
+  SessionDetails {
+    // Only there when pam_systemd comes before this module in the
+    // session stack.
+    logind_session_id: unsafe { get_environment_variable(pamh, c"XDG_SESSION_ID") },
+    service: unsafe { get_string_item(pamh, pam::PAM_SERVICE) },
+    tty: unsafe { get_string_item(pamh, pam::PAM_TTY) },
+    remote_host: unsafe { get_string_item(pamh, pam::PAM_RHOST) },
+    process_id: u32::try_from(unsafe { libc::getpid() }).ok(),
+    // The daemon works it out.
+    login_class: None,
+  }
+}
+
 // #[unsafe(no_mangle)]
 // pub unsafe extern "C" fn pam_sm_authenticate(
 //   pam_handle: *mut pam_sys::pam_handle_t,
@@ -105,77 +192,193 @@ unsafe fn get_user_name(pamh: *mut pam_handle_t) -> Result<UserName, ()> {
 //   pam_sys::PAM_IGNORE
 // }
 
-unsafe fn set_login_denial_phrase(
-  pamh: *mut pam::pam_handle_t,
-  phrase: CString,
-) -> Result<(), CString> {
-  // PAM will free this when it's no longer needed.
-  let phrase = phrase.as_ptr() as *const c_void;
+// Shows `message` to the user through the application's conversation
+// function. Applications that have none, like cron, don't get it.
+unsafe fn send_message(
+  pamh: *mut pam_handle_t,
+  message_style: c_int,
+  message: &CStr,
+) -> Result<(), ()> {
+  let mut conversation: *const c_void = ptr::null();
 
-  let status_code = unsafe { 
-    pam::pam_set_item(pamh, pam::PAM_TEXT_INFO, phrase)
+  let status_code = unsafe {
+    pam::pam_get_item(
+      pamh,
+      pam::PAM_CONV,
+      (&mut conversation) as *mut *const c_void,
+    )
+  };
+
+  if status_code != pam::PAM_SUCCESS || conversation.is_null() {
+    return Err(());
+  }
+
+  let conversation = unsafe { &*(conversation as *const pam::pam_conv) };
+  let Some(conv) = conversation.conv else {
+    return Err(());
+  };
+
+  let message = pam::pam_message {
+    msg_style: message_style,
+    msg: message.as_ptr(),
+  };
+  let mut messages = [&message as *const pam::pam_message];
+  let mut responses: *mut pam::pam_response = ptr::null_mut();
+
+  let status_code = unsafe {
+    conv(
+      1,
+      messages.as_mut_ptr(),
+      &mut responses,
+      conversation.appdata_ptr,
+    )
   };
 
+  // Informational messages get no answer, but the application may
+  // still have allocated one, which is ours to free.
+  if !responses.is_null() {
+    unsafe {
+      if !(*responses).resp.is_null() {
+        libc::free((*responses).resp as *mut c_void);
+      }
+      libc::free(responses as *mut c_void);
+    }
+  }
+
   if status_code != pam::PAM_SUCCESS {
-    return Err(unsafe {
-      CString::from_raw(phrase as *mut i8)
-    });
+    return Err(());
   }
 
   Ok(())
 }
 
-// unsafe fn pam_set_item(
-//   pamh: *mut pam::pam_handle_t, 
-
-//   PAM_TEXT_INFO, 
-//                     "Your account is locked. Contact administrator.") {
+// Decides a login without our data, and so without the configured
+// failure policies, under the one compiled into the module.
+unsafe fn decide_without_module(
+  pamh: *mut pam_handle_t,
+  flags: c_int,
+  error: &GetModuleDataError,
+) -> c_int {
+  // There's no heartbeat path to go by either.
+  let decision = DEFAULT_FAILURE_POLICY.decide(None);
+
+  let outcome = match decision {
+    FailureDecision::Allow => {
+      "allowed"
+    }
+    FailureDecision::Refuse => {
+      "refused"
+    }
+  };
 
-//                     }
+  log_without_module(&format!(
+    "Couldn't get the Discipline Linux-PAM Module data ({}), {outcome} the login under the compiled-in {DEFAULT_FAILURE_POLICY:?} failure policy.",
+    error.describe(),
+  ));
+
+  match decision {
+    FailureDecision::Allow => {
+      pam::PAM_SUCCESS
+    }
+    FailureDecision::Refuse => {
+      if flags & pam::PAM_SILENT == 0 {
+        let _ = unsafe { send_message(pamh, pam::PAM_ERROR_MSG, c"Discipline can't verify whether you may log in right now.") };
+      }
+
+      pam::PAM_PERM_DENIED
+    }
+  }
+}
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn pam_sm_acct_mgmt(
   pamh: *mut pam_handle_t,
-  _flags: c_int,
+  flags: c_int,
   _argc: c_int,
   _argv: *mut *const c_char,
 ) -> c_int {
-  let data = unsafe { get_module_data(pamh) };
+  let data = match unsafe { get_module_data(pamh) } {
+    Ok(data) => {
+      data
+    }
+    Err(error) => {
+      return unsafe { decide_without_module(pamh, flags, &error) };
+    }
+  };
 
-  let Ok(data) = data else {
-    return pam::PAM_SUCCESS;
+  let data = unsafe { &*data };
+  let login_context = unsafe { get_login_context(pamh) };
+
+  let refusal = match unsafe { get_user_name(pamh) } {
+    Ok(user_name) => {
+      match data.get_login_refusal(&user_name, &login_context) {
+        Ok(refusal) => {
+          refusal
+        }
+        Err(()) => {
+          data.get_login_refusal_without_daemon(Some(&user_name), &login_context, "the daemon is unreachable or didn't answer in time")
+        }
+      }
+    }
+    Err(()) => {
+      data.get_login_refusal_without_daemon(None, &login_context, "the user name is unavailable")
+    }
   };
 
-  let Ok(user_name) = (unsafe { get_user_name(pamh) }) else {
+  let Some(refusal) = refusal else {
     return pam::PAM_SUCCESS;
   };
 
-  let is_login_blocked = unsafe { &*data }.is_login_blocked(&user_name);
-
-  if is_login_blocked {
-    return pam::PAM_PERM_DENIED;
+  if flags & pam::PAM_SILENT == 0 {
+    // A message that can't be shown doesn't change the decision.
+    if let Ok(message) = CString::new(data.get_refusal_message(&refusal)) {
+      let _ = unsafe { send_message(pamh, pam::PAM_ERROR_MSG, &message) };
+    }
   }
 
-  pam::PAM_SUCCESS
+  pam::PAM_PERM_DENIED
 }
 
+#[unsafe(no_mangle)]
 pub unsafe extern "C" fn pam_sm_open_session(
   pamh: *mut pam::pam_handle_t,
   _flags: c_int,
   _argc: c_int,
   _argv: *mut *const c_char,
 ) -> c_int {
-  let data = unsafe { get_module_data(pamh) };
-
-  let Ok(data) = data else {
-    return pam::PAM_SUCCESS;
+  // A session that can't be reported still opens.
+  let data = match unsafe { get_module_data(pamh) } {
+    Ok(data) => {
+      unsafe { &*data }
+    }
+    Err(error) => {
+      log_without_module(&format!(
+        "Couldn't get the Discipline Linux-PAM Module data ({}), so Discipline Daemon isn't told that a session opened.",
+        error.describe(),
+      ));
+      return pam::PAM_SUCCESS;
+    }
   };
 
   let Ok(user_name) = (unsafe { get_user_name(pamh) }) else {
+    data.log_message("The user name of an opening session is unavailable, so Discipline Daemon isn't told about it.");
     return pam::PAM_SUCCESS;
   };
 
-  unsafe { &*data }.on_session_opened(&user_name);
+  let details = unsafe { get_session_details(pamh) };
+  data.on_session_opened(&user_name, &details);
+
+  let login_context = unsafe { get_login_context(pamh) };
+
+  for variable in data.get_session_environment(&user_name, &login_context) {
+    let Ok(variable) = CString::new(variable) else {
+      continue;
+    };
+
+    // pam_putenv copies the variable. Failing to set it doesn't stop
+    // the session from opening.
+    let _ = unsafe { pam::pam_putenv(pamh, variable.as_ptr()) };
+  }
 
   pam::PAM_SUCCESS
 }
@@ -187,17 +390,26 @@ pub unsafe extern "C" fn pam_sm_close_session(
   _argc: c_int,
   _argv: *mut *const c_char,
 ) -> c_int {
-  let data = unsafe { get_module_data(pamh) };
-
-  let Ok(data) = data else {
-    return PAM_SUCCESS;
+  let data = match unsafe { get_module_data(pamh) } {
+    Ok(data) => {
+      unsafe { &*data }
+    }
+    Err(error) => {
+      log_without_module(&format!(
+        "Couldn't get the Discipline Linux-PAM Module data ({}), so Discipline Daemon isn't told that a session closed.",
+        error.describe(),
+      ));
+      return PAM_SUCCESS;
+    }
   };
 
   let Ok(user_name) = (unsafe { get_user_name(pamh) }) else {
+    data.log_message("The user name of a closing session is unavailable, so Discipline Daemon isn't told about it.");
     return PAM_SUCCESS;
   };
 
-  unsafe { &*data }.on_session_closed(&user_name);
+  let details = unsafe { get_session_details(pamh) };
+  data.on_session_closed(&user_name, &details);
 
   PAM_SUCCESS
 }
\ No newline at end of file
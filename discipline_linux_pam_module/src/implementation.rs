@@ -1,14 +1,14 @@
 use std::fmt::Debug;
 use std::sync::Mutex;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
-use tokio::runtime::Runtime;
 
 use discipline_daemon::{TextualError, ToTextualError, TextualErrorContext};
 use discipline_daemon::chronic::duration::Duration;
-use discipline_daemon::operating_system::UserName;
-use discipline_daemon::operating_system::linux_pam_api::ClientConnection;
+use discipline_daemon::chronic::datetime::DateTime;
+use discipline_daemon::launcher::{UserName, LoginContext, LoginRefusal, LoginRefusalReason, SessionDetails};
+use discipline_daemon::launcher::pam::{AuthenticationToken, FailureDecision, FailurePolicies, Heartbeat, PolicyCache, PolicyCacheDecision, PolicyCacheReaderConfiguration, ClientConnection, render_refusal_message, render_session_environment};
 
 use crate::*;
 
@@ -68,9 +68,16 @@ use crate::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModuleConfiguration {
+  authentication_token: AuthenticationToken,
   pam_call_timeout: Duration,
   pam_login_blocked_message: String,
   discipline_daemon_unix_domain_server_path: PathBuf,
+  #[serde(default)]
+  failure_policies: FailurePolicies,
+  #[serde(default)]
+  discipline_daemon_heartbeat_path: Option<PathBuf>,
+  #[serde(default)]
+  policy_cache: Option<PolicyCacheReaderConfiguration>,
 }
 
 // fn load_configuration(configuration_file_path: PathBuf) -> Result<ModuleConfiguration, LoadModuleConfigurationError> {
@@ -102,8 +109,8 @@ pub struct ModuleConfiguration {
 //   Ok(configuration)
 // }
 
-fn load_configuration_or_textual_error(configuration_file_path: PathBuf) -> Result<ModuleConfiguration, TextualError> {
-  let configuration_file_content = std::fs::read(&configuration_file_path)
+fn load_configuration_or_textual_error(configuration_file_path: &Path) -> Result<ModuleConfiguration, TextualError> {
+  let configuration_file_content = std::fs::read(configuration_file_path)
     .map_err(|error| {
       TextualError::new("Reading the json confiugration file for Discipline Linux-PAM Module")
         .with_message("A filesystem error occured while reading the file")
@@ -123,6 +130,14 @@ fn load_configuration_or_textual_error(configuration_file_path: PathBuf) -> Resu
   Ok(configuration)
 }
 
+pub enum CreateModuleError {
+  // The token is readable by others or still the placeholder, so the
+  // daemon can't tell this module from anyone else. The failure
+  // policies in the same file aren't trusted either.
+  AuthenticationTokenRejected,
+  ConfigurationUnusable,
+}
+
 // TODO: Add a field containing magic bytes that we check
 // when we get the Module from "pam_get_data" to verify that
 // the data is our data.
@@ -130,33 +145,31 @@ pub struct Module {
   // discipline_installation_directory: PathBuf,
   // discipline_daemon_unix_server_path: PathBuf,
   // discipline_pam_configuration_path: PathBuf,
-  runtime: Runtime,
+  configuration: ModuleConfiguration,
   discipline_daemon_connection: Mutex<ClientConnection>,
-  logger: Logger
+  logger: Mutex<Logger>,
 }
 
 impl Module {
-  pub async fn create() -> Result<Self, ()> {
-    let logger = Logger::create(discipline_installation_directory().join("linux_pam_module.log"));
-
-    let runtime = match Runtime::new() {
-      Ok(value) => {
-        value
-      }
-      Err(error) => {
-        logger.write_displayable(
-          TextualError::new("Creating Discipline Linux-PAM Module Data")
-            .with_message("An error occured while creating a tokio async runtime.")
-            .with_attachement_display("Tokio error", error)
-        );
-        return Err(())
-      }
-    };
+  pub fn create() -> Result<Self, CreateModuleError> {
+    let mut logger = Logger::create(discipline_pam_module_log_file_path());
 
     let discipline_pam_configuration_path = discipline_installation_directory()
       .join("linux_pam_module_configuration.json");
 
-    let configuration = match load_configuration_or_textual_error(discipline_pam_configuration_path) {
+    // The daemon only talks to root anyway, but a token other users can
+    // read would let them pose as this module.
+    let mut textual_error = TextualError::new("Checking the authentication token of Discipline Linux-PAM Module");
+    if let Err(()) = AuthenticationToken::ensure_stored_privately(&discipline_pam_configuration_path, &mut textual_error) {
+      logger.write_displayable(
+        textual_error
+          .with_context("Creating Discipline Linux-PAM Module Data")
+          .with_message("The authentication token is rejected, so logins are decided by the compiled-in failure policy")
+      );
+      return Err(CreateModuleError::AuthenticationTokenRejected);
+    }
+
+    let configuration = match load_configuration_or_textual_error(&discipline_pam_configuration_path) {
       Ok(value) => {
         value
       }
@@ -164,82 +177,235 @@ impl Module {
         logger.write_displayable(
           error
             .with_context("Creating Discipline Linux-PAM Module Data")
+            .with_message("The configuration is unusable, so logins are decided by the compiled-in failure policy")
         );
-        return Err(());
+        return Err(CreateModuleError::ConfigurationUnusable);
       }
     };
 
-    let discipline_daemon_connection = ClientConnection::connect_or_textual_error(
-      &configuration.discipline_daemon_unix_domain_server_path, 
-      password
-    );
+    let mut textual_error = TextualError::new("Checking the authentication token of Discipline Linux-PAM Module");
+    if let Err(()) = configuration.authentication_token.ensure_not_placeholder(&mut textual_error) {
+      logger.write_displayable(
+        textual_error
+          .with_context("Creating Discipline Linux-PAM Module Data")
+          .with_message("The authentication token is rejected, so logins are decided by the compiled-in failure policy")
+      );
+      return Err(CreateModuleError::AuthenticationTokenRejected);
+    }
 
-    let discipline_daemon_connection = BlockingClientConnection
-      ::connect(
-        &configuration.discipline_daemon_unix_domain_server_path,
-        configuration.pam_call_timeout,
-      )
-      .map_err(|error| {
-        CreateModuleError::ErrorWhileConnectingToDisciplineDaemon(error)
-      })?;
+    // Connects on first use, so not reaching the daemon isn't fatal: the
+    // failure policies decide until it's back.
+    let discipline_daemon_connection = ClientConnection::create(
+      configuration.discipline_daemon_unix_domain_server_path.clone(),
+      configuration.authentication_token.clone(),
+      configuration.pam_call_timeout,
+    );
 
     Ok(Self {
       // discipline_daemon_unix_server_path,
       // discipline_pam_configuration_path,
+      configuration,
       discipline_daemon_connection: Mutex::new(discipline_daemon_connection),
+      logger: Mutex::new(logger),
     })
   }
 
-
-  // Fatal error.
-  //
-  // TODO: log the error.
-  //
-  // Fallback to the safest course of action: Let the user
-  // access their account. If we do otherwise, we might prevent
-  // the user from accessing their account forever.
-
-  pub fn is_login_blocked(&self, user_name: &UserName) -> bool {
+  // Why the daemon refuses the login, or None if it allows it. Err when
+  // the daemon couldn't answer in time, for
+  // `get_login_refusal_without_daemon` to decide instead.
+  pub fn get_login_refusal(&self, user_name: &UserName, login_context: &LoginContext) -> Result<Option<LoginRefusal>, ()> {
     let Ok(mut connection) = self.discipline_daemon_connection.lock() else {
-      return false;
+      return Err(());
     };
 
-    let Ok(is_login_blocked) = connection.is_user_account_access_blocked(user_name) else {
-      return false;
-    };
+    let mut textual_error = TextualError::new("Asking Discipline Daemon whether a user may log in");
 
-    is_login_blocked
+    match connection.get_login_refusal(user_name.as_ref(), login_context, &mut textual_error) {
+      Ok(refusal) => {
+        Ok(refusal)
+      }
+      Err(()) => {
+        self.log_error(textual_error);
+        Err(())
+      }
+    }
   }
 
-  pub fn on_session_opened(&self, user_name: &UserName) {
-    let mut connection = match self.discipline_daemon_connection.lock() {
-      Ok(value) => {
-        value
+  fn log_error(&self, textual_error: TextualError) {
+    if let Ok(mut logger) = self.logger.lock() {
+      logger.write_displayable(textual_error);
+    }
+  }
+
+  pub fn log_message(&self, message: &str) {
+    if let Ok(mut logger) = self.logger.lock() {
+      logger.write_str(message);
+    }
+  }
+
+  // Decides from the policy cache the daemon left behind when there's a
+  // recent enough one, and otherwise applies the failure policy of
+  // `user_name`, or the default one when the user isn't known. Either
+  // way the decision is logged, since each one is a login decided on
+  // no one's word but ours.
+  pub fn get_login_refusal_without_daemon(
+    &self, 
+    user_name: Option<&UserName>, 
+    login_context: &LoginContext,
+    reason: &str,
+  ) -> Option<LoginRefusal> {
+    let mut cache_problem = "no policy cache is configured".to_string();
+
+    if let (Some(user_name), Some(policy_cache)) = (user_name, &self.configuration.policy_cache) {
+      let mut textual_error = TextualError::new("Deciding a login from the PAM policy cache");
+
+      match PolicyCache::load(&policy_cache.path, &self.configuration.authentication_token, &mut textual_error) {
+        Ok(cache) => {
+          let decision = cache.decide(user_name, login_context, policy_cache.maximum_age, DateTime::now());
+
+          let refusal = match decision {
+            PolicyCacheDecision::Allow => {
+              Some(None)
+            }
+            PolicyCacheDecision::Refuse(refusal) => {
+              Some(Some(refusal))
+            }
+            PolicyCacheDecision::Stale => {
+              cache_problem = format!(
+                "the policy cache is stale, written {} milliseconds ago", 
+                cache.get_age(DateTime::now()).as_total_milliseconds(),
+              );
+              None
+            }
+          };
+
+          if let Some(refusal) = refusal {
+            let outcome = if refusal.is_some() { "refused" } else { "allowed" };
+            if let Ok(mut logger) = self.logger.lock() {
+              logger.write_str(&format!(
+                "Couldn't ask Discipline Daemon whether {} may log in ({reason}), {outcome} the login according to the policy cache.",
+                user_name.inner().to_string_lossy(),
+              ));
+            }
+
+            return refusal;
+          }
+        }
+        Err(()) => {
+          cache_problem = format!("the policy cache is unusable: {textual_error}");
+        }
       }
-      Err(error) => {
-        // TODO: Log the error.
-        return;
+    }
+
+    let failure_policies = &self.configuration.failure_policies;
+    let failure_policy = match user_name {
+      Some(user_name) => {
+        failure_policies.get_policy(user_name)
+      }
+      None => {
+        failure_policies.default
       }
     };
 
-    if let Err(error) = connection.notify_that_user_session_opened(user_name) {
-      // TODO: Log the error.
+    let daemon_heartbeat_age = self
+      .configuration
+      .discipline_daemon_heartbeat_path
+      .as_deref()
+      .and_then(Heartbeat::get_age);
+
+    let decision = failure_policy.decide(daemon_heartbeat_age);
+
+    let user_name = user_name
+      .map(|user_name| user_name.inner().to_string_lossy().into_owned())
+      .unwrap_or_else(|| "an unknown user".into());
+
+    let outcome = match decision {
+      FailureDecision::Allow => {
+        "allowed"
+      }
+      FailureDecision::Refuse => {
+        "refused"
+      }
+    };
+
+    let daemon_heartbeat_age = match daemon_heartbeat_age {
+      Some(age) => {
+        format!("{} milliseconds ago", age.as_total_milliseconds())
+      }
+      None => {
+        "never".into()
+      }
+    };
+
+    if let Ok(mut logger) = self.logger.lock() {
+      logger.write_str(&format!(
+        "Couldn't ask Discipline Daemon whether {user_name} may log in ({reason}), and {cache_problem}, {outcome} the login under the {failure_policy:?} failure policy. The daemon was last alive {daemon_heartbeat_age}."
+      ));
+    }
+
+    match decision {
+      FailureDecision::Allow => {
+        None
+      }
+      FailureDecision::Refuse => {
+        Some(LoginRefusal {
+          reason: LoginRefusalReason::Unverifiable,
+          blocked_until: None,
+        })
+      }
     }
   }
 
-  pub fn on_session_closed(&self, user_name: &UserName) {
-    let mut connection = match self.discipline_daemon_connection.lock() {
-      Ok(value) => {
-        value
+  // The configured `pam_login_blocked_message`, filled in for `refusal`.
+  pub fn get_refusal_message(&self, refusal: &LoginRefusal) -> String {
+    render_refusal_message(&self.configuration.pam_login_blocked_message, refusal, DateTime::now())
+  }
+
+  // The variables to put in a session's environment, as rendered by
+  // `render_session_environment`. Empty when the daemon can't be
+  // reached, since a session without them works all the same.
+  pub fn get_session_environment(&self, user_name: &UserName, login_context: &LoginContext) -> Vec<String> {
+    let Ok(mut connection) = self.discipline_daemon_connection.lock() else {
+      return Vec::new();
+    };
+
+    let mut textual_error = TextualError::new("Asking Discipline Daemon for a session's deadline");
+
+    match connection.get_session_deadline(user_name.as_ref(), login_context, &mut textual_error) {
+      Ok(Some(deadline)) => {
+        render_session_environment(&deadline)
       }
-      Err(error) => {
-        // TODO: Log the error.
-        return;
+      Ok(None) => {
+        Vec::new()
+      }
+      Err(()) => {
+        self.log_error(textual_error);
+        Vec::new()
       }
+    }
+  }
+
+  pub fn on_session_opened(&self, user_name: &UserName, details: &SessionDetails) {
+    let Ok(mut connection) = self.discipline_daemon_connection.lock() else {
+      return;
     };
 
-    if let Err(error) = connection.notify_that_user_session_closed(user_name) {
-      // TODO: Log the error.
+    let mut textual_error = TextualError::new("Telling Discipline Daemon that a session opened");
+
+    if let Err(()) = connection.send_user_session_opened_notification(user_name.as_ref(), details, &mut textual_error) {
+      self.log_error(textual_error);
+    }
+  }
+
+  pub fn on_session_closed(&self, user_name: &UserName, details: &SessionDetails) {
+    let Ok(mut connection) = self.discipline_daemon_connection.lock() else {
+      return;
+    };
+
+    let mut textual_error = TextualError::new("Telling Discipline Daemon that a session closed");
+
+    if let Err(()) = connection.send_user_session_closed_notification(user_name.as_ref(), details, &mut textual_error) {
+      self.log_error(textual_error);
     }
   }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::fs::{File, OpenOptions};
@@ -120,10 +120,10 @@ impl Logger {
   }
 
   pub fn write_debugable(&mut self, message: impl Debug) {
-    
+    self.write_str(&format!("{message:?}"));
   }
 
-  pub fn write_displayable(&mut self, message: impl Debug) {
-    
+  pub fn write_displayable(&mut self, message: impl Display) {
+    self.write_str(&message.to_string());
   }
 }
\ No newline at end of file
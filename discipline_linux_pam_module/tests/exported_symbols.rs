@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+// Linux-PAM looks the service functions up by name, so one that lost its
+// #[unsafe(no_mangle)] still builds but is silently never called.
+const SERVICE_FUNCTIONS: &[&str] = &[
+  "pam_sm_acct_mgmt",
+  "pam_sm_open_session",
+  "pam_sm_close_session",
+];
+
+// Cargo doesn't rebuild a cdylib-only library for integration tests,
+// so this builds it next to the test executable, which lives in
+// target/<profile>/deps.
+fn build_module() -> PathBuf {
+  let status = Command::new(env!("CARGO"))
+    .arg("build")
+    .arg("--manifest-path")
+    .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+    .status()
+    .unwrap();
+
+  assert!(status.success(), "building the module failed");
+
+  let test_executable = std::env::current_exe().unwrap();
+  test_executable
+    .parent()
+    .and_then(|deps| deps.parent())
+    .unwrap()
+    .join("libdiscipline_linux_pam_module.so")
+}
+
+#[test]
+fn service_functions_are_exported() {
+  let module_path = build_module();
+
+  let output = Command::new("nm")
+    .arg("--dynamic")
+    .arg("--defined-only")
+    .arg(&module_path)
+    .output()
+    .unwrap();
+
+  assert!(output.status.success(), "nm failed on {}", module_path.display());
+
+  let symbols = String::from_utf8(output.stdout).unwrap();
+  for function in SERVICE_FUNCTIONS {
+    let is_exported = symbols
+      .lines()
+      .any(|line| line.split_whitespace().last() == Some(*function));
+
+    assert!(is_exported, "{function} isn't exported from {}", module_path.display());
+  }
+}